@@ -0,0 +1,69 @@
+use std::fs;
+use std::path::PathBuf;
+
+use java_rs::classloader::assembler::assemble;
+use java_rs::classloader::load_class;
+
+const USAGE: &str = "Usage: java_rs-asm [-d <directory>] <file.j>...
+           (to assemble Jasmin source files into class files)
+
+ where options include:
+    -d <directory>
+                  write the class files in this directory, in a subdirectory
+                  for the package, instead of the current directory";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (directory, files) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let mut exit_code = 0;
+    for file in files {
+        if let Err(error) = assemble_file(&directory, file) {
+            eprintln!("Error: {}: {}", file, error);
+            exit_code = 1;
+        }
+    }
+    std::process::exit(exit_code);
+}
+
+/// assembles the file into [directory]/[package_path]/[class].class
+fn assemble_file(directory: &str, file: &str) -> Result<(), String> {
+    let source = fs::read_to_string(file).map_err(|error| error.to_string())?;
+    let bytecode = assemble(&source).map_err(|error| error.to_string())?;
+    let class_name = load_class(bytecode.clone())
+        .map_err(|error| error.to_string())?
+        .name()
+        .to_owned();
+    let path = PathBuf::from(directory).join(format!("{}.class", class_name));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+    }
+    fs::write(&path, bytecode).map_err(|error| error.to_string())
+}
+
+/// the output directory and the source files
+fn parse_args(args: &[String]) -> Result<(String, Vec<&String>), String> {
+    let mut directory = ".".to_owned();
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" => {
+                directory = args.next().ok_or("-d requires a directory")?.clone();
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unrecognized option: {}", arg)),
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        return Err("no source files".into());
+    }
+    Ok((directory, files))
+}
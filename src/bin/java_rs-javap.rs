@@ -0,0 +1,107 @@
+use std::fs;
+
+use java_rs::classloader::boot::BootClassPath;
+use java_rs::classloader::disassembler::disassemble;
+use java_rs::classloader::load_class;
+use java_rs::classloader::source::{classpath_sources, ClassSource};
+
+const USAGE: &str = "Usage: java_rs-javap [options] <class|file.class>...
+           (to print the constant pool and the parsed code of classes)
+
+ where options include:
+    -cp <class search path of directories and zip/jar files>
+    -classpath <class search path of directories and zip/jar files>
+    --class-path <class search path of directories and zip/jar files>
+                  A : separated list of directories, JAR archives,
+                  and ZIP archives to search for class files.
+    --system <jdk>
+                  find the system classes in this JDK
+                  instead of the one in JAVA_HOME or on the PATH";
+
+/// the parsed command line
+struct Options<'a> {
+    classpath: String,
+    system: Option<&'a str>,
+    classes: Vec<&'a String>,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let mut exit_code = 0;
+    for class in &options.classes {
+        match read_class(&options, class)
+            .and_then(|bytecode| load_class(bytecode).map_err(|error| error.to_string()))
+        {
+            Ok(classdef) => print!("{}", disassemble(&classdef)),
+            Err(error) => {
+                eprintln!("Error: {}: {}", class, error);
+                exit_code = 1;
+            }
+        }
+    }
+    std::process::exit(exit_code);
+}
+
+/// the bytecode of a class file, or of a class (with dots or slashes) on the classpath,
+/// or else in the JDK
+fn read_class(options: &Options, class: &str) -> Result<Vec<u8>, String> {
+    if class.ends_with(".class") {
+        return fs::read(class).map_err(|error| error.to_string());
+    }
+    let class_name = class.replace('.', "/");
+    for source in classpath_sources(&options.classpath) {
+        if let Some(bytecode) = source
+            .read_class(&class_name)
+            .map_err(|error| error.to_string())?
+        {
+            return Ok(bytecode);
+        }
+    }
+    BootClassPath::locate(options.system)
+        .and_then(|boot| boot.read_class(&class_name))
+        .map_err(|error| error.to_string())?
+        .ok_or_else(|| "class not found".to_owned())
+}
+
+fn parse_args(args: &[String]) -> Result<Options<'_>, String> {
+    let mut classpath = None;
+    let mut system = None;
+    let mut classes = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-cp" | "-classpath" | "--class-path" => {
+                classpath = Some(
+                    args.next()
+                        .ok_or_else(|| format!("{} requires class path specification", arg))?
+                        .clone(),
+                );
+            }
+            "--system" => {
+                system = Some(args.next().ok_or("--system requires a directory")?.as_str());
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unrecognized option: {}", arg)),
+            _ => classes.push(arg),
+        }
+    }
+    if classes.is_empty() {
+        return Err("no classes".into());
+    }
+    let classpath = classpath
+        .or_else(|| std::env::var("CLASSPATH").ok())
+        .unwrap_or_else(|| ".".into());
+    Ok(Options {
+        classpath,
+        system,
+        classes,
+    })
+}
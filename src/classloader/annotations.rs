@@ -0,0 +1,375 @@
+use crate::classloader::attributes::{read_table, write_table};
+use crate::classloader::error::ClassFormatError;
+use crate::classloader::io::{read_u16, read_u8, write_u16, write_u8};
+
+// The contents of the annotation attributes, JVMS 4.7.16 - 4.7.22.
+// Like in the other attributes, indices in the constant pool are kept as they are.
+
+/// an annotation in the Runtime(In)VisibleAnnotations and parameter annotation attributes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    // field descriptor of the annotation interface
+    pub type_index: u16,
+    pub element_value_pairs: Vec<ElementValuePair>,
+}
+
+impl Annotation {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            type_index: read_u16(data, index)?,
+            element_value_pairs: read_table(data, index, ElementValuePair::read)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.type_index);
+        write_table(out, &self.element_value_pairs, ElementValuePair::write);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementValuePair {
+    pub element_name_index: u16,
+    pub value: ElementValue,
+}
+
+impl ElementValuePair {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            element_name_index: read_u16(data, index)?,
+            value: ElementValue::read(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.element_name_index);
+        self.value.write(out);
+    }
+}
+
+/// the value of an annotation element, or the default value of an element (AnnotationDefault)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementValue {
+    // (tag, constant), tag is one of B C D F I J S Z s
+    // the constant is an Integer, Long, Float or Double entry, or Utf8 for a String
+    Const(u8, u16),
+    Enum {
+        // field descriptor of the enum class
+        type_name_index: u16,
+        const_name_index: u16,
+    },
+    // (return descriptor, V for void.class)
+    Class(u16),
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
+
+impl ElementValue {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        let tag_index = *index;
+        let tag = read_u8(data, index)?;
+        Ok(match tag {
+            b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' => {
+                ElementValue::Const(tag, read_u16(data, index)?)
+            }
+            b'e' => ElementValue::Enum {
+                type_name_index: read_u16(data, index)?,
+                const_name_index: read_u16(data, index)?,
+            },
+            b'c' => ElementValue::Class(read_u16(data, index)?),
+            b'@' => ElementValue::Annotation(Annotation::read(data, index)?),
+            b'[' => ElementValue::Array(read_table(data, index, ElementValue::read)?),
+            _ => {
+                return Err(ClassFormatError::new(
+                    tag_index,
+                    format!("invalid element value tag {}", tag),
+                ))
+            }
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            ElementValue::Const(tag, const_value_index) => {
+                write_u8(out, *tag);
+                write_u16(out, *const_value_index);
+            }
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => {
+                write_u8(out, b'e');
+                write_u16(out, *type_name_index);
+                write_u16(out, *const_name_index);
+            }
+            ElementValue::Class(class_info_index) => {
+                write_u8(out, b'c');
+                write_u16(out, *class_info_index);
+            }
+            ElementValue::Annotation(annotation) => {
+                write_u8(out, b'@');
+                annotation.write(out);
+            }
+            ElementValue::Array(values) => {
+                write_u8(out, b'[');
+                write_table(out, values, ElementValue::write);
+            }
+        }
+    }
+}
+
+/// reads the annotations of each parameter, in the Runtime(In)VisibleParameterAnnotations attribute
+/// the number of parameters is a u1, and may be less than the number in the method descriptor
+pub(crate) fn read_parameter_annotations(
+    data: &[u8],
+    index: &mut usize,
+) -> Result<Vec<Vec<Annotation>>, ClassFormatError> {
+    let num_parameters = read_u8(data, index)?;
+    let mut parameters = Vec::with_capacity(num_parameters as usize);
+    for _ in 0..num_parameters {
+        parameters.push(read_table(data, index, Annotation::read)?);
+    }
+    Ok(parameters)
+}
+
+pub(crate) fn write_parameter_annotations(out: &mut Vec<u8>, parameters: &[Vec<Annotation>]) {
+    write_u8(out, parameters.len() as u8);
+    for annotations in parameters {
+        write_table(out, annotations, Annotation::write);
+    }
+}
+
+/// an annotation on a use of a type, in the Runtime(In)VisibleTypeAnnotations attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeAnnotation {
+    // the kind of target, eg. 0x10 for a supertype, 0x13 for the type of a field
+    pub target_type: u8,
+    pub target_info: TargetInfo,
+    pub type_path: Vec<TypePathEntry>,
+    pub annotation: Annotation,
+}
+
+impl TypeAnnotation {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        let target_type_index = *index;
+        let target_type = read_u8(data, index)?;
+        let target_info = match target_type {
+            0x00 | 0x01 => TargetInfo::TypeParameter(read_u8(data, index)?),
+            0x10 => TargetInfo::Supertype(read_u16(data, index)?),
+            0x11 | 0x12 => TargetInfo::TypeParameterBound {
+                type_parameter_index: read_u8(data, index)?,
+                bound_index: read_u8(data, index)?,
+            },
+            0x13..=0x15 => TargetInfo::Empty,
+            0x16 => TargetInfo::FormalParameter(read_u8(data, index)?),
+            0x17 => TargetInfo::Throws(read_u16(data, index)?),
+            0x40 | 0x41 => TargetInfo::Localvar(read_table(data, index, LocalvarTarget::read)?),
+            0x42 => TargetInfo::Catch(read_u16(data, index)?),
+            0x43..=0x46 => TargetInfo::Offset(read_u16(data, index)?),
+            0x47..=0x4B => TargetInfo::TypeArgument {
+                offset: read_u16(data, index)?,
+                type_argument_index: read_u8(data, index)?,
+            },
+            _ => {
+                return Err(ClassFormatError::new(
+                    target_type_index,
+                    format!("invalid type annotation target type {:#X}", target_type),
+                ))
+            }
+        };
+        let path_length = read_u8(data, index)?;
+        let mut type_path = Vec::with_capacity(path_length as usize);
+        for _ in 0..path_length {
+            type_path.push(TypePathEntry::read(data, index)?);
+        }
+        Ok(Self {
+            target_type,
+            target_info,
+            type_path,
+            annotation: Annotation::read(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u8(out, self.target_type);
+        match &self.target_info {
+            TargetInfo::TypeParameter(index) | TargetInfo::FormalParameter(index) => {
+                write_u8(out, *index)
+            }
+            TargetInfo::Supertype(index)
+            | TargetInfo::Throws(index)
+            | TargetInfo::Catch(index)
+            | TargetInfo::Offset(index) => write_u16(out, *index),
+            TargetInfo::TypeParameterBound {
+                type_parameter_index,
+                bound_index,
+            } => {
+                write_u8(out, *type_parameter_index);
+                write_u8(out, *bound_index);
+            }
+            TargetInfo::Empty => {}
+            TargetInfo::Localvar(table) => write_table(out, table, LocalvarTarget::write),
+            TargetInfo::TypeArgument {
+                offset,
+                type_argument_index,
+            } => {
+                write_u16(out, *offset);
+                write_u8(out, *type_argument_index);
+            }
+        }
+        write_u8(out, self.type_path.len() as u8);
+        for entry in &self.type_path {
+            entry.write(out);
+        }
+        self.annotation.write(out);
+    }
+}
+
+/// the part of the class, field, method or code that a type annotation is on, JVMS 4.7.20.1
+/// offsets are byte offsets in the code
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetInfo {
+    TypeParameter(u8),
+    // (index in the interfaces, 65535 for the superclass)
+    Supertype(u16),
+    TypeParameterBound {
+        type_parameter_index: u8,
+        bound_index: u8,
+    },
+    // the type of a field or record component, the return type or the receiver type
+    Empty,
+    FormalParameter(u8),
+    // (index in the Exceptions attribute)
+    Throws(u16),
+    Localvar(Vec<LocalvarTarget>),
+    // (index in the exception table)
+    Catch(u16),
+    // instanceof, new, or a method reference
+    Offset(u16),
+    // a cast, or the type arguments of a constructor or method invocation or reference
+    TypeArgument {
+        offset: u16,
+        type_argument_index: u8,
+    },
+}
+
+/// the range in the code where a local variable has the annotated type
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalvarTarget {
+    pub start_pc: u16,
+    pub length: u16,
+    // the slot in the local variables
+    pub index: u16,
+}
+
+impl LocalvarTarget {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            start_pc: read_u16(data, index)?,
+            length: read_u16(data, index)?,
+            index: read_u16(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.start_pc);
+        write_u16(out, self.length);
+        write_u16(out, self.index);
+    }
+}
+
+/// a step into a nested, array, wildcard or parameterized type, JVMS 4.7.20.2
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypePathEntry {
+    pub type_path_kind: u8,
+    pub type_argument_index: u8,
+}
+
+impl TypePathEntry {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            type_path_kind: read_u8(data, index)?,
+            type_argument_index: read_u8(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u8(out, self.type_path_kind);
+        write_u8(out, self.type_argument_index);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_element_values() {
+        // @#1(#2 = {7, @#3()}, #4 = #5.#6)
+        let data = [
+            0, 1, 0, 2, //
+            0, 2, b'[', 0, 2, b'I', 0, 7, b'@', 0, 3, 0, 0, //
+            0, 4, b'e', 0, 5, 0, 6,
+        ];
+        let index = &mut 0;
+        let annotation = Annotation::read(&data, index).unwrap();
+        assert_eq!(data.len(), *index);
+        assert_eq!(
+            Annotation {
+                type_index: 1,
+                element_value_pairs: vec![
+                    ElementValuePair {
+                        element_name_index: 2,
+                        value: ElementValue::Array(vec![
+                            ElementValue::Const(b'I', 7),
+                            ElementValue::Annotation(Annotation {
+                                type_index: 3,
+                                element_value_pairs: vec![]
+                            })
+                        ])
+                    },
+                    ElementValuePair {
+                        element_name_index: 4,
+                        value: ElementValue::Enum {
+                            type_name_index: 5,
+                            const_name_index: 6
+                        }
+                    }
+                ]
+            },
+            annotation
+        );
+        assert_eq!(
+            2,
+            ElementValue::read(&[0, 0, b'x'], &mut 2)
+                .err()
+                .unwrap()
+                .offset
+        );
+    }
+
+    #[test]
+    fn type_annotation_on_local_variable() {
+        // on the local variable in slot 1, for pc 0 to 10, in the element type of an array
+        let data = [0x40, 0, 1, 0, 0, 0, 10, 0, 1, 1, 0, 0, 0, 9, 0, 0];
+        let index = &mut 0;
+        let annotation = TypeAnnotation::read(&data, index).unwrap();
+        assert_eq!(data.len(), *index);
+        assert_eq!(
+            TargetInfo::Localvar(vec![LocalvarTarget {
+                start_pc: 0,
+                length: 10,
+                index: 1
+            }]),
+            annotation.target_info
+        );
+        assert_eq!(
+            vec![TypePathEntry {
+                type_path_kind: 0,
+                type_argument_index: 0
+            }],
+            annotation.type_path
+        );
+        assert_eq!(9, annotation.annotation.type_index);
+    }
+}
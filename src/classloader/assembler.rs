@@ -0,0 +1,824 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use crate::classloader::builder::{parameter_slots, ClassBuilder, Code};
+use crate::classloader::code_parser::get_opcode;
+use crate::vm::opcodes::Opcode::{self, *};
+use crate::vm::opcodes::MNEMONICS;
+
+// An assembler for the Jasmin syntax, eg.
+//
+//   .class public t/Max
+//   .super java/lang/Object
+//
+//   .method public static max(II)I
+//       iload_0
+//       iload_1
+//       if_icmplt Second   ; comments start with a semicolon
+//       iload_0
+//       ireturn
+//   Second:
+//       iload_1
+//       ireturn
+//   .end method
+//
+// Supported directives: .bytecode, .class, .interface, .super, .implements, .field (with a
+// constant value), .method, .end method, .limit stack/locals and .catch.
+// Instructions are written like in the JVMS. Loads, stores, ret and iinc are made wide when
+// the operands do not fit, or with a wide prefix. ldc becomes ldc_w for constant pool indices
+// above 255. Classes are made with the ClassBuilder, so the default class file version is 49.
+
+/// An error in the assembler source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblyError {
+    /// line number, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AssemblyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, at line {}", self.message, self.line)
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+/// assembles the source into the bytes of a class file
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let mut assembler = Assembler::default();
+    let mut line_number = 0;
+    for (index, line) in source.lines().enumerate() {
+        line_number = index + 1;
+        let error = |message| AssemblyError {
+            line: line_number,
+            message,
+        };
+        let tokens = tokenize(line).map_err(error)?;
+        if !tokens.is_empty() {
+            assembler.line(line_number, &tokens).map_err(error)?;
+        }
+    }
+    let error = |message| AssemblyError {
+        line: line_number,
+        message,
+    };
+    if assembler.method.is_some() {
+        return Err(error("missing .end method".into()));
+    }
+    assembler
+        .builder()
+        .map_err(error)?
+        .to_bytes()
+        .map_err(|e| error(e.to_string()))
+}
+
+#[derive(Default)]
+struct Assembler {
+    // the header, up to the first field or method
+    version: Option<(u16, u16)>,
+    // (access flags, name)
+    class: Option<(u16, String)>,
+    super_class: Option<String>,
+    interfaces: Vec<String>,
+
+    class_builder: Option<ClassBuilder>,
+    method: Option<Method>,
+}
+
+/// the method that is being assembled
+struct Method {
+    access_flags: u16,
+    name: String,
+    descriptor: String,
+    code: Code,
+    has_code: bool,
+    labels: HashSet<String>,
+    // label -> line of the first use
+    used_labels: HashMap<String, usize>,
+    switch: Option<Switch>,
+}
+
+/// the switch that is being assembled, the labels are on the lines after the instruction
+enum Switch {
+    Table {
+        low: i32,
+        high: Option<i32>,
+        labels: Vec<String>,
+    },
+    Lookup {
+        pairs: Vec<(i32, String)>,
+    },
+}
+
+impl Assembler {
+    fn line(&mut self, line_number: usize, tokens: &[String]) -> Result<(), String> {
+        let directive = tokens[0].as_str();
+        let args = &tokens[1..];
+        if let Some(method) = &mut self.method {
+            if method.switch.is_some() {
+                return method.switch_line(line_number, tokens);
+            }
+        }
+        match directive {
+            ".bytecode" => {
+                self.header(directive)?;
+                let [version] = args else {
+                    return Err("expected .bytecode <major>.<minor>".into());
+                };
+                let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+                self.version = Some((parse_number(major)?, parse_number(minor)?));
+            }
+            ".class" | ".interface" => {
+                self.header(directive)?;
+                let (name, flags) = args
+                    .split_last()
+                    .ok_or_else(|| format!("expected {} <access> <name>", directive))?;
+                let mut access_flags = access_flags(flags)?;
+                if directive == ".interface" {
+                    access_flags |= ACC_INTERFACE | ACC_ABSTRACT;
+                } else {
+                    access_flags |= ACC_SUPER;
+                }
+                self.class = Some((access_flags, name.clone()));
+            }
+            ".super" => {
+                self.header(directive)?;
+                let [name] = args else {
+                    return Err("expected .super <class>".into());
+                };
+                self.super_class = Some(name.clone());
+            }
+            ".implements" => {
+                self.header(directive)?;
+                let [name] = args else {
+                    return Err("expected .implements <interface>".into());
+                };
+                self.interfaces.push(name.clone());
+            }
+            ".field" => self.field(args)?,
+            ".method" => {
+                if self.method.is_some() {
+                    return Err("missing .end method".into());
+                }
+                self.builder()?;
+                let (signature, flags) = args
+                    .split_last()
+                    .ok_or("expected .method <access> <name><descriptor>")?;
+                let paren = signature
+                    .find('(')
+                    .ok_or_else(|| format!("invalid method {}", signature))?;
+                self.method = Some(Method {
+                    access_flags: access_flags(flags)?,
+                    name: signature[..paren].into(),
+                    descriptor: signature[paren..].into(),
+                    code: Code::new(),
+                    has_code: false,
+                    labels: HashSet::new(),
+                    used_labels: HashMap::new(),
+                    switch: None,
+                });
+            }
+            ".end" => {
+                if args != ["method"] {
+                    return Err("expected .end method".into());
+                }
+                let method = self.method.take().ok_or(".end method outside a method")?;
+                method.end(self.builder()?)?;
+            }
+            _ => {
+                let builder = self.class_builder.as_mut();
+                match (&mut self.method, builder) {
+                    (Some(method), Some(builder)) => method.line(builder, line_number, tokens)?,
+                    _ if directive.starts_with('.') => {
+                        return Err(format!("unsupported directive {}", directive))
+                    }
+                    _ => return Err(format!("{} outside a method", directive)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// checks that a header directive comes before the fields and methods
+    fn header(&self, directive: &str) -> Result<(), String> {
+        if self.class_builder.is_some() {
+            return Err(format!("{} after a field or method", directive));
+        }
+        Ok(())
+    }
+
+    /// the builder for the class, that is made after the header
+    fn builder(&mut self) -> Result<&mut ClassBuilder, String> {
+        if self.class_builder.is_none() {
+            let (access_flags, name) = self.class.as_ref().ok_or("missing .class")?;
+            let super_class = match &self.super_class {
+                Some(super_class) => Some(super_class.as_str()),
+                None if name == "java/lang/Object" => None,
+                None => Some("java/lang/Object"),
+            };
+            let mut builder = ClassBuilder::new(name, super_class);
+            builder.access_flags(*access_flags);
+            if let Some((major, minor)) = self.version {
+                builder.version(major, minor);
+            }
+            for interface in &self.interfaces {
+                builder.interface(interface);
+            }
+            self.class_builder = Some(builder);
+        }
+        Ok(self.class_builder.as_mut().unwrap())
+    }
+
+    /// .field <access> <name> <descriptor> [= <value>]
+    fn field(&mut self, args: &[String]) -> Result<(), String> {
+        if self.method.is_some() {
+            return Err(".field in a method".into());
+        }
+        let (args, value) = match args.iter().position(|arg| arg == "=") {
+            Some(position) => match &args[position + 1..] {
+                [value] => (&args[..position], Some(value)),
+                _ => return Err("expected a value after =".into()),
+            },
+            None => (args, None),
+        };
+        let [flags @ .., name, descriptor] = args else {
+            return Err("expected .field <access> <name> <descriptor> [= <value>]".into());
+        };
+        let access_flags = access_flags(flags)?;
+        let builder = self.builder()?;
+        match value {
+            None => {
+                builder.field(access_flags, name, descriptor);
+            }
+            Some(value) => {
+                let value = match descriptor.as_str() {
+                    "I" | "S" | "B" | "C" | "Z" => builder.integer(parse_number(value)?),
+                    "J" => builder.long(parse_number(value)?),
+                    "F" => builder.float(parse_float(value)? as f32),
+                    "D" => builder.double(parse_float(value)?),
+                    "Ljava/lang/String;" => builder.string(&parse_string(value)?),
+                    _ => {
+                        return Err(format!(
+                            "a field of type {} has no constant value",
+                            descriptor
+                        ))
+                    }
+                };
+                builder.constant_field(access_flags, name, descriptor, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Method {
+    /// a label, directive or instruction in the method
+    fn line(
+        &mut self,
+        builder: &mut ClassBuilder,
+        line_number: usize,
+        tokens: &[String],
+    ) -> Result<(), String> {
+        let mut tokens = tokens;
+        if tokens.len() >= 2 && tokens[1] == ":" {
+            let label = &tokens[0];
+            if !self.labels.insert(label.clone()) {
+                return Err(format!("duplicate label {}", label));
+            }
+            self.code.label(label);
+            tokens = &tokens[2..];
+            if tokens.is_empty() {
+                return Ok(());
+            }
+        }
+        let args = &tokens[1..];
+        match tokens[0].as_str() {
+            ".limit" => match args {
+                [what, value] if what == "stack" => {
+                    self.code.max_stack(parse_number(value)?);
+                }
+                [what, value] if what == "locals" => {
+                    self.code.max_locals(parse_number(value)?);
+                }
+                _ => return Err("expected .limit stack|locals <number>".into()),
+            },
+            // .catch <class>|all from <label> to <label> using <label>
+            ".catch" => match args {
+                [catch_type, from, start, to, end, using, handler]
+                    if from == "from" && to == "to" && using == "using" =>
+                {
+                    for label in [start, end, handler] {
+                        self.use_label(label, line_number);
+                    }
+                    let catch_type = Some(catch_type.as_str()).filter(|c| *c != "all");
+                    self.code.catch(start, end, handler, catch_type);
+                }
+                _ => {
+                    return Err(
+                        "expected .catch <class> from <label> to <label> using <label>".into(),
+                    )
+                }
+            },
+            directive if directive.starts_with('.') => {
+                return Err(format!("unsupported directive {}", directive))
+            }
+            "wide" => {
+                let (mnemonic, args) = args.split_first().ok_or("expected an instruction")?;
+                self.instruction(builder, line_number, mnemonic, args, true)?;
+            }
+            mnemonic => self.instruction(builder, line_number, mnemonic, args, false)?,
+        }
+        Ok(())
+    }
+
+    fn instruction(
+        &mut self,
+        builder: &mut ClassBuilder,
+        line_number: usize,
+        mnemonic: &str,
+        args: &[String],
+        wide: bool,
+    ) -> Result<(), String> {
+        let opcode = MNEMONICS
+            .iter()
+            .position(|name| *name == mnemonic)
+            .ok_or_else(|| format!("unknown instruction {}", mnemonic))? as u8;
+        if wide && !matches!(opcode, 21..=25 | 54..=58 | 132 | 169) {
+            return Err(format!("{} cannot be wide", mnemonic));
+        }
+        self.has_code = true;
+        let code = &mut self.code;
+        match (opcode, args) {
+            (16, [value]) => code.op(BIPUSH(parse_number(value)?)),
+            (17, [value]) => code.op(SIPUSH(parse_number(value)?)),
+            (18 | 19, [value]) => {
+                let index = if value.starts_with('"') {
+                    builder.string(&parse_string(value)?)
+                } else if let Ok(value) = parse_number(value) {
+                    builder.integer(value)
+                } else {
+                    builder.float(parse_float(value)? as f32)
+                };
+                if opcode == 18 && index <= 0xFF {
+                    code.op(LDC(index))
+                } else {
+                    code.op(LDC_W(index))
+                }
+            }
+            (20, [value]) => {
+                let index = if let Ok(value) = parse_number(value) {
+                    builder.long(value)
+                } else {
+                    builder.double(parse_float(value)?)
+                };
+                code.op(LDC2_W(index))
+            }
+            (21..=25 | 54..=58 | 169, [index]) => {
+                let index: u16 = parse_number(index)?;
+                code.op(local_variable_opcode(opcode, index, wide || index > 0xFF))
+            }
+            (132, [index, value]) => {
+                let index: u16 = parse_number(index)?;
+                let value: i16 = parse_number(value)?;
+                match i8::try_from(value) {
+                    Ok(value) if !wide && index <= 0xFF => code.op(IINC(index as u8, value)),
+                    _ => code.op(WIDE_IINC(index, value)),
+                }
+            }
+            (153..=168 | 198 | 199, [label]) => {
+                self.use_label(label, line_number);
+                self.code.jump(jump_opcode(opcode), label)
+            }
+            (200 | 201, [label]) => {
+                self.use_label(label, line_number);
+                let jump: fn(i32) -> Opcode = if opcode == 200 { GOTOW } else { JSR_W };
+                self.code.jump_w(jump, label)
+            }
+            (170, [low, rest @ ..]) if rest.len() <= 1 => {
+                self.switch = Some(Switch::Table {
+                    low: parse_number(low)?,
+                    high: rest.first().map(|high| parse_number(high)).transpose()?,
+                    labels: vec![],
+                });
+                code
+            }
+            (171, []) => {
+                self.switch = Some(Switch::Lookup { pairs: vec![] });
+                code
+            }
+            (178..=181, [field, descriptor]) => {
+                let (class, name) = member(field)?;
+                let index = builder.field_ref(class, name, descriptor);
+                code.op(match opcode {
+                    178 => GETSTATIC(index),
+                    179 => PUTSTATIC(index),
+                    180 => GETFIELD(index),
+                    _ => PUTFIELD(index),
+                })
+            }
+            (182..=184, [method]) => {
+                let (class, name, descriptor) = method_member(method)?;
+                let index = builder.method_ref(class, name, descriptor);
+                code.op(match opcode {
+                    182 => INVOKEVIRTUAL(index),
+                    183 => INVOKESPECIAL(index),
+                    _ => INVOKESTATIC(index),
+                })
+            }
+            (185, [method, count @ ..]) if count.len() <= 1 => {
+                let (class, name, descriptor) = method_member(method)?;
+                let count = match count.first() {
+                    Some(count) => parse_number(count)?,
+                    // the receiver and the arguments
+                    None => 1 + parameter_slots(descriptor) as u8,
+                };
+                let index = builder.interface_method_ref(class, name, descriptor);
+                code.op(INVOKEINTERFACE(index, count))
+            }
+            (187 | 189 | 192 | 193, [class]) => {
+                let index = builder.class(class);
+                code.op(match opcode {
+                    187 => NEW(index),
+                    189 => ANEWARRAY(index),
+                    192 => CHECKCAST(index),
+                    _ => INSTANCEOF(index),
+                })
+            }
+            (188, [element_type]) => {
+                let array_type = [
+                    "boolean", "char", "float", "double", "byte", "short", "int", "long",
+                ]
+                .iter()
+                .position(|t| t == element_type)
+                .ok_or_else(|| format!("invalid array type {}", element_type))?;
+                code.op(NEWARRAY(array_type as u8 + 4))
+            }
+            (197, [class, dimensions]) => {
+                let index = builder.class(class);
+                code.op(MULTIANEWARRAY(index, parse_number(dimensions)?))
+            }
+            (186, _) => return Err("invokedynamic is not supported".into()),
+            (_, []) => {
+                let opcode = get_opcode(&[opcode], &mut 0)
+                    .map_err(|_| format!("invalid operands for {}", mnemonic))?;
+                code.op(opcode)
+            }
+            _ => return Err(format!("invalid operands for {}", mnemonic)),
+        };
+        Ok(())
+    }
+
+    /// a line after a tableswitch or lookupswitch
+    fn switch_line(&mut self, line_number: usize, tokens: &[String]) -> Result<(), String> {
+        let label = tokens.last().unwrap();
+        self.use_label(label, line_number);
+        match (self.switch.as_mut().unwrap(), tokens) {
+            (Switch::Table { labels, .. }, [label]) => labels.push(label.clone()),
+            (Switch::Lookup { pairs }, [key, colon, label]) if colon == ":" && key != "default" => {
+                pairs.push((parse_number(key)?, label.clone()))
+            }
+            (_, [default, colon, default_label]) if default == "default" && colon == ":" => {
+                match self.switch.take().unwrap() {
+                    Switch::Table { low, high, labels } => {
+                        if high.is_some_and(|high| high - low + 1 != labels.len() as i32) {
+                            return Err("the number of labels does not match low and high".into());
+                        }
+                        let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+                        self.code.tableswitch(low, &labels, default_label);
+                    }
+                    Switch::Lookup { pairs } => {
+                        let pairs: Vec<(i32, &str)> =
+                            pairs.iter().map(|(key, l)| (*key, l.as_str())).collect();
+                        self.code.lookupswitch(&pairs, default_label);
+                    }
+                }
+            }
+            (Switch::Table { .. }, _) => return Err("expected <label> or default : <label>".into()),
+            (Switch::Lookup { .. }, _) => {
+                return Err("expected <key> : <label> or default : <label>".into())
+            }
+        }
+        Ok(())
+    }
+
+    fn use_label(&mut self, label: &str, line_number: usize) {
+        self.used_labels
+            .entry(label.to_owned())
+            .or_insert(line_number);
+    }
+
+    /// adds the method to the class
+    fn end(self, builder: &mut ClassBuilder) -> Result<(), String> {
+        if self.switch.is_some() {
+            return Err("missing default in switch".into());
+        }
+        let mut used_labels: Vec<_> = self.used_labels.iter().collect();
+        used_labels.sort_by_key(|(_, line)| **line);
+        if let Some((label, line)) = used_labels
+            .into_iter()
+            .find(|(label, _)| !self.labels.contains(*label))
+        {
+            return Err(format!("undefined label {} (used at line {})", label, line));
+        }
+        if self.access_flags & (ACC_ABSTRACT | ACC_NATIVE) != 0 {
+            if self.has_code {
+                return Err("abstract or native method with code".into());
+            }
+            builder.abstract_method(self.access_flags, &self.name, &self.descriptor);
+        } else {
+            builder.method(self.access_flags, &self.name, &self.descriptor, self.code);
+        }
+        Ok(())
+    }
+}
+
+const ACC_SUPER: u16 = 0x0020;
+const ACC_NATIVE: u16 = 0x0100;
+const ACC_INTERFACE: u16 = 0x0200;
+const ACC_ABSTRACT: u16 = 0x0400;
+
+fn access_flags(keywords: &[String]) -> Result<u16, String> {
+    keywords.iter().try_fold(0, |flags, keyword| {
+        let flag = match keyword.as_str() {
+            "public" => 0x0001,
+            "private" => 0x0002,
+            "protected" => 0x0004,
+            "static" => 0x0008,
+            "final" => 0x0010,
+            "super" | "synchronized" => 0x0020,
+            "volatile" | "bridge" => 0x0040,
+            "transient" | "varargs" => 0x0080,
+            "native" => ACC_NATIVE,
+            "interface" => ACC_INTERFACE,
+            "abstract" => ACC_ABSTRACT,
+            "strict" => 0x0800,
+            "synthetic" => 0x1000,
+            "annotation" => 0x2000,
+            "enum" => 0x4000,
+            _ => return Err(format!("invalid access flag {}", keyword)),
+        };
+        Ok(flags | flag)
+    })
+}
+
+/// the load, store or ret opcode for the local variable
+fn local_variable_opcode(opcode: u8, index: u16, wide: bool) -> Opcode {
+    let short = index as u8;
+    match (opcode, wide) {
+        (21, false) => ILOAD(short),
+        (22, false) => LLOAD(short),
+        (23, false) => FLOAD(short),
+        (24, false) => DLOAD(short),
+        (25, false) => ALOAD(short),
+        (54, false) => ISTORE(short),
+        (55, false) => LSTORE(short),
+        (56, false) => FSTORE(short),
+        (57, false) => DSTORE(short),
+        (58, false) => ASTORE(short),
+        (169, false) => RET(short),
+        (21, true) => WIDE_ILOAD(index),
+        (22, true) => WIDE_LLOAD(index),
+        (23, true) => WIDE_FLOAD(index),
+        (24, true) => WIDE_DLOAD(index),
+        (25, true) => WIDE_ALOAD(index),
+        (54, true) => WIDE_ISTORE(index),
+        (55, true) => WIDE_LSTORE(index),
+        (56, true) => WIDE_FSTORE(index),
+        (57, true) => WIDE_DSTORE(index),
+        (58, true) => WIDE_ASTORE(index),
+        _ => WIDE_RET(index),
+    }
+}
+
+fn jump_opcode(opcode: u8) -> fn(u16) -> Opcode {
+    match opcode {
+        153 => IFEQ,
+        154 => IFNE,
+        155 => IFLT,
+        156 => IFGE,
+        157 => IFGT,
+        158 => IFLE,
+        159 => IF_ICMPEQ,
+        160 => IF_ICMPNE,
+        161 => IF_ICMPLT,
+        162 => IF_ICMPGE,
+        163 => IF_ICMPGT,
+        164 => IF_ICMPLE,
+        165 => IF_ACMPEQ,
+        166 => IF_ACMPNE,
+        167 => GOTO,
+        168 => JSR,
+        198 => IFNULL,
+        _ => IFNONNULL,
+    }
+}
+
+/// class/name -> (class, name)
+fn member(member: &str) -> Result<(&str, &str), String> {
+    member
+        .rsplit_once('/')
+        .ok_or_else(|| format!("expected <class>/<name> instead of {}", member))
+}
+
+/// class/name(descriptor) -> (class, name, descriptor)
+fn method_member(method: &str) -> Result<(&str, &str, &str), String> {
+    let paren = method
+        .find('(')
+        .ok_or_else(|| format!("expected <class>/<name><descriptor> instead of {}", method))?;
+    let (class, name) = member(&method[..paren])?;
+    Ok((class, name, &method[paren..]))
+}
+
+/// a decimal or (0x) hexadecimal integer
+fn parse_number<T: TryFrom<i64>>(token: &str) -> Result<T, String> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).map(|value| value as i64),
+        None => digits.parse::<i64>(),
+    }
+    .map_err(|_| format!("invalid number {}", token))?;
+    let value = if negative { -value } else { value };
+    T::try_from(value).map_err(|_| format!("number out of range {}", token))
+}
+
+fn parse_float(token: &str) -> Result<f64, String> {
+    token
+        .parse()
+        .map_err(|_| format!("invalid number {}", token))
+}
+
+/// a string in double quotes, with escapes like in java
+fn parse_string(token: &str) -> Result<String, String> {
+    let invalid = || format!("invalid string {}", token);
+    let contents = token
+        .strip_prefix('"')
+        .and_then(|token| token.strip_suffix('"'))
+        .ok_or_else(invalid)?;
+    let mut string = String::new();
+    let mut chars = contents.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        string.push(match chars.next().ok_or_else(invalid)? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            '0' => '\0',
+            'u' => {
+                let hex: String = chars.by_ref().take(4).collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(invalid)?
+            }
+            c @ ('"' | '\'' | '\\') => c,
+            _ => return Err(invalid()),
+        });
+    }
+    Ok(string)
+}
+
+/// splits the line in words, strings (with the quotes) and colons, leaving out the comment
+/// a comment starts with a semicolon at the start of a word, so not in descriptors
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            break;
+        } else if c == ':' {
+            tokens.push(chars.next().unwrap().to_string());
+        } else if c == '"' {
+            let mut token = chars.next().unwrap().to_string();
+            loop {
+                let c = chars.next().ok_or("unterminated string")?;
+                token.push(c);
+                if c == '\\' {
+                    token.push(chars.next().ok_or("unterminated string")?);
+                } else if c == '"' {
+                    break;
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ':' || c == '"' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::classloader::classdef::{AttributeType, CpEntry};
+    use crate::classloader::load_class;
+
+    #[test]
+    fn assembles_class() {
+        let source = r#"
+            .bytecode 50.0
+            .class public final t/Asm
+            .super java/lang/Object
+            .implements java/lang/Runnable
+
+            .field public static final GREETING Ljava/lang/String; = "hi \"there\"\n"
+
+            .method public run()V
+                return
+            .end method
+
+            .method public static select(I)J
+                .limit stack 2
+                iload_0
+                tableswitch 1 2
+                    One
+                    Two
+                    default : Other
+            One:    ldc2_w 100
+                    lreturn
+            Two:
+                iinc 0 -1000    ; wide, the constant does not fit in a byte
+                wide iload 0
+                lookupswitch
+                    -5 : One
+                    7 : Two
+                    default : Other
+            Other:
+                ldc2_w 1.5
+                d2l
+                lreturn
+            .end method
+        "#;
+        let classdef = load_class(assemble(source).unwrap()).unwrap();
+        assert_eq!("t/Asm", classdef.name());
+        assert_eq!((50, 0), classdef.version());
+        assert_eq!(0x0031, classdef.access_flags);
+        assert_eq!(1, classdef.interfaces.len());
+        let field = &classdef.fields["GREETING"];
+        let Some(AttributeType::ConstantValue(value)) = field.attributes.get("ConstantValue")
+        else {
+            panic!("no constant value")
+        };
+        let CpEntry::StringRef(utf8) = classdef.constant_pool[value] else {
+            panic!("not a string")
+        };
+        assert_eq!("hi \"there\"\n", classdef.cp_utf8(&utf8));
+
+        let select = classdef.get_method("select(I)J").unwrap();
+        assert!(matches!(select.code[1], TABLESWITCH(_)));
+        let wide = |index: usize| match &select.code[index] {
+            WIDE(opcode) => *opcode.clone(),
+            opcode => panic!("{:?} is not wide", opcode),
+        };
+        assert!(matches!(wide(4), WIDE_IINC(0, -1000)));
+        assert!(matches!(wide(5), WIDE_ILOAD(0)));
+        assert!(matches!(select.code[6], LOOKUPSWITCH(_)));
+        let Some(AttributeType::Code(code)) = select.attributes.get("Code") else {
+            panic!("no code")
+        };
+        assert_eq!(2, code.max_stack);
+        assert_eq!(1, code.max_locals);
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let error = |source: &str| assemble(source).err().unwrap();
+        assert_eq!(
+            AssemblyError {
+                line: 3,
+                message: "unknown instruction iload_9".into()
+            },
+            error(".class A\n.method static m()V\n  iload_9\n.end method")
+        );
+        assert_eq!(
+            "undefined label Loop (used at line 3), at line 4",
+            error(".class A\n.method static m()V\n  goto Loop\n.end method").to_string()
+        );
+        assert_eq!(
+            "number out of range 200",
+            error(".class A\n.method static m()V\n  bipush 200\n.end method").message
+        );
+        assert_eq!(
+            "missing .class",
+            error(".method static m()V\n.end method").message
+        );
+    }
+}
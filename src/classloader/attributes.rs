@@ -0,0 +1,529 @@
+use crate::classloader::error::ClassFormatError;
+use crate::classloader::io::{read_u16, read_u8, write_u16, write_u8};
+
+// The contents of the attributes in JVMS 4.7 that are more than a single constant pool index.
+// Indices in the constant pool are kept as they are, pc's are byte offsets in the code.
+
+/// reads a u2 count, followed by that many items
+pub(crate) fn read_table<T>(
+    data: &[u8],
+    index: &mut usize,
+    read_item: impl Fn(&[u8], &mut usize) -> Result<T, ClassFormatError>,
+) -> Result<Vec<T>, ClassFormatError> {
+    let count = read_u16(data, index)?;
+    let mut items = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        items.push(read_item(data, index)?);
+    }
+    Ok(items)
+}
+
+/// writes a u2 count, followed by the items
+pub(crate) fn write_table<T>(
+    out: &mut Vec<u8>,
+    items: &[T],
+    write_item: impl Fn(&T, &mut Vec<u8>),
+) {
+    write_u16(out, items.len() as u16);
+    for item in items {
+        write_item(item, out);
+    }
+}
+
+/// writes a table of constant pool indices
+pub(crate) fn write_indices(out: &mut Vec<u8>, indices: &[u16]) {
+    write_table(out, indices, |index, out| write_u16(out, *index));
+}
+
+/// an entry in the InnerClasses attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct InnerClass {
+    pub inner_class_info_index: u16,
+    // 0 if not a member
+    pub outer_class_info_index: u16,
+    // 0 if anonymous
+    pub inner_name_index: u16,
+    pub inner_class_access_flags: u16,
+}
+
+impl InnerClass {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            inner_class_info_index: read_u16(data, index)?,
+            outer_class_info_index: read_u16(data, index)?,
+            inner_name_index: read_u16(data, index)?,
+            inner_class_access_flags: read_u16(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.inner_class_info_index);
+        write_u16(out, self.outer_class_info_index);
+        write_u16(out, self.inner_name_index);
+        write_u16(out, self.inner_class_access_flags);
+    }
+}
+
+/// the EnclosingMethod attribute, of local and anonymous classes
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnclosingMethod {
+    pub class_index: u16,
+    // NameAndType, 0 if the class is not enclosed by a method (but by an initializer)
+    pub method_index: u16,
+}
+
+impl EnclosingMethod {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            class_index: read_u16(data, index)?,
+            method_index: read_u16(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.class_index);
+        write_u16(out, self.method_index);
+    }
+}
+
+/// an entry in the LocalVariableTable or LocalVariableTypeTable attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalVariable {
+    pub start_pc: u16,
+    pub length: u16,
+    pub name_index: u16,
+    // the descriptor, or in the LocalVariableTypeTable the signature
+    pub descriptor_index: u16,
+    // the slot in the local variables
+    pub index: u16,
+}
+
+impl LocalVariable {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            start_pc: read_u16(data, index)?,
+            length: read_u16(data, index)?,
+            name_index: read_u16(data, index)?,
+            descriptor_index: read_u16(data, index)?,
+            index: read_u16(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.start_pc);
+        write_u16(out, self.length);
+        write_u16(out, self.name_index);
+        write_u16(out, self.descriptor_index);
+        write_u16(out, self.index);
+    }
+}
+
+/// an entry in the MethodParameters attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodParameter {
+    // 0 if the parameter has no name
+    pub name_index: u16,
+    pub access_flags: u16,
+}
+
+impl MethodParameter {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            name_index: read_u16(data, index)?,
+            access_flags: read_u16(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.name_index);
+        write_u16(out, self.access_flags);
+    }
+}
+
+/// the type of a local variable or operand stack entry in a stack map frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    // (class)
+    Object(u16),
+    // (offset of the NEW instruction)
+    Uninitialized(u16),
+}
+
+impl VerificationType {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        let tag_index = *index;
+        Ok(match read_u8(data, index)? {
+            0 => VerificationType::Top,
+            1 => VerificationType::Integer,
+            2 => VerificationType::Float,
+            3 => VerificationType::Double,
+            4 => VerificationType::Long,
+            5 => VerificationType::Null,
+            6 => VerificationType::UninitializedThis,
+            7 => VerificationType::Object(read_u16(data, index)?),
+            8 => VerificationType::Uninitialized(read_u16(data, index)?),
+            tag => {
+                return Err(ClassFormatError::new(
+                    tag_index,
+                    format!("invalid verification type {}", tag),
+                ))
+            }
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            VerificationType::Top => write_u8(out, 0),
+            VerificationType::Integer => write_u8(out, 1),
+            VerificationType::Float => write_u8(out, 2),
+            VerificationType::Double => write_u8(out, 3),
+            VerificationType::Long => write_u8(out, 4),
+            VerificationType::Null => write_u8(out, 5),
+            VerificationType::UninitializedThis => write_u8(out, 6),
+            VerificationType::Object(class_index) => {
+                write_u8(out, 7);
+                write_u16(out, *class_index);
+            }
+            VerificationType::Uninitialized(offset) => {
+                write_u8(out, 8);
+                write_u16(out, *offset);
+            }
+        }
+    }
+}
+
+/// a frame in the StackMapTable attribute
+/// the variants are the frame types, so that a frame is written back the way it was read
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackMapFrame {
+    // frame types 0-63
+    Same {
+        offset_delta: u16,
+    },
+    // 64-127
+    SameLocals1StackItem {
+        offset_delta: u16,
+        stack: VerificationType,
+    },
+    // 247
+    SameLocals1StackItemExtended {
+        offset_delta: u16,
+        stack: VerificationType,
+    },
+    // 248-250, the last 1 to 3 locals are absent
+    Chop {
+        absent_locals: u8,
+        offset_delta: u16,
+    },
+    // 251
+    SameExtended {
+        offset_delta: u16,
+    },
+    // 252-254, 1 to 3 locals are added
+    Append {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+    },
+    // 255
+    Full {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+        stack: Vec<VerificationType>,
+    },
+}
+
+impl StackMapFrame {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        let frame_type_index = *index;
+        let frame_type = read_u8(data, index)?;
+        Ok(match frame_type {
+            0..=63 => StackMapFrame::Same {
+                offset_delta: frame_type as u16,
+            },
+            64..=127 => StackMapFrame::SameLocals1StackItem {
+                offset_delta: frame_type as u16 - 64,
+                stack: VerificationType::read(data, index)?,
+            },
+            247 => StackMapFrame::SameLocals1StackItemExtended {
+                offset_delta: read_u16(data, index)?,
+                stack: VerificationType::read(data, index)?,
+            },
+            248..=250 => StackMapFrame::Chop {
+                absent_locals: 251 - frame_type,
+                offset_delta: read_u16(data, index)?,
+            },
+            251 => StackMapFrame::SameExtended {
+                offset_delta: read_u16(data, index)?,
+            },
+            252..=254 => {
+                let offset_delta = read_u16(data, index)?;
+                let mut locals = vec![];
+                for _ in 0..frame_type - 251 {
+                    locals.push(VerificationType::read(data, index)?);
+                }
+                StackMapFrame::Append {
+                    offset_delta,
+                    locals,
+                }
+            }
+            255 => StackMapFrame::Full {
+                offset_delta: read_u16(data, index)?,
+                locals: read_table(data, index, VerificationType::read)?,
+                stack: read_table(data, index, VerificationType::read)?,
+            },
+            _ => {
+                return Err(ClassFormatError::new(
+                    frame_type_index,
+                    format!("invalid stack map frame type {}", frame_type),
+                ))
+            }
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            StackMapFrame::Same { offset_delta } => write_u8(out, *offset_delta as u8),
+            StackMapFrame::SameLocals1StackItem {
+                offset_delta,
+                stack,
+            } => {
+                write_u8(out, 64 + *offset_delta as u8);
+                stack.write(out);
+            }
+            StackMapFrame::SameLocals1StackItemExtended {
+                offset_delta,
+                stack,
+            } => {
+                write_u8(out, 247);
+                write_u16(out, *offset_delta);
+                stack.write(out);
+            }
+            StackMapFrame::Chop {
+                absent_locals,
+                offset_delta,
+            } => {
+                write_u8(out, 251 - absent_locals);
+                write_u16(out, *offset_delta);
+            }
+            StackMapFrame::SameExtended { offset_delta } => {
+                write_u8(out, 251);
+                write_u16(out, *offset_delta);
+            }
+            StackMapFrame::Append {
+                offset_delta,
+                locals,
+            } => {
+                write_u8(out, 251 + locals.len() as u8);
+                write_u16(out, *offset_delta);
+                for local in locals {
+                    local.write(out);
+                }
+            }
+            StackMapFrame::Full {
+                offset_delta,
+                locals,
+                stack,
+            } => {
+                write_u8(out, 255);
+                write_u16(out, *offset_delta);
+                write_table(out, locals, VerificationType::write);
+                write_table(out, stack, VerificationType::write);
+            }
+        }
+    }
+
+    pub fn offset_delta(&self) -> u16 {
+        match self {
+            StackMapFrame::Same { offset_delta }
+            | StackMapFrame::SameLocals1StackItem { offset_delta, .. }
+            | StackMapFrame::SameLocals1StackItemExtended { offset_delta, .. }
+            | StackMapFrame::Chop { offset_delta, .. }
+            | StackMapFrame::SameExtended { offset_delta }
+            | StackMapFrame::Append { offset_delta, .. }
+            | StackMapFrame::Full { offset_delta, .. } => *offset_delta,
+        }
+    }
+}
+
+/// the Module attribute, of module-info.class
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub module_name_index: u16,
+    pub module_flags: u16,
+    // 0 if there is no version
+    pub module_version_index: u16,
+    pub requires: Vec<Requires>,
+    pub exports: Vec<Exports>,
+    pub opens: Vec<Exports>,
+    // classes
+    pub uses: Vec<u16>,
+    pub provides: Vec<Provides>,
+}
+
+impl Module {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            module_name_index: read_u16(data, index)?,
+            module_flags: read_u16(data, index)?,
+            module_version_index: read_u16(data, index)?,
+            requires: read_table(data, index, Requires::read)?,
+            exports: read_table(data, index, Exports::read)?,
+            opens: read_table(data, index, Exports::read)?,
+            uses: read_table(data, index, read_u16)?,
+            provides: read_table(data, index, Provides::read)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.module_name_index);
+        write_u16(out, self.module_flags);
+        write_u16(out, self.module_version_index);
+        write_table(out, &self.requires, Requires::write);
+        write_table(out, &self.exports, Exports::write);
+        write_table(out, &self.opens, Exports::write);
+        write_indices(out, &self.uses);
+        write_table(out, &self.provides, Provides::write);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requires {
+    pub requires_index: u16,
+    pub requires_flags: u16,
+    pub requires_version_index: u16,
+}
+
+impl Requires {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            requires_index: read_u16(data, index)?,
+            requires_flags: read_u16(data, index)?,
+            requires_version_index: read_u16(data, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.requires_index);
+        write_u16(out, self.requires_flags);
+        write_u16(out, self.requires_version_index);
+    }
+}
+
+/// exports and opens have the same structure
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exports {
+    // package
+    pub index: u16,
+    pub flags: u16,
+    // modules, empty if unqualified
+    pub to: Vec<u16>,
+}
+
+impl Exports {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            index: read_u16(data, index)?,
+            flags: read_u16(data, index)?,
+            to: read_table(data, index, read_u16)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.index);
+        write_u16(out, self.flags);
+        write_indices(out, &self.to);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provides {
+    // the service interface
+    pub provides_index: u16,
+    // the implementations
+    pub provides_with: Vec<u16>,
+}
+
+impl Provides {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            provides_index: read_u16(data, index)?,
+            provides_with: read_table(data, index, read_u16)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.provides_index);
+        write_indices(out, &self.provides_with);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stack_map_frames() {
+        let data = [
+            3, // same, offset 3
+            64 + 2,
+            1, // same locals 1 stack item, offset 2, int
+            249,
+            0,
+            10, // chop 2, offset 10
+            253,
+            0,
+            4,
+            7,
+            0,
+            9,
+            4, // append object #9 and long, offset 4
+            255,
+            0,
+            1,
+            0,
+            1,
+            6,
+            0,
+            1,
+            8,
+            0,
+            5, // full, offset 1
+        ];
+        let index = &mut 0;
+        let mut frames = vec![];
+        while *index < data.len() {
+            frames.push(StackMapFrame::read(&data, index).unwrap());
+        }
+        assert_eq!(
+            vec![
+                StackMapFrame::Same { offset_delta: 3 },
+                StackMapFrame::SameLocals1StackItem {
+                    offset_delta: 2,
+                    stack: VerificationType::Integer
+                },
+                StackMapFrame::Chop {
+                    absent_locals: 2,
+                    offset_delta: 10
+                },
+                StackMapFrame::Append {
+                    offset_delta: 4,
+                    locals: vec![VerificationType::Object(9), VerificationType::Long]
+                },
+                StackMapFrame::Full {
+                    offset_delta: 1,
+                    locals: vec![VerificationType::UninitializedThis],
+                    stack: vec![VerificationType::Uninitialized(5)]
+                },
+            ],
+            frames
+        );
+        assert!(StackMapFrame::read(&[200], &mut 0).is_err());
+    }
+}
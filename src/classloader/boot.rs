@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::{anyhow, Error};
+
+use crate::classloader::io::archive_entries;
+use crate::classloader::source::{ClassSource, JImageSource, JmodSource};
+
+/// The boot classpath: the classes of the JDK runtime image.
+/// The packages of all modules, in lib/modules or else the jmods directory, are indexed once,
+/// so that a class name resolves directly to the module that contains it.
+#[derive(Default)]
+pub struct BootClassPath {
+    // package name (with slashes) -> the jimage or jmod that has the package
+    packages: HashMap<String, Rc<dyn ClassSource>>,
+}
+
+impl BootClassPath {
+    /// finds the JDK in the explicit system directory if there is one,
+    /// otherwise in JAVA_HOME or else the java executable on the PATH
+    pub fn locate(system: Option<&str>) -> Result<Self, Error> {
+        let candidates = match system {
+            Some(system) => vec![(PathBuf::from(system), "--system".to_owned())],
+            None => default_candidates(),
+        };
+
+        let mut looked_in = vec![];
+        if system.is_none() && env::var_os("JAVA_HOME").is_none() {
+            looked_in.push("    JAVA_HOME is not set".to_owned());
+        }
+        for (java_home, origin) in candidates {
+            if java_home.join("lib/modules").is_file() || java_home.join("jmods").is_dir() {
+                return Self::open(&java_home);
+            }
+            looked_in.push(format!(
+                "    {} ({}): no lib/modules or jmods",
+                java_home.display(),
+                origin
+            ));
+        }
+        Err(anyhow!(
+            "could not find the JDK runtime image, looked in:\n{}\nuse --system <jdk> or set JAVA_HOME",
+            looked_in.join("\n")
+        ))
+    }
+
+    /// indexes the packages of the jimage in lib/modules,
+    /// or if there is none, of all .jmod files in the jmods directory
+    pub fn open(java_home: &Path) -> Result<Self, Error> {
+        let image = java_home.join("lib/modules");
+        if image.is_file() {
+            let image = Rc::new(JImageSource::open(&image.to_string_lossy())?);
+            let packages = image
+                .packages()
+                .map(|package| (package.clone(), image.clone() as Rc<dyn ClassSource>))
+                .collect();
+            return Ok(Self { packages });
+        }
+
+        let mut jmods: Vec<PathBuf> = fs::read_dir(java_home.join("jmods"))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "jmod")
+            })
+            .collect();
+        jmods.sort();
+
+        let mut packages = HashMap::new();
+        for jmod in jmods {
+            let jmod = jmod.to_string_lossy().into_owned();
+            let source: Rc<dyn ClassSource> = Rc::new(JmodSource::new(&jmod));
+            for entry in archive_entries(&jmod)? {
+                if let Some(package) = entry
+                    .strip_prefix("classes/")
+                    .filter(|class_file| class_file.ends_with(".class"))
+                    .and_then(|class_file| class_file.rsplit_once('/'))
+                    .map(|(package, _)| package)
+                {
+                    packages
+                        .entry(package.to_owned())
+                        .or_insert_with(|| source.clone());
+                }
+            }
+        }
+        Ok(Self { packages })
+    }
+}
+
+impl ClassSource for BootClassPath {
+    fn read_class(&self, class_name: &str) -> Result<Option<Vec<u8>>, Error> {
+        match class_name
+            .rsplit_once('/')
+            .and_then(|(package, _)| self.packages.get(package))
+        {
+            Some(source) => source.read_class(class_name),
+            None => Ok(None),
+        }
+    }
+}
+
+/// JAVA_HOME, followed by the JDK of the java executable on the PATH
+fn default_candidates() -> Vec<(PathBuf, String)> {
+    let mut candidates = vec![];
+    if let Some(java_home) = env::var_os("JAVA_HOME") {
+        candidates.push((PathBuf::from(java_home), "JAVA_HOME".to_owned()));
+    }
+    if let Some(path) = env::var_os("PATH") {
+        let java_home = env::split_paths(&path)
+            .map(|dir| dir.join("java"))
+            .find(|java| java.is_file())
+            .and_then(|java| fs::canonicalize(java).ok())
+            .and_then(|java| java.parent()?.parent().map(Path::to_path_buf));
+        if let Some(java_home) = java_home {
+            candidates.push((java_home, "java on the PATH".to_owned()));
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_lists_where_it_looked() {
+        let error = BootClassPath::locate(Some("/nonexistent/jdk"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("/nonexistent/jdk (--system)"));
+    }
+}
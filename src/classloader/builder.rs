@@ -0,0 +1,829 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+
+use crate::classloader::attributes::{write_table, StackMapFrame, VerificationType};
+use crate::classloader::classdef::{BootstrapMethod, ClassDef, CpEntry, Exception};
+use crate::classloader::code_parser::write_opcode;
+use crate::classloader::io::{write_u16, write_u32};
+use crate::classloader::load_class;
+use crate::classloader::writer::write_constant_pool;
+use crate::vm::opcodes::Opcode::*;
+use crate::vm::opcodes::{Lookupswitch, Opcode, Tableswitch};
+
+// Builds classes in code, for instance for tests that should not depend on javac or a JDK.
+// The builder writes a class file, that is read with load_class, so that the ClassDef is
+// exactly like one that is loaded from a source.
+// Constant pool entries are added on demand and shared. Jumps go to labels, that are
+// turned into byte offsets when the class is built.
+
+const ACC_PUBLIC: u16 = 0x0001;
+const ACC_STATIC: u16 = 0x0008;
+const ACC_SUPER: u16 = 0x0020;
+
+/// Builds a class, eg.
+/// ```
+/// use java_rs::classloader::builder::{ClassBuilder, Code};
+/// use java_rs::classloader::classdef::Modifier;
+/// use java_rs::vm::opcodes::Opcode::*;
+///
+/// let mut class = ClassBuilder::new("t/Max", Some("java/lang/Object"));
+/// let mut code = Code::new();
+/// code.op(ILOAD(0))
+///     .op(ILOAD(1))
+///     .jump(IF_ICMPLT, "second")
+///     .op(ILOAD(0))
+///     .op(IRETURN)
+///     .label("second")
+///     .op(ILOAD(1))
+///     .op(IRETURN);
+/// class.method(Modifier::Public as u16 | Modifier::Static as u16, "max", "(II)I", code);
+/// let classdef = class.build().unwrap();
+/// assert_eq!("t/Max", classdef.name());
+/// ```
+pub struct ClassBuilder {
+    minor_version: u16,
+    major_version: u16,
+    constant_pool: HashMap<u16, CpEntry>,
+    // the index for the next constant pool entry
+    next_index: u16,
+    access_flags: u16,
+    this_class: u16,
+    super_class: Option<u16>,
+    interfaces: Vec<u16>,
+    fields: Vec<Member>,
+    methods: Vec<Member>,
+    // (attribute name, entries) of the BootstrapMethods attribute
+    bootstrap_methods: Option<(u16, Vec<BootstrapMethod>)>,
+}
+
+struct Member {
+    access_flags: u16,
+    name_index: u16,
+    descriptor_index: u16,
+    code: Option<MemberCode>,
+    // (attribute name, constant)
+    constant_value: Option<(u16, u16)>,
+}
+
+struct MemberCode {
+    // the Utf8 entry for the attribute name
+    name_index: u16,
+    code: Code,
+    // the class entries for the catch types of the exception handlers, 0 for any
+    catch_types: Vec<u16>,
+    // the Utf8 entry for the LineNumberTable attribute name, if there are line numbers
+    line_number_table_index: Option<u16>,
+    // the Utf8 entry for the StackMapTable attribute name, if there are frames
+    stack_map_table_index: Option<u16>,
+    // parameter slots, including this
+    parameter_slots: u16,
+}
+
+impl ClassBuilder {
+    /// a public class, with the superclass (None only for java/lang/Object)
+    /// the class file version is 49, so that the code needs no StackMapTable (see Code::frame)
+    pub fn new(name: &str, super_class: Option<&str>) -> Self {
+        let mut builder = Self {
+            minor_version: 0,
+            major_version: 49,
+            constant_pool: HashMap::new(),
+            next_index: 1,
+            access_flags: ACC_PUBLIC | ACC_SUPER,
+            this_class: 0,
+            super_class: None,
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![],
+            bootstrap_methods: None,
+        };
+        builder.this_class = builder.class(name);
+        builder.super_class = super_class.map(|super_class| builder.class(super_class));
+        builder
+    }
+
+    pub fn version(&mut self, major_version: u16, minor_version: u16) -> &mut Self {
+        self.major_version = major_version;
+        self.minor_version = minor_version;
+        self
+    }
+
+    pub fn access_flags(&mut self, access_flags: u16) -> &mut Self {
+        self.access_flags = access_flags;
+        self
+    }
+
+    pub fn interface(&mut self, name: &str) -> &mut Self {
+        let index = self.class(name);
+        self.interfaces.push(index);
+        self
+    }
+
+    /// the index of the constant pool entry, that is added if there is none yet
+    pub fn constant(&mut self, entry: CpEntry) -> u16 {
+        if let Some(index) = self
+            .constant_pool
+            .iter()
+            .find(|(_, existing)| is_same_constant(existing, &entry))
+            .map(|(index, _)| *index)
+        {
+            return index;
+        }
+        let index = self.next_index;
+        // long and double take two entries
+        self.next_index += match entry {
+            CpEntry::Long(_) | CpEntry::Double(_) => 2,
+            _ => 1,
+        };
+        self.constant_pool.insert(index, entry);
+        index
+    }
+
+    pub fn utf8(&mut self, string: &str) -> u16 {
+        self.constant(CpEntry::utf8(string))
+    }
+
+    /// a class, by its internal name (with slashes), or the descriptor for an array class
+    pub fn class(&mut self, name: &str) -> u16 {
+        let name_index = self.utf8(name);
+        self.constant(CpEntry::ClassRef(name_index))
+    }
+
+    pub fn string(&mut self, string: &str) -> u16 {
+        let utf8_index = self.utf8(string);
+        self.constant(CpEntry::StringRef(utf8_index))
+    }
+
+    pub fn integer(&mut self, value: i32) -> u16 {
+        self.constant(CpEntry::Integer(value))
+    }
+
+    pub fn float(&mut self, value: f32) -> u16 {
+        self.constant(CpEntry::Float(value))
+    }
+
+    pub fn long(&mut self, value: i64) -> u16 {
+        self.constant(CpEntry::Long(value))
+    }
+
+    pub fn double(&mut self, value: f64) -> u16 {
+        self.constant(CpEntry::Double(value))
+    }
+
+    pub fn name_and_type(&mut self, name: &str, descriptor: &str) -> u16 {
+        let name_index = self.utf8(name);
+        let descriptor_index = self.utf8(descriptor);
+        self.constant(CpEntry::NameAndType(name_index, descriptor_index))
+    }
+
+    pub fn field_ref(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.constant(CpEntry::Fieldref(class_index, name_and_type_index))
+    }
+
+    pub fn method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.constant(CpEntry::MethodRef(class_index, name_and_type_index))
+    }
+
+    pub fn interface_method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.constant(CpEntry::InterfaceMethodref(
+            class_index,
+            name_and_type_index,
+        ))
+    }
+
+    /// a method handle, the reference is the Fieldref, MethodRef or InterfaceMethodref entry
+    pub fn method_handle(&mut self, reference_kind: u8, reference: u16) -> u16 {
+        self.constant(CpEntry::MethodHandle(reference_kind, reference))
+    }
+
+    /// adds an entry to the BootstrapMethods attribute and returns its index,
+    /// the arguments are loadable constants
+    pub fn bootstrap_method(&mut self, method_handle: u16, arguments: &[u16]) -> u16 {
+        let name_index = self.utf8("BootstrapMethods");
+        let (_, methods) = self.bootstrap_methods.get_or_insert((name_index, vec![]));
+        methods.push(BootstrapMethod {
+            method_ref: method_handle,
+            arguments: arguments.to_vec(),
+        });
+        methods.len() as u16 - 1
+    }
+
+    /// a dynamically-computed constant, the index of the bootstrap method is the one that
+    /// bootstrap_method returns
+    pub fn dynamic(&mut self, bootstrap_method: u16, name: &str, descriptor: &str) -> u16 {
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.constant(CpEntry::Dynamic(bootstrap_method, name_and_type_index))
+    }
+
+    pub fn field(&mut self, access_flags: u16, name: &str, descriptor: &str) -> &mut Self {
+        let member = self.member(access_flags, name, descriptor);
+        self.fields.push(member);
+        self
+    }
+
+    /// a field with a ConstantValue attribute, the value is the index of an Integer, Float,
+    /// Long, Double or String entry
+    pub fn constant_field(
+        &mut self,
+        access_flags: u16,
+        name: &str,
+        descriptor: &str,
+        value: u16,
+    ) -> &mut Self {
+        let mut member = self.member(access_flags, name, descriptor);
+        member.constant_value = Some((self.utf8("ConstantValue"), value));
+        self.fields.push(member);
+        self
+    }
+
+    /// a method with code
+    pub fn method(
+        &mut self,
+        access_flags: u16,
+        name: &str,
+        descriptor: &str,
+        code: Code,
+    ) -> &mut Self {
+        let mut member = self.member(access_flags, name, descriptor);
+        let catch_types = code
+            .handlers
+            .iter()
+            .map(|handler| {
+                handler
+                    .catch_type
+                    .as_ref()
+                    .map_or(0, |catch_type| self.class(catch_type))
+            })
+            .collect();
+        let this_slot = if access_flags & ACC_STATIC == 0 { 1 } else { 0 };
+        let line_number_table_index = if code.line_number_tables.is_empty() {
+            None
+        } else {
+            Some(self.utf8("LineNumberTable"))
+        };
+        let stack_map_table_index = if code.frames.is_empty() {
+            None
+        } else {
+            Some(self.utf8("StackMapTable"))
+        };
+        member.code = Some(MemberCode {
+            name_index: self.utf8("Code"),
+            code,
+            catch_types,
+            line_number_table_index,
+            stack_map_table_index,
+            parameter_slots: this_slot + parameter_slots(descriptor),
+        });
+        self.methods.push(member);
+        self
+    }
+
+    /// a method without code, ie. an abstract or native method
+    pub fn abstract_method(
+        &mut self,
+        access_flags: u16,
+        name: &str,
+        descriptor: &str,
+    ) -> &mut Self {
+        let member = self.member(access_flags, name, descriptor);
+        self.methods.push(member);
+        self
+    }
+
+    fn member(&mut self, access_flags: u16, name: &str, descriptor: &str) -> Member {
+        Member {
+            access_flags,
+            name_index: self.utf8(name),
+            descriptor_index: self.utf8(descriptor),
+            code: None,
+            constant_value: None,
+        }
+    }
+
+    /// the bytes of the class file
+    /// fails if a jump or exception handler refers to a label that is not in the code
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut out = vec![];
+        write_u32(&mut out, 0xCAFEBABE);
+        write_u16(&mut out, self.minor_version);
+        write_u16(&mut out, self.major_version);
+        write_constant_pool(&mut out, &self.constant_pool)?;
+        write_u16(&mut out, self.access_flags);
+        write_u16(&mut out, self.this_class);
+        write_u16(&mut out, self.super_class.unwrap_or(0));
+        write_u16(&mut out, self.interfaces.len() as u16);
+        for interface in &self.interfaces {
+            write_u16(&mut out, *interface);
+        }
+        for members in [&self.fields, &self.methods] {
+            write_u16(&mut out, members.len() as u16);
+            for member in members {
+                write_u16(&mut out, member.access_flags);
+                write_u16(&mut out, member.name_index);
+                write_u16(&mut out, member.descriptor_index);
+                let attributes_count =
+                    member.code.is_some() as u16 + member.constant_value.is_some() as u16;
+                write_u16(&mut out, attributes_count);
+                if let Some(code) = &member.code {
+                    write_u16(&mut out, code.name_index);
+                    let info = code.code.write(code)?;
+                    write_u32(&mut out, info.len() as u32);
+                    out.extend_from_slice(&info);
+                }
+                if let Some((name_index, value)) = member.constant_value {
+                    write_u16(&mut out, name_index);
+                    write_u32(&mut out, 2);
+                    write_u16(&mut out, value);
+                }
+            }
+        }
+        write_u16(&mut out, self.bootstrap_methods.is_some() as u16);
+        if let Some((name_index, methods)) = &self.bootstrap_methods {
+            write_u16(&mut out, *name_index);
+            let mut info = vec![];
+            write_table(&mut info, methods, BootstrapMethod::write);
+            write_u32(&mut out, info.len() as u32);
+            out.extend_from_slice(&info);
+        }
+        Ok(out)
+    }
+
+    /// the class, that can be added to the ClassManager with define_class
+    pub fn build(&self) -> Result<ClassDef, Error> {
+        Ok(load_class(self.to_bytes()?)?)
+    }
+}
+
+/// The code of a method: opcodes and the labels in between them.
+/// The operands of opcodes are as in the class file, for instance constant pool indices
+/// and local variable slots.
+#[derive(Default)]
+pub struct Code {
+    instructions: Vec<Instruction>,
+    // label -> index of the instruction that follows it
+    labels: HashMap<String, usize>,
+    handlers: Vec<Handler>,
+    // (label, line number) for each LineNumberTable
+    line_number_tables: Vec<Vec<(String, u16)>>,
+    // (label, locals, stack) of the StackMapTable
+    frames: Vec<(String, Vec<VerificationType>, Vec<VerificationType>)>,
+    max_stack: Option<u16>,
+    max_locals: Option<u16>,
+}
+
+enum Instruction {
+    Opcode(Opcode),
+    // a jump, with the target label
+    Jump(fn(u16) -> Opcode, String),
+    // goto_w or jsr_w, with the target label
+    WideJump(fn(i32) -> Opcode, String),
+    Tableswitch {
+        low: i32,
+        labels: Vec<String>,
+        default: String,
+    },
+    Lookupswitch {
+        // (key, label)
+        pairs: Vec<(i32, String)>,
+        default: String,
+    },
+}
+
+struct Handler {
+    start: String,
+    end: String,
+    handler: String,
+    // the class name, None for any exception (finally)
+    catch_type: Option<String>,
+}
+
+impl Code {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds the opcode
+    /// the operand of a jump is a byte offset relative to the opcode, use jump to jump to a label
+    pub fn op(&mut self, opcode: Opcode) -> &mut Self {
+        self.instructions.push(Instruction::Opcode(opcode));
+        self
+    }
+
+    /// adds a jump to the label, eg. jump(IFEQ, "end")
+    pub fn jump(&mut self, opcode: fn(u16) -> Opcode, label: &str) -> &mut Self {
+        self.instructions
+            .push(Instruction::Jump(opcode, label.into()));
+        self
+    }
+
+    /// adds a goto_w or jsr_w to the label
+    pub fn jump_w(&mut self, opcode: fn(i32) -> Opcode, label: &str) -> &mut Self {
+        self.instructions
+            .push(Instruction::WideJump(opcode, label.into()));
+        self
+    }
+
+    /// adds a tableswitch, with the labels for the keys low, low + 1, ...
+    pub fn tableswitch(&mut self, low: i32, labels: &[&str], default: &str) -> &mut Self {
+        self.instructions.push(Instruction::Tableswitch {
+            low,
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            default: default.into(),
+        });
+        self
+    }
+
+    /// adds a lookupswitch, with the labels for the keys, sorted by key
+    pub fn lookupswitch(&mut self, pairs: &[(i32, &str)], default: &str) -> &mut Self {
+        let mut pairs: Vec<(i32, String)> = pairs
+            .iter()
+            .map(|(key, label)| (*key, label.to_string()))
+            .collect();
+        pairs.sort_by_key(|(key, _)| *key);
+        self.instructions.push(Instruction::Lookupswitch {
+            pairs,
+            default: default.into(),
+        });
+        self
+    }
+
+    /// puts the label at the next opcode (or the end of the code)
+    pub fn label(&mut self, label: &str) -> &mut Self {
+        self.labels.insert(label.into(), self.instructions.len());
+        self
+    }
+
+    /// adds an exception handler at the label handler, for the code from the label start
+    /// up to the label end. The catch type is a class name, or None to catch anything
+    pub fn catch(
+        &mut self,
+        start: &str,
+        end: &str,
+        handler: &str,
+        catch_type: Option<&str>,
+    ) -> &mut Self {
+        self.handlers.push(Handler {
+            start: start.into(),
+            end: end.into(),
+            handler: handler.into(),
+            catch_type: catch_type.map(|catch_type| catch_type.into()),
+        });
+        self
+    }
+
+    /// adds a LineNumberTable, with the source line of the opcode at each label
+    /// a method can have more than one, each with a part of the lines
+    pub fn line_numbers(&mut self, lines: &[(&str, u16)]) -> &mut Self {
+        self.line_number_tables.push(
+            lines
+                .iter()
+                .map(|(label, line_number)| (label.to_string(), *line_number))
+                .collect(),
+        );
+        self
+    }
+
+    /// adds a full frame to the StackMapTable, with the types of the local variables and the
+    /// operand stack at the label. From class file version 50 the verifier needs a frame at each
+    /// branch target and exception handler. Long and double take one entry, and classes are
+    /// constant pool indices, eg. VerificationType::Object(class.class("java/lang/String"))
+    pub fn frame(
+        &mut self,
+        label: &str,
+        locals: &[VerificationType],
+        stack: &[VerificationType],
+    ) -> &mut Self {
+        self.frames
+            .push((label.into(), locals.to_vec(), stack.to_vec()));
+        self
+    }
+
+    /// by default 2 slots for each opcode, which is more than any code will use
+    pub fn max_stack(&mut self, max_stack: u16) -> &mut Self {
+        self.max_stack = Some(max_stack);
+        self
+    }
+
+    /// by default the parameters, or the highest local variable that is used if that is higher
+    pub fn max_locals(&mut self, max_locals: u16) -> &mut Self {
+        self.max_locals = Some(max_locals);
+        self
+    }
+
+    /// the contents of the Code attribute
+    fn write(&self, method: &MemberCode) -> Result<Vec<u8>, Error> {
+        // the offsets of the opcodes do not depend on the jump targets, only on the opcodes
+        // before them (because of the padding in switches), so they are known after one pass
+        let mut offsets = vec![];
+        let mut code = vec![];
+        for instruction in &self.instructions {
+            offsets.push(code.len());
+            write_opcode(&mut code, &instruction.opcode(|_| Ok(0))?);
+        }
+        offsets.push(code.len());
+        let offset = |label: &str| {
+            self.labels
+                .get(label)
+                .map(|index| offsets[*index])
+                .ok_or_else(|| anyhow!("undefined label {}", label))
+        };
+
+        let mut code = vec![];
+        for (instruction, opcode_offset) in self.instructions.iter().zip(&offsets) {
+            let relative_offset = |label: &str| Ok(offset(label)? as i32 - *opcode_offset as i32);
+            write_opcode(&mut code, &instruction.opcode(relative_offset)?);
+        }
+
+        let mut out = vec![];
+        let max_stack = self
+            .max_stack
+            .unwrap_or((self.instructions.len() * 2).min(u16::MAX as usize) as u16);
+        let max_locals = self.max_locals.unwrap_or_else(|| {
+            self.instructions
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::Opcode(opcode) => locals_used(opcode),
+                    _ => None,
+                })
+                .fold(method.parameter_slots, u16::max)
+        });
+        write_u16(&mut out, max_stack);
+        write_u16(&mut out, max_locals);
+        write_u32(&mut out, code.len() as u32);
+        out.extend_from_slice(&code);
+        write_u16(&mut out, self.handlers.len() as u16);
+        for (handler, catch_type) in self.handlers.iter().zip(&method.catch_types) {
+            Exception {
+                start_pc: offset(&handler.start)? as u16,
+                end_pc: offset(&handler.end)? as u16,
+                handler_pc: offset(&handler.handler)? as u16,
+                catch_type: *catch_type,
+            }
+            .write(&mut out);
+        }
+        let attributes_count = self.line_number_tables.len() + !self.frames.is_empty() as usize;
+        write_u16(&mut out, attributes_count as u16);
+        for table in &self.line_number_tables {
+            write_u16(&mut out, method.line_number_table_index.unwrap_or(0));
+            write_u32(&mut out, 2 + 4 * table.len() as u32);
+            write_u16(&mut out, table.len() as u16);
+            for (label, line_number) in table {
+                write_u16(&mut out, offset(label)? as u16);
+                write_u16(&mut out, *line_number);
+            }
+        }
+        if let Some(name_index) = method.stack_map_table_index {
+            // the frames in the order of their offsets, each offset is relative to the previous
+            // frame (plus one), the first one is relative to the start of the code
+            let mut frames = self
+                .frames
+                .iter()
+                .map(|(label, locals, stack)| Ok((offset(label)?, locals, stack)))
+                .collect::<Result<Vec<_>, Error>>()?;
+            frames.sort_by_key(|(offset, _, _)| *offset);
+            let mut entries = vec![];
+            let mut previous: Option<usize> = None;
+            for (offset, locals, stack) in frames {
+                let offset_delta = match previous {
+                    None => offset,
+                    Some(previous) if offset > previous => offset - previous - 1,
+                    Some(_) => return Err(anyhow!("two frames at offset {}", offset)),
+                };
+                entries.push(StackMapFrame::Full {
+                    offset_delta: offset_delta as u16,
+                    locals: locals.clone(),
+                    stack: stack.clone(),
+                });
+                previous = Some(offset);
+            }
+            let mut info = vec![];
+            write_table(&mut info, &entries, StackMapFrame::write);
+            write_u16(&mut out, name_index);
+            write_u32(&mut out, info.len() as u32);
+            out.extend_from_slice(&info);
+        }
+        Ok(out)
+    }
+}
+
+impl Instruction {
+    /// the opcode, with the byte offsets of the labels relative to the opcode
+    fn opcode(
+        &self,
+        relative_offset: impl Fn(&str) -> Result<i32, Error>,
+    ) -> Result<Opcode, Error> {
+        Ok(match self {
+            Instruction::Opcode(opcode) => opcode.clone(),
+            Instruction::Jump(opcode, label) => {
+                let offset = i16::try_from(relative_offset(label)?)
+                    .map_err(|_| anyhow!("label {} is too far away", label))?;
+                opcode(offset as u16)
+            }
+            Instruction::WideJump(opcode, label) => opcode(relative_offset(label)?),
+            Instruction::Tableswitch {
+                low,
+                labels,
+                default,
+            } => TABLESWITCH(Tableswitch::new(
+                relative_offset(default)?,
+                *low,
+                labels
+                    .iter()
+                    .map(|label| relative_offset(label))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Instruction::Lookupswitch { pairs, default } => LOOKUPSWITCH(Lookupswitch::new(
+                relative_offset(default)?,
+                pairs
+                    .iter()
+                    .map(|(key, label)| Ok((*key, relative_offset(label)?)))
+                    .collect::<Result<_, Error>>()?,
+            )),
+        })
+    }
+}
+
+/// whether the entries are the same constant: floats and doubles by their bits,
+/// so that -0.0 is not taken for 0.0, and a NaN is the same as itself
+fn is_same_constant(entry1: &CpEntry, entry2: &CpEntry) -> bool {
+    match (entry1, entry2) {
+        (CpEntry::Float(value1), CpEntry::Float(value2)) => value1.to_bits() == value2.to_bits(),
+        (CpEntry::Double(value1), CpEntry::Double(value2)) => value1.to_bits() == value2.to_bits(),
+        _ => entry1 == entry2,
+    }
+}
+
+/// the number of local variables up to and including the one the opcode uses
+fn locals_used(opcode: &Opcode) -> Option<u16> {
+    match opcode {
+        ILOAD(index)
+        | FLOAD(index)
+        | ALOAD(index)
+        | ISTORE(index)
+        | FSTORE(index)
+        | ASTORE(index)
+        | IINC(index, _)
+        | RET(index) => Some(*index as u16 + 1),
+        LLOAD(index) | DLOAD(index) | LSTORE(index) | DSTORE(index) => Some(*index as u16 + 2),
+        WIDE_ILOAD(index)
+        | WIDE_FLOAD(index)
+        | WIDE_ALOAD(index)
+        | WIDE_ISTORE(index)
+        | WIDE_FSTORE(index)
+        | WIDE_ASTORE(index)
+        | WIDE_IINC(index, _)
+        | WIDE_RET(index) => Some(index + 1),
+        WIDE_LLOAD(index) | WIDE_DLOAD(index) | WIDE_LSTORE(index) | WIDE_DSTORE(index) => {
+            Some(index + 2)
+        }
+        WIDE(opcode) => locals_used(opcode),
+        _ => None,
+    }
+}
+
+/// the number of local variable slots for the parameters in the method descriptor
+pub(crate) fn parameter_slots(descriptor: &str) -> u16 {
+    let parameters = descriptor
+        .strip_prefix('(')
+        .and_then(|descriptor| descriptor.split_once(')'))
+        .map_or("", |(parameters, _)| parameters);
+    let mut slots = 0;
+    let mut chars = parameters.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            'J' | 'D' => slots += 2,
+            'L' => {
+                chars.by_ref().find(|c| *c == ';');
+                slots += 1;
+            }
+            '[' => {
+                // the element type does not take a slot of its own
+                let mut element = chars.next();
+                while element == Some('[') {
+                    element = chars.next();
+                }
+                if element == Some('L') {
+                    chars.by_ref().find(|c| *c == ';');
+                }
+                slots += 1;
+            }
+            _ => slots += 1,
+        }
+    }
+    slots
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::classloader::classdef::{AttributeType, Modifier};
+
+    #[test]
+    fn jumps_to_labels() {
+        let mut class = ClassBuilder::new("t/Loop", Some("java/lang/Object"));
+        let mut code = Code::new();
+        code.op(ICONST(0))
+            .op(ISTORE(2))
+            .label("loop")
+            .op(ILOAD(0))
+            .jump(IFLE, "end")
+            .op(IINC(0, -1))
+            .op(IINC(2, 1))
+            .jump(GOTO, "loop")
+            .label("end")
+            .op(ILOAD(2))
+            .op(IRETURN);
+        class.method(ACC_PUBLIC | ACC_STATIC, "count", "(IJ)I", code);
+
+        let classdef = class.build().unwrap();
+        let method = classdef.get_method("count(IJ)I").unwrap();
+        assert!(method.is(Modifier::Static));
+        // the jump targets are opcode indices after parsing
+        assert!(matches!(method.code[3], IFLE(7)));
+        assert!(matches!(method.code[6], GOTO(2)));
+    }
+
+    #[test]
+    fn writes_stack_map_frames() {
+        let mut class = ClassBuilder::new("t/Frames", Some("java/lang/Object"));
+        let string = class.class("java/lang/String");
+        let mut code = Code::new();
+        code.op(ALOAD(0))
+            .jump(IFNULL, "null")
+            .op(ICONST(1))
+            .jump(GOTO, "end")
+            .label("null")
+            .op(ICONST(0))
+            .label("end")
+            .op(IRETURN)
+            // in the order of the offsets in the StackMapTable
+            .frame(
+                "end",
+                &[VerificationType::Object(string)],
+                &[VerificationType::Integer],
+            )
+            .frame("null", &[VerificationType::Object(string)], &[]);
+        class.version(51, 0).method(
+            ACC_PUBLIC | ACC_STATIC,
+            "isNull",
+            "(Ljava/lang/String;)I",
+            code,
+        );
+
+        let classdef = class.build().unwrap();
+        let method = classdef.get_method("isNull(Ljava/lang/String;)I").unwrap();
+        let Some(AttributeType::Code(code)) = method.attributes.get("Code") else {
+            panic!("no code");
+        };
+        let Some(AttributeType::StackMapTable(frames)) = code.code_attributes.get("StackMapTable")
+        else {
+            panic!("no StackMapTable");
+        };
+        // null is at offset 8, end at 9
+        let offset_deltas: Vec<u16> = frames.iter().map(StackMapFrame::offset_delta).collect();
+        assert_eq!(vec![8, 0], offset_deltas);
+    }
+
+    #[test]
+    fn shares_constant_pool_entries() {
+        let mut class = ClassBuilder::new("t/Cp", Some("java/lang/Object"));
+        let string = class.string("t/Cp");
+        let this_class = class.class("t/Cp");
+        assert_eq!(class.this_class, this_class);
+        assert_eq!(string, class.string("t/Cp"));
+        assert_eq!(6, class.long(1));
+        assert_eq!(8, class.integer(1));
+        let name_index = class.utf8("t/Cp");
+        let classdef = class.build().unwrap();
+        assert_eq!(name_index, *classdef.cp_class_ref(&this_class));
+    }
+
+    #[test]
+    fn keeps_negative_zero_apart_from_zero() {
+        let mut class = ClassBuilder::new("t/Zero", Some("java/lang/Object"));
+        let zero = class.float(0.0);
+        let negative_zero = class.float(-0.0);
+        assert_ne!(zero, negative_zero);
+        assert_eq!(negative_zero, class.float(-0.0));
+        assert_ne!(class.double(0.0), class.double(-0.0));
+        assert_eq!(class.float(f32::NAN), class.float(f32::NAN));
+        let classdef = class.build().unwrap();
+        let Some(CpEntry::Float(value)) = classdef.constant_pool.get(&negative_zero) else {
+            panic!("no float at #{}", negative_zero)
+        };
+        assert!(value.is_sign_negative());
+    }
+
+    #[test]
+    fn counts_parameter_slots() {
+        assert_eq!(0, parameter_slots("()V"));
+        assert_eq!(5, parameter_slots("(I[[JLjava/lang/String;D)V"));
+        assert_eq!(2, parameter_slots("([Ljava/lang/Object;Z)[J"));
+    }
+}
@@ -1,24 +1,33 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::rc::Rc;
 
-use crate::classloader::io::read_u16;
+use crate::classloader::annotations::{Annotation, ElementValue, TypeAnnotation};
+use crate::classloader::attributes::{
+    write_indices, EnclosingMethod, InnerClass, LocalVariable, MethodParameter, Module,
+    StackMapFrame,
+};
+use crate::classloader::error::ClassFormatError;
+use crate::classloader::io::{read_u16, write_u16};
 use crate::vm::opcodes::Opcode;
 
+const ACC_INTERFACE: u16 = 0x0200;
+const ACC_ANNOTATION: u16 = 0x2000;
+
 /// This is the class representation when the bytecode had just been loaded.
 
-pub(crate) struct ClassDef {
+pub struct ClassDef {
     pub minor_version: u16,
     pub major_version: u16,
     pub constant_pool: Rc<HashMap<u16, CpEntry>>,
     pub access_flags: u16,
-    this_class: u16,
+    pub(crate) this_class: u16,
     pub super_class: Option<u16>,
     pub interfaces: Vec<u16>,
     pub fields: HashMap<String, Field>,
     pub methods: HashMap<String, Method>,
-    pub attributes: HashMap<String, AttributeType>,
+    pub attributes: Attributes,
 }
 
 impl Debug for ClassDef {
@@ -38,7 +47,7 @@ impl ClassDef {
         interfaces: Vec<u16>,
         fields: HashMap<String, Field>,
         methods: HashMap<String, Method>,
-        attributes: HashMap<String, AttributeType>,
+        attributes: Attributes,
     ) -> Self {
         Self {
             major_version,
@@ -71,6 +80,48 @@ impl ClassDef {
         self.methods.contains_key(name)
     }
 
+    /// the name of the source file, if the class has the SourceFile attribute
+    pub fn source_file(&self) -> Option<&String> {
+        if let Some(AttributeType::SourceFile(index)) = self.attributes.get("SourceFile") {
+            Some(self.cp_utf8(index))
+        } else {
+            None
+        }
+    }
+
+    /// the name of the superclass, None for java/lang/Object
+    pub fn super_class_name(&self) -> Option<&str> {
+        self.super_class
+            .as_ref()
+            .map(|index| self.cp_class_name(index).as_str())
+    }
+
+    /// whether this is an interface (ACC_INTERFACE)
+    pub fn is_interface(&self) -> bool {
+        self.access_flags & ACC_INTERFACE != 0
+    }
+
+    /// whether this is an annotation interface (ACC_ANNOTATION)
+    pub fn is_annotation(&self) -> bool {
+        self.access_flags & ACC_ANNOTATION != 0
+    }
+
+    /// the annotations on the class that are visible at runtime
+    pub fn annotations(&self) -> &[Annotation] {
+        runtime_visible_annotations(&self.attributes)
+    }
+
+    /// the entry in the BootstrapMethods attribute
+    pub fn bootstrap_method(&self, index: &u16) -> Option<&BootstrapMethod> {
+        if let Some(AttributeType::BootstrapMethods(methods)) =
+            self.attributes.get("BootstrapMethods")
+        {
+            methods.get(*index as usize)
+        } else {
+            None
+        }
+    }
+
     pub fn cp_field_ref(&self, index: &u16) -> (&u16, &u16) {
         if let CpEntry::Fieldref(class_index, name_and_type_index) =
             self.constant_pool.get(index).unwrap()
@@ -108,13 +159,44 @@ impl ClassDef {
     }
 
     pub fn cp_utf8(&self, index: &u16) -> &String {
-        if let CpEntry::Utf8(utf8) = self.constant_pool.get(index).unwrap() {
+        if let CpEntry::Utf8(utf8, _) = self.constant_pool.get(index).unwrap() {
             utf8
         } else {
             unreachable!("should be utf8 entry")
         }
     }
 
+    /// the utf-16 code units of the utf8 entry, for string constants
+    pub fn cp_utf16(&self, index: &u16) -> &[u16] {
+        if let CpEntry::Utf8(_, units) = self.constant_pool.get(index).unwrap() {
+            units
+        } else {
+            unreachable!("should be utf8 entry")
+        }
+    }
+
+    /// returns (reference_kind, reference_index)
+    pub fn cp_method_handle(&self, index: &u16) -> (&u8, &u16) {
+        if let CpEntry::MethodHandle(reference_kind, reference_index) =
+            self.constant_pool.get(index).unwrap()
+        {
+            (reference_kind, reference_index)
+        } else {
+            unreachable!("should be method handle")
+        }
+    }
+
+    /// returns (bootstrap_method_attr_index, name_and_type_index)
+    pub fn cp_dynamic(&self, index: &u16) -> (&u16, &u16) {
+        if let CpEntry::Dynamic(bootstrap_method_attr_index, name_and_type_index) =
+            self.constant_pool.get(index).unwrap()
+        {
+            (bootstrap_method_attr_index, name_and_type_index)
+        } else {
+            unreachable!("should be dynamic")
+        }
+    }
+
     pub fn cp_name_and_type(&self, index: &u16) -> (&u16, &u16) {
         if let CpEntry::NameAndType(name_index, type_index) = self.constant_pool.get(index).unwrap()
         {
@@ -125,9 +207,11 @@ impl ClassDef {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum CpEntry {
-    Utf8(String),
+    Utf8(String, Vec<u16>),
+    // (string, utf-16 code units)
+    // the units are what java strings hold, the string has U+FFFD for lone surrogates
     Integer(i32),
     Float(f32),
     Long(i64),
@@ -145,10 +229,27 @@ pub enum CpEntry {
     NameAndType(u16, u16),
     // (name, descriptor)
     MethodHandle(u8, u16),
+    // (reference_kind, reference)
     MethodType(u16),
+    // (descriptor)
+    Dynamic(u16, u16),
+    // (bootstrap_method_attr, name_and_type)
     InvokeDynamic(u16, u16),
+    // (bootstrap_method_attr, name_and_type)
+    Module(u16),
+    // (utf8)
+    Package(u16),
+    // (utf8)
 }
 
+impl CpEntry {
+    /// the Utf8 entry for the string
+    pub fn utf8(string: &str) -> Self {
+        CpEntry::Utf8(string.to_owned(), string.encode_utf16().collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Modifier {
     Public = 0x0001,
     Private = 0x0002,
@@ -164,42 +265,103 @@ pub enum Modifier {
     Synthetic = 0x1000,
 }
 
-//TODO implement more types
+/// The attributes of a class, field, method, code or record component, in class file order.
+/// Some attributes can occur more than once, like LineNumberTable (JVMS 4.7.12).
+#[derive(Debug, Default)]
+pub struct Attributes(Vec<(String, AttributeType)>);
+
+impl Attributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, name: impl Into<String>, attribute: AttributeType) {
+        self.0.push((name.into(), attribute));
+    }
+
+    /// the first attribute with the name
+    pub fn get(&self, name: &str) -> Option<&AttributeType> {
+        self.0
+            .iter()
+            .find(|(attribute_name, _)| attribute_name == name)
+            .map(|(_, attribute)| attribute)
+    }
+
+    /// all attributes with the name, in class file order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a AttributeType> {
+        self.0
+            .iter()
+            .filter(move |(attribute_name, _)| attribute_name == name)
+            .map(|(_, attribute)| attribute)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// (name, attribute), in class file order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &AttributeType)> {
+        self.0.iter().map(|(name, attribute)| (name, attribute))
+    }
+}
+
+/// The attributes of classes, fields, methods and code, JVMS 4.7
+/// Values of u16 are indices in the constant pool.
 #[derive(Debug)]
 pub enum AttributeType {
     ConstantValue(u16),
     Code(Box<MethodCode>),
-    StackMapTable,
-    BootstrapMethods,
-    NestHost,
-    NestMembers,
-    PermittedSubclasses,
-    Exceptions,
-    InnerClasses,
-    EnclosingMethod,
+    StackMapTable(Vec<StackMapFrame>),
+    BootstrapMethods(Vec<BootstrapMethod>),
+    NestHost(u16),
+    NestMembers(Vec<u16>),
+    PermittedSubclasses(Vec<u16>),
+    Exceptions(Vec<u16>),
+    InnerClasses(Vec<InnerClass>),
+    EnclosingMethod(EnclosingMethod),
     Synthetic,
-    Signature,
-    Record,
-    SourceFile,
-    LineNumberTable,
-    LocalVariableTable,
-    LocalVariableTypeTable,
-    SourceDebugExtension,
+    Signature(u16),
+    Record(Vec<RecordComponent>),
+    SourceFile(u16),
+    // modified UTF-8, that is not further interpreted
+    SourceDebugExtension(Vec<u8>),
+    LineNumberTable(Vec<LineNumber>),
+    LocalVariableTable(Vec<LocalVariable>),
+    LocalVariableTypeTable(Vec<LocalVariable>),
     Deprecated,
-    RuntimeVisibleAnnotations,
-    RuntimeInvisibleAnnotations,
-    RuntimeVisibleParameterAnnotations,
-    RuntimeInvisibleParameterAnnotations,
-    RuntimeVisibleTypeAnnotations,
-    RuntimeInvisibleTypeAnnotations,
-    AnnotationDefault,
-    MethodParameters,
-    Module,
-    ModulePackages,
-    ModuleMainClass,
+    RuntimeVisibleAnnotations(Vec<Annotation>),
+    RuntimeInvisibleAnnotations(Vec<Annotation>),
+    // the annotations per parameter
+    RuntimeVisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    RuntimeInvisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    RuntimeVisibleTypeAnnotations(Vec<TypeAnnotation>),
+    RuntimeInvisibleTypeAnnotations(Vec<TypeAnnotation>),
+    AnnotationDefault(ElementValue),
+    MethodParameters(Vec<MethodParameter>),
+    Module(Module),
+    ModulePackages(Vec<u16>),
+    ModuleMainClass(u16),
+    // an attribute that is not defined in the JVMS (or not parsed yet), with its contents
+    Unknown(Vec<u8>),
 }
 
+/// a component in the Record attribute
 #[derive(Debug)]
+pub struct RecordComponent {
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Attributes,
+}
+
+#[derive(Debug, Clone)]
 pub struct Exception {
     pub start_pc: u16,
     pub end_pc: u16,
@@ -208,55 +370,153 @@ pub struct Exception {
 }
 
 impl Exception {
-    pub fn read(code: &[u8], index: &mut usize) -> Self {
+    pub fn read(code: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            start_pc: read_u16(code, index)?,
+            end_pc: read_u16(code, index)?,
+            handler_pc: read_u16(code, index)?,
+            catch_type: read_u16(code, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.start_pc);
+        write_u16(out, self.end_pc);
+        write_u16(out, self.handler_pc);
+        write_u16(out, self.catch_type);
+    }
+
+    /// translates the byte offsets in this entry to opcode indices, using the mapping from the code parser
+    pub(crate) fn to_opcode_indices(&self, offsets: &BTreeMap<u16, u16>) -> Self {
         Self {
-            start_pc: read_u16(code, index),
-            end_pc: read_u16(code, index),
-            handler_pc: read_u16(code, index),
-            catch_type: read_u16(code, index),
+            start_pc: offsets[&self.start_pc],
+            end_pc: offsets[&self.end_pc],
+            handler_pc: offsets[&self.handler_pc],
+            catch_type: self.catch_type,
         }
     }
+
+    /// checks whether the opcode at index pc is covered by this entry
+    pub(crate) fn covers(&self, pc: usize) -> bool {
+        self.start_pc as usize <= pc && pc < self.end_pc as usize
+    }
+}
+
+/// an entry in the BootstrapMethods attribute
+#[derive(Debug)]
+pub struct BootstrapMethod {
+    // MethodHandle entry in the constant pool
+    pub method_ref: u16,
+    // constant pool entries
+    pub arguments: Vec<u16>,
+}
+
+impl BootstrapMethod {
+    pub fn read(data: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        let method_ref = read_u16(data, index)?;
+        let num_arguments = read_u16(data, index)?;
+        let mut arguments = Vec::with_capacity(num_arguments as usize);
+        for _ in 0..num_arguments {
+            arguments.push(read_u16(data, index)?);
+        }
+        Ok(Self {
+            method_ref,
+            arguments,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.method_ref);
+        write_indices(out, &self.arguments);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LineNumber {
+    pub start_pc: u16,
+    pub line_number: u16,
+}
+
+impl LineNumber {
+    pub fn read(code: &[u8], index: &mut usize) -> Result<Self, ClassFormatError> {
+        Ok(Self {
+            start_pc: read_u16(code, index)?,
+            line_number: read_u16(code, index)?,
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.start_pc);
+        write_u16(out, self.line_number);
+    }
+
+    /// translates the byte offset in this entry to an opcode index, using the mapping from the code parser
+    /// None if the offset is not the start of an instruction
+    pub(crate) fn to_opcode_index(&self, offsets: &BTreeMap<u16, u16>) -> Option<Self> {
+        Some(Self {
+            start_pc: *offsets.get(&self.start_pc)?,
+            line_number: self.line_number,
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct MethodCode {
-    _max_stack: u16,
-    _max_locals: u16,
+    pub(crate) max_stack: u16,
+    pub(crate) max_locals: u16,
     pub(crate) opcodes: Vec<u8>,
-    _exception_table: Vec<Exception>,
-    _code_attributes: HashMap<String, AttributeType>,
+    // the parsed opcodes and the mapping from byte offset to opcode index
+    pub(crate) instructions: Vec<Opcode>,
+    pub(crate) opcode_indices: BTreeMap<u16, u16>,
+    pub(crate) exception_table: Vec<Exception>,
+    pub(crate) code_attributes: Attributes,
 }
 
 impl MethodCode {
     pub(crate) fn new(
-        _max_stack: u16,
-        _max_locals: u16,
+        max_stack: u16,
+        max_locals: u16,
         code: Vec<u8>,
-        _exception_table: Vec<Exception>,
-        _code_attributes: HashMap<String, AttributeType>,
+        instructions: Vec<Opcode>,
+        opcode_indices: BTreeMap<u16, u16>,
+        exception_table: Vec<Exception>,
+        code_attributes: Attributes,
     ) -> Self {
         Self {
-            _max_stack,
-            _max_locals,
+            max_stack,
+            max_locals,
             opcodes: code,
-            _exception_table,
-            _code_attributes,
+            instructions,
+            opcode_indices,
+            exception_table,
+            code_attributes,
         }
     }
+
+    /// the entries of all LineNumberTable attributes
+    pub(crate) fn line_number_table(&self) -> impl Iterator<Item = &LineNumber> {
+        self.code_attributes
+            .get_all("LineNumberTable")
+            .flat_map(|attribute| match attribute {
+                AttributeType::LineNumberTable(table) => table.as_slice(),
+                _ => &[],
+            })
+    }
 }
 
-pub(crate) const _MODIFIERS: [(Modifier, &str); 12] = [
-    (Modifier::Public, "public "),
-    (Modifier::Private, "private "),
-    (Modifier::Protected, "protected "),
-    (Modifier::Static, "static "),
-    (Modifier::Final, "final "),
-    (Modifier::Synchronized, "synchronized "),
-    (Modifier::Volatile, "volatile "),
-    (Modifier::Transient, "transient "),
-    (Modifier::Native, "native "),
+/// the modifiers, as in the java language
+pub(crate) const MODIFIERS: [(Modifier, &str); 12] = [
+    (Modifier::Public, "public"),
+    (Modifier::Private, "private"),
+    (Modifier::Protected, "protected"),
+    (Modifier::Static, "static"),
+    (Modifier::Final, "final"),
+    (Modifier::Synchronized, "synchronized"),
+    (Modifier::Volatile, "volatile"),
+    (Modifier::Transient, "transient"),
+    (Modifier::Native, "native"),
     (Modifier::Abstract, "abstract"),
-    (Modifier::Strict, "strict"),
+    (Modifier::Strict, "strictfp"),
     (Modifier::Synthetic, "synthetic"),
 ];
 
@@ -264,9 +524,10 @@ pub struct Field {
     constant_pool: Rc<HashMap<u16, CpEntry>>,
     access_flags: u16,
     pub(crate) name_index: u16,
-    descriptor_index: u16,
-    attributes: HashMap<String, AttributeType>,
-    _index: u16,
+    pub(crate) descriptor_index: u16,
+    pub(crate) attributes: Attributes,
+    // the position in the class file
+    pub(crate) index: u16,
 }
 
 impl fmt::Debug for Field {
@@ -285,7 +546,7 @@ impl Field {
         access_flags: u16,
         name_index: u16,
         descriptor_index: u16,
-        attributes: HashMap<String, AttributeType>,
+        attributes: Attributes,
         field_index: u16,
     ) -> Self {
         Field {
@@ -294,7 +555,7 @@ impl Field {
             name_index,
             descriptor_index,
             attributes,
-            _index: field_index,
+            index: field_index,
         }
     }
 
@@ -304,27 +565,42 @@ impl Field {
     }
 
     pub fn name(&self) -> &String {
-        if let CpEntry::Utf8(utf8) = &self.constant_pool.get(&self.name_index).unwrap() {
+        if let CpEntry::Utf8(utf8, _) = &self.constant_pool.get(&self.name_index).unwrap() {
             return utf8;
         }
         unreachable!()
     }
 
     pub fn type_of(&self) -> &String {
-        if let CpEntry::Utf8(s) = &self.constant_pool.get(&self.descriptor_index).unwrap() {
+        if let CpEntry::Utf8(s, _) = &self.constant_pool.get(&self.descriptor_index).unwrap() {
             return s;
         }
         panic!()
     }
+
+    pub fn access_flags(&self) -> u16 {
+        self.access_flags
+    }
+
+    /// the annotations that are visible at runtime
+    pub fn annotations(&self) -> &[Annotation] {
+        runtime_visible_annotations(&self.attributes)
+    }
 }
 
 pub struct Method {
     pub(crate) constant_pool: Rc<HashMap<u16, CpEntry>>,
     pub access_flags: u16,
-    name_index: u16,
-    descriptor_index: u16,
-    pub(crate) attributes: HashMap<String, AttributeType>,
+    pub(crate) name_index: u16,
+    pub(crate) descriptor_index: u16,
+    pub(crate) attributes: Attributes,
+    // the position in the class file
+    pub(crate) index: u16,
     pub(crate) code: Vec<Opcode>,
+    // exception handlers, with pc's as opcode indices instead of byte offsets
+    pub(crate) exception_table: Vec<Exception>,
+    // line numbers, with pc's as opcode indices instead of byte offsets
+    pub(crate) line_numbers: Vec<LineNumber>,
 }
 
 impl Debug for Method {
@@ -338,30 +614,49 @@ impl Debug for Method {
 }
 
 impl Method {
+    /// the code, exception handlers and line numbers are taken from the Code attribute
     pub(crate) fn new(
         constant_pool: Rc<HashMap<u16, CpEntry>>,
         access_flags: u16,
         name_index: u16,
         descriptor_index: u16,
-        attributes: HashMap<String, AttributeType>,
-        code: Vec<Opcode>,
+        attributes: Attributes,
+        index: u16,
     ) -> Self {
+        let (code, exception_table, line_numbers) =
+            if let Some(AttributeType::Code(code)) = attributes.get("Code") {
+                let exception_table = code
+                    .exception_table
+                    .iter()
+                    .map(|e| e.to_opcode_indices(&code.opcode_indices))
+                    .collect();
+                let line_numbers = code
+                    .line_number_table()
+                    .filter_map(|l| l.to_opcode_index(&code.opcode_indices))
+                    .collect();
+                (code.instructions.clone(), exception_table, line_numbers)
+            } else {
+                (vec![], vec![], vec![])
+            };
         Method {
             constant_pool,
             access_flags,
             name_index,
             descriptor_index,
             attributes,
+            index,
             code,
+            exception_table,
+            line_numbers,
         }
     }
 
     pub fn name(&self) -> String {
         let mut full_name = String::new();
-        if let CpEntry::Utf8(s) = &self.constant_pool.get(&self.name_index).unwrap() {
+        if let CpEntry::Utf8(s, _) = &self.constant_pool.get(&self.name_index).unwrap() {
             full_name.push_str(s);
         }
-        if let CpEntry::Utf8(s) = &self.constant_pool.get(&self.descriptor_index).unwrap() {
+        if let CpEntry::Utf8(s, _) = &self.constant_pool.get(&self.descriptor_index).unwrap() {
             full_name.push_str(s);
         }
 
@@ -372,4 +667,60 @@ impl Method {
         let m = modifier as u16;
         (self.access_flags & m) == m
     }
+
+    /// the number of local variable slots, 0 for a method without code
+    pub fn max_locals(&self) -> usize {
+        if let Some(AttributeType::Code(code)) = self.attributes.get("Code") {
+            code.max_locals as usize
+        } else {
+            0
+        }
+    }
+
+    /// the source line for the opcode at index pc, if the class was compiled with line numbers
+    pub fn line_number(&self, pc: usize) -> Option<u16> {
+        self.line_numbers
+            .iter()
+            .filter(|l| l.start_pc as usize <= pc)
+            .max_by_key(|l| l.start_pc)
+            .map(|l| l.line_number)
+    }
+
+    /// the annotations that are visible at runtime
+    pub fn annotations(&self) -> &[Annotation] {
+        runtime_visible_annotations(&self.attributes)
+    }
+
+    /// the annotations of each parameter that are visible at runtime
+    /// there may be fewer entries than parameters, eg. for the implicit parameters of an inner class constructor
+    pub fn parameter_annotations(&self) -> &[Vec<Annotation>] {
+        if let Some(AttributeType::RuntimeVisibleParameterAnnotations(parameters)) =
+            self.attributes.get("RuntimeVisibleParameterAnnotations")
+        {
+            parameters
+        } else {
+            &[]
+        }
+    }
+
+    /// the default value, for an element of an annotation interface
+    pub fn annotation_default(&self) -> Option<&ElementValue> {
+        if let Some(AttributeType::AnnotationDefault(value)) =
+            self.attributes.get("AnnotationDefault")
+        {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+fn runtime_visible_annotations(attributes: &Attributes) -> &[Annotation] {
+    if let Some(AttributeType::RuntimeVisibleAnnotations(annotations)) =
+        attributes.get("RuntimeVisibleAnnotations")
+    {
+        annotations
+    } else {
+        &[]
+    }
 }
@@ -1,52 +1,90 @@
 use log::debug;
 use std::collections::BTreeMap;
 
+use crate::classloader::error::ClassFormatError;
 use crate::classloader::io::{
-    read_i16, read_i32, read_lookupswitch, read_tableswitch, read_u16, read_u8, read_wide_opcode,
+    read_i16, read_i32, read_i8, read_lookupswitch, read_tableswitch, read_u16, read_u8,
+    read_wide_opcode, write_u16, write_u32, write_u8,
 };
 use crate::vm::opcodes::Opcode::{self, *};
 
-pub(crate) fn parse_code(opcodes: &[u8]) -> Vec<Opcode> {
+/// parses the bytecode into opcodes
+/// also returns the mapping from byte offset to opcode index, including an entry for the end of
+/// the code, so that the offsets in exception tables and other attributes can be translated
+/// offsets in errors are relative to the start of the code
+pub(crate) fn parse_code(
+    opcodes: &[u8],
+) -> Result<(Vec<Opcode>, BTreeMap<u16, u16>), ClassFormatError> {
     let mut code: BTreeMap<u16, (u16, Opcode)> = BTreeMap::new();
     let mut c = 0;
     let mut opcode_index: u16 = 0;
     let mut index_before_read: u16;
     while c < opcodes.len() {
         index_before_read = c as u16;
-        let opcode = get_opcode(opcodes, &mut c);
+        let opcode = get_opcode(opcodes, &mut c)?;
         code.insert(index_before_read, (opcode_index, opcode));
         opcode_index += 1;
     }
     let code2 = code.clone(); //clone to look up
+    let mut offsets: BTreeMap<u16, u16> = code2.iter().map(|(k, v)| (*k, v.0)).collect();
+    offsets.insert(opcodes.len() as u16, opcode_index);
 
     // for jumps, map index of opcode as u8 to index of opcode as enum
     debug!("{:?}", code);
-    code.into_iter()
-        .map(|(_, (_, opcode))| match opcode {
-            IFNULL(goto) => IFNULL(code2.get(&goto).unwrap().0),
-            IFNONNULL(goto) => IFNONNULL(code2.get(&goto).unwrap().0),
+    let target = |offset: u16, goto: u16| {
+        code2.get(&goto).map(|(index, _)| *index).ok_or_else(|| {
+            ClassFormatError::new(offset as usize, format!("invalid branch target {}", goto))
+        })
+    };
+    // for the jumps with an offset that is relative to the opcode
+    let relative_target = |offset: u16, jump: i64| {
+        let goto = offset as i64 + jump;
+        u16::try_from(goto)
+            .map_err(|_| {
+                ClassFormatError::new(offset as usize, format!("invalid branch target {}", goto))
+            })
+            .and_then(|goto| target(offset, goto))
+    };
+    let code = code
+        .into_iter()
+        .map(|(offset, (_, opcode))| match opcode {
+            IFNULL(goto) => Ok(IFNULL(target(offset, goto)?)),
+            IFNONNULL(goto) => Ok(IFNONNULL(target(offset, goto)?)),
 
-            IF_ICMPEQ(goto) => IF_ICMPEQ(code2.get(&goto).unwrap().0),
-            IF_ICMPNE(goto) => IF_ICMPNE(code2.get(&goto).unwrap().0),
-            IF_ICMPGT(goto) => IF_ICMPGT(code2.get(&goto).unwrap().0),
-            IF_ICMPGE(goto) => IF_ICMPGE(code2.get(&goto).unwrap().0),
-            IF_ICMPLT(goto) => IF_ICMPLT(code2.get(&goto).unwrap().0),
-            IF_ICMPLE(goto) => IF_ICMPLE(code2.get(&goto).unwrap().0),
-            IFEQ(goto) => IFEQ(code2.get(&goto).unwrap().0),
-            IFNE(goto) => IFNE(code2.get(&goto).unwrap().0),
-            IFGT(goto) => IFGT(code2.get(&goto).unwrap().0),
-            IFGE(goto) => IFGE(code2.get(&goto).unwrap().0),
-            IFLT(goto) => IFLT(code2.get(&goto).unwrap().0),
-            IFLE(goto) => IFLE(code2.get(&goto).unwrap().0),
+            IF_ICMPEQ(goto) => Ok(IF_ICMPEQ(target(offset, goto)?)),
+            IF_ICMPNE(goto) => Ok(IF_ICMPNE(target(offset, goto)?)),
+            IF_ICMPGT(goto) => Ok(IF_ICMPGT(target(offset, goto)?)),
+            IF_ICMPGE(goto) => Ok(IF_ICMPGE(target(offset, goto)?)),
+            IF_ICMPLT(goto) => Ok(IF_ICMPLT(target(offset, goto)?)),
+            IF_ICMPLE(goto) => Ok(IF_ICMPLE(target(offset, goto)?)),
+            IF_ACMPEQ(goto) => Ok(IF_ACMPEQ(target(offset, goto)?)),
+            IF_ACMPNE(goto) => Ok(IF_ACMPNE(target(offset, goto)?)),
 
-            //TODO more jump instructions
-            _ => opcode,
+            GOTO(jump) => Ok(GOTO(relative_target(offset, jump as i16 as i64)?)),
+            GOTOW(jump) => Ok(GOTOW(relative_target(offset, jump as i64)? as i32)),
+            JSR(jump) => Ok(JSR(relative_target(offset, jump as i16 as i64)?)),
+            JSR_W(jump) => Ok(JSR_W(relative_target(offset, jump as i64)? as i32)),
+            TABLESWITCH(tableswitch) => Ok(TABLESWITCH(tableswitch.map_targets(|jump| {
+                relative_target(offset, jump as i64).map(|index| index as i32)
+            })?)),
+            LOOKUPSWITCH(lookupswitch) => Ok(LOOKUPSWITCH(lookupswitch.map_targets(|jump| {
+                relative_target(offset, jump as i64).map(|index| index as i32)
+            })?)),
+            IFEQ(goto) => Ok(IFEQ(target(offset, goto)?)),
+            IFNE(goto) => Ok(IFNE(target(offset, goto)?)),
+            IFGT(goto) => Ok(IFGT(target(offset, goto)?)),
+            IFGE(goto) => Ok(IFGE(target(offset, goto)?)),
+            IFLT(goto) => Ok(IFLT(target(offset, goto)?)),
+            IFLE(goto) => Ok(IFLE(target(offset, goto)?)),
+            _ => Ok(opcode),
         })
-        .collect()
+        .collect::<Result<_, _>>()?;
+    Ok((code, offsets))
 }
 
-fn get_opcode(opcodes: &[u8], c: &mut usize) -> Opcode {
-    let opcode_u8 = read_u8(opcodes, c);
+pub(crate) fn get_opcode(opcodes: &[u8], c: &mut usize) -> Result<Opcode, ClassFormatError> {
+    let opcode_pos = *c;
+    let opcode_u8 = read_u8(opcodes, c)?;
 
     let opcode = match opcode_u8 {
         0 => NOP,
@@ -65,16 +103,16 @@ fn get_opcode(opcodes: &[u8], c: &mut usize) -> Opcode {
         13 => FCONST(2),
         14 => DCONST(0),
         15 => DCONST(1),
-        16 => BIPUSH(read_u8(opcodes, c)),
-        17 => SIPUSH(read_u16(opcodes, c)),
-        18 => LDC(read_u8(opcodes, c) as u16),
-        19 => LDC_W(read_u16(opcodes, c) as u16),
-        20 => LDC2_W(read_u16(opcodes, c)),
-        21 => ILOAD(read_u8(opcodes, c)),
-        22 => LLOAD(read_u8(opcodes, c)),
-        23 => FLOAD(read_u8(opcodes, c)),
-        24 => DLOAD(read_u8(opcodes, c)),
-        25 => ALOAD(read_u8(opcodes, c)),
+        16 => BIPUSH(read_i8(opcodes, c)?),
+        17 => SIPUSH(read_i16(opcodes, c)?),
+        18 => LDC(read_u8(opcodes, c)? as u16),
+        19 => LDC_W(read_u16(opcodes, c)?),
+        20 => LDC2_W(read_u16(opcodes, c)?),
+        21 => ILOAD(read_u8(opcodes, c)?),
+        22 => LLOAD(read_u8(opcodes, c)?),
+        23 => FLOAD(read_u8(opcodes, c)?),
+        24 => DLOAD(read_u8(opcodes, c)?),
+        25 => ALOAD(read_u8(opcodes, c)?),
         26 => ILOAD(0),
         27 => ILOAD(1),
         28 => ILOAD(2),
@@ -103,11 +141,11 @@ fn get_opcode(opcodes: &[u8], c: &mut usize) -> Opcode {
         51 => BALOAD,
         52 => CALOAD,
         53 => SALOAD,
-        54 => ISTORE(read_u8(opcodes, c)),
-        55 => LSTORE(read_u8(opcodes, c)),
-        56 => FSTORE(read_u8(opcodes, c)),
-        57 => DSTORE(read_u8(opcodes, c)),
-        58 => ASTORE(read_u8(opcodes, c)),
+        54 => ISTORE(read_u8(opcodes, c)?),
+        55 => LSTORE(read_u8(opcodes, c)?),
+        56 => FSTORE(read_u8(opcodes, c)?),
+        57 => DSTORE(read_u8(opcodes, c)?),
+        58 => ASTORE(read_u8(opcodes, c)?),
         59 => ISTORE(0),
         60 => ISTORE(1),
         61 => ISTORE(2),
@@ -137,12 +175,14 @@ fn get_opcode(opcodes: &[u8], c: &mut usize) -> Opcode {
         85 => CASTORE,
         86 => SASTORE,
         87 => POP,
+        88 => POP2,
         89 => DUP,
         90 => DUP_X1,
         91 => DUP_X2,
         92 => DUP2,
         93 => DUP2_X1,
         94 => DUP2_X2,
+        95 => SWAP,
         96 => IADD,
         97 => LADD,
         98 => FADD,
@@ -179,7 +219,7 @@ fn get_opcode(opcodes: &[u8], c: &mut usize) -> Opcode {
         129 => LOR,
         130 => IXOR,
         131 => LXOR,
-        132 => IINC(read_u8(opcodes, c), read_u8(opcodes, c)),
+        132 => IINC(read_u8(opcodes, c)?, read_i8(opcodes, c)?),
         133 => I2L,
         134 => I2F,
         135 => I2D,
@@ -200,73 +240,356 @@ fn get_opcode(opcodes: &[u8], c: &mut usize) -> Opcode {
         150 => FCMPG,
         151 => DCMPL,
         152 => DCMPG,
-        153 => IFEQ(offset(opcodes, c)),
-        154 => IFNE(offset(opcodes, c)),
-        155 => IFLT(offset(opcodes, c)),
-        156 => IFGE(offset(opcodes, c)),
-        157 => IFGT(offset(opcodes, c)),
-        158 => IFLE(offset(opcodes, c)),
-        159 => IF_ICMPEQ(offset(opcodes, c)),
-        160 => IF_ICMPNE(offset(opcodes, c)),
-        161 => IF_ICMPLT(offset(opcodes, c)),
-        162 => IF_ICMPGE(offset(opcodes, c)),
-        163 => IF_ICMPGT(offset(opcodes, c)),
-        164 => IF_ICMPLE(offset(opcodes, c)),
-        165 => IF_ACMPEQ(offset(opcodes, c)),
-        166 => IF_ACMPNE(offset(opcodes, c)),
-        167 => GOTO(read_u16(opcodes, c)),
-        168 => JSR(read_u16(opcodes, c)),
-        169 => RET(read_u8(opcodes, c)),
-        170 => TABLESWITCH(read_tableswitch(opcodes, c)),
-        171 => LOOKUPSWITCH(read_lookupswitch(opcodes, c)),
+        153 => IFEQ(offset(opcodes, c)?),
+        154 => IFNE(offset(opcodes, c)?),
+        155 => IFLT(offset(opcodes, c)?),
+        156 => IFGE(offset(opcodes, c)?),
+        157 => IFGT(offset(opcodes, c)?),
+        158 => IFLE(offset(opcodes, c)?),
+        159 => IF_ICMPEQ(offset(opcodes, c)?),
+        160 => IF_ICMPNE(offset(opcodes, c)?),
+        161 => IF_ICMPLT(offset(opcodes, c)?),
+        162 => IF_ICMPGE(offset(opcodes, c)?),
+        163 => IF_ICMPGT(offset(opcodes, c)?),
+        164 => IF_ICMPLE(offset(opcodes, c)?),
+        165 => IF_ACMPEQ(offset(opcodes, c)?),
+        166 => IF_ACMPNE(offset(opcodes, c)?),
+        167 => GOTO(read_u16(opcodes, c)?),
+        168 => JSR(read_u16(opcodes, c)?),
+        169 => RET(read_u8(opcodes, c)?),
+        170 => TABLESWITCH(read_tableswitch(opcodes, c)?),
+        171 => LOOKUPSWITCH(read_lookupswitch(opcodes, c)?),
         172 => IRETURN,
         173 => LRETURN,
         174 => FRETURN,
         175 => DRETURN,
         176 => ARETURN,
         177 => RETURN_VOID,
-        178 => GETSTATIC(read_u16(opcodes, c)),
-        179 => PUTSTATIC(read_u16(opcodes, c)),
-        180 => GETFIELD(read_u16(opcodes, c)),
-        181 => PUTFIELD(read_u16(opcodes, c)),
-        182 => INVOKEVIRTUAL(read_u16(opcodes, c)),
-        183 => INVOKESPECIAL(read_u16(opcodes, c)),
-        184 => INVOKESTATIC(read_u16(opcodes, c)),
+        178 => GETSTATIC(read_u16(opcodes, c)?),
+        179 => PUTSTATIC(read_u16(opcodes, c)?),
+        180 => GETFIELD(read_u16(opcodes, c)?),
+        181 => PUTFIELD(read_u16(opcodes, c)?),
+        182 => INVOKEVIRTUAL(read_u16(opcodes, c)?),
+        183 => INVOKESPECIAL(read_u16(opcodes, c)?),
+        184 => INVOKESTATIC(read_u16(opcodes, c)?),
         185 => {
-            let index = read_u16(opcodes, c);
-            let count = read_u8(opcodes, c);
+            let index = read_u16(opcodes, c)?;
+            let count = read_u8(opcodes, c)?;
             *c += 1;
             INVOKEINTERFACE(index, count)
         }
         186 => {
-            let i = read_u16(opcodes, c);
+            let i = read_u16(opcodes, c)?;
             *c += 2;
             INVOKEDYNAMIC(i)
         }
-        187 => NEW(read_u16(opcodes, c)),
-        188 => NEWARRAY(read_u8(opcodes, c)),
-        189 => ANEWARRAY(read_u16(opcodes, c)),
+        187 => NEW(read_u16(opcodes, c)?),
+        188 => NEWARRAY(read_u8(opcodes, c)?),
+        189 => ANEWARRAY(read_u16(opcodes, c)?),
         190 => ARRAYLENGTH,
         191 => ATHROW,
-        192 => CHECKCAST(read_u16(opcodes, c)),
-        193 => INSTANCEOF(read_u16(opcodes, c)),
+        192 => CHECKCAST(read_u16(opcodes, c)?),
+        193 => INSTANCEOF(read_u16(opcodes, c)?),
         194 => MONITORENTER,
         195 => MONITOREXIT,
-        196 => WIDE(Box::new(read_wide_opcode(opcodes, c))),
-        197 => MULTIANEWARRAY(read_u16(opcodes, c), read_u8(opcodes, c)),
-        198 => IFNULL(offset(opcodes, c)),
-        199 => IFNONNULL(offset(opcodes, c)),
-        200 => GOTOW(read_i32(opcodes, c)),
-        201 => JSR_W(read_i32(opcodes, c)),
+        196 => WIDE(Box::new(read_wide_opcode(opcodes, c)?)),
+        197 => MULTIANEWARRAY(read_u16(opcodes, c)?, read_u8(opcodes, c)?),
+        198 => IFNULL(offset(opcodes, c)?),
+        199 => IFNONNULL(offset(opcodes, c)?),
+        200 => GOTOW(read_i32(opcodes, c)?),
+        201 => JSR_W(read_i32(opcodes, c)?),
 
-        _ => panic!("{}", opcode_u8),
+        _ => {
+            return Err(ClassFormatError::new(
+                opcode_pos,
+                format!("invalid opcode {}", opcode_u8),
+            ))
+        }
     };
     // debug!("{}: {:?}", c, opcode);
-    opcode
+    Ok(opcode)
+}
+
+/// the target of a branch with a 16 bit offset, that is relative to the opcode
+/// computed in i64, because the code can be longer than i16::MAX
+fn offset(opcodes: &[u8], c: &mut usize) -> Result<u16, ClassFormatError> {
+    let opcode_pos = *c - 1;
+    let jump = read_i16(opcodes, c)?;
+    let goto = opcode_pos as i64 + jump as i64;
+    u16::try_from(goto)
+        .map_err(|_| ClassFormatError::new(opcode_pos, format!("invalid branch target {}", goto)))
+}
+
+/// writes the opcode at the end of the code, the reverse of get_opcode
+/// the operands of jumps are written as they are, so they must be byte offsets
+/// relative to the opcode, as in the class file
+pub(crate) fn write_opcode(code: &mut Vec<u8>, opcode: &Opcode) {
+    match opcode {
+        ICONST(value) => write_u8(code, (3 + value) as u8),
+        LCONST(value) => write_u8(code, 9 + value),
+        FCONST(value) => write_u8(code, 11 + value),
+        DCONST(value) => write_u8(code, 14 + value),
+        BIPUSH(value) => write_operand_u8(code, 16, *value as u8),
+        SIPUSH(value) => write_operand_u16(code, 17, *value as u16),
+        LDC(index) => write_operand_u8(code, 18, *index as u8),
+        LDC_W(index) => write_operand_u16(code, 19, *index),
+        LDC2_W(index) => write_operand_u16(code, 20, *index),
+        ILOAD(index) => write_local(code, 21, 26, *index),
+        LLOAD(index) => write_local(code, 22, 30, *index),
+        FLOAD(index) => write_local(code, 23, 34, *index),
+        DLOAD(index) => write_local(code, 24, 38, *index),
+        ALOAD(index) => write_local(code, 25, 42, *index),
+        ISTORE(index) => write_local(code, 54, 59, *index),
+        LSTORE(index) => write_local(code, 55, 63, *index),
+        FSTORE(index) => write_local(code, 56, 67, *index),
+        DSTORE(index) => write_local(code, 57, 71, *index),
+        ASTORE(index) => write_local(code, 58, 75, *index),
+        WIDE_ILOAD(index) => write_wide(code, 21, *index),
+        WIDE_LLOAD(index) => write_wide(code, 22, *index),
+        WIDE_FLOAD(index) => write_wide(code, 23, *index),
+        WIDE_DLOAD(index) => write_wide(code, 24, *index),
+        WIDE_ALOAD(index) => write_wide(code, 25, *index),
+        WIDE_ISTORE(index) => write_wide(code, 54, *index),
+        WIDE_LSTORE(index) => write_wide(code, 55, *index),
+        WIDE_FSTORE(index) => write_wide(code, 56, *index),
+        WIDE_DSTORE(index) => write_wide(code, 57, *index),
+        WIDE_ASTORE(index) => write_wide(code, 58, *index),
+        WIDE_RET(index) => write_wide(code, 169, *index),
+        IINC(index, value) => {
+            write_operand_u8(code, 132, *index);
+            write_u8(code, *value as u8);
+        }
+        WIDE_IINC(index, value) => {
+            write_wide(code, 132, *index);
+            write_u16(code, *value as u16);
+        }
+        IFEQ(offset) => write_operand_u16(code, 153, *offset),
+        IFNE(offset) => write_operand_u16(code, 154, *offset),
+        IFLT(offset) => write_operand_u16(code, 155, *offset),
+        IFGE(offset) => write_operand_u16(code, 156, *offset),
+        IFGT(offset) => write_operand_u16(code, 157, *offset),
+        IFLE(offset) => write_operand_u16(code, 158, *offset),
+        IF_ICMPEQ(offset) => write_operand_u16(code, 159, *offset),
+        IF_ICMPNE(offset) => write_operand_u16(code, 160, *offset),
+        IF_ICMPLT(offset) => write_operand_u16(code, 161, *offset),
+        IF_ICMPGE(offset) => write_operand_u16(code, 162, *offset),
+        IF_ICMPGT(offset) => write_operand_u16(code, 163, *offset),
+        IF_ICMPLE(offset) => write_operand_u16(code, 164, *offset),
+        IF_ACMPEQ(offset) => write_operand_u16(code, 165, *offset),
+        IF_ACMPNE(offset) => write_operand_u16(code, 166, *offset),
+        GOTO(offset) => write_operand_u16(code, 167, *offset),
+        JSR(offset) => write_operand_u16(code, 168, *offset),
+        RET(index) => write_operand_u8(code, 169, *index),
+        TABLESWITCH(tableswitch) => {
+            write_u8(code, 170);
+            tableswitch.write(code);
+        }
+        LOOKUPSWITCH(lookupswitch) => {
+            write_u8(code, 171);
+            lookupswitch.write(code);
+        }
+        GETSTATIC(index) => write_operand_u16(code, 178, *index),
+        PUTSTATIC(index) => write_operand_u16(code, 179, *index),
+        GETFIELD(index) => write_operand_u16(code, 180, *index),
+        PUTFIELD(index) => write_operand_u16(code, 181, *index),
+        INVOKEVIRTUAL(index) => write_operand_u16(code, 182, *index),
+        INVOKESPECIAL(index) => write_operand_u16(code, 183, *index),
+        INVOKESTATIC(index) => write_operand_u16(code, 184, *index),
+        INVOKEINTERFACE(index, count) => {
+            write_operand_u16(code, 185, *index);
+            write_u8(code, *count);
+            write_u8(code, 0);
+        }
+        INVOKEDYNAMIC(index) => {
+            write_operand_u16(code, 186, *index);
+            write_u16(code, 0);
+        }
+        NEW(index) => write_operand_u16(code, 187, *index),
+        NEWARRAY(atype) => write_operand_u8(code, 188, *atype),
+        ANEWARRAY(index) => write_operand_u16(code, 189, *index),
+        CHECKCAST(index) => write_operand_u16(code, 192, *index),
+        INSTANCEOF(index) => write_operand_u16(code, 193, *index),
+        // the wide opcodes write the wide prefix themselves
+        WIDE(opcode) => write_opcode(code, opcode),
+        MULTIANEWARRAY(index, dimensions) => {
+            write_operand_u16(code, 197, *index);
+            write_u8(code, *dimensions);
+        }
+        IFNULL(offset) => write_operand_u16(code, 198, *offset),
+        IFNONNULL(offset) => write_operand_u16(code, 199, *offset),
+        GOTOW(offset) => {
+            write_u8(code, 200);
+            write_u32(code, *offset as u32);
+        }
+        JSR_W(offset) => {
+            write_u8(code, 201);
+            write_u32(code, *offset as u32);
+        }
+        _ => write_u8(code, opcode_without_operands(opcode)),
+    }
+}
+
+fn write_operand_u8(code: &mut Vec<u8>, opcode: u8, operand: u8) {
+    write_u8(code, opcode);
+    write_u8(code, operand);
 }
 
-fn offset(opcodes: &[u8], c: &mut usize) -> u16 {
-    let j = read_i16(opcodes, c);
-    // debug!("JUMP TO {} + {}",c, j);
-    (*c as i16 + j - 3) as u16
+fn write_operand_u16(code: &mut Vec<u8>, opcode: u8, operand: u16) {
+    write_u8(code, opcode);
+    write_u16(code, operand);
+}
+
+/// a load or store, in the short form (eg. iload_0) for the first 4 local variables
+fn write_local(code: &mut Vec<u8>, opcode: u8, short_opcode: u8, index: u8) {
+    if index <= 3 {
+        write_u8(code, short_opcode + index);
+    } else {
+        write_operand_u8(code, opcode, index);
+    }
+}
+
+fn write_wide(code: &mut Vec<u8>, opcode: u8, index: u16) {
+    write_operand_u8(code, 196, opcode);
+    write_u16(code, index);
+}
+
+fn opcode_without_operands(opcode: &Opcode) -> u8 {
+    match opcode {
+        NOP => 0,
+        ACONST_NULL => 1,
+        IALOAD => 46,
+        LALOAD => 47,
+        FALOAD => 48,
+        DALOAD => 49,
+        AALOAD => 50,
+        BALOAD => 51,
+        CALOAD => 52,
+        SALOAD => 53,
+        IASTORE => 79,
+        LASTORE => 80,
+        FASTORE => 81,
+        DASTORE => 82,
+        AASTORE => 83,
+        BASTORE => 84,
+        CASTORE => 85,
+        SASTORE => 86,
+        POP => 87,
+        POP2 => 88,
+        DUP => 89,
+        DUP_X1 => 90,
+        DUP_X2 => 91,
+        DUP2 => 92,
+        DUP2_X1 => 93,
+        DUP2_X2 => 94,
+        SWAP => 95,
+        IADD => 96,
+        LADD => 97,
+        FADD => 98,
+        DADD => 99,
+        ISUB => 100,
+        LSUB => 101,
+        FSUB => 102,
+        DSUB => 103,
+        IMUL => 104,
+        LMUL => 105,
+        FMUL => 106,
+        DMUL => 107,
+        IDIV => 108,
+        LDIV => 109,
+        FDIV => 110,
+        DDIV => 111,
+        IREM => 112,
+        LREM => 113,
+        FREM => 114,
+        DREM => 115,
+        INEG => 116,
+        LNEG => 117,
+        FNEG => 118,
+        DNEG => 119,
+        ISHL => 120,
+        LSHL => 121,
+        ISHR => 122,
+        LSHR => 123,
+        IUSHR => 124,
+        LUSHR => 125,
+        IAND => 126,
+        LAND => 127,
+        IOR => 128,
+        LOR => 129,
+        IXOR => 130,
+        LXOR => 131,
+        I2L => 133,
+        I2F => 134,
+        I2D => 135,
+        L2I => 136,
+        L2F => 137,
+        L2D => 138,
+        F2I => 139,
+        F2L => 140,
+        F2D => 141,
+        D2I => 142,
+        D2L => 143,
+        D2F => 144,
+        I2B => 145,
+        I2C => 146,
+        I2S => 147,
+        LCMP => 148,
+        FCMPL => 149,
+        FCMPG => 150,
+        DCMPL => 151,
+        DCMPG => 152,
+        IRETURN => 172,
+        LRETURN => 173,
+        FRETURN => 174,
+        DRETURN => 175,
+        ARETURN => 176,
+        RETURN_VOID => 177,
+        ARRAYLENGTH => 190,
+        ATHROW => 191,
+        MONITORENTER => 194,
+        MONITOREXIT => 195,
+        _ => unreachable!("{:?} has operands", opcode),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// writes the opcode and parses it again
+    fn round_trip(opcode: Opcode) -> Opcode {
+        let mut code = vec![];
+        write_opcode(&mut code, &opcode);
+        let mut c = 0;
+        let parsed = get_opcode(&code, &mut c).unwrap();
+        assert_eq!(code.len(), c);
+        parsed
+    }
+
+    #[test]
+    fn branches_beyond_32k() {
+        // an ifeq just after offset 32767, back to the nop 10 bytes before it
+        let mut code = vec![0; 32767];
+        code.extend([153, 0xFF, 0xF6, 177]);
+        let (instructions, _) = parse_code(&code).unwrap();
+        assert!(matches!(instructions[32767], IFEQ(32757)));
+
+        code[32768..32770].copy_from_slice(&i16::MIN.to_be_bytes());
+        assert!(parse_code(&code).is_err());
+    }
+
+    #[test]
+    fn signed_immediates() {
+        for value in i8::MIN..=i8::MAX {
+            assert!(matches!(round_trip(BIPUSH(value)), BIPUSH(v) if v == value));
+            assert!(matches!(round_trip(IINC(7, value)), IINC(7, v) if v == value));
+        }
+        for value in i16::MIN..=i16::MAX {
+            assert!(matches!(round_trip(SIPUSH(value)), SIPUSH(v) if v == value));
+            let WIDE(wide) = round_trip(WIDE_IINC(300, value)) else {
+                panic!("not wide")
+            };
+            assert!(matches!(*wide, WIDE_IINC(300, v) if v == value));
+        }
+    }
 }
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::classloader::classdef::{AttributeType, ClassDef, CpEntry, Modifier, MODIFIERS};
+use crate::vm::opcodes::Opcode::{self, *};
+
+// Prints a class like javap -c -v does, but as this vm sees it: the constant pool entries and
+// the opcodes are printed as CpEntry and Opcode values. The code is printed after parsing, with
+// the opcode index in front of the byte offset, and the exception table and line numbers refer
+// to opcode indices, as they are used by the interpreter.
+
+const CLASS_MODIFIERS: [Modifier; 4] = [
+    Modifier::Public,
+    Modifier::Final,
+    Modifier::Abstract,
+    Modifier::Synthetic,
+];
+const FIELD_MODIFIERS: [Modifier; 8] = [
+    Modifier::Public,
+    Modifier::Private,
+    Modifier::Protected,
+    Modifier::Static,
+    Modifier::Final,
+    Modifier::Volatile,
+    Modifier::Transient,
+    Modifier::Synthetic,
+];
+const METHOD_MODIFIERS: [Modifier; 10] = [
+    Modifier::Public,
+    Modifier::Private,
+    Modifier::Protected,
+    Modifier::Static,
+    Modifier::Final,
+    Modifier::Synchronized,
+    Modifier::Native,
+    Modifier::Abstract,
+    Modifier::Strict,
+    Modifier::Synthetic,
+];
+
+/// the class as text
+pub fn disassemble(classdef: &ClassDef) -> String {
+    let mut out = String::new();
+    // writing to a String does not fail
+    write_class(&mut out, classdef).unwrap();
+    out
+}
+
+fn write_class(out: &mut String, classdef: &ClassDef) -> std::fmt::Result {
+    let cp = &classdef.constant_pool;
+    let kind = if classdef.is_interface() {
+        "interface"
+    } else {
+        "class"
+    };
+    write!(
+        out,
+        "{}{} {}",
+        prefix(&modifiers(classdef.access_flags, &CLASS_MODIFIERS)),
+        kind,
+        classdef.name()
+    )?;
+    if let Some(super_class) = &classdef.super_class {
+        write!(out, " extends {}", classdef.cp_class_name(super_class))?;
+    }
+    if !classdef.interfaces.is_empty() {
+        let interfaces: Vec<&str> = classdef
+            .interfaces
+            .iter()
+            .map(|interface| classdef.cp_class_name(interface).as_str())
+            .collect();
+        write!(out, " implements {}", interfaces.join(", "))?;
+    }
+    writeln!(out)?;
+    writeln!(out, "  minor version: {}", classdef.minor_version)?;
+    writeln!(out, "  major version: {}", classdef.major_version)?;
+    writeln!(out, "  flags: {:#06x}", classdef.access_flags)?;
+    if let Some(source_file) = classdef.source_file() {
+        writeln!(out, "  source file: {}", source_file)?;
+    }
+
+    writeln!(out, "Constant pool:")?;
+    let mut indices: Vec<&u16> = cp.keys().collect();
+    indices.sort();
+    for index in indices {
+        let entry = &cp[index];
+        let resolved = match entry {
+            CpEntry::Utf8(..) => String::new(),
+            _ => format!(" // {}", constant(cp, *index)),
+        };
+        writeln!(
+            out,
+            "{:>6} = {:?}{}",
+            format!("#{}", index),
+            entry,
+            resolved
+        )?;
+    }
+    writeln!(out, "{{")?;
+
+    let mut fields: Vec<_> = classdef.fields.values().collect();
+    fields.sort_by_key(|field| field.index);
+    for field in fields {
+        let flags = field.access_flags();
+        writeln!(
+            out,
+            "  {}{} {}",
+            prefix(&modifiers(flags, &FIELD_MODIFIERS)),
+            field.name(),
+            field.type_of()
+        )?;
+        writeln!(out, "    flags: {:#06x}", flags)?;
+        if let Some(AttributeType::ConstantValue(value)) = field.attributes.get("ConstantValue") {
+            writeln!(out, "    ConstantValue: {}", constant(cp, *value))?;
+        }
+        writeln!(out)?;
+    }
+
+    let mut methods: Vec<_> = classdef.methods.values().collect();
+    methods.sort_by_key(|method| method.index);
+    for method in methods {
+        writeln!(
+            out,
+            "  {}{}",
+            prefix(&modifiers(method.access_flags, &METHOD_MODIFIERS)),
+            method.name()
+        )?;
+        writeln!(out, "    flags: {:#06x}", method.access_flags)?;
+        if let Some(AttributeType::Code(code)) = method.attributes.get("Code") {
+            writeln!(
+                out,
+                "    Code: max_stack={}, max_locals={}",
+                code.max_stack, code.max_locals
+            )?;
+            writeln!(out, "       index offset  opcode")?;
+            // opcode index -> byte offset
+            let offsets: HashMap<u16, u16> = code
+                .opcode_indices
+                .iter()
+                .map(|(offset, index)| (*index, *offset))
+                .collect();
+            for (index, opcode) in method.code.iter().enumerate() {
+                let resolved = cp_operand(opcode)
+                    .map(|operand| format!(" // {}", constant(cp, operand)))
+                    .unwrap_or_default();
+                writeln!(
+                    out,
+                    "      {:>6} {:>6}  {:?}{}",
+                    index,
+                    offsets[&(index as u16)],
+                    opcode,
+                    resolved
+                )?;
+            }
+            if !method.exception_table.is_empty() {
+                writeln!(out, "    Exception table:")?;
+                writeln!(out, "        from     to target type")?;
+                for exception in &method.exception_table {
+                    let catch_type = if exception.catch_type == 0 {
+                        "any"
+                    } else {
+                        classdef.cp_class_name(&exception.catch_type)
+                    };
+                    writeln!(
+                        out,
+                        "      {:>6} {:>6} {:>6} {}",
+                        exception.start_pc, exception.end_pc, exception.handler_pc, catch_type
+                    )?;
+                }
+            }
+            if !method.line_numbers.is_empty() {
+                writeln!(out, "    Line numbers:")?;
+                for line_number in &method.line_numbers {
+                    writeln!(
+                        out,
+                        "      line {}: {}",
+                        line_number.line_number, line_number.start_pc
+                    )?;
+                }
+            }
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "}}")
+}
+
+fn modifiers(access_flags: u16, applicable: &[Modifier]) -> String {
+    MODIFIERS
+        .iter()
+        .filter(|(modifier, _)| {
+            applicable.contains(modifier) && access_flags & *modifier as u16 != 0
+        })
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// the modifiers followed by a space, if there are any
+fn prefix(modifiers: &str) -> String {
+    if modifiers.is_empty() {
+        String::new()
+    } else {
+        format!("{} ", modifiers)
+    }
+}
+
+/// the constant pool index in the operands of the opcode
+fn cp_operand(opcode: &Opcode) -> Option<u16> {
+    match opcode {
+        LDC(index)
+        | LDC_W(index)
+        | LDC2_W(index)
+        | GETSTATIC(index)
+        | PUTSTATIC(index)
+        | GETFIELD(index)
+        | PUTFIELD(index)
+        | INVOKEVIRTUAL(index)
+        | INVOKESPECIAL(index)
+        | INVOKESTATIC(index)
+        | INVOKEINTERFACE(index, _)
+        | INVOKEDYNAMIC(index)
+        | NEW(index)
+        | ANEWARRAY(index)
+        | CHECKCAST(index)
+        | INSTANCEOF(index)
+        | MULTIANEWARRAY(index, _) => Some(*index),
+        _ => None,
+    }
+}
+
+/// the constant, with the entries that it refers to resolved
+fn constant(cp: &HashMap<u16, CpEntry>, index: u16) -> String {
+    let Some(entry) = cp.get(&index) else {
+        return format!("invalid constant pool index #{}", index);
+    };
+    match entry {
+        CpEntry::Utf8(string, _) => string.clone(),
+        CpEntry::Integer(value) => value.to_string(),
+        CpEntry::Float(value) => format!("{}f", value),
+        CpEntry::Long(value) => format!("{}l", value),
+        CpEntry::Double(value) => format!("{}d", value),
+        CpEntry::ClassRef(name_index)
+        | CpEntry::MethodType(name_index)
+        | CpEntry::Module(name_index)
+        | CpEntry::Package(name_index) => constant(cp, *name_index),
+        CpEntry::StringRef(utf8_index) => format!("{:?}", constant(cp, *utf8_index)),
+        CpEntry::Fieldref(class_index, name_and_type_index)
+        | CpEntry::MethodRef(class_index, name_and_type_index)
+        | CpEntry::InterfaceMethodref(class_index, name_and_type_index) => format!(
+            "{}.{}",
+            constant(cp, *class_index),
+            constant(cp, *name_and_type_index)
+        ),
+        CpEntry::NameAndType(name_index, descriptor_index) => format!(
+            "{}:{}",
+            constant(cp, *name_index),
+            constant(cp, *descriptor_index)
+        ),
+        CpEntry::MethodHandle(reference_kind, reference_index) => {
+            format!("{} {}", reference_kind, constant(cp, *reference_index))
+        }
+        CpEntry::Dynamic(bootstrap_method, name_and_type_index)
+        | CpEntry::InvokeDynamic(bootstrap_method, name_and_type_index) => format!(
+            "bootstrap method {} {}",
+            bootstrap_method,
+            constant(cp, *name_and_type_index)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::classloader::load_class;
+
+    #[test]
+    fn disassemble_class() {
+        let bytecode = include_bytes!("../../tests/testclasses/IfCmp.class");
+        let text = disassemble(&load_class(bytecode.to_vec()).unwrap());
+        assert!(text.starts_with("public class testclasses/IfCmp extends java/lang/Object\n"));
+        assert!(text.contains("  private static i I\n"));
+        assert!(text.contains("  public static i_is_1()Z\n"));
+        assert!(text.contains("GETSTATIC(7) // testclasses/IfCmp.i:I\n"));
+        assert!(text.contains("source file: IfCmp.java\n"));
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt::{Display, Formatter};
+
+/// A class file that does not conform to the class file format.
+/// The vm throws it into the running java code as a java.lang.ClassFormatError,
+/// or as a java.lang.UnsupportedClassVersionError when the version check failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassFormatError {
+    /// byte offset in the class file where the problem was found
+    pub offset: usize,
+    /// the structure that was being parsed, innermost first,
+    /// eg. "attribute Code of method main([Ljava/lang/String;)V of class Main"
+    pub structure: String,
+    pub message: String,
+    /// the check that failed
+    pub check: FormatCheck,
+}
+
+/// The checks that a class file has to pass before it is loaded, JVMS 4.8
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatCheck {
+    /// the class file can be parsed: the items are complete and there are no extra bytes
+    Structure,
+    /// the vm supports the major and minor version
+    Version,
+    /// the constant pool indices refer to entries of the right kind, JVMS 4.4
+    ConstantPool,
+    /// the access flags of the class, the fields and the methods are a valid combination
+    AccessFlags,
+    /// class, field and method names are well-formed, JVMS 4.2
+    Name,
+    /// field and method descriptors are well-formed, JVMS 4.3
+    Descriptor,
+    /// fields and methods are unique, and only methods that are not abstract or native have code
+    Members,
+}
+
+impl Display for FormatCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FormatCheck::Structure => "structure",
+            FormatCheck::Version => "version",
+            FormatCheck::ConstantPool => "constant pool",
+            FormatCheck::AccessFlags => "access flags",
+            FormatCheck::Name => "name",
+            FormatCheck::Descriptor => "descriptor",
+            FormatCheck::Members => "members",
+        })
+    }
+}
+
+impl ClassFormatError {
+    pub(crate) fn new(offset: usize, message: impl Into<String>) -> Self {
+        Self::failed(FormatCheck::Structure, offset, message)
+    }
+
+    /// a class file that can be parsed, but does not pass the check
+    pub(crate) fn failed(check: FormatCheck, offset: usize, message: impl Into<String>) -> Self {
+        Self {
+            offset,
+            structure: String::new(),
+            message: message.into(),
+            check,
+        }
+    }
+
+    /// the data ends before the item at the offset is complete
+    pub(crate) fn truncated(offset: usize) -> Self {
+        Self::new(offset, "truncated class file")
+    }
+
+    /// adds the structure that encloses the one that was being parsed
+    pub(crate) fn within(mut self, structure: impl Display) -> Self {
+        self.structure = if self.structure.is_empty() {
+            structure.to_string()
+        } else {
+            format!("{} of {}", self.structure, structure)
+        };
+        self
+    }
+
+    /// for errors found in a part of the class file that was parsed on its own, like the code
+    /// of a method, makes the offset relative to the class file
+    pub(crate) fn offset_by(mut self, start: usize) -> Self {
+        self.offset += start;
+        self
+    }
+}
+
+impl Display for ClassFormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.structure.is_empty() {
+            write!(f, "{}, at offset {}", self.message, self.offset)?;
+        } else {
+            write!(
+                f,
+                "{} in {}, at offset {}",
+                self.message, self.structure, self.offset
+            )?;
+        }
+        write!(f, " (failed check: {})", self.check)
+    }
+}
+
+impl std::error::Error for ClassFormatError {}
+
+/// Code that does not pass the type checking verifier (JVMS 4.10.1).
+/// The vm throws it into the running java code as a java.lang.VerifyError.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub class_name: String,
+    /// name and descriptor of the method, eg. "main([Ljava/lang/String;)V"
+    pub method: String,
+    /// byte offset in the code of the method, of the instruction that was being checked
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in method {} of class {}, at offset {}",
+            self.message, self.method, self.class_name, self.offset
+        )
+    }
+}
+
+impl std::error::Error for VerifyError {}
@@ -0,0 +1,714 @@
+use std::collections::HashMap;
+
+use crate::classloader::classdef::{AttributeType, Attributes, CpEntry};
+use crate::classloader::error::{ClassFormatError, FormatCheck};
+
+// The format checks of JVMS 4.8, for a class file that could be parsed. They are done while
+// loading, so that a malformed class file is rejected before anything in the vm relies on it.
+// The rules for old class file versions are as lenient as the ones in hotspot.
+
+/// the newest class file version that the vm supports, the one of Java 21
+pub const MAX_MAJOR_VERSION: u16 = 65;
+/// the oldest class file version, the one of JDK 1.0.2
+pub const MIN_MAJOR_VERSION: u16 = 45;
+/// the minor version of classes that use preview features
+const PREVIEW_MINOR_VERSION: u16 = 0xFFFF;
+
+const ACC_PUBLIC: u16 = 0x0001;
+const ACC_PRIVATE: u16 = 0x0002;
+const ACC_PROTECTED: u16 = 0x0004;
+const ACC_STATIC: u16 = 0x0008;
+const ACC_FINAL: u16 = 0x0010;
+const ACC_SUPER: u16 = 0x0020;
+const ACC_SYNCHRONIZED: u16 = 0x0020;
+const ACC_VOLATILE: u16 = 0x0040;
+const ACC_BRIDGE: u16 = 0x0040;
+const ACC_TRANSIENT: u16 = 0x0080;
+const ACC_NATIVE: u16 = 0x0100;
+const ACC_INTERFACE: u16 = 0x0200;
+const ACC_ABSTRACT: u16 = 0x0400;
+const ACC_STRICT: u16 = 0x0800;
+const ACC_ANNOTATION: u16 = 0x2000;
+const ACC_ENUM: u16 = 0x4000;
+const ACC_MODULE: u16 = 0x8000;
+
+/// the vm loads the versions from 45.0 up to MAX_MAJOR_VERSION.0,
+/// minor versions are only used by classes before Java 12
+pub(crate) fn check_version(
+    minor_version: u16,
+    major_version: u16,
+) -> Result<(), ClassFormatError> {
+    let version = format!("class file version {}.{}", major_version, minor_version);
+    if major_version > MAX_MAJOR_VERSION {
+        return Err(ClassFormatError::failed(
+            FormatCheck::Version,
+            6,
+            format!(
+                "compiled by a more recent version of the Java Runtime ({}), \
+                 this vm only recognizes class file versions up to {}.0",
+                version, MAX_MAJOR_VERSION
+            ),
+        ));
+    }
+    if major_version < MIN_MAJOR_VERSION {
+        return Err(ClassFormatError::failed(
+            FormatCheck::Version,
+            6,
+            format!("unsupported {}", version),
+        ));
+    }
+    if major_version >= 56 && minor_version == PREVIEW_MINOR_VERSION {
+        return Err(ClassFormatError::failed(
+            FormatCheck::Version,
+            4,
+            format!("preview features are not supported ({})", version),
+        ));
+    }
+    if major_version >= 56 && minor_version != 0 {
+        return Err(ClassFormatError::failed(
+            FormatCheck::Version,
+            4,
+            format!("invalid non-zero minor version ({})", version),
+        ));
+    }
+    Ok(())
+}
+
+/// checks the parts of a class file against each other, once they are parsed
+pub(crate) struct FormatChecker<'a> {
+    constant_pool: &'a HashMap<u16, CpEntry>,
+    major_version: u16,
+    /// the access flags of the class
+    access_flags: u16,
+}
+
+impl<'a> FormatChecker<'a> {
+    pub(crate) fn new(
+        constant_pool: &'a HashMap<u16, CpEntry>,
+        major_version: u16,
+        access_flags: u16,
+    ) -> Self {
+        Self {
+            constant_pool,
+            major_version,
+            access_flags,
+        }
+    }
+
+    fn is_interface(&self) -> bool {
+        self.access_flags & ACC_INTERFACE != 0
+    }
+
+    fn is_module(&self) -> bool {
+        self.access_flags & ACC_MODULE != 0
+    }
+
+    /// every entry refers to entries of the right kind, with well-formed names and descriptors,
+    /// JVMS 4.4. The entries are given with their byte offset in the class file.
+    pub(crate) fn check_constant_pool(
+        &self,
+        entries: &[(u16, usize)],
+    ) -> Result<(), ClassFormatError> {
+        for (index, offset) in entries {
+            self.check_entry(&self.constant_pool[index], *offset)
+                .map_err(|error| error.within(format!("constant pool entry #{}", index)))?;
+        }
+        Ok(())
+    }
+
+    fn check_entry(&self, entry: &CpEntry, offset: usize) -> Result<(), ClassFormatError> {
+        // the first operand follows the tag, the second one comes after a u16 operand
+        let (first, second) = (offset + 1, offset + 3);
+        let since = |major_version: u16| {
+            if self.major_version < major_version {
+                Err(ClassFormatError::failed(
+                    FormatCheck::ConstantPool,
+                    offset,
+                    format!(
+                        "{} constants are not allowed before class file version {}",
+                        entry_kind(entry),
+                        major_version
+                    ),
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        match entry {
+            CpEntry::Utf8(..)
+            | CpEntry::Integer(_)
+            | CpEntry::Float(_)
+            | CpEntry::Long(_)
+            | CpEntry::Double(_) => {}
+            CpEntry::ClassRef(name_index) => {
+                let name = self.utf8(*name_index, first)?;
+                let valid = if name.starts_with('[') {
+                    is_field_descriptor(name)
+                } else {
+                    is_class_name(name)
+                };
+                if !valid {
+                    return Err(ClassFormatError::failed(
+                        FormatCheck::Name,
+                        first,
+                        format!("illegal class name \"{}\"", name),
+                    ));
+                }
+            }
+            CpEntry::StringRef(utf8_index) => {
+                self.utf8(*utf8_index, first)?;
+            }
+            CpEntry::Fieldref(class_index, name_and_type_index) => {
+                self.class_ref(*class_index, first)?;
+                let (name, descriptor) = self.name_and_type(*name_and_type_index, second)?;
+                check_field_name(name, second)?;
+                check_field_descriptor(descriptor, second)?;
+            }
+            CpEntry::MethodRef(class_index, name_and_type_index)
+            | CpEntry::InterfaceMethodref(class_index, name_and_type_index) => {
+                self.class_ref(*class_index, first)?;
+                let (name, descriptor) = self.name_and_type(*name_and_type_index, second)?;
+                // a class initializer cannot be invoked
+                if name != "<init>" {
+                    check_method_name(name, second)?;
+                }
+                check_method_descriptor(descriptor, second)?;
+                if name == "<init>" && !descriptor.ends_with(")V") {
+                    return Err(ClassFormatError::failed(
+                        FormatCheck::Descriptor,
+                        second,
+                        format!("<init> must return void, not \"{}\"", descriptor),
+                    ));
+                }
+            }
+            CpEntry::NameAndType(name_index, descriptor_index) => {
+                self.utf8(*name_index, first)?;
+                self.utf8(*descriptor_index, second)?;
+            }
+            CpEntry::MethodHandle(reference_kind, reference_index) => {
+                since(51)?;
+                self.check_method_handle(*reference_kind, *reference_index, offset)?;
+            }
+            CpEntry::MethodType(descriptor_index) => {
+                since(51)?;
+                check_method_descriptor(self.utf8(*descriptor_index, first)?, first)?;
+            }
+            CpEntry::Dynamic(_, name_and_type_index) => {
+                since(55)?;
+                let (name, descriptor) = self.name_and_type(*name_and_type_index, second)?;
+                check_field_name(name, second)?;
+                check_field_descriptor(descriptor, second)?;
+            }
+            CpEntry::InvokeDynamic(_, name_and_type_index) => {
+                since(51)?;
+                let (name, descriptor) = self.name_and_type(*name_and_type_index, second)?;
+                check_method_name(name, second)?;
+                check_method_descriptor(descriptor, second)?;
+            }
+            CpEntry::Module(name_index) | CpEntry::Package(name_index) => {
+                since(53)?;
+                if !self.is_module() {
+                    return Err(ClassFormatError::failed(
+                        FormatCheck::ConstantPool,
+                        offset,
+                        format!(
+                            "{} constants are only allowed in a module",
+                            entry_kind(entry)
+                        ),
+                    ));
+                }
+                self.utf8(*name_index, first)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// the reference kind determines what the handle can refer to, JVMS 4.4.8
+    fn check_method_handle(
+        &self,
+        reference_kind: u8,
+        reference_index: u16,
+        offset: usize,
+    ) -> Result<(), ClassFormatError> {
+        let reference = self.constant_pool.get(&reference_index);
+        let valid = match reference_kind {
+            // getField, getStatic, putField, putStatic
+            1..=4 => matches!(reference, Some(CpEntry::Fieldref(..))),
+            // invokeVirtual, newInvokeSpecial
+            5 | 8 => matches!(reference, Some(CpEntry::MethodRef(..))),
+            // invokeStatic, invokeSpecial
+            6 | 7 => {
+                matches!(reference, Some(CpEntry::MethodRef(..)))
+                    || (self.major_version >= 52
+                        && matches!(reference, Some(CpEntry::InterfaceMethodref(..))))
+            }
+            // invokeInterface
+            9 => matches!(reference, Some(CpEntry::InterfaceMethodref(..))),
+            _ => {
+                return Err(ClassFormatError::failed(
+                    FormatCheck::ConstantPool,
+                    offset + 1,
+                    format!("invalid method handle reference kind {}", reference_kind),
+                ))
+            }
+        };
+        if !valid {
+            return Err(ClassFormatError::failed(
+                FormatCheck::ConstantPool,
+                offset + 2,
+                format!(
+                    "constant pool index {} is not a valid reference for method handle kind {}",
+                    reference_index, reference_kind
+                ),
+            ));
+        }
+        if let Some(
+            CpEntry::MethodRef(_, name_and_type_index)
+            | CpEntry::InterfaceMethodref(_, name_and_type_index),
+        ) = reference
+        {
+            let (name, _) = self.name_and_type(*name_and_type_index, offset + 2)?;
+            if (reference_kind == 8) != (name == "<init>") {
+                return Err(ClassFormatError::failed(
+                    FormatCheck::ConstantPool,
+                    offset + 2,
+                    format!(
+                        "method handle kind {} cannot refer to method {}",
+                        reference_kind, name
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// every Dynamic and InvokeDynamic constant refers to an entry in the BootstrapMethods
+    /// attribute, JVMS 4.7.23
+    pub(crate) fn check_bootstrap_method_indices(
+        &self,
+        entries: &[(u16, usize)],
+        attributes: &Attributes,
+    ) -> Result<(), ClassFormatError> {
+        let count = match attributes.get("BootstrapMethods") {
+            Some(AttributeType::BootstrapMethods(methods)) => methods.len(),
+            _ => 0,
+        };
+        for (index, offset) in entries {
+            if let CpEntry::Dynamic(bootstrap_method, _)
+            | CpEntry::InvokeDynamic(bootstrap_method, _) = &self.constant_pool[index]
+            {
+                if *bootstrap_method as usize >= count {
+                    return Err(ClassFormatError::failed(
+                        FormatCheck::ConstantPool,
+                        offset + 1,
+                        format!(
+                            "bootstrap method {} is not in the BootstrapMethods attribute",
+                            bootstrap_method
+                        ),
+                    )
+                    .within(format!("constant pool entry #{}", index)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// the access flags, this class, the super class and the interfaces, JVMS 4.1.
+    /// The offset is the one of the access flags, the class indices follow them.
+    pub(crate) fn check_class(
+        &self,
+        offset: usize,
+        this_class: u16,
+        super_class: Option<u16>,
+        interfaces: &[u16],
+    ) -> Result<(), ClassFormatError> {
+        let flags = self.access_flags;
+        let has = |flag: u16| flags & flag != 0;
+        let this_name = self.class_ref(this_class, offset + 2)?;
+        if has(ACC_MODULE) {
+            // module-info has no super class, interfaces, fields or methods
+            if flags != ACC_MODULE || self.major_version < 53 {
+                return Err(invalid_flags("class", flags, offset));
+            }
+            return Ok(());
+        }
+
+        // before Java 6 the compilers did not always set abstract on interfaces
+        let is_abstract = has(ACC_ABSTRACT) || (has(ACC_INTERFACE) && self.major_version < 50);
+        let since_java_5 = self.major_version >= 49;
+        let illegal = if has(ACC_INTERFACE) {
+            !is_abstract || has(ACC_FINAL) || (since_java_5 && (has(ACC_SUPER) || has(ACC_ENUM)))
+        } else {
+            (since_java_5 && has(ACC_ANNOTATION)) || (has(ACC_FINAL) && is_abstract)
+        };
+        if illegal {
+            return Err(invalid_flags("class", flags, offset));
+        }
+        if this_name.starts_with('[') {
+            return Err(ClassFormatError::failed(
+                FormatCheck::Name,
+                offset + 2,
+                format!("this class cannot be the array class {}", this_name),
+            ));
+        }
+
+        match super_class {
+            Some(super_class) => {
+                let super_name = self.class_ref(super_class, offset + 4)?;
+                if super_name.starts_with('[') {
+                    return Err(ClassFormatError::failed(
+                        FormatCheck::Name,
+                        offset + 4,
+                        format!("super class cannot be the array class {}", super_name),
+                    ));
+                }
+                if self.is_interface() && super_name != "java/lang/Object" {
+                    return Err(ClassFormatError::failed(
+                        FormatCheck::ConstantPool,
+                        offset + 4,
+                        format!(
+                            "interfaces must have java/lang/Object as super class, not {}",
+                            super_name
+                        ),
+                    ));
+                }
+            }
+            None if this_name != "java/lang/Object" => {
+                return Err(ClassFormatError::failed(
+                    FormatCheck::ConstantPool,
+                    offset + 4,
+                    "only java/lang/Object has no super class",
+                ));
+            }
+            None => {}
+        }
+
+        for (i, interface) in interfaces.iter().enumerate() {
+            let interface_offset = offset + 8 + 2 * i;
+            let name = self.class_ref(*interface, interface_offset)?;
+            if name.starts_with('[') {
+                return Err(ClassFormatError::failed(
+                    FormatCheck::Name,
+                    interface_offset,
+                    format!("interface cannot be the array class {}", name),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// the access flags, name and descriptor of a field, JVMS 4.5.
+    /// The offset is the one of the field in the class file.
+    pub(crate) fn check_field(
+        &self,
+        offset: usize,
+        access_flags: u16,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<(), ClassFormatError> {
+        let has = |flag: u16| access_flags & flag != 0;
+        let illegal = if self.is_interface() {
+            !has(ACC_PUBLIC)
+                || !has(ACC_STATIC)
+                || !has(ACC_FINAL)
+                || has(ACC_PRIVATE)
+                || has(ACC_PROTECTED)
+                || has(ACC_VOLATILE)
+                || has(ACC_TRANSIENT)
+                || (self.major_version >= 49 && has(ACC_ENUM))
+        } else {
+            !at_most_one_visibility(access_flags) || (has(ACC_FINAL) && has(ACC_VOLATILE))
+        };
+        if illegal {
+            return Err(invalid_flags("field", access_flags, offset));
+        }
+        check_field_name(name, offset + 2)?;
+        check_field_descriptor(descriptor, offset + 4)
+    }
+
+    /// the access flags, name and descriptor of a method, and whether it has code,
+    /// JVMS 4.6 and 4.7.3. The offset is the one of the method in the class file.
+    pub(crate) fn check_method(
+        &self,
+        offset: usize,
+        access_flags: u16,
+        name: &str,
+        descriptor: &str,
+        has_code: bool,
+    ) -> Result<(), ClassFormatError> {
+        let has = |flag: u16| access_flags & flag != 0;
+        let is_initializer = name == "<init>";
+        let is_class_initializer = name == "<clinit>";
+        if !is_initializer && !is_class_initializer {
+            check_method_name(name, offset + 2)?;
+        }
+
+        // other flags of a class initializer are ignored
+        let illegal = if is_class_initializer {
+            self.major_version >= 51 && !has(ACC_STATIC)
+        } else if self.is_interface() {
+            if self.major_version >= 52 {
+                // interfaces have default, static and private methods since Java 8
+                is_initializer
+                    || has(ACC_PUBLIC) == has(ACC_PRIVATE)
+                    || has(ACC_PROTECTED)
+                    || has(ACC_FINAL)
+                    || has(ACC_SYNCHRONIZED)
+                    || has(ACC_NATIVE)
+                    || (has(ACC_ABSTRACT)
+                        && (has(ACC_PRIVATE) || has(ACC_STATIC) || self.has_strict(access_flags)))
+            } else {
+                is_initializer
+                    || !has(ACC_PUBLIC)
+                    || !has(ACC_ABSTRACT)
+                    || has(ACC_STATIC)
+                    || has(ACC_FINAL)
+                    || has(ACC_NATIVE)
+                    || (self.major_version >= 49
+                        && (has(ACC_PRIVATE)
+                            || has(ACC_PROTECTED)
+                            || has(ACC_SYNCHRONIZED)
+                            || has(ACC_STRICT)))
+            }
+        } else if is_initializer {
+            !at_most_one_visibility(access_flags)
+                || has(ACC_STATIC)
+                || has(ACC_FINAL)
+                || has(ACC_SYNCHRONIZED)
+                || has(ACC_NATIVE)
+                || has(ACC_ABSTRACT)
+                || (self.major_version >= 49 && has(ACC_BRIDGE))
+        } else {
+            !at_most_one_visibility(access_flags)
+                || (has(ACC_ABSTRACT)
+                    && (has(ACC_FINAL)
+                        || has(ACC_NATIVE)
+                        || has(ACC_PRIVATE)
+                        || has(ACC_STATIC)
+                        || (self.major_version >= 49
+                            && (has(ACC_SYNCHRONIZED) || self.has_strict(access_flags)))))
+        };
+        if illegal {
+            return Err(invalid_flags("method", access_flags, offset));
+        }
+
+        let parameter_slots = check_method_descriptor(descriptor, offset + 4)?;
+        let this_slot = if has(ACC_STATIC) { 0 } else { 1 };
+        if parameter_slots + this_slot > 255 {
+            return Err(ClassFormatError::failed(
+                FormatCheck::Descriptor,
+                offset + 4,
+                format!(
+                    "too many parameters in method descriptor \"{}\"",
+                    descriptor
+                ),
+            ));
+        }
+        if (is_initializer || is_class_initializer) && !descriptor.ends_with(")V") {
+            return Err(ClassFormatError::failed(
+                FormatCheck::Descriptor,
+                offset + 4,
+                format!("{} must return void, not \"{}\"", name, descriptor),
+            ));
+        }
+
+        let needs_code = !has(ACC_ABSTRACT) && !has(ACC_NATIVE);
+        if has_code != needs_code {
+            let message = if has_code {
+                "abstract and native methods cannot have a Code attribute"
+            } else {
+                "missing Code attribute in a method that is not abstract or native"
+            };
+            return Err(ClassFormatError::failed(
+                FormatCheck::Members,
+                offset,
+                message,
+            ));
+        }
+        Ok(())
+    }
+
+    /// strictfp means something from Java 1.2 up to Java 17, which made it the default
+    fn has_strict(&self, access_flags: u16) -> bool {
+        access_flags & ACC_STRICT != 0 && (46..=60).contains(&self.major_version)
+    }
+
+    /// the utf8 constant at the index
+    fn utf8(&self, index: u16, offset: usize) -> Result<&'a str, ClassFormatError> {
+        match self.constant_pool.get(&index) {
+            Some(CpEntry::Utf8(string, _)) => Ok(string),
+            _ => Err(not_a(index, "utf8", offset)),
+        }
+    }
+
+    /// the class name of the class constant at the index
+    fn class_ref(&self, index: u16, offset: usize) -> Result<&'a str, ClassFormatError> {
+        match self.constant_pool.get(&index) {
+            Some(CpEntry::ClassRef(name_index)) => self.utf8(*name_index, offset),
+            _ => Err(not_a(index, "class", offset)),
+        }
+    }
+
+    /// the name and the descriptor of the name and type constant at the index
+    fn name_and_type(
+        &self,
+        index: u16,
+        offset: usize,
+    ) -> Result<(&'a str, &'a str), ClassFormatError> {
+        match self.constant_pool.get(&index) {
+            Some(CpEntry::NameAndType(name_index, descriptor_index)) => Ok((
+                self.utf8(*name_index, offset)?,
+                self.utf8(*descriptor_index, offset)?,
+            )),
+            _ => Err(not_a(index, "name and type", offset)),
+        }
+    }
+}
+
+fn not_a(index: u16, kind: &str, offset: usize) -> ClassFormatError {
+    ClassFormatError::failed(
+        FormatCheck::ConstantPool,
+        offset,
+        format!("constant pool index {} is not a {} constant", index, kind),
+    )
+}
+
+fn invalid_flags(member: &str, access_flags: u16, offset: usize) -> ClassFormatError {
+    ClassFormatError::failed(
+        FormatCheck::AccessFlags,
+        offset,
+        format!("illegal {} access flags {:#06x}", member, access_flags),
+    )
+}
+
+/// the name of the tag, as in JVMS table 4.4-B
+fn entry_kind(entry: &CpEntry) -> &'static str {
+    match entry {
+        CpEntry::MethodHandle(..) => "MethodHandle",
+        CpEntry::MethodType(_) => "MethodType",
+        CpEntry::Dynamic(..) => "Dynamic",
+        CpEntry::InvokeDynamic(..) => "InvokeDynamic",
+        CpEntry::Module(_) => "Module",
+        CpEntry::Package(_) => "Package",
+        _ => "other",
+    }
+}
+
+fn at_most_one_visibility(access_flags: u16) -> bool {
+    (access_flags & (ACC_PUBLIC | ACC_PRIVATE | ACC_PROTECTED)).count_ones() <= 1
+}
+
+fn check_field_name(name: &str, offset: usize) -> Result<(), ClassFormatError> {
+    if is_unqualified_name(name) {
+        Ok(())
+    } else {
+        Err(ClassFormatError::failed(
+            FormatCheck::Name,
+            offset,
+            format!("illegal field name \"{}\"", name),
+        ))
+    }
+}
+
+/// the name of a method other than <init> and <clinit>
+fn check_method_name(name: &str, offset: usize) -> Result<(), ClassFormatError> {
+    if is_unqualified_name(name) && !name.contains(['<', '>']) {
+        Ok(())
+    } else {
+        Err(ClassFormatError::failed(
+            FormatCheck::Name,
+            offset,
+            format!("illegal method name \"{}\"", name),
+        ))
+    }
+}
+
+fn check_field_descriptor(descriptor: &str, offset: usize) -> Result<(), ClassFormatError> {
+    if is_field_descriptor(descriptor) {
+        Ok(())
+    } else {
+        Err(ClassFormatError::failed(
+            FormatCheck::Descriptor,
+            offset,
+            format!("illegal field descriptor \"{}\"", descriptor),
+        ))
+    }
+}
+
+/// the number of local variable slots that the parameters take
+fn check_method_descriptor(descriptor: &str, offset: usize) -> Result<usize, ClassFormatError> {
+    method_parameter_slots(descriptor).ok_or_else(|| {
+        ClassFormatError::failed(
+            FormatCheck::Descriptor,
+            offset,
+            format!("illegal method descriptor \"{}\"", descriptor),
+        )
+    })
+}
+
+/// a field or method name, JVMS 4.2.2
+fn is_unqualified_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['.', ';', '[', '/'])
+}
+
+/// a class name in internal form, like java/lang/Object, JVMS 4.2.1
+fn is_class_name(name: &str) -> bool {
+    name.split('/').all(is_unqualified_name)
+}
+
+fn is_field_descriptor(descriptor: &str) -> bool {
+    field_type_length(descriptor) == Some(descriptor.len())
+}
+
+/// the length of the field type at the start of the descriptor, JVMS 4.3.2
+fn field_type_length(descriptor: &str) -> Option<usize> {
+    let dimensions = descriptor.bytes().take_while(|b| *b == b'[').count();
+    if dimensions > 255 {
+        return None;
+    }
+    let component = &descriptor[dimensions..];
+    match component.bytes().next()? {
+        b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' => Some(dimensions + 1),
+        b'L' => {
+            let end = component.find(';')?;
+            is_class_name(&component[1..end]).then_some(dimensions + end + 1)
+        }
+        _ => None,
+    }
+}
+
+/// the slots of the parameters, if it is a valid method descriptor, JVMS 4.3.3
+fn method_parameter_slots(descriptor: &str) -> Option<usize> {
+    let mut rest = descriptor.strip_prefix('(')?;
+    let mut slots = 0;
+    while !rest.starts_with(')') {
+        let length = field_type_length(rest)?;
+        slots += if length == 1 && matches!(&rest[..1], "J" | "D") {
+            2
+        } else {
+            1
+        };
+        rest = &rest[length..];
+    }
+    let return_type = &rest[1..];
+    (return_type == "V" || is_field_descriptor(return_type)).then_some(slots)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn descriptors() {
+        assert!(is_field_descriptor("[[Ljava/lang/String;"));
+        assert!(!is_field_descriptor("Ljava/lang/String"));
+        assert!(!is_field_descriptor("L;"));
+        assert!(!is_field_descriptor("V"));
+        assert_eq!(Some(4), method_parameter_slots("(IJ[D)V"));
+        assert_eq!(None, method_parameter_slots("(I)"));
+        assert_eq!(None, method_parameter_slots("(V)I"));
+        assert!(is_class_name("java/lang/Object"));
+        assert!(!is_class_name("java.lang.Object"));
+        assert!(!is_class_name("java//Object"));
+    }
+}
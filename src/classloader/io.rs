@@ -1,8 +1,13 @@
-use std::fs::{self};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
 
+use crate::classloader::error::ClassFormatError;
 use crate::vm::opcodes::Opcode;
 use crate::vm::opcodes::Opcode::*;
-use anyhow::{anyhow, Error};
+use anyhow::Error;
+use zip::ZipArchive;
 
 #[cfg(target_family = "unix")]
 pub const PATH_SEPARATOR: char = ':';
@@ -10,162 +15,175 @@ pub const PATH_SEPARATOR: char = ':';
 #[cfg(target_family = "windows")]
 pub const PATH_SEPARATOR: char = ';';
 
-/// resolves the actual path where the class file is found
-/// for std lib there is a  special case that resolves to the jmod
-/// notation:
-/// * [jmod]#classes/[package_path]/[class].class
-/// * [jar/zip]#[package_path]/[class].class
-/// * [dir]/[package_path]/[class].class
-pub fn find_class(classpath: &Vec<String>, class_name: &str) -> Result<String, Error> {
-    if class_name.starts_with("java")
-        || class_name.starts_with("sun/")
-        || class_name.starts_with("com/sun/")
-        || class_name.starts_with("jdk/")
-    {
-        let mut path: String = "jmods/java.base.jmod#classes/".into();
-        path.push_str(class_name);
-        path.push_str(".class");
-        return Ok(path);
-    }
-
-    for clp_entry in classpath {
-        if fs::metadata(clp_entry)?.is_dir() {
-            let mut maybe_path = clp_entry.clone();
-            maybe_path.push('/');
-            maybe_path.push_str(class_name);
-            maybe_path.push_str(".class");
-            if fs::metadata(&maybe_path)?.is_file() {
-                return Ok(maybe_path);
-            }
-        } else {
-            //TODO jar/zip files
+/// an opened jar, zip or jmod file, with the names of its entries
+struct Archive {
+    zip: ZipArchive<File>,
+    entries: HashSet<String>,
+}
+
+thread_local! {
+    // archives are opened and indexed once, on first use
+    static ARCHIVES: RefCell<HashMap<String, Archive>> = RefCell::new(HashMap::new());
+}
+
+/// runs the function on the (cached) archive
+fn with_archive<T>(
+    archive_path: &str,
+    f: impl FnOnce(&mut Archive) -> Result<T, Error>,
+) -> Result<T, Error> {
+    ARCHIVES.with(|archives| {
+        let mut archives = archives.borrow_mut();
+        if !archives.contains_key(archive_path) {
+            let zip = ZipArchive::new(File::open(archive_path)?)?;
+            let entries = zip.file_names().map(|name| name.to_owned()).collect();
+            archives.insert(archive_path.to_owned(), Archive { zip, entries });
         }
-    }
-    Err(anyhow!("Class not found {}", class_name))
+        f(archives.get_mut(archive_path).unwrap())
+    })
+}
+
+/// checks whether the archive has an entry with the name
+pub(crate) fn archive_contains(archive_path: &str, entry_name: &str) -> Result<bool, Error> {
+    with_archive(archive_path, |archive| {
+        Ok(archive.entries.contains(entry_name))
+    })
+}
+
+/// the names of all entries in the archive
+pub(crate) fn archive_entries(archive_path: &str) -> Result<Vec<String>, Error> {
+    with_archive(archive_path, |archive| {
+        Ok(archive.entries.iter().cloned().collect())
+    })
+}
+
+/// reads the contents of an entry in the archive
+pub(crate) fn read_archive_entry(archive_path: &str, entry_name: &str) -> Result<Vec<u8>, Error> {
+    with_archive(archive_path, |archive| {
+        let mut entry = archive.zip.by_name(entry_name)?;
+        let mut buffer = vec![0; entry.size() as usize];
+        entry.read_exact(&mut buffer)?;
+        Ok(buffer)
+    })
 }
 
 // methods to read values from big-endian binary data
+// reading past the end of the data is a ClassFormatError at the offset of the incomplete value
+
+/// the next len bytes
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], ClassFormatError> {
+    let end = pos
+        .checked_add(len)
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| ClassFormatError::truncated(*pos))?;
+    let bytes = &data[*pos..end];
+    *pos = end;
+    Ok(bytes)
+}
+
+pub(crate) fn read_u8(data: &[u8], pos: &mut usize) -> Result<u8, ClassFormatError> {
+    Ok(take(data, pos, 1)?[0])
+}
+
+pub(crate) fn read_bytes(
+    data: &[u8],
+    pos: &mut usize,
+    len: usize,
+) -> Result<Vec<u8>, ClassFormatError> {
+    Ok(take(data, pos, len)?.to_vec())
+}
+
+pub(crate) fn read_u16(data: &[u8], pos: &mut usize) -> Result<u16, ClassFormatError> {
+    Ok(u16::from_be_bytes(take(data, pos, 2)?.try_into().unwrap()))
+}
+
+pub(crate) fn read_i8(data: &[u8], pos: &mut usize) -> Result<i8, ClassFormatError> {
+    Ok(take(data, pos, 1)?[0] as i8)
+}
+
+pub(crate) fn read_i16(data: &[u8], pos: &mut usize) -> Result<i16, ClassFormatError> {
+    Ok(i16::from_be_bytes(take(data, pos, 2)?.try_into().unwrap()))
+}
+
+pub(crate) fn read_i32(data: &[u8], pos: &mut usize) -> Result<i32, ClassFormatError> {
+    Ok(i32::from_be_bytes(take(data, pos, 4)?.try_into().unwrap()))
+}
+
+pub(crate) fn read_u32(data: &[u8], pos: &mut usize) -> Result<u32, ClassFormatError> {
+    Ok(u32::from_be_bytes(take(data, pos, 4)?.try_into().unwrap()))
+}
+
+pub(crate) fn read_f32(data: &[u8], pos: &mut usize) -> Result<f32, ClassFormatError> {
+    Ok(f32::from_be_bytes(take(data, pos, 4)?.try_into().unwrap()))
+}
+
+pub(crate) fn read_i64(data: &[u8], pos: &mut usize) -> Result<i64, ClassFormatError> {
+    Ok(i64::from_be_bytes(take(data, pos, 8)?.try_into().unwrap()))
+}
+
+pub(crate) fn read_f64(data: &[u8], pos: &mut usize) -> Result<f64, ClassFormatError> {
+    Ok(f64::from_be_bytes(take(data, pos, 8)?.try_into().unwrap()))
+}
+
+pub(crate) fn write_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+pub(crate) fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
 
-pub(crate) fn read_u8(data: &[u8], pos: &mut usize) -> u8 {
-    *pos += 1;
-    u8::from_be_bytes(
-        data[*pos - 1..*pos]
-            .try_into()
-            .expect("slice with incorrect length"),
-    )
-}
-
-pub(crate) fn read_bytes(data: &[u8], pos: &mut usize, len: usize) -> Vec<u8> {
-    *pos += len;
-    data[*pos - len..*pos]
-        .try_into()
-        .expect("slice with incorrect length")
-}
-
-pub(crate) fn read_u16(data: &[u8], pos: &mut usize) -> u16 {
-    *pos += 2;
-    u16::from_be_bytes(
-        data[*pos - 2..*pos]
-            .try_into()
-            .expect("slice with incorrect length"),
-    )
-}
-
-pub(crate) fn read_i16(data: &[u8], pos: &mut usize) -> i16 {
-    *pos += 2;
-    i16::from_be_bytes(
-        data[*pos - 2..*pos]
-            .try_into()
-            .expect("slice with incorrect length"),
-    )
-}
-
-pub(crate) fn read_i32(data: &[u8], pos: &mut usize) -> i32 {
-    *pos += 4;
-    i32::from_be_bytes(
-        data[*pos - 4..*pos]
-            .try_into()
-            .expect("slice with incorrect length"),
-    )
-}
-
-pub(crate) fn read_u32(data: &[u8], pos: &mut usize) -> u32 {
-    *pos += 4;
-    u32::from_be_bytes(
-        data[*pos - 4..*pos]
-            .try_into()
-            .expect("slice with incorrect length"),
-    )
-}
-
-pub(crate) fn read_f32(data: &[u8], pos: &mut usize) -> f32 {
-    *pos += 4;
-    f32::from_be_bytes(
-        data[*pos - 4..*pos]
-            .try_into()
-            .expect("slice with incorrect length"),
-    )
-}
-
-pub(crate) fn read_i64(data: &[u8], pos: &mut usize) -> i64 {
-    *pos += 8;
-    i64::from_be_bytes(
-        data[*pos - 8..*pos]
-            .try_into()
-            .expect("slice with incorrect length"),
-    )
-}
-
-pub(crate) fn read_f64(data: &[u8], pos: &mut usize) -> f64 {
-    *pos += 8;
-    f64::from_be_bytes(
-        data[*pos - 8..*pos]
-            .try_into()
-            .expect("slice with incorrect length"),
-    )
-}
-
-pub(crate) fn read_tableswitch(data: &[u8], pos: &mut usize) -> Tableswitch {
+pub(crate) fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn read_tableswitch(
+    data: &[u8],
+    pos: &mut usize,
+) -> Result<Tableswitch, ClassFormatError> {
     while *pos % 4 != 0 {
         *pos += 1;
     }
-    let default = read_i32(data, pos);
-    let low = read_i32(data, pos);
-    let high = read_i32(data, pos);
+    let default = read_i32(data, pos)?;
+    let low = read_i32(data, pos)?;
+    let high = read_i32(data, pos)?;
     let mut offsets = vec![];
     for _ in low..=high {
-        offsets.push(read_i32(data, pos));
+        offsets.push(read_i32(data, pos)?);
     }
-    Tableswitch {
+    Ok(Tableswitch {
         default,
         low,
         high,
         offsets,
-    }
+    })
 }
 
-pub(crate) fn read_lookupswitch(data: &[u8], pos: &mut usize) -> Lookupswitch {
+pub(crate) fn read_lookupswitch(
+    data: &[u8],
+    pos: &mut usize,
+) -> Result<Lookupswitch, ClassFormatError> {
     while *pos % 4 != 0 {
         *pos += 1;
     }
-    let default = read_i32(data, pos);
-    let npairs = read_i32(data, pos);
+    let default = read_i32(data, pos)?;
+    let npairs = read_i32(data, pos)?;
     let mut match_offset_pairs = vec![];
     for _ in 0..npairs {
-        match_offset_pairs.push((read_i32(data, pos), read_i32(data, pos)));
+        match_offset_pairs.push((read_i32(data, pos)?, read_i32(data, pos)?));
     }
-    Lookupswitch {
+    Ok(Lookupswitch {
         default,
         match_offset_pairs,
-    }
+    })
 }
 
-pub(crate) fn read_wide_opcode(data: &[u8], pos: &mut usize) -> Opcode {
-    let opcode = read_u8(data, pos);
-    if opcode == 132 {
-        WIDE_IINC(read_u16(data, pos), read_u16(data, pos))
+pub(crate) fn read_wide_opcode(data: &[u8], pos: &mut usize) -> Result<Opcode, ClassFormatError> {
+    let opcode_pos = *pos;
+    let opcode = read_u8(data, pos)?;
+    Ok(if opcode == 132 {
+        WIDE_IINC(read_u16(data, pos)?, read_i16(data, pos)?)
     } else {
-        let index = read_u16(data, pos);
+        let index = read_u16(data, pos)?;
         match opcode {
             21 => WIDE_ILOAD(index),
             22 => WIDE_LLOAD(index),
@@ -179,22 +197,147 @@ pub(crate) fn read_wide_opcode(data: &[u8], pos: &mut usize) -> Opcode {
             58 => WIDE_ASTORE(index),
             169 => WIDE_RET(index),
             _ => {
-                unreachable!("unknown opcode for WIDE")
+                return Err(ClassFormatError::new(
+                    opcode_pos,
+                    format!("invalid opcode {} for wide", opcode),
+                ))
             }
         }
-    }
+    })
 }
 
+/// the operands of a tableswitch, offsets are relative to the start of the instruction.
+/// After parsing, the offsets are opcode indices.
 #[derive(Clone, Debug)]
-pub(crate) struct Tableswitch {
+pub struct Tableswitch {
     default: i32,
     low: i32,
     high: i32,
     offsets: Vec<i32>,
 }
 
+impl Tableswitch {
+    /// the offsets are for the keys low, low + 1, ...
+    pub fn new(default: i32, low: i32, offsets: Vec<i32>) -> Self {
+        Self {
+            default,
+            low,
+            high: low + offsets.len() as i32 - 1,
+            offsets,
+        }
+    }
+
+    /// the default offset, followed by the offsets for the keys
+    pub(crate) fn targets(&self) -> Vec<i32> {
+        let mut targets = vec![self.default];
+        targets.extend_from_slice(&self.offsets);
+        targets
+    }
+
+    /// the switch with the offsets replaced, eg. by opcode indices
+    pub(crate) fn map_targets<E>(
+        self,
+        mut map: impl FnMut(i32) -> Result<i32, E>,
+    ) -> Result<Self, E> {
+        Ok(Self {
+            default: map(self.default)?,
+            offsets: self
+                .offsets
+                .into_iter()
+                .map(map)
+                .collect::<Result<_, _>>()?,
+            ..self
+        })
+    }
+
+    /// the offset for the key, or the default offset if it is out of range
+    pub(crate) fn target(&self, key: i32) -> i32 {
+        if key < self.low || key > self.high {
+            self.default
+        } else {
+            self.offsets[(key as i64 - self.low as i64) as usize]
+        }
+    }
+
+    /// writes the operands after the opcode, with the padding to a multiple of 4 in the code
+    pub(crate) fn write(&self, code: &mut Vec<u8>) {
+        write_padding(code);
+        write_u32(code, self.default as u32);
+        write_u32(code, self.low as u32);
+        write_u32(code, self.high as u32);
+        for offset in &self.offsets {
+            write_u32(code, *offset as u32);
+        }
+    }
+}
+
+/// the operands of a lookupswitch, offsets are relative to the start of the instruction.
+/// After parsing, the offsets are opcode indices.
 #[derive(Clone, Debug)]
-pub(crate) struct Lookupswitch {
+pub struct Lookupswitch {
     default: i32,
     match_offset_pairs: Vec<(i32, i32)>,
 }
+
+impl Lookupswitch {
+    /// the pairs are (key, offset), sorted by key
+    pub fn new(default: i32, match_offset_pairs: Vec<(i32, i32)>) -> Self {
+        Self {
+            default,
+            match_offset_pairs,
+        }
+    }
+
+    /// the default offset, followed by the offsets for the keys
+    pub(crate) fn targets(&self) -> Vec<i32> {
+        let mut targets = vec![self.default];
+        targets.extend(self.match_offset_pairs.iter().map(|(_, offset)| offset));
+        targets
+    }
+
+    /// the switch with the offsets replaced, eg. by opcode indices
+    pub(crate) fn map_targets<E>(
+        self,
+        mut map: impl FnMut(i32) -> Result<i32, E>,
+    ) -> Result<Self, E> {
+        Ok(Self {
+            default: map(self.default)?,
+            match_offset_pairs: self
+                .match_offset_pairs
+                .into_iter()
+                .map(|(key, offset)| Ok((key, map(offset)?)))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// the offset for the key, or the default offset if there is no pair with the key
+    pub(crate) fn target(&self, key: i32) -> i32 {
+        self.match_offset_pairs
+            .binary_search_by_key(&key, |(pair_key, _)| *pair_key)
+            .map_or(self.default, |index| self.match_offset_pairs[index].1)
+    }
+
+    /// whether the keys are in ascending order, as required for the binary search
+    pub(crate) fn is_sorted(&self) -> bool {
+        self.match_offset_pairs
+            .windows(2)
+            .all(|pairs| pairs[0].0 < pairs[1].0)
+    }
+
+    /// writes the operands after the opcode, with the padding to a multiple of 4 in the code
+    pub(crate) fn write(&self, code: &mut Vec<u8>) {
+        write_padding(code);
+        write_u32(code, self.default as u32);
+        write_u32(code, self.match_offset_pairs.len() as u32);
+        for (key, offset) in &self.match_offset_pairs {
+            write_u32(code, *key as u32);
+            write_u32(code, *offset as u32);
+        }
+    }
+}
+
+fn write_padding(code: &mut Vec<u8>) {
+    while !code.len().is_multiple_of(4) {
+        code.push(0);
+    }
+}
@@ -0,0 +1,596 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use anyhow::{anyhow, Error};
+use flate2::read::ZlibDecoder;
+
+use crate::classloader::error::ClassFormatError;
+use crate::classloader::io::{read_bytes, read_u16, read_u8};
+
+const IMAGE_MAGIC: [u8; 4] = [0xCA, 0xFE, 0xDA, 0xDA];
+const IMAGE_MAJOR_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 7 * 4;
+const HASH_MULTIPLIER: i32 = 0x01000193;
+
+// the kinds of location attributes
+const ATTRIBUTE_END: usize = 0;
+const ATTRIBUTE_MODULE: usize = 1;
+const ATTRIBUTE_PARENT: usize = 2;
+const ATTRIBUTE_BASE: usize = 3;
+const ATTRIBUTE_EXTENSION: usize = 4;
+const ATTRIBUTE_OFFSET: usize = 5;
+const ATTRIBUTE_COMPRESSED: usize = 6;
+const ATTRIBUTE_UNCOMPRESSED: usize = 7;
+const ATTRIBUTE_COUNT: usize = 8;
+
+const COMPRESSED_HEADER_MAGIC: u32 = 0xCAFEFAFA;
+const COMPRESSED_HEADER_SIZE: usize = 29;
+
+// constant pool tags that the compact-cp compression adds
+const EXTERNALIZED_STRING: u8 = 23;
+const EXTERNALIZED_STRING_DESCRIPTOR: u8 = 25;
+
+/// A jimage file, the format of lib/modules in a JDK or JRE.
+/// The index (header, redirect table, location offsets, locations and strings) is kept in memory,
+/// resources are read from the file when they are needed.
+struct Image {
+    file: File,
+    // jimage files are written in the byte order of the platform
+    big_endian: bool,
+    index: Vec<u8>,
+    table_length: usize,
+    // where the tables start in the index
+    redirect_start: usize,
+    offsets_start: usize,
+    locations_start: usize,
+    strings_start: usize,
+}
+
+/// the attributes of a resource, indexed by attribute kind
+type Location = [u64; ATTRIBUTE_COUNT];
+
+thread_local! {
+    // images are opened once, on first use
+    static IMAGES: RefCell<HashMap<String, Image>> = RefCell::new(HashMap::new());
+}
+
+/// runs the function on the (cached) image
+fn with_image<T>(
+    image_path: &str,
+    f: impl FnOnce(&mut Image) -> Result<T, Error>,
+) -> Result<T, Error> {
+    IMAGES.with(|images| {
+        let mut images = images.borrow_mut();
+        if !images.contains_key(image_path) {
+            images.insert(image_path.to_owned(), Image::open(image_path)?);
+        }
+        f(images.get_mut(image_path).unwrap())
+    })
+}
+
+/// the packages in the image, with the module they belong to
+pub(crate) fn image_packages(image_path: &str) -> Result<Vec<(String, String)>, Error> {
+    with_image(image_path, |image| Ok(image.packages()))
+}
+
+/// checks whether the image has a resource with the name, ie. /[module]/[path]
+pub(crate) fn image_contains(image_path: &str, resource_name: &str) -> Result<bool, Error> {
+    with_image(image_path, |image| Ok(image.find(resource_name).is_some()))
+}
+
+/// reads the (decompressed) contents of a resource in the image
+pub(crate) fn read_image_resource(image_path: &str, resource_name: &str) -> Result<Vec<u8>, Error> {
+    with_image(image_path, |image| image.read_resource(resource_name))
+}
+
+impl Image {
+    fn open(path: &str) -> Result<Self, Error> {
+        let mut file = File::open(path)?;
+        let mut header = [0; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+
+        let magic: [u8; 4] = header[0..4].try_into()?;
+        let big_endian = if magic == IMAGE_MAGIC {
+            true
+        } else if magic.iter().rev().eq(IMAGE_MAGIC.iter()) {
+            false
+        } else {
+            return Err(anyhow!("{} is not a jimage file", path));
+        };
+        let field = |index: usize| u32_at(&header, index * 4, big_endian);
+        let major_version = field(1) >> 16;
+        if major_version != IMAGE_MAJOR_VERSION {
+            return Err(anyhow!(
+                "{} has unsupported jimage version {}",
+                path,
+                major_version
+            ));
+        }
+        let table_length = field(4) as usize;
+        let locations_size = field(5) as usize;
+        let strings_size = field(6) as usize;
+
+        let redirect_start = HEADER_SIZE;
+        let offsets_start = redirect_start + table_length * 4;
+        let locations_start = offsets_start + table_length * 4;
+        let strings_start = locations_start + locations_size;
+        let mut index = vec![0; strings_start + strings_size];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut index)?;
+
+        Ok(Self {
+            file,
+            big_endian,
+            index,
+            table_length,
+            redirect_start,
+            offsets_start,
+            locations_start,
+            strings_start,
+        })
+    }
+
+    /// the NUL-terminated string at the offset in the strings table
+    fn string(&self, offset: u64) -> &[u8] {
+        let start = self.strings_start + offset as usize;
+        let length = self.index[start..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(self.index.len() - start);
+        &self.index[start..start + length]
+    }
+
+    /// decodes the attributes of the location with the index
+    /// each attribute is a byte with the kind and the length of the value, followed by the value
+    fn location(&self, index: usize) -> Location {
+        let offset = u32_at(&self.index, self.offsets_start + index * 4, self.big_endian);
+        let mut pos = self.locations_start + offset as usize;
+        let mut location = [0; ATTRIBUTE_COUNT];
+        loop {
+            let byte = self.index[pos];
+            let kind = (byte >> 3) as usize;
+            if kind == ATTRIBUTE_END || kind >= ATTRIBUTE_COUNT {
+                break;
+            }
+            let length = (byte & 0x7) as usize + 1;
+            location[kind] = self.index[pos + 1..pos + 1 + length]
+                .iter()
+                .fold(0, |value, byte| value << 8 | *byte as u64);
+            pos += 1 + length;
+        }
+        location
+    }
+
+    /// the full name of the location: /[module]/[parent]/[base].[extension]
+    fn name(&self, location: &Location) -> Vec<u8> {
+        let mut name = vec![];
+        let module = self.string(location[ATTRIBUTE_MODULE]);
+        if !module.is_empty() {
+            name.push(b'/');
+            name.extend_from_slice(module);
+            name.push(b'/');
+        }
+        let parent = self.string(location[ATTRIBUTE_PARENT]);
+        if !parent.is_empty() {
+            name.extend_from_slice(parent);
+            name.push(b'/');
+        }
+        name.extend_from_slice(self.string(location[ATTRIBUTE_BASE]));
+        let extension = self.string(location[ATTRIBUTE_EXTENSION]);
+        if !extension.is_empty() {
+            name.push(b'.');
+            name.extend_from_slice(extension);
+        }
+        name
+    }
+
+    /// looks up the location through the perfect hash of the name
+    /// the redirect table has either the (negated) index of the location,
+    /// or the seed for a second hash that gives the index
+    fn find(&self, name: &str) -> Option<Location> {
+        if self.table_length == 0 {
+            return None;
+        }
+        let slot = hash_code(name.as_bytes(), HASH_MULTIPLIER) as usize % self.table_length;
+        let redirect = u32_at(&self.index, self.redirect_start + slot * 4, self.big_endian) as i32;
+        let index = match redirect {
+            0 => return None,
+            redirect if redirect < 0 => (-1 - redirect) as usize,
+            seed => hash_code(name.as_bytes(), seed) as usize % self.table_length,
+        };
+        let location = self.location(index);
+        (self.name(&location) == name.as_bytes()).then_some(location)
+    }
+
+    fn packages(&self) -> Vec<(String, String)> {
+        let mut packages = HashMap::new();
+        for index in 0..self.table_length {
+            let location = self.location(index);
+            let module = self.string(location[ATTRIBUTE_MODULE]);
+            let package = self.string(location[ATTRIBUTE_PARENT]);
+            if self.string(location[ATTRIBUTE_EXTENSION]) == b"class"
+                && !module.is_empty()
+                && !package.is_empty()
+            {
+                packages.insert(package, module);
+            }
+        }
+        packages
+            .into_iter()
+            .map(|(package, module)| {
+                (
+                    String::from_utf8_lossy(package).into_owned(),
+                    String::from_utf8_lossy(module).into_owned(),
+                )
+            })
+            .collect()
+    }
+
+    fn read_resource(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let location = self
+            .find(name)
+            .ok_or_else(|| anyhow!("{} not found in jimage", name))?;
+        let compressed_size = location[ATTRIBUTE_COMPRESSED] as usize;
+        let size = if compressed_size != 0 {
+            compressed_size
+        } else {
+            location[ATTRIBUTE_UNCOMPRESSED] as usize
+        };
+        // resource offsets are relative to the end of the index
+        let offset = self.index.len() as u64 + location[ATTRIBUTE_OFFSET];
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut content = vec![0; size];
+        self.file.read_exact(&mut content)?;
+
+        if compressed_size != 0 {
+            content = self.decompress(content)?;
+        }
+        Ok(content)
+    }
+
+    /// a resource can be compressed several times, each compression adds a header with the name
+    /// of the decompressor that is needed
+    /// header: magic (u4), compressed size (u8), uncompressed size (u8),
+    /// decompressor name (u4), content (u4), is terminal (u1)
+    fn decompress(&self, mut content: Vec<u8>) -> Result<Vec<u8>, Error> {
+        while content.len() >= COMPRESSED_HEADER_SIZE
+            && u32_at(&content, 0, self.big_endian) == COMPRESSED_HEADER_MAGIC
+        {
+            let uncompressed_size = u64_at(&content, 12, self.big_endian) as usize;
+            let decompressor = self.string(u32_at(&content, 20, self.big_endian) as u64);
+            let compressed = &content[COMPRESSED_HEADER_SIZE..];
+            content = match decompressor {
+                b"zip" => {
+                    let mut decompressed = Vec::with_capacity(uncompressed_size);
+                    ZlibDecoder::new(compressed).read_to_end(&mut decompressed)?;
+                    decompressed
+                }
+                b"compact-cp" => self.expand_shared_strings(compressed)?,
+                other => {
+                    return Err(anyhow!(
+                        "unsupported jimage decompressor {}",
+                        String::from_utf8_lossy(other)
+                    ))
+                }
+            };
+        }
+        Ok(content)
+    }
+
+    /// restores a class file from which the compact-cp compression moved the utf8 constants
+    /// into the strings table of the image
+    fn expand_shared_strings(&self, class: &[u8]) -> Result<Vec<u8>, ClassFormatError> {
+        let pos = &mut 0;
+        let mut expanded = Vec::with_capacity(class.len() * 2);
+        // magic, minor and major version
+        expanded.extend(read_bytes(class, pos, 8)?);
+        let constant_pool_count = read_u16(class, pos)?;
+        expanded.extend(constant_pool_count.to_be_bytes());
+
+        let mut cp_index = 1;
+        while cp_index < constant_pool_count {
+            let tag = read_u8(class, pos)?;
+            match tag {
+                1 => {
+                    let length = read_u16(class, pos)?;
+                    push_utf8(&mut expanded, &read_bytes(class, pos, length as usize)?);
+                }
+                EXTERNALIZED_STRING => {
+                    let string = self.string(read_compressed_int(class, pos)?);
+                    push_utf8(&mut expanded, string);
+                }
+                EXTERNALIZED_STRING_DESCRIPTOR => {
+                    let descriptor = self.expand_descriptor(class, pos)?;
+                    push_utf8(&mut expanded, &descriptor);
+                }
+                _ => {
+                    let size = constant_size(tag).ok_or_else(|| {
+                        ClassFormatError::new(
+                            *pos - 1,
+                            format!("invalid constant pool tag {}", tag),
+                        )
+                    })?;
+                    expanded.push(tag);
+                    expanded.extend(read_bytes(class, pos, size)?);
+                    if tag == 5 || tag == 6 {
+                        // long and double take two entries
+                        cp_index += 1;
+                    }
+                }
+            }
+            cp_index += 1;
+        }
+        expanded.extend_from_slice(&class[*pos..]);
+        Ok(expanded)
+    }
+
+    /// a descriptor is stored as the descriptor with the class names left out,
+    /// followed by a package and a simple name for each L in the descriptor
+    fn expand_descriptor(
+        &self,
+        class: &[u8],
+        pos: &mut usize,
+    ) -> Result<Vec<u8>, ClassFormatError> {
+        let descriptor = self.string(read_compressed_int(class, pos)?);
+        let indexes_length = read_compressed_int(class, pos)? as usize;
+        let indexes = read_bytes(class, pos, indexes_length)?;
+        let indexes_pos = &mut 0;
+        let mut next_string = || {
+            read_compressed_int(&indexes, indexes_pos)
+                .map(|index| self.string(index))
+                .map_err(|error| error.offset_by(*pos - indexes_length))
+        };
+
+        let mut expanded = vec![];
+        for byte in descriptor {
+            expanded.push(*byte);
+            if *byte == b'L' {
+                let package = next_string()?;
+                if !package.is_empty() {
+                    expanded.extend_from_slice(package);
+                    expanded.push(b'/');
+                }
+                expanded.extend_from_slice(next_string()?);
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+fn push_utf8(class: &mut Vec<u8>, string: &[u8]) {
+    class.push(1);
+    class.extend((string.len() as u16).to_be_bytes());
+    class.extend_from_slice(string);
+}
+
+/// the size of constant pool entries other than utf8
+fn constant_size(tag: u8) -> Option<usize> {
+    match tag {
+        3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => Some(4),
+        5 | 6 => Some(8),
+        7 | 8 | 16 | 19 | 20 => Some(2),
+        15 => Some(3),
+        _ => None,
+    }
+}
+
+/// an int of 1 to 4 bytes: if the high bit is set, the next two bits are the length
+/// and the other 5 bits are the most significant bits of the value, otherwise it is a plain u4
+fn read_compressed_int(data: &[u8], pos: &mut usize) -> Result<u64, ClassFormatError> {
+    let header = read_u8(data, pos)?;
+    let (length, mut value) = if header & 0x80 != 0 {
+        ((header >> 5) & 0x3, (header & 0x1F) as u64)
+    } else {
+        (4, header as u64)
+    };
+    for _ in 1..length {
+        value = value << 8 | read_u8(data, pos)? as u64;
+    }
+    Ok(value)
+}
+
+/// the hash function of the jimage lookup table (FNV-1a like)
+fn hash_code(name: &[u8], seed: i32) -> i32 {
+    name.iter().fold(seed, |hash, byte| {
+        hash.wrapping_mul(HASH_MULTIPLIER) ^ *byte as i32
+    }) & 0x7FFFFFFF
+}
+
+fn u32_at(data: &[u8], pos: usize, big_endian: bool) -> u32 {
+    let bytes = data[pos..pos + 4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+fn u64_at(data: &[u8], pos: usize, big_endian: bool) -> u64 {
+    let bytes = data[pos..pos + 8].try_into().unwrap();
+    if big_endian {
+        u64::from_be_bytes(bytes)
+    } else {
+        u64::from_le_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_code_matches_the_jdk() {
+        let name = b"/java.base/java/lang/Object.class";
+        assert_eq!(2066871583, hash_code(name, HASH_MULTIPLIER));
+        assert_eq!(1812041453, hash_code(name, 12345));
+    }
+
+    #[test]
+    fn compressed_ints() {
+        let data = [0x85, 0xC1, 0x02, 0x00, 0x00, 0x01, 0x00];
+        let pos = &mut 0;
+        assert_eq!(5, read_compressed_int(&data, pos).unwrap());
+        assert_eq!(0x102, read_compressed_int(&data, pos).unwrap());
+        assert_eq!(0x100, read_compressed_int(&data, pos).unwrap());
+        assert_eq!(7, *pos);
+    }
+
+    #[test]
+    fn read_resources_from_image() {
+        use crate::classloader::source::{ClassSource, JImageSource};
+
+        let int = &include_bytes!("../../tests/testclasses/Int.class")[..];
+        let double = &include_bytes!("../../tests/testclasses/Double.class")[..];
+        let float = &include_bytes!("../../tests/testclasses/Float.class")[..];
+        // Double and Float hash to the same slot, so one of them is found through a seed
+        let resources = [
+            ("Int", int, false),
+            ("Double", double, true),
+            ("Float", float, false),
+        ];
+        let image = write_image(&resources);
+        let path = std::env::temp_dir().join(format!("java_rs_test_{}.jimage", std::process::id()));
+        std::fs::write(&path, &image).unwrap();
+        let path = path.to_str().unwrap();
+
+        let redirects = (0..resources.len())
+            .map(|slot| u32_at(&image, HEADER_SIZE + slot * 4, false) as i32)
+            .collect::<Vec<_>>();
+        assert!(redirects.iter().any(|redirect| *redirect > 0));
+
+        let source = JImageSource::open(path).unwrap();
+        assert_eq!(vec!["testclasses"], source.packages().collect::<Vec<_>>());
+        for (name, bytecode, _) in resources {
+            assert_eq!(
+                Some(bytecode.to_vec()),
+                source.read_class(&format!("testclasses/{}", name)).unwrap()
+            );
+        }
+        assert_eq!(None, source.read_class("testclasses/Const").unwrap());
+        assert_eq!(None, source.read_class("other/Int").unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// a little-endian image with the classes in /testmodule/testclasses,
+    /// the compressed ones with the zip decompressor
+    fn write_image(classes: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut strings = vec![0];
+        let mut add_string = |string: &str| {
+            let offset = strings.len() as u64;
+            strings.extend_from_slice(string.as_bytes());
+            strings.push(0);
+            offset
+        };
+        let module = add_string("testmodule");
+        let parent = add_string("testclasses");
+        let extension = add_string("class");
+        let zip = add_string("zip");
+
+        let mut locations = vec![0];
+        let mut location_offsets = vec![];
+        let mut contents = vec![];
+        for (name, bytecode, compress) in classes {
+            let mut location = [0; ATTRIBUTE_COUNT];
+            location[ATTRIBUTE_MODULE] = module;
+            location[ATTRIBUTE_PARENT] = parent;
+            location[ATTRIBUTE_BASE] = add_string(name);
+            location[ATTRIBUTE_EXTENSION] = extension;
+            location[ATTRIBUTE_OFFSET] = contents.len() as u64;
+            location[ATTRIBUTE_UNCOMPRESSED] = bytecode.len() as u64;
+            if *compress {
+                let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+                encoder.write_all(bytecode).unwrap();
+                let compressed = encoder.finish().unwrap();
+                let start = contents.len();
+                contents.extend(COMPRESSED_HEADER_MAGIC.to_le_bytes());
+                contents.extend((compressed.len() as u64).to_le_bytes());
+                contents.extend((bytecode.len() as u64).to_le_bytes());
+                contents.extend((zip as u32).to_le_bytes());
+                contents.extend(0u32.to_le_bytes());
+                contents.push(1);
+                contents.extend(compressed);
+                location[ATTRIBUTE_COMPRESSED] = (contents.len() - start) as u64;
+            } else {
+                contents.extend_from_slice(bytecode);
+            }
+
+            location_offsets.push(locations.len() as u32);
+            for (kind, value) in location.iter().enumerate().skip(1) {
+                let bytes = value.to_be_bytes();
+                let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(7);
+                locations.push((kind << 3) as u8 | (7 - skip) as u8);
+                locations.extend_from_slice(&bytes[skip..]);
+            }
+            locations.push(ATTRIBUTE_END as u8);
+        }
+
+        // the perfect hash: a slot with one name points at its location,
+        // a slot with more names has the seed that spreads them over free locations
+        let table_length = classes.len();
+        let names = classes
+            .iter()
+            .map(|(name, _, _)| format!("/testmodule/testclasses/{}.class", name))
+            .collect::<Vec<_>>();
+        let mut buckets = vec![vec![]; table_length];
+        for (index, name) in names.iter().enumerate() {
+            let slot = hash_code(name.as_bytes(), HASH_MULTIPLIER) as usize % table_length;
+            buckets[slot].push(index);
+        }
+        let mut redirects = vec![0i32; table_length];
+        let mut order = vec![None; table_length];
+        let mut slots = (0..table_length).collect::<Vec<_>>();
+        slots.sort_by_key(|slot| std::cmp::Reverse(buckets[*slot].len()));
+        for slot in slots.iter().filter(|slot| buckets[**slot].len() > 1) {
+            let bucket = &buckets[*slot];
+            let seed = (1..)
+                .find(|seed| {
+                    let mut taken = order.clone();
+                    bucket.iter().all(|index| {
+                        let target =
+                            hash_code(names[*index].as_bytes(), *seed) as usize % table_length;
+                        taken[target].replace(*index).is_none()
+                    })
+                })
+                .unwrap();
+            for index in bucket {
+                let target = hash_code(names[*index].as_bytes(), seed) as usize % table_length;
+                order[target] = Some(*index);
+            }
+            redirects[*slot] = seed;
+        }
+        for slot in slots.iter().filter(|slot| buckets[**slot].len() == 1) {
+            let target = order.iter().position(Option::is_none).unwrap();
+            order[target] = Some(buckets[*slot][0]);
+            redirects[*slot] = -1 - target as i32;
+        }
+
+        let mut image = vec![];
+        for field in [
+            u32::from_be_bytes(IMAGE_MAGIC),
+            IMAGE_MAJOR_VERSION << 16,
+            0,
+            classes.len() as u32,
+            table_length as u32,
+            locations.len() as u32,
+            strings.len() as u32,
+        ] {
+            image.extend(field.to_le_bytes());
+        }
+        for redirect in redirects {
+            image.extend(redirect.to_le_bytes());
+        }
+        for index in order {
+            image.extend(location_offsets[index.unwrap()].to_le_bytes());
+        }
+        image.extend(locations);
+        image.extend(strings);
+        image.extend(contents);
+        image
+    }
+}
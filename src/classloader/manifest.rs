@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Error;
+
+use crate::classloader::io::{archive_contains, read_archive_entry};
+
+const MANIFEST_PATH: &str = "META-INF/MANIFEST.MF";
+
+/// The main section of a jar manifest (META-INF/MANIFEST.MF)
+/// per-entry sections are not needed to launch a jar and are ignored
+pub struct Manifest {
+    main_attributes: HashMap<String, String>,
+}
+
+impl Manifest {
+    /// reads the manifest from the jar, None if the jar has no manifest
+    pub fn read(jar_path: &str) -> Result<Option<Self>, Error> {
+        if !archive_contains(jar_path, MANIFEST_PATH)? {
+            return Ok(None);
+        }
+        let bytes = read_archive_entry(jar_path, MANIFEST_PATH)?;
+        Ok(Some(Self::parse(&String::from_utf8_lossy(&bytes))))
+    }
+
+    /// parses the main section, ie. the name: value lines up to the first empty line
+    /// a line starting with a space continues the value on the previous line
+    pub fn parse(manifest: &str) -> Self {
+        let mut main_attributes = HashMap::new();
+        let mut last_name: Option<String> = None;
+        for line in manifest.lines() {
+            if line.is_empty() {
+                break;
+            }
+            if let Some(continuation) = line.strip_prefix(' ') {
+                if let Some(name) = &last_name {
+                    main_attributes
+                        .entry(name.clone())
+                        .and_modify(|value: &mut String| value.push_str(continuation));
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                let name = name.trim().to_owned();
+                main_attributes.insert(name.clone(), value.trim_start().to_owned());
+                last_name = Some(name);
+            }
+        }
+        Self { main_attributes }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.main_attributes.get(name)
+    }
+
+    /// the class that has the main method, in internal form (with slashes)
+    pub fn main_class(&self) -> Option<String> {
+        self.get("Main-Class").map(|c| c.replace('.', "/"))
+    }
+
+    /// the Class-Path entries, resolved against the directory that contains the jar
+    pub fn class_path(&self, jar_path: &str) -> Vec<String> {
+        let jar_dir = Path::new(jar_path).parent().unwrap_or(Path::new(""));
+        self.get("Class-Path")
+            .map(|class_path| {
+                class_path
+                    .split_whitespace()
+                    .map(|entry| jar_dir.join(entry).to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_main_section() {
+        let manifest = Manifest::parse(
+            "Manifest-Version: 1.0\r\nMain-Class: com.example.\r\n Main\r\nClass-Path: lib/a.jar  lib/b.jar\r\n\r\nName: com/example/\r\nSealed: true\r\n",
+        );
+        assert_eq!(Some("com/example/Main".to_owned()), manifest.main_class());
+        assert_eq!(
+            vec![
+                "/opt/app/lib/a.jar".to_owned(),
+                "/opt/app/lib/b.jar".to_owned()
+            ],
+            manifest.class_path("/opt/app/app.jar")
+        );
+        assert_eq!(None, manifest.get("Sealed"));
+    }
+}
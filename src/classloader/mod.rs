@@ -1,107 +1,131 @@
-use std::collections::HashMap;
-use std::fs;
-use std::fs::File;
-use std::io::Read;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use log::debug;
 
+use crate::classloader::annotations::{
+    read_parameter_annotations, Annotation, ElementValue, TypeAnnotation,
+};
+use crate::classloader::attributes::{
+    read_table, EnclosingMethod, InnerClass, LocalVariable, MethodParameter, Module, StackMapFrame,
+};
 use crate::classloader::classdef::{
-    AttributeType, ClassDef, CpEntry, Exception, Field, Method, MethodCode,
+    AttributeType, Attributes, BootstrapMethod, ClassDef, CpEntry, Exception, Field, LineNumber,
+    Method, MethodCode, RecordComponent,
 };
 use crate::classloader::code_parser::parse_code;
+use crate::classloader::error::{ClassFormatError, FormatCheck};
+use crate::classloader::format_checker::{check_version, FormatChecker};
 use crate::classloader::io::{
-    find_class, read_bytes, read_f32, read_f64, read_i32, read_i64, read_u16, read_u32, read_u8,
+    read_bytes, read_f32, read_f64, read_i32, read_i64, read_u16, read_u32, read_u8,
 };
+use crate::classloader::source::ClassSource;
 
+pub mod annotations;
+pub mod assembler;
+pub mod attributes;
+pub mod boot;
+pub mod builder;
 pub mod classdef;
 mod code_parser;
+pub mod disassembler;
+pub mod error;
+mod format_checker;
 pub(crate) mod io;
+mod jimage;
+pub mod manifest;
+pub mod mutf8;
+pub mod source;
+pub mod verifier;
+pub mod writer;
 
-pub(crate) fn get_classdef(classpath: &Vec<String>, class_name: &str) -> Result<ClassDef, Error> {
+/// loads the class from the first source that has it
+pub(crate) fn get_classdef(
+    sources: &[Box<dyn ClassSource>],
+    class_name: &str,
+) -> Result<ClassDef, Error> {
     debug!("read class {} ", class_name);
-    let resolved_path = find_class(classpath, class_name)?;
-    let bytecode = read_bytecode(resolved_path)?;
-    load_class(bytecode)
-}
-
-/// reads the binary class file from file path or archive
-/// and returns the byte array as Vec
-fn read_bytecode(name: String) -> Result<Vec<u8>, Error> {
-    let mut buffer;
-    if name.contains('#') {
-        let parts: Vec<&str> = name.split('#').collect();
-        let archive_file = File::open(parts[0])?;
-        let mut archive_zip = zip::ZipArchive::new(archive_file)?;
-        let mut entry = archive_zip.by_name(parts[1])?;
-        buffer = vec![0; entry.size() as usize];
-        entry.read_exact(&mut buffer)?;
-    } else {
-        let mut f = File::open(&name)?;
-        let metadata = fs::metadata(&name)?;
-        buffer = vec![0; metadata.len() as usize];
-        f.read_exact(&mut buffer)?;
+    for source in sources {
+        if let Some(bytecode) = source.read_class(class_name)? {
+            return Ok(load_class(bytecode)
+                .map_err(|error| error.within(format!("class {}", class_name)))?);
+        }
     }
-    Ok(buffer)
+    Err(anyhow!("Class not found {}", class_name))
 }
 
 // The native classoader
-fn load_class(bytecode: Vec<u8>) -> Result<ClassDef, Error> {
+pub fn load_class(bytecode: Vec<u8>) -> Result<ClassDef, ClassFormatError> {
     let pos = &mut 0;
-    check_magic(&bytecode, pos);
-    let minor_version = read_u16(&bytecode, pos);
-    let major_version = read_u16(&bytecode, pos);
+    check_magic(&bytecode, pos)?;
+    let minor_version = read_u16(&bytecode, pos)?;
+    let major_version = read_u16(&bytecode, pos)?;
+    check_version(minor_version, major_version)?;
 
-    let constant_pool_count = read_u16(&bytecode, pos);
+    let constant_pool_count = read_u16(&bytecode, pos)?;
     let mut constant_pool: HashMap<u16, CpEntry> =
         HashMap::with_capacity(constant_pool_count as usize);
+    // index and byte offset of the entries, for the format checks
+    let mut cp_entries = Vec::with_capacity(constant_pool_count as usize);
     let mut cp_index = 1;
     while cp_index < constant_pool_count {
-        constant_pool.insert(
-            cp_index,
-            read_constant_pool_entry(&mut cp_index, pos, &bytecode),
-        );
+        let entry_index = cp_index;
+        cp_entries.push((entry_index, *pos));
+        let entry = read_constant_pool_entry(&mut cp_index, pos, &bytecode)
+            .map_err(|error| error.within(format!("constant pool entry #{}", entry_index)))?;
+        constant_pool.insert(entry_index, entry);
         cp_index += 1;
     }
     let constant_pool = Rc::new(constant_pool);
-    let access_flags = read_u16(&bytecode, pos);
-    let this_class = read_u16(&bytecode, pos);
-    let super_class = read_u16(&bytecode, pos);
+    let access_flags_offset = *pos;
+    let access_flags = read_u16(&bytecode, pos)?;
+    let checker = FormatChecker::new(&constant_pool, major_version, access_flags);
+    checker.check_constant_pool(&cp_entries)?;
+    let this_class = read_u16(&bytecode, pos)?;
+    let super_class = read_u16(&bytecode, pos)?;
     let super_class = if super_class != 0 {
         Some(super_class)
     } else {
         None
     };
-    let interfaces_count = read_u16(&bytecode, pos);
+    let interfaces_count = read_u16(&bytecode, pos)?;
     let mut interfaces = vec![];
     for _ in 0..interfaces_count {
-        interfaces.push(read_u16(&bytecode, pos));
+        interfaces.push(read_u16(&bytecode, pos)?);
     }
+    checker.check_class(access_flags_offset, this_class, super_class, &interfaces)?;
 
-    let fields_count = read_u16(&bytecode, pos);
+    let fields_count = read_u16(&bytecode, pos)?;
     let mut fields = HashMap::new();
+    let mut field_types = HashSet::new();
     for i in 0..fields_count {
-        let field = read_field(constant_pool.clone(), pos, &bytecode, i);
+        let start = *pos;
+        let field = read_field(constant_pool.clone(), pos, &bytecode, i, &checker)?;
+        if !field_types.insert((field.name_index, field.descriptor_index)) {
+            return Err(duplicate(start, format!("field {}", field.name())));
+        }
         fields.insert(field.name().to_owned(), field);
     }
 
-    let methods_count = read_u16(&bytecode, pos);
+    let methods_count = read_u16(&bytecode, pos)?;
     let mut methods = HashMap::new();
-    for _ in 0..methods_count {
-        let m = read_method(constant_pool.clone(), pos, &bytecode);
-        methods.insert(m.name(), m);
+    for i in 0..methods_count {
+        let start = *pos;
+        let m = read_method(constant_pool.clone(), pos, &bytecode, i, &checker)?;
+        let name = m.name();
+        if methods.insert(name.clone(), m).is_some() {
+            return Err(duplicate(start, format!("method {}", name)));
+        }
     }
 
-    let attributes_count = read_u16(&bytecode, pos);
-    let mut attributes = HashMap::new();
-    for _ in 0..attributes_count {
-        let some = read_attribute(constant_pool.clone(), &bytecode, pos);
-        if let Some(att) = some {
-            attributes.insert(att.0, att.1);
-        } else {
-            panic!("attribute not found"); // bug/not-implemented
-        }
+    let attributes = read_attributes(constant_pool.clone(), &bytecode, pos)?;
+    checker.check_bootstrap_method_indices(&cp_entries, &attributes)?;
+    if *pos != bytecode.len() {
+        return Err(ClassFormatError::new(
+            *pos,
+            "extra bytes at the end of the class file",
+        ));
     }
 
     Ok(ClassDef::new(
@@ -118,217 +142,477 @@ fn load_class(bytecode: Vec<u8>) -> Result<ClassDef, Error> {
     ))
 }
 
-fn check_magic(bytecode: &[u8], pos: &mut usize) {
-    if bytecode[*pos..*pos + 4] != [0xCA, 0xFE, 0xBA, 0xBE] {
-        panic!("Invalid class file");
+fn duplicate(offset: usize, member: String) -> ClassFormatError {
+    ClassFormatError::failed(
+        FormatCheck::Members,
+        offset,
+        format!("duplicate {}", member),
+    )
+}
+
+fn check_magic(bytecode: &[u8], pos: &mut usize) -> Result<(), ClassFormatError> {
+    let magic = read_u32(bytecode, pos)?;
+    if magic != 0xCAFEBABE {
+        return Err(ClassFormatError::new(
+            0,
+            format!("incompatible magic value {:#X}", magic),
+        ));
     }
-    *pos += 4;
+    Ok(())
 }
 
-fn read_constant_pool_entry(cp_index: &mut u16, index: &mut usize, bytecode: &[u8]) -> CpEntry {
-    let tag = read_u8(bytecode, index);
+fn read_constant_pool_entry(
+    cp_index: &mut u16,
+    index: &mut usize,
+    bytecode: &[u8],
+) -> Result<CpEntry, ClassFormatError> {
+    let tag_index = *index;
+    let tag = read_u8(bytecode, index)?;
     // debug!("tag {}", tag);
-    match tag {
+    Ok(match tag {
         1 => {
-            let len = read_u16(bytecode, index) as usize;
-            let utf: Vec<u8> = read_bytes(&bytecode, index, len);
-            CpEntry::Utf8(String::from_utf8(utf).unwrap())
+            let len = read_u16(bytecode, index)? as usize;
+            let bytes_start = *index;
+            let utf: Vec<u8> = read_bytes(bytecode, index, len)?;
+            let units = mutf8::decode(&utf).map_err(|error| error.offset_by(bytes_start))?;
+            // a lone surrogate, which a rust string cannot hold, becomes U+FFFD in the string,
+            // string constants are made from the units
+            CpEntry::Utf8(String::from_utf16_lossy(&units), units)
         }
         3 => {
-            let value = read_i32(bytecode, index);
+            let value = read_i32(bytecode, index)?;
             CpEntry::Integer(value)
         }
         4 => {
-            let value = read_f32(bytecode, index);
+            let value = read_f32(bytecode, index)?;
             CpEntry::Float(value)
         }
         5 => {
-            let value = read_i64(bytecode, index);
+            let value = read_i64(bytecode, index)?;
             let val = CpEntry::Long(value);
             *cp_index += 1;
             val
         }
         6 => {
-            let value = read_f64(bytecode, index);
+            let value = read_f64(bytecode, index)?;
             let val = CpEntry::Double(value); //TODO order can be smarter
             *cp_index += 1;
             val
         }
         7 => {
-            let name_index = read_u16(bytecode, index);
+            let name_index = read_u16(bytecode, index)?;
             CpEntry::ClassRef(name_index)
         }
         8 => {
-            let string_index = read_u16(bytecode, index);
+            let string_index = read_u16(bytecode, index)?;
             CpEntry::StringRef(string_index)
         }
         9 => {
-            let class_index = read_u16(bytecode, index);
-            let name_and_type_index = read_u16(bytecode, index);
+            let class_index = read_u16(bytecode, index)?;
+            let name_and_type_index = read_u16(bytecode, index)?;
             CpEntry::Fieldref(class_index, name_and_type_index)
         }
         10 => {
-            let class_index = read_u16(bytecode, index);
-            let name_and_type_index = read_u16(bytecode, index);
+            let class_index = read_u16(bytecode, index)?;
+            let name_and_type_index = read_u16(bytecode, index)?;
             CpEntry::MethodRef(class_index, name_and_type_index)
         }
         11 => {
-            let class_index = read_u16(bytecode, index);
-            let name_and_type_index = read_u16(bytecode, index);
+            let class_index = read_u16(bytecode, index)?;
+            let name_and_type_index = read_u16(bytecode, index)?;
             CpEntry::InterfaceMethodref(class_index, name_and_type_index)
         }
         12 => {
-            let name_index = read_u16(bytecode, index);
-            let descriptor_index = read_u16(bytecode, index);
+            let name_index = read_u16(bytecode, index)?;
+            let descriptor_index = read_u16(bytecode, index)?;
             CpEntry::NameAndType(name_index, descriptor_index)
         }
         15 => {
-            let reference_kind = read_u8(bytecode, index);
-            let reference_index = read_u16(bytecode, index);
+            let reference_kind = read_u8(bytecode, index)?;
+            let reference_index = read_u16(bytecode, index)?;
             CpEntry::MethodHandle(reference_kind, reference_index)
         }
         16 => {
-            let descriptor_index = read_u16(bytecode, index);
+            let descriptor_index = read_u16(bytecode, index)?;
             CpEntry::MethodType(descriptor_index)
         }
-        // 17 Dynamic,
+        17 => {
+            let bootstrap_method_attr_index = read_u16(bytecode, index)?;
+            let name_and_type_index = read_u16(bytecode, index)?;
+            CpEntry::Dynamic(bootstrap_method_attr_index, name_and_type_index)
+        }
         18 => {
-            let bootstrap_method_attr_index = read_u16(bytecode, index);
-            let name_and_type_index = read_u16(bytecode, index);
+            let bootstrap_method_attr_index = read_u16(bytecode, index)?;
+            let name_and_type_index = read_u16(bytecode, index)?;
             CpEntry::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index)
         }
-        // 19 Module,
-        // 20 Package,
-        _ => panic!("cp entry type not recognized"),
+        19 => {
+            let name_index = read_u16(bytecode, index)?;
+            CpEntry::Module(name_index)
+        }
+        20 => {
+            let name_index = read_u16(bytecode, index)?;
+            CpEntry::Package(name_index)
+        }
+        _ => {
+            return Err(ClassFormatError::new(
+                tag_index,
+                format!("invalid constant pool tag {}", tag),
+            ))
+        }
+    })
+}
+
+/// the utf8 constant at the index, for names and descriptors
+fn utf8_at(
+    constant_pool: &HashMap<u16, CpEntry>,
+    cp_index: u16,
+    offset: usize,
+) -> Result<&String, ClassFormatError> {
+    match constant_pool.get(&cp_index) {
+        Some(CpEntry::Utf8(s, _)) => Ok(s),
+        _ => Err(ClassFormatError::failed(
+            FormatCheck::ConstantPool,
+            offset,
+            format!("constant pool index {} is not a utf8 constant", cp_index),
+        )),
     }
 }
 
+/// access flags, name index and descriptor index of a field or method
+fn read_member_header(
+    bytecode: &[u8],
+    index: &mut usize,
+) -> Result<(u16, u16, u16), ClassFormatError> {
+    Ok((
+        read_u16(bytecode, index)?,
+        read_u16(bytecode, index)?,
+        read_u16(bytecode, index)?,
+    ))
+}
+
 fn read_field(
     constant_pool: Rc<HashMap<u16, CpEntry>>,
     index: &mut usize,
     bytecode: &[u8],
     field_index: u16,
-) -> Field {
-    let access_flags = read_u16(bytecode, index);
-    let name_index = read_u16(bytecode, index);
-    let descriptor_index = read_u16(bytecode, index);
-    let attributes_count = read_u16(bytecode, index);
-    let mut attributes = HashMap::new();
-    for _ in 0..attributes_count {
-        if let Some(att) = read_attribute(constant_pool.clone(), bytecode, index) {
-            attributes.insert(att.0, att.1);
-        } else {
-            panic!("attribute not recognized"); // bug/not-implemented
-        }
-    }
-    Field::new(
+    checker: &FormatChecker,
+) -> Result<Field, ClassFormatError> {
+    let start = *index;
+    let (access_flags, name_index, descriptor_index) = read_member_header(bytecode, index)
+        .map_err(|error| error.within(format!("field #{}", field_index)))?;
+    let name = utf8_at(&constant_pool, name_index, start + 2)
+        .map_err(|error| error.within(format!("field #{}", field_index)))?;
+    let descriptor = utf8_at(&constant_pool, descriptor_index, start + 4)
+        .map_err(|error| error.within(format!("field {}", name)))?;
+    checker
+        .check_field(start, access_flags, name, descriptor)
+        .map_err(|error| error.within(format!("field {}", name)))?;
+
+    let attributes = read_attributes(constant_pool.clone(), bytecode, index)
+        .map_err(|error| error.within(format!("field {}", name)))?;
+    Ok(Field::new(
         constant_pool,
         access_flags,
         name_index,
         descriptor_index,
         attributes,
         field_index,
-    )
+    ))
 }
 
 fn read_method(
     constant_pool: Rc<HashMap<u16, CpEntry>>,
     index: &mut usize,
     bytecode: &[u8],
-) -> Method {
-    let access_flags = read_u16(bytecode, index);
-    let name_index = read_u16(bytecode, index);
-    let name = constant_pool.get(&name_index);
-    let descriptor_index = read_u16(bytecode, index);
-    let attributes_count = read_u16(bytecode, index);
-
-    let mut attributes = HashMap::new();
-    for _ in 0..attributes_count {
-        if let Some(att) = read_attribute(constant_pool.clone(), bytecode, index) {
-            attributes.insert(att.0, att.1);
-        }
-    }
+    method_index: u16,
+    checker: &FormatChecker,
+) -> Result<Method, ClassFormatError> {
+    let start = *index;
+    let (access_flags, name_index, descriptor_index) = read_member_header(bytecode, index)
+        .map_err(|error| error.within(format!("method #{}", method_index)))?;
+    let name = utf8_at(&constant_pool, name_index, start + 2)
+        .map_err(|error| error.within(format!("method #{}", method_index)))?;
+    let descriptor = utf8_at(&constant_pool, descriptor_index, start + 4)
+        .map_err(|error| error.within(format!("method {}", name)))?;
+    let method = format!("method {}{}", name, descriptor);
 
-    let code = if let Some(AttributeType::Code(code)) = attributes.get("Code") {
-        parse_code(&code.opcodes)
-    } else {
-        vec![]
-    };
+    let attributes = read_attributes(constant_pool.clone(), bytecode, index)
+        .map_err(|error| error.within(&method))?;
+    checker
+        .check_method(
+            start,
+            access_flags,
+            name,
+            descriptor,
+            attributes.contains("Code"),
+        )
+        .map_err(|error| error.within(&method))?;
 
-    Method::new(
+    Ok(Method::new(
         constant_pool,
         access_flags,
         name_index,
         descriptor_index,
         attributes,
-        code,
-    )
+        method_index,
+    ))
+}
+
+/// reads an attributes count, followed by that many attributes
+fn read_attributes(
+    constant_pool: Rc<HashMap<u16, CpEntry>>,
+    bytecode: &[u8],
+    index: &mut usize,
+) -> Result<Attributes, ClassFormatError> {
+    let attributes_count = read_u16(bytecode, index)?;
+    let mut attributes = Attributes::new();
+    for _ in 0..attributes_count {
+        let (name, attribute) = read_attribute(constant_pool.clone(), bytecode, index)?;
+        attributes.push(name, attribute);
+    }
+    Ok(attributes)
 }
 
+/// reads the attribute, the ones that are not in the JVMS are kept as raw bytes
+/// the attribute is parsed in place, with the data cut off at the attribute length,
+/// so that offsets stay relative to the class file
 fn read_attribute(
     constant_pool: Rc<HashMap<u16, CpEntry>>,
     bytecode: &[u8],
     index: &mut usize,
-) -> Option<(String, AttributeType)> {
-    let attribute_name_index = read_u16(bytecode, index);
-    let attribute_length = read_u32(bytecode, index) as usize;
-    let info: Vec<u8> = Vec::from(&bytecode[*index..*index + attribute_length]);
-    *index += attribute_length;
-
-    if let CpEntry::Utf8(s) = &constant_pool.get(&attribute_name_index).unwrap() {
-        return match s.as_str() {
-            "ConstantValue" => {
-                assert_eq!(info.len(), 2);
-                Some((
-                    "ConstantValue".into(),
-                    AttributeType::ConstantValue(read_u16(&info, &mut 0)),
-                ))
-            }
-            "Code" => {
-                let ci = &mut 0;
-                let max_stack = read_u16(&info, ci);
-                let max_locals = read_u16(&info, ci);
-                let code_length = read_u32(&info, ci) as usize;
-                let code = read_bytes(&info, ci, code_length);
-                let exception_table_length = read_u16(&info, ci) as usize;
-
-                let mut exception_table = vec![];
-                for _ in 0..exception_table_length {
-                    exception_table.push(Exception::read(&info, ci));
-                }
-                let attribute_count = read_u16(&info, ci);
-                let mut code_attributes = HashMap::new();
-                for _ in 0..attribute_count {
-                    if let Some(att) = read_attribute(constant_pool.clone(), &info, ci) {
-                        code_attributes.insert(att.0, att.1);
-                    }
+) -> Result<(String, AttributeType), ClassFormatError> {
+    let start = *index;
+    let attribute_name_index = read_u16(bytecode, index)?;
+    let attribute_length = read_u32(bytecode, index)? as usize;
+    let name = utf8_at(&constant_pool, attribute_name_index, start)?;
+    let end = index
+        .checked_add(attribute_length)
+        .filter(|end| *end <= bytecode.len())
+        .ok_or_else(|| ClassFormatError::truncated(*index).within(format!("attribute {}", name)))?;
+    let info = &bytecode[..end];
+    let ci = &mut index.clone();
+    *index = end;
+
+    let attribute = read_attribute_info(constant_pool.clone(), name, info, ci)
+        .map_err(|error| error.within(format!("attribute {}", name)))?;
+    if *ci != end {
+        return Err(
+            ClassFormatError::new(*ci, "attribute length does not match its contents")
+                .within(format!("attribute {}", name)),
+        );
+    }
+    Ok((name.to_owned(), attribute))
+}
+
+fn read_attribute_info(
+    constant_pool: Rc<HashMap<u16, CpEntry>>,
+    name: &str,
+    info: &[u8],
+    ci: &mut usize,
+) -> Result<AttributeType, ClassFormatError> {
+    Ok(match name {
+        "ConstantValue" => AttributeType::ConstantValue(read_u16(info, ci)?),
+        "Code" => {
+            let max_stack = read_u16(info, ci)?;
+            let max_locals = read_u16(info, ci)?;
+            let code_length = read_u32(info, ci)? as usize;
+            let code_start = *ci;
+            let code = read_bytes(info, ci, code_length)?;
+            let (instructions, opcode_indices) =
+                parse_code(&code).map_err(|error| error.offset_by(code_start))?;
+
+            let exception_table_length = read_u16(info, ci)? as usize;
+            let mut exception_table = vec![];
+            for _ in 0..exception_table_length {
+                let entry_start = *ci;
+                let exception = Exception::read(info, ci)?;
+                if [exception.start_pc, exception.end_pc, exception.handler_pc]
+                    .iter()
+                    .any(|pc| !opcode_indices.contains_key(pc))
+                {
+                    return Err(ClassFormatError::new(
+                        entry_start,
+                        "invalid pc in exception table",
+                    ));
                 }
-                Some((
-                    "Code".into(),
-                    AttributeType::Code(Box::new(MethodCode::new(
-                        max_stack,
-                        max_locals,
-                        code,
-                        exception_table,
-                        code_attributes,
-                    ))),
-                ))
+                exception_table.push(exception);
+            }
+            let code_attributes = read_attributes(constant_pool, info, ci)?;
+            AttributeType::Code(Box::new(MethodCode::new(
+                max_stack,
+                max_locals,
+                code,
+                instructions,
+                opcode_indices,
+                exception_table,
+                code_attributes,
+            )))
+        }
+        "StackMapTable" => AttributeType::StackMapTable(read_table(info, ci, StackMapFrame::read)?),
+        "BootstrapMethods" => {
+            AttributeType::BootstrapMethods(read_table(info, ci, BootstrapMethod::read)?)
+        }
+        "NestHost" => AttributeType::NestHost(read_u16(info, ci)?),
+        "NestMembers" => AttributeType::NestMembers(read_table(info, ci, read_u16)?),
+        "PermittedSubclasses" => {
+            AttributeType::PermittedSubclasses(read_table(info, ci, read_u16)?)
+        }
+        "Exceptions" => AttributeType::Exceptions(read_table(info, ci, read_u16)?),
+        "InnerClasses" => AttributeType::InnerClasses(read_table(info, ci, InnerClass::read)?),
+        "EnclosingMethod" => AttributeType::EnclosingMethod(EnclosingMethod::read(info, ci)?),
+        "Synthetic" => AttributeType::Synthetic,
+        "Signature" => AttributeType::Signature(read_u16(info, ci)?),
+        "Record" => {
+            let components_count = read_u16(info, ci)?;
+            let mut components = vec![];
+            for i in 0..components_count {
+                let component = (|| {
+                    Ok(RecordComponent {
+                        name_index: read_u16(info, ci)?,
+                        descriptor_index: read_u16(info, ci)?,
+                        attributes: read_attributes(constant_pool.clone(), info, ci)?,
+                    })
+                })()
+                .map_err(|error: ClassFormatError| {
+                    error.within(format!("record component #{}", i))
+                })?;
+                components.push(component);
+            }
+            AttributeType::Record(components)
+        }
+        "SourceFile" => AttributeType::SourceFile(read_u16(info, ci)?),
+        "SourceDebugExtension" => {
+            let debug_extension = info[*ci..].to_vec();
+            *ci = info.len();
+            AttributeType::SourceDebugExtension(debug_extension)
+        }
+        "LineNumberTable" => {
+            AttributeType::LineNumberTable(read_table(info, ci, LineNumber::read)?)
+        }
+        "LocalVariableTable" => {
+            AttributeType::LocalVariableTable(read_table(info, ci, LocalVariable::read)?)
+        }
+        "LocalVariableTypeTable" => {
+            AttributeType::LocalVariableTypeTable(read_table(info, ci, LocalVariable::read)?)
+        }
+        "Deprecated" => AttributeType::Deprecated,
+        "RuntimeVisibleAnnotations" => {
+            AttributeType::RuntimeVisibleAnnotations(read_table(info, ci, Annotation::read)?)
+        }
+        "RuntimeInvisibleAnnotations" => {
+            AttributeType::RuntimeInvisibleAnnotations(read_table(info, ci, Annotation::read)?)
+        }
+        "RuntimeVisibleParameterAnnotations" => {
+            AttributeType::RuntimeVisibleParameterAnnotations(read_parameter_annotations(info, ci)?)
+        }
+        "RuntimeInvisibleParameterAnnotations" => {
+            AttributeType::RuntimeInvisibleParameterAnnotations(read_parameter_annotations(
+                info, ci,
+            )?)
+        }
+        "RuntimeVisibleTypeAnnotations" => AttributeType::RuntimeVisibleTypeAnnotations(
+            read_table(info, ci, TypeAnnotation::read)?,
+        ),
+        "RuntimeInvisibleTypeAnnotations" => AttributeType::RuntimeInvisibleTypeAnnotations(
+            read_table(info, ci, TypeAnnotation::read)?,
+        ),
+        "AnnotationDefault" => AttributeType::AnnotationDefault(ElementValue::read(info, ci)?),
+        "MethodParameters" => {
+            let parameters_count = read_u8(info, ci)?;
+            let mut parameters = vec![];
+            for _ in 0..parameters_count {
+                parameters.push(MethodParameter::read(info, ci)?);
             }
-            "SourceFile" => Some(("SourceFile".into(), AttributeType::SourceFile)), //stub
-            "LineNumberTable" => Some(("SourceFile".into(), AttributeType::LineNumberTable)), //stub
-            "RuntimeVisibleAnnotations" => {
-                Some(("".into(), AttributeType::RuntimeInvisibleAnnotations))
-            } //stub
-            "NestMembers" => Some(("".into(), AttributeType::NestMembers)),         //stub
-            "BootstrapMethods" => Some(("".into(), AttributeType::BootstrapMethods)), //stub
-            "InnerClasses" => Some(("".into(), AttributeType::InnerClasses)),       //stub
-            "Signature" => Some(("".into(), AttributeType::Signature)),             //stub
-            "NestHost" => Some(("".into(), AttributeType::NestHost)),               //stub
-            "EnclosingMethod" => Some(("".into(), AttributeType::EnclosingMethod)), //stub
-            "PermittedSubclasses" => Some(("".into(), AttributeType::PermittedSubclasses)), //stub
-            //TODO more actual attribute implementations
-            _ => None,
+            AttributeType::MethodParameters(parameters)
+        }
+        "Module" => AttributeType::Module(Module::read(info, ci)?),
+        "ModulePackages" => AttributeType::ModulePackages(read_table(info, ci, read_u16)?),
+        "ModuleMainClass" => AttributeType::ModuleMainClass(read_u16(info, ci)?),
+        // other attributes are kept as they are, as the JVMS requires
+        _ => {
+            let contents = info[*ci..].to_vec();
+            *ci = info.len();
+            AttributeType::Unknown(contents)
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::classloader::builder::ClassBuilder;
+
+    #[test]
+    fn malformed_class_file() {
+        let bytecode = include_bytes!("../../tests/testclasses/Int.class");
+
+        let error = load_class(bytecode[..100].to_vec()).err().unwrap();
+        assert_eq!("truncated class file", error.message);
+        assert!(error.structure.starts_with("constant pool entry #"));
+
+        let mut bytecode = bytecode.to_vec();
+        bytecode[0] = 0;
+        let error = load_class(bytecode).err().unwrap();
+        assert_eq!(0, error.offset);
+        assert_eq!("incompatible magic value 0xFEBABE", error.message);
+    }
+
+    #[test]
+    fn unsupported_class_version() {
+        let mut bytecode = include_bytes!("../../tests/testclasses/Int.class").to_vec();
+        bytecode[7] = 66;
+        let error = load_class(bytecode).err().unwrap();
+        assert_eq!(FormatCheck::Version, error.check);
+        assert_eq!(6, error.offset);
+        assert!(error.message.contains("(class file version 66.0)"));
+    }
+
+    #[test]
+    fn failed_format_checks() {
+        let check = |build: fn(&mut ClassBuilder)| {
+            let mut class = ClassBuilder::new("Test", Some("java/lang/Object"));
+            build(&mut class);
+            load_class(class.to_bytes().unwrap()).err().unwrap()
         };
+
+        let error = check(|class| {
+            class.field(0x0003, "both", "I");
+        });
+        assert_eq!(FormatCheck::AccessFlags, error.check);
+        assert_eq!("field both", error.structure);
+
+        let error = check(|class| {
+            class.field(0x0001, "name", "Ljava/lang/String");
+        });
+        assert_eq!(FormatCheck::Descriptor, error.check);
+
+        let error = check(|class| {
+            class.field(0x0001, "a.b", "I");
+        });
+        assert_eq!(FormatCheck::Name, error.check);
+
+        let error = check(|class| {
+            class
+                .field(0x0001, "twice", "I")
+                .field(0x0001, "twice", "I");
+        });
+        assert_eq!(FormatCheck::Members, error.check);
+
+        let error = check(|class| {
+            let test = class.class("Test");
+            let name_and_type = class.utf8("not a name and type");
+            class.constant(CpEntry::Fieldref(test, name_and_type));
+        });
+        assert_eq!(FormatCheck::ConstantPool, error.check);
+        assert!(error.structure.starts_with("constant pool entry #"));
+    }
+
+    #[test]
+    fn dynamic_module_and_package_entries() {
+        let read = |bytes: &[u8]| read_constant_pool_entry(&mut 1, &mut 0, bytes).unwrap();
+        assert!(matches!(read(&[17, 0, 1, 0, 2]), CpEntry::Dynamic(1, 2)));
+        assert!(matches!(read(&[19, 0, 3]), CpEntry::Module(3)));
+        assert!(matches!(read(&[20, 0, 4]), CpEntry::Package(4)));
     }
-    None
 }
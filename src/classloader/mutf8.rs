@@ -0,0 +1,98 @@
+use crate::classloader::error::ClassFormatError;
+
+// Modified UTF-8, the encoding of strings in class files (JVMS 4.4.7).
+// It differs from standard UTF-8 in that NUL is encoded in two bytes (0xC0 0x80), so that
+// the encoded string never contains a zero byte, and that characters outside the basic
+// multilingual plane are encoded as their UTF-16 surrogate pair, three bytes each.
+// Decoding therefore yields UTF-16 code units, like java.lang.String holds.
+
+/// decodes to UTF-16 code units, the offset in the error is relative to the start of the bytes
+pub fn decode(bytes: &[u8]) -> Result<Vec<u16>, ClassFormatError> {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let continuation = |index: usize| match bytes.get(index) {
+            Some(byte) if byte & 0xC0 == 0x80 => Ok((byte & 0x3F) as u16),
+            _ => Err(ClassFormatError::new(start, "illegal utf8 string")),
+        };
+        let byte = bytes[pos];
+        let unit = match byte {
+            0x01..=0x7F => {
+                pos += 1;
+                byte as u16
+            }
+            0xC0..=0xDF => {
+                pos += 2;
+                ((byte & 0x1F) as u16) << 6 | continuation(start + 1)?
+            }
+            0xE0..=0xEF => {
+                pos += 3;
+                ((byte & 0x0F) as u16) << 12
+                    | continuation(start + 1)? << 6
+                    | continuation(start + 2)?
+            }
+            // zero bytes and 4 byte forms do not occur
+            _ => return Err(ClassFormatError::new(start, "illegal utf8 string")),
+        };
+        units.push(unit);
+    }
+    Ok(units)
+}
+
+/// encodes UTF-16 code units, lone surrogates included
+pub fn encode(units: &[u16]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(units.len());
+    for unit in units {
+        match unit {
+            0x0001..=0x007F => bytes.push(*unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | (unit >> 6 & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+    bytes
+}
+
+/// encodes a rust string, eg. a name or a descriptor
+pub fn encode_str(string: &str) -> Vec<u8> {
+    encode(&string.encode_utf16().collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nul_and_supplementary_characters() {
+        let string = "a\0é€😀";
+        let bytes = encode_str(string);
+        assert_eq!(
+            vec![
+                0x61, 0xC0, 0x80, 0xC3, 0xA9, 0xE2, 0x82, 0xAC, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80
+            ],
+            bytes
+        );
+        let units = decode(&bytes).unwrap();
+        assert_eq!(string.encode_utf16().collect::<Vec<_>>(), units);
+    }
+
+    #[test]
+    fn lone_surrogates_round_trip() {
+        let units = vec![0xD800, 0x41, 0xDFFF];
+        assert_eq!(units, decode(&encode(&units)).unwrap());
+    }
+
+    #[test]
+    fn illegal_bytes() {
+        assert_eq!(1, decode(&[0x41, 0x00]).err().unwrap().offset);
+        assert_eq!(0, decode(&[0xF0, 0x9F, 0x98, 0x80]).err().unwrap().offset);
+        assert_eq!(1, decode(&[0x41, 0xE2, 0x82]).err().unwrap().offset);
+    }
+}
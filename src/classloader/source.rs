@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+
+use crate::classloader::io::{archive_contains, read_archive_entry, PATH_SEPARATOR};
+use crate::classloader::jimage::{image_contains, image_packages, read_image_resource};
+
+/// A place that class files are loaded from.
+/// The ClassManager asks its sources in order, the first one that has the class wins.
+pub trait ClassSource {
+    /// the bytecode of the class (name with slashes), or None if this source does not have it
+    fn read_class(&self, class_name: &str) -> Result<Option<Vec<u8>>, Error>;
+}
+
+/// turns a classpath string into sources, directories and jar/zip files
+/// entries that do not exist are left out
+pub fn classpath_sources(classpath: &str) -> Vec<Box<dyn ClassSource>> {
+    classpath
+        .split(PATH_SEPARATOR)
+        .filter_map(|entry| -> Option<Box<dyn ClassSource>> {
+            let path = Path::new(entry);
+            if path.is_dir() {
+                Some(Box::new(DirectorySource::new(path)))
+            } else if path.is_file() {
+                Some(Box::new(JarSource::new(entry)))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// class files in a directory tree, at [dir]/[package_path]/[class].class
+pub struct DirectorySource {
+    dir: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl ClassSource for DirectorySource {
+    fn read_class(&self, class_name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.dir.join(format!("{}.class", class_name));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read(path)?))
+    }
+}
+
+/// class files in a jar or zip, at [package_path]/[class].class
+pub struct JarSource {
+    path: String,
+}
+
+impl JarSource {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl ClassSource for JarSource {
+    fn read_class(&self, class_name: &str) -> Result<Option<Vec<u8>>, Error> {
+        read_entry(&self.path, &format!("{}.class", class_name))
+    }
+}
+
+/// class files in a jmod, at classes/[package_path]/[class].class
+pub struct JmodSource {
+    path: String,
+}
+
+impl JmodSource {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl ClassSource for JmodSource {
+    fn read_class(&self, class_name: &str) -> Result<Option<Vec<u8>>, Error> {
+        read_entry(&self.path, &format!("classes/{}.class", class_name))
+    }
+}
+
+fn read_entry(archive_path: &str, entry_name: &str) -> Result<Option<Vec<u8>>, Error> {
+    if !archive_contains(archive_path, entry_name)? {
+        return Ok(None);
+    }
+    Ok(Some(read_archive_entry(archive_path, entry_name)?))
+}
+
+/// class files in a jimage (lib/modules), at /[module]/[package_path]/[class].class
+pub struct JImageSource {
+    path: String,
+    // package name (with slashes) -> module
+    modules: HashMap<String, String>,
+}
+
+impl JImageSource {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        Ok(Self {
+            path: path.to_owned(),
+            modules: image_packages(path)?.into_iter().collect(),
+        })
+    }
+
+    /// the packages in the image
+    pub fn packages(&self) -> impl Iterator<Item = &String> {
+        self.modules.keys()
+    }
+}
+
+impl ClassSource for JImageSource {
+    fn read_class(&self, class_name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let Some(module) = class_name
+            .rsplit_once('/')
+            .and_then(|(package, _)| self.modules.get(package))
+        else {
+            return Ok(None);
+        };
+        let resource = format!("/{}/{}.class", module, class_name);
+        if !image_contains(&self.path, &resource)? {
+            return Ok(None);
+        }
+        Ok(Some(read_image_resource(&self.path, &resource)?))
+    }
+}
+
+/// classes that are registered in memory, for instance generated or included in the binary
+/// with include_bytes!
+#[derive(Default)]
+pub struct MemorySource {
+    classes: HashMap<String, Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// registers the bytecode for the class (name with slashes or dots)
+    pub fn add(&mut self, class_name: &str, bytecode: impl Into<Vec<u8>>) {
+        self.classes
+            .insert(class_name.replace('.', "/"), bytecode.into());
+    }
+}
+
+impl ClassSource for MemorySource {
+    fn read_class(&self, class_name: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.classes.get(class_name).cloned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::classloader::get_classdef;
+
+    #[test]
+    fn load_class_from_memory() {
+        let mut source = MemorySource::new();
+        source.add(
+            "testclasses.Int",
+            &include_bytes!("../../tests/testclasses/Int.class")[..],
+        );
+        let sources: Vec<Box<dyn ClassSource>> = vec![Box::new(source)];
+
+        let classdef = get_classdef(&sources, "testclasses/Int").unwrap();
+        assert_eq!("testclasses/Int", classdef.name());
+        assert!(get_classdef(&sources, "testclasses/Double").is_err());
+    }
+
+    #[test]
+    fn load_class_from_jar() {
+        use std::io::Write;
+        use zip::write::FileOptions;
+        use zip::CompressionMethod;
+
+        let int = &include_bytes!("../../tests/testclasses/Int.class")[..];
+        let double = &include_bytes!("../../tests/testclasses/Double.class")[..];
+        let path = std::env::temp_dir().join(format!("java_rs_test_{}.jar", std::process::id()));
+        let mut jar = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (name, bytecode, compression) in [
+            ("testclasses/Int.class", int, CompressionMethod::Stored),
+            (
+                "testclasses/Double.class",
+                double,
+                CompressionMethod::Deflated,
+            ),
+        ] {
+            let options = FileOptions::default().compression_method(compression);
+            jar.start_file(name, options).unwrap();
+            jar.write_all(bytecode).unwrap();
+        }
+        jar.finish().unwrap();
+
+        let source = JarSource::new(path.to_str().unwrap());
+        assert_eq!(
+            Some(int.to_vec()),
+            source.read_class("testclasses/Int").unwrap()
+        );
+        // the archive is opened and indexed once, so it is still readable when the file is gone
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Some(double.to_vec()),
+            source.read_class("testclasses/Double").unwrap()
+        );
+        assert_eq!(None, source.read_class("testclasses/Float").unwrap());
+
+        let sources: Vec<Box<dyn ClassSource>> = vec![Box::new(source)];
+        let classdef = get_classdef(&sources, "testclasses/Double").unwrap();
+        assert_eq!("testclasses/Double", classdef.name());
+    }
+}
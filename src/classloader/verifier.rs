@@ -0,0 +1,1472 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::classloader::attributes::{StackMapFrame, VerificationType};
+use crate::classloader::classdef::{
+    AttributeType, ClassDef, CpEntry, Method, MethodCode, Modifier,
+};
+use crate::classloader::code_parser::get_opcode;
+use crate::classloader::error::VerifyError;
+use crate::vm::opcodes::Opcode::{self, *};
+
+// The type checking verifier of JVMS 4.10.1. Every method is checked in one pass over its code,
+// with the types in the StackMapTable as the frames at the branch targets and exception handlers.
+// Class files before version 50 have no StackMapTable and would need verification by type
+// inference (JVMS 4.10.2), which is not implemented, so they are reported as not verified.
+// So are version 50 classes that fail type checking, where the JVMS falls back to type inference.
+// The check on protected members (JVMS 4.10.1.8) is not done.
+
+/// what the verifier needs to know about classes other than the one that is verified,
+/// to check whether a class type is assignable to another one
+pub trait ClassHierarchy {
+    /// the name of the superclass (None for java/lang/Object) and whether the class is an
+    /// interface, or None if the class cannot be loaded
+    fn class_info(&mut self, class_name: &str) -> Option<(Option<String>, bool)>;
+}
+
+/// the outcome of a verification that found no error
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verification {
+    Verified,
+    /// the code was not (fully) checked, with the reason
+    NotVerified(String),
+}
+
+/// checks the code of all methods in the class
+pub fn verify(
+    classdef: &ClassDef,
+    hierarchy: &mut dyn ClassHierarchy,
+) -> Result<Verification, VerifyError> {
+    if classdef.major_version < 50 {
+        return Ok(Verification::NotVerified(format!(
+            "class file version {} needs verification by type inference",
+            classdef.major_version
+        )));
+    }
+    let mut methods: Vec<&Method> = classdef.methods.values().collect();
+    methods.sort_by_key(|method| method.index);
+    for method in methods {
+        let Some(AttributeType::Code(code)) = method.attributes.get("Code") else {
+            continue;
+        };
+        let mut verifier = MethodVerifier::new(classdef, method, code, hierarchy);
+        if let Err(message) = verifier.verify() {
+            // version 50 falls back to verification by type inference (JVMS 4.10)
+            if classdef.major_version == 50 {
+                return Ok(Verification::NotVerified(format!(
+                    "{} in method {} fails type checking at offset {}, and version 50 falls \
+                     back to verification by type inference",
+                    message,
+                    method.name(),
+                    verifier.offset
+                )));
+            }
+            return Err(VerifyError {
+                class_name: classdef.name().to_owned(),
+                method: method.name(),
+                offset: verifier.offset,
+                message,
+            });
+        }
+    }
+    Ok(Verification::Verified)
+}
+
+/// the verification type of a local variable or an entry on the operand stack
+/// long and double take two local variables, the second one is Top
+#[derive(Clone, Debug, PartialEq)]
+enum Type {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    // the object created by the NEW at the byte offset
+    Uninitialized(usize),
+    // a class name, or the descriptor of an array
+    Reference(String),
+}
+
+use Type::*;
+
+impl Type {
+    fn reference(name: &str) -> Self {
+        Reference(name.to_owned())
+    }
+
+    /// the type for a field descriptor, boolean, byte, char and short are int
+    fn of(descriptor: &str) -> Result<Self, String> {
+        match descriptor {
+            "B" | "C" | "I" | "S" | "Z" => Ok(Integer),
+            "F" => Ok(Float),
+            "J" => Ok(Long),
+            "D" => Ok(Double),
+            _ if descriptor.starts_with('[') && descriptor.len() > 1 => {
+                Ok(Type::reference(descriptor))
+            }
+            _ if descriptor.starts_with('L') && descriptor.ends_with(';') => {
+                Ok(Type::reference(&descriptor[1..descriptor.len() - 1]))
+            }
+            _ => Err(format!("invalid descriptor {}", descriptor)),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Long | Double => 2,
+            _ => 1,
+        }
+    }
+
+    fn is_reference(&self) -> bool {
+        matches!(
+            self,
+            Null | UninitializedThis | Uninitialized(_) | Reference(_)
+        )
+    }
+
+    fn is_array(&self) -> bool {
+        matches!(self, Reference(name) if name.starts_with('['))
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Top => write!(f, "top"),
+            Integer => write!(f, "int"),
+            Float => write!(f, "float"),
+            Long => write!(f, "long"),
+            Double => write!(f, "double"),
+            Null => write!(f, "null"),
+            UninitializedThis => write!(f, "uninitializedThis"),
+            Uninitialized(offset) => write!(f, "uninitialized({})", offset),
+            Reference(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// the types of the local variables and the operand stack before an instruction
+#[derive(Clone, Debug)]
+struct Frame {
+    locals: Vec<Type>,
+    stack: Vec<Type>,
+}
+
+impl Frame {
+    /// the size of the stack, long and double count twice
+    fn stack_size(&self) -> usize {
+        self.stack.iter().map(Type::size).sum()
+    }
+
+    /// whether this is not yet initialized in a constructor, flagThisUninit in the JVMS
+    fn this_uninitialized(&self) -> bool {
+        self.locals.contains(&UninitializedThis)
+    }
+
+    /// the locals and the stack after the constructor has been called on the object
+    fn initialize(&mut self, uninitialized: &Type, initialized: &Type) {
+        for t in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if t == uninitialized {
+                *t = initialized.clone();
+            }
+        }
+    }
+}
+
+struct MethodVerifier<'a> {
+    classdef: &'a ClassDef,
+    method: &'a Method,
+    code: &'a MethodCode,
+    hierarchy: &'a mut dyn ClassHierarchy,
+    // the instructions by byte offset
+    instructions: BTreeMap<usize, Opcode>,
+    // the frames from the StackMapTable by byte offset
+    stack_map: BTreeMap<usize, Frame>,
+    // None for void
+    return_type: Option<Type>,
+    is_constructor: bool,
+    // the byte offset of the instruction that is being checked, for the error
+    offset: usize,
+}
+
+impl<'a> MethodVerifier<'a> {
+    fn new(
+        classdef: &'a ClassDef,
+        method: &'a Method,
+        code: &'a MethodCode,
+        hierarchy: &'a mut dyn ClassHierarchy,
+    ) -> Self {
+        Self {
+            classdef,
+            method,
+            code,
+            hierarchy,
+            instructions: BTreeMap::new(),
+            stack_map: BTreeMap::new(),
+            return_type: None,
+            is_constructor: false,
+            offset: 0,
+        }
+    }
+
+    /// returns the error message, the offset of the error is in self.offset
+    fn verify(&mut self) -> Result<(), String> {
+        let name = self.classdef.cp_utf8(&self.method.name_index).as_str();
+        let descriptor = self.classdef.cp_utf8(&self.method.descriptor_index);
+        let (parameters, return_type) = method_descriptor(descriptor)?;
+        self.return_type = return_type;
+        self.is_constructor = name == "<init>";
+
+        let mut offset = 0;
+        while offset < self.code.opcodes.len() {
+            self.offset = offset;
+            let opcode =
+                get_opcode(&self.code.opcodes, &mut offset).map_err(|error| error.message)?;
+            self.instructions.insert(self.offset, opcode);
+        }
+
+        let mut locals = vec![];
+        if !self.method.is(Modifier::Static) {
+            locals.push(
+                if self.is_constructor && self.classdef.name() != "java/lang/Object" {
+                    UninitializedThis
+                } else {
+                    Type::reference(self.classdef.name())
+                },
+            );
+        }
+        locals.extend(parameters);
+        self.offset = 0;
+        let initial_frame = self
+            .frame(&locals, vec![])
+            .map_err(|_| "the parameters do not fit in max_locals".to_owned())?;
+        self.stack_map = self.read_stack_map(locals)?;
+        self.check_exception_table()?;
+
+        let mut current = Some(initial_frame);
+        let offsets: Vec<usize> = self.instructions.keys().copied().collect();
+        for offset in offsets {
+            self.offset = offset;
+            if let Some(stack_map_frame) = self.stack_map.get(&offset).cloned() {
+                if let Some(frame) = &current {
+                    self.check_frame(frame, &stack_map_frame, "")?;
+                }
+                current = Some(stack_map_frame);
+            }
+            let mut frame = current
+                .take()
+                .ok_or("no stack map frame after an unconditional branch")?;
+            self.check_handlers(&frame)?;
+            let opcode = self.instructions[&offset].clone();
+            if self.execute(&mut frame, offset, &opcode)? {
+                current = Some(frame);
+            }
+        }
+        if current.is_some() {
+            self.offset = self.code.opcodes.len();
+            return Err("falling off the end of the code".into());
+        }
+        Ok(())
+    }
+
+    /// the frame for the locals, where long and double are one entry,
+    /// filled up with Top to max_locals
+    fn frame(&self, locals: &[Type], stack: Vec<Type>) -> Result<Frame, String> {
+        let mut expanded = vec![];
+        for local in locals {
+            expanded.push(local.clone());
+            if local.size() == 2 {
+                expanded.push(Top);
+            }
+        }
+        let max_locals = self.code.max_locals as usize;
+        if expanded.len() > max_locals {
+            return Err(format!(
+                "{} local variables, more than max_locals {}",
+                expanded.len(),
+                max_locals
+            ));
+        }
+        expanded.resize(max_locals, Top);
+        let frame = Frame {
+            locals: expanded,
+            stack,
+        };
+        if frame.stack_size() > self.code.max_stack as usize {
+            return Err(format!(
+                "stack size {}, more than max_stack {}",
+                frame.stack_size(),
+                self.code.max_stack
+            ));
+        }
+        Ok(frame)
+    }
+
+    /// the frames in the StackMapTable, starting with the locals of the initial frame
+    fn read_stack_map(&mut self, mut locals: Vec<Type>) -> Result<BTreeMap<usize, Frame>, String> {
+        let frames: &[StackMapFrame] = match self.code.code_attributes.get("StackMapTable") {
+            Some(AttributeType::StackMapTable(frames)) => frames,
+            _ => &[],
+        };
+        let mut stack_map = BTreeMap::new();
+        let mut previous_offset = None;
+        for stack_map_frame in frames {
+            let delta = stack_map_frame.offset_delta() as usize;
+            let offset = previous_offset.map_or(delta, |previous| previous + delta + 1);
+            self.offset = offset;
+            let stack = match stack_map_frame {
+                StackMapFrame::Same { .. } | StackMapFrame::SameExtended { .. } => vec![],
+                StackMapFrame::SameLocals1StackItem { stack, .. }
+                | StackMapFrame::SameLocals1StackItemExtended { stack, .. } => {
+                    vec![self.verification_type(stack)?]
+                }
+                StackMapFrame::Chop { absent_locals, .. } => {
+                    let absent_locals = *absent_locals as usize;
+                    if absent_locals > locals.len() {
+                        return Err("stack map frame removes more locals than there are".into());
+                    }
+                    locals.truncate(locals.len() - absent_locals);
+                    vec![]
+                }
+                StackMapFrame::Append {
+                    locals: appended, ..
+                } => {
+                    for local in appended {
+                        locals.push(self.verification_type(local)?);
+                    }
+                    vec![]
+                }
+                StackMapFrame::Full {
+                    locals: full_locals,
+                    stack,
+                    ..
+                } => {
+                    locals = full_locals
+                        .iter()
+                        .map(|local| self.verification_type(local))
+                        .collect::<Result<_, _>>()?;
+                    stack
+                        .iter()
+                        .map(|entry| self.verification_type(entry))
+                        .collect::<Result<_, _>>()?
+                }
+            };
+            if !self.instructions.contains_key(&offset) {
+                return Err("stack map frame is not at the start of an instruction".into());
+            }
+            stack_map.insert(offset, self.frame(&locals, stack)?);
+            previous_offset = Some(offset);
+        }
+        Ok(stack_map)
+    }
+
+    fn verification_type(&self, verification_type: &VerificationType) -> Result<Type, String> {
+        Ok(match verification_type {
+            VerificationType::Top => Top,
+            VerificationType::Integer => Integer,
+            VerificationType::Float => Float,
+            VerificationType::Long => Long,
+            VerificationType::Double => Double,
+            VerificationType::Null => Null,
+            VerificationType::UninitializedThis => UninitializedThis,
+            VerificationType::Object(index) => Type::reference(self.class_name(*index)?),
+            VerificationType::Uninitialized(offset) => {
+                let offset = *offset as usize;
+                if !matches!(self.instructions.get(&offset), Some(NEW(_))) {
+                    return Err(format!("uninitialized({}) is not created by new", offset));
+                }
+                Uninitialized(offset)
+            }
+        })
+    }
+
+    fn check_exception_table(&mut self) -> Result<(), String> {
+        let code_length = self.code.opcodes.len();
+        for handler in &self.code.exception_table {
+            let (start, end) = (handler.start_pc as usize, handler.end_pc as usize);
+            self.offset = start;
+            if start >= end
+                || !self.instructions.contains_key(&start)
+                || (end != code_length && !self.instructions.contains_key(&end))
+            {
+                return Err(format!("invalid exception handler range {}-{}", start, end));
+            }
+            if !self
+                .instructions
+                .contains_key(&(handler.handler_pc as usize))
+            {
+                return Err(format!(
+                    "exception handler {} is not at the start of an instruction",
+                    handler.handler_pc
+                ));
+            }
+            if handler.catch_type != 0 {
+                let catch_type = self.class_name(handler.catch_type)?;
+                if !self.is_java_assignable(catch_type, "java/lang/Throwable")? {
+                    return Err(format!("catch type {} is not a Throwable", catch_type));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// checks that the locals before the instruction match the frames of the exception handlers
+    /// that cover it
+    fn check_handlers(&mut self, frame: &Frame) -> Result<(), String> {
+        let code = self.code;
+        for handler in &code.exception_table {
+            if !handler.covers(self.offset) {
+                continue;
+            }
+            let catch_type = match handler.catch_type {
+                0 => "java/lang/Throwable",
+                index => self.class_name(index)?,
+            };
+            let handler_pc = handler.handler_pc as usize;
+            let handler_frame = self.stack_map.get(&handler_pc).cloned().ok_or_else(|| {
+                format!(
+                    "no stack map frame for the exception handler {}",
+                    handler_pc
+                )
+            })?;
+            let exception_frame = Frame {
+                locals: frame.locals.clone(),
+                stack: vec![Type::reference(catch_type)],
+            };
+            self.check_frame(
+                &exception_frame,
+                &handler_frame,
+                &format!(" of the exception handler {}", handler_pc),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// checks that the frame is assignable to the one in the stack map
+    fn check_frame(
+        &mut self,
+        frame: &Frame,
+        stack_map_frame: &Frame,
+        of: &str,
+    ) -> Result<(), String> {
+        if frame.stack.len() != stack_map_frame.stack.len() {
+            return Err(format!(
+                "inconsistent stack height {} for the stack map frame{} with {}",
+                frame.stack_size(),
+                of,
+                stack_map_frame.stack_size()
+            ));
+        }
+        for (index, (from, to)) in frame.locals.iter().zip(&stack_map_frame.locals).enumerate() {
+            if !self.is_assignable(from, to)? {
+                return Err(format!(
+                    "local variable {} is {}, not assignable to {} in the stack map frame{}",
+                    index, from, to, of
+                ));
+            }
+        }
+        for (index, (from, to)) in frame.stack.iter().zip(&stack_map_frame.stack).enumerate() {
+            if !self.is_assignable(from, to)? {
+                return Err(format!(
+                    "stack entry {} is {}, not assignable to {} in the stack map frame{}",
+                    index, from, to, of
+                ));
+            }
+        }
+        if frame.this_uninitialized() && !stack_map_frame.this_uninitialized() {
+            return Err(format!(
+                "this is not initialized, as in the stack map frame{}",
+                of
+            ));
+        }
+        Ok(())
+    }
+
+    /// checks the instruction and applies it to the frame
+    /// returns whether the next instruction can be reached from this one
+    fn execute(
+        &mut self,
+        frame: &mut Frame,
+        offset: usize,
+        opcode: &Opcode,
+    ) -> Result<bool, String> {
+        match opcode {
+            NOP => {}
+            ACONST_NULL => self.push(frame, Null)?,
+            ICONST(_) | BIPUSH(_) | SIPUSH(_) => self.push(frame, Integer)?,
+            LCONST(_) => self.push(frame, Long)?,
+            FCONST(_) => self.push(frame, Float)?,
+            DCONST(_) => self.push(frame, Double)?,
+            LDC(index) | LDC_W(index) | LDC2_W(index) => {
+                let constant = self.constant_type(*index)?;
+                if (constant.size() == 2) != matches!(opcode, LDC2_W(_)) {
+                    return Err(format!(
+                        "constant {} of type {} cannot be loaded with {:?}",
+                        index, constant, opcode
+                    ));
+                }
+                self.push(frame, constant)?
+            }
+            ILOAD(index) => self.load(frame, *index as usize, Integer)?,
+            WIDE_ILOAD(index) => self.load(frame, *index as usize, Integer)?,
+            LLOAD(index) => self.load(frame, *index as usize, Long)?,
+            WIDE_LLOAD(index) => self.load(frame, *index as usize, Long)?,
+            FLOAD(index) => self.load(frame, *index as usize, Float)?,
+            WIDE_FLOAD(index) => self.load(frame, *index as usize, Float)?,
+            DLOAD(index) => self.load(frame, *index as usize, Double)?,
+            WIDE_DLOAD(index) => self.load(frame, *index as usize, Double)?,
+            ALOAD(index) => self.load_reference(frame, *index as usize)?,
+            WIDE_ALOAD(index) => self.load_reference(frame, *index as usize)?,
+            IALOAD => self.array_load(frame, &["[I"], Integer)?,
+            LALOAD => self.array_load(frame, &["[J"], Long)?,
+            FALOAD => self.array_load(frame, &["[F"], Float)?,
+            DALOAD => self.array_load(frame, &["[D"], Double)?,
+            BALOAD => self.array_load(frame, &["[B", "[Z"], Integer)?,
+            CALOAD => self.array_load(frame, &["[C"], Integer)?,
+            SALOAD => self.array_load(frame, &["[S"], Integer)?,
+            AALOAD => {
+                self.pop(frame, &Integer)?;
+                let component = match self.pop_reference(frame)? {
+                    Null => Null,
+                    Reference(array) if array.starts_with("[L") || array.starts_with("[[") => {
+                        Type::of(&array[1..])?
+                    }
+                    array => {
+                        return Err(format!("expecting an array of references, found {}", array))
+                    }
+                };
+                self.push(frame, component)?
+            }
+            ISTORE(index) => self.store(frame, *index as usize, Integer)?,
+            WIDE_ISTORE(index) => self.store(frame, *index as usize, Integer)?,
+            LSTORE(index) => self.store(frame, *index as usize, Long)?,
+            WIDE_LSTORE(index) => self.store(frame, *index as usize, Long)?,
+            FSTORE(index) => self.store(frame, *index as usize, Float)?,
+            WIDE_FSTORE(index) => self.store(frame, *index as usize, Float)?,
+            DSTORE(index) => self.store(frame, *index as usize, Double)?,
+            WIDE_DSTORE(index) => self.store(frame, *index as usize, Double)?,
+            ASTORE(index) => self.store_reference(frame, *index as usize)?,
+            WIDE_ASTORE(index) => self.store_reference(frame, *index as usize)?,
+            IASTORE => self.array_store(frame, &["[I"], Integer)?,
+            LASTORE => self.array_store(frame, &["[J"], Long)?,
+            FASTORE => self.array_store(frame, &["[F"], Float)?,
+            DASTORE => self.array_store(frame, &["[D"], Double)?,
+            BASTORE => self.array_store(frame, &["[B", "[Z"], Integer)?,
+            CASTORE => self.array_store(frame, &["[C"], Integer)?,
+            SASTORE => self.array_store(frame, &["[S"], Integer)?,
+            AASTORE => {
+                self.pop_reference(frame)?;
+                self.pop(frame, &Integer)?;
+                let array = self.pop_reference(frame)?;
+                if array != Null
+                    && !matches!(&array, Reference(name) if name.starts_with("[L") || name.starts_with("[["))
+                {
+                    return Err(format!("expecting an array of references, found {}", array));
+                }
+            }
+            POP => {
+                self.pop_category1(frame)?;
+            }
+            POP2 => {
+                if self.pop_any(frame)?.size() == 1 {
+                    self.pop_category1(frame)?;
+                }
+            }
+            DUP => {
+                let value1 = self.pop_category1(frame)?;
+                self.push_all(frame, [value1.clone(), value1])?;
+            }
+            DUP_X1 => {
+                let value1 = self.pop_category1(frame)?;
+                let value2 = self.pop_category1(frame)?;
+                self.push_all(frame, [value1.clone(), value2, value1])?;
+            }
+            DUP_X2 => {
+                let value1 = self.pop_category1(frame)?;
+                let value2 = self.pop_any(frame)?;
+                if value2.size() == 2 {
+                    self.push_all(frame, [value1.clone(), value2, value1])?;
+                } else {
+                    let value3 = self.pop_category1(frame)?;
+                    self.push_all(frame, [value1.clone(), value3, value2, value1])?;
+                }
+            }
+            DUP2 => {
+                let value1 = self.pop_any(frame)?;
+                if value1.size() == 2 {
+                    self.push_all(frame, [value1.clone(), value1])?;
+                } else {
+                    let value2 = self.pop_category1(frame)?;
+                    self.push_all(frame, [value2.clone(), value1.clone(), value2, value1])?;
+                }
+            }
+            DUP2_X1 => {
+                let value1 = self.pop_any(frame)?;
+                if value1.size() == 2 {
+                    let value2 = self.pop_category1(frame)?;
+                    self.push_all(frame, [value1.clone(), value2, value1])?;
+                } else {
+                    let value2 = self.pop_category1(frame)?;
+                    let value3 = self.pop_category1(frame)?;
+                    self.push_all(
+                        frame,
+                        [value2.clone(), value1.clone(), value3, value2, value1],
+                    )?;
+                }
+            }
+            DUP2_X2 => {
+                let value1 = self.pop_any(frame)?;
+                if value1.size() == 2 {
+                    let value2 = self.pop_any(frame)?;
+                    if value2.size() == 2 {
+                        self.push_all(frame, [value1.clone(), value2, value1])?;
+                    } else {
+                        let value3 = self.pop_category1(frame)?;
+                        self.push_all(frame, [value1.clone(), value3, value2, value1])?;
+                    }
+                } else {
+                    let value2 = self.pop_category1(frame)?;
+                    let value3 = self.pop_any(frame)?;
+                    if value3.size() == 2 {
+                        self.push_all(
+                            frame,
+                            [value2.clone(), value1.clone(), value3, value2, value1],
+                        )?;
+                    } else {
+                        let value4 = self.pop_category1(frame)?;
+                        self.push_all(
+                            frame,
+                            [
+                                value2.clone(),
+                                value1.clone(),
+                                value4,
+                                value3,
+                                value2,
+                                value1,
+                            ],
+                        )?;
+                    }
+                }
+            }
+            SWAP => {
+                let value1 = self.pop_category1(frame)?;
+                let value2 = self.pop_category1(frame)?;
+                self.push_all(frame, [value1, value2])?;
+            }
+            IADD | ISUB | IMUL | IDIV | IREM | ISHL | ISHR | IUSHR | IAND | IOR | IXOR => {
+                self.operation(frame, &[Integer, Integer], Some(Integer))?
+            }
+            LADD | LSUB | LMUL | LDIV | LREM | LAND | LOR | LXOR => {
+                self.operation(frame, &[Long, Long], Some(Long))?
+            }
+            LSHL | LSHR | LUSHR => self.operation(frame, &[Long, Integer], Some(Long))?,
+            FADD | FSUB | FMUL | FDIV | FREM => {
+                self.operation(frame, &[Float, Float], Some(Float))?
+            }
+            DADD | DSUB | DMUL | DDIV | DREM => {
+                self.operation(frame, &[Double, Double], Some(Double))?
+            }
+            INEG | I2B | I2C | I2S => self.operation(frame, &[Integer], Some(Integer))?,
+            LNEG => self.operation(frame, &[Long], Some(Long))?,
+            FNEG => self.operation(frame, &[Float], Some(Float))?,
+            DNEG => self.operation(frame, &[Double], Some(Double))?,
+            IINC(index, _) => self.local(frame, *index as usize, &Integer).map(|_| ())?,
+            WIDE_IINC(index, _) => self.local(frame, *index as usize, &Integer).map(|_| ())?,
+            I2L => self.operation(frame, &[Integer], Some(Long))?,
+            I2F => self.operation(frame, &[Integer], Some(Float))?,
+            I2D => self.operation(frame, &[Integer], Some(Double))?,
+            L2I => self.operation(frame, &[Long], Some(Integer))?,
+            L2F => self.operation(frame, &[Long], Some(Float))?,
+            L2D => self.operation(frame, &[Long], Some(Double))?,
+            F2I => self.operation(frame, &[Float], Some(Integer))?,
+            F2L => self.operation(frame, &[Float], Some(Long))?,
+            F2D => self.operation(frame, &[Float], Some(Double))?,
+            D2I => self.operation(frame, &[Double], Some(Integer))?,
+            D2L => self.operation(frame, &[Double], Some(Long))?,
+            D2F => self.operation(frame, &[Double], Some(Float))?,
+            LCMP => self.operation(frame, &[Long, Long], Some(Integer))?,
+            FCMPL | FCMPG => self.operation(frame, &[Float, Float], Some(Integer))?,
+            DCMPL | DCMPG => self.operation(frame, &[Double, Double], Some(Integer))?,
+            IFEQ(target) | IFNE(target) | IFLT(target) | IFGE(target) | IFGT(target)
+            | IFLE(target) => {
+                self.pop(frame, &Integer)?;
+                self.branch(frame, *target as i64)?;
+            }
+            IF_ICMPEQ(target) | IF_ICMPNE(target) | IF_ICMPLT(target) | IF_ICMPGE(target)
+            | IF_ICMPGT(target) | IF_ICMPLE(target) => {
+                self.operation(frame, &[Integer, Integer], None)?;
+                self.branch(frame, *target as i64)?;
+            }
+            IF_ACMPEQ(target) | IF_ACMPNE(target) => {
+                self.pop_reference(frame)?;
+                self.pop_reference(frame)?;
+                self.branch(frame, *target as i64)?;
+            }
+            IFNULL(target) | IFNONNULL(target) => {
+                self.pop_reference(frame)?;
+                self.branch(frame, *target as i64)?;
+            }
+            GOTO(jump) => {
+                self.branch(frame, offset as i64 + *jump as i16 as i64)?;
+                return Ok(false);
+            }
+            GOTOW(jump) => {
+                self.branch(frame, offset as i64 + *jump as i64)?;
+                return Ok(false);
+            }
+            JSR(_) | JSR_W(_) | RET(_) | WIDE_RET(_) => {
+                return Err(format!(
+                    "{:?} is not allowed in a class file of version {}",
+                    opcode, self.classdef.major_version
+                ));
+            }
+            TABLESWITCH(tableswitch) => {
+                self.pop(frame, &Integer)?;
+                for jump in tableswitch.targets() {
+                    self.branch(frame, offset as i64 + jump as i64)?;
+                }
+                return Ok(false);
+            }
+            LOOKUPSWITCH(lookupswitch) => {
+                if !lookupswitch.is_sorted() {
+                    return Err("the keys of the lookupswitch are not sorted".into());
+                }
+                self.pop(frame, &Integer)?;
+                for jump in lookupswitch.targets() {
+                    self.branch(frame, offset as i64 + jump as i64)?;
+                }
+                return Ok(false);
+            }
+            IRETURN => return self.return_value(frame, Integer).map(|_| false),
+            LRETURN => return self.return_value(frame, Long).map(|_| false),
+            FRETURN => return self.return_value(frame, Float).map(|_| false),
+            DRETURN => return self.return_value(frame, Double).map(|_| false),
+            ARETURN => {
+                return match self.return_type.clone() {
+                    Some(return_type) if return_type.is_reference() => {
+                        self.pop(frame, &return_type).map(|_| false)
+                    }
+                    return_type => Err(format!(
+                        "areturn in a method that returns {}",
+                        return_type.map_or("void".into(), |t| t.to_string())
+                    )),
+                }
+            }
+            RETURN_VOID => {
+                if let Some(return_type) = &self.return_type {
+                    return Err(format!("return in a method that returns {}", return_type));
+                }
+                if frame.this_uninitialized() {
+                    return Err("return before the constructor of the superclass is called".into());
+                }
+                return Ok(false);
+            }
+            GETSTATIC(index) => {
+                let (_, _, descriptor) = self.field_ref(*index)?;
+                self.push(frame, Type::of(descriptor)?)?
+            }
+            PUTSTATIC(index) => {
+                let (_, _, descriptor) = self.field_ref(*index)?;
+                self.pop(frame, &Type::of(descriptor)?)?;
+            }
+            GETFIELD(index) => {
+                let (class_name, _, descriptor) = self.field_ref(*index)?;
+                self.pop(frame, &Type::reference(class_name))?;
+                self.push(frame, Type::of(descriptor)?)?
+            }
+            PUTFIELD(index) => {
+                let (class_name, _, descriptor) = self.field_ref(*index)?;
+                self.pop(frame, &Type::of(descriptor)?)?;
+                let object = self.pop_any(frame)?;
+                // a constructor can set the fields of its own class before calling super()
+                if !(object == UninitializedThis && class_name == self.classdef.name()) {
+                    self.check_assignable(&object, &Type::reference(class_name))?;
+                }
+            }
+            INVOKEVIRTUAL(_) | INVOKESPECIAL(_) | INVOKESTATIC(_) | INVOKEINTERFACE(_, _) => {
+                self.invoke(frame, opcode)?
+            }
+            INVOKEDYNAMIC(index) => {
+                let CpEntry::InvokeDynamic(_, name_and_type) = self.cp(*index)? else {
+                    return Err(format!("constant {} is not an InvokeDynamic", index));
+                };
+                let (name, descriptor) = self.name_and_type(*name_and_type)?;
+                if name.starts_with('<') {
+                    return Err(format!("invokedynamic of {}", name));
+                }
+                let (parameters, return_type) = method_descriptor(descriptor)?;
+                for parameter in parameters.iter().rev() {
+                    self.pop(frame, parameter)?;
+                }
+                if let Some(return_type) = return_type {
+                    self.push(frame, return_type)?;
+                }
+            }
+            NEW(index) => {
+                let class_name = self.class_name(*index)?;
+                if class_name.starts_with('[') {
+                    return Err(format!("new of array class {}", class_name));
+                }
+                let uninitialized = Uninitialized(offset);
+                if frame.stack.contains(&uninitialized) {
+                    return Err(format!("{} is already on the stack", uninitialized));
+                }
+                frame.initialize(&uninitialized, &Top);
+                self.push(frame, uninitialized)?
+            }
+            NEWARRAY(atype) => {
+                let descriptor = match atype {
+                    4 => "[Z",
+                    5 => "[C",
+                    6 => "[F",
+                    7 => "[D",
+                    8 => "[B",
+                    9 => "[S",
+                    10 => "[I",
+                    11 => "[J",
+                    _ => return Err(format!("invalid array type {}", atype)),
+                };
+                self.operation(frame, &[Integer], Some(Type::reference(descriptor)))?
+            }
+            ANEWARRAY(index) => {
+                let class_name = self.class_name(*index)?;
+                let array = if class_name.starts_with('[') {
+                    format!("[{}", class_name)
+                } else {
+                    format!("[L{};", class_name)
+                };
+                self.operation(frame, &[Integer], Some(Reference(array)))?
+            }
+            ARRAYLENGTH => {
+                let array = self.pop_reference(frame)?;
+                if array != Null && !array.is_array() {
+                    return Err(format!("expecting an array, found {}", array));
+                }
+                self.push(frame, Integer)?
+            }
+            ATHROW => {
+                self.pop(frame, &Type::reference("java/lang/Throwable"))?;
+                return Ok(false);
+            }
+            CHECKCAST(index) => {
+                let class_name = self.class_name(*index)?;
+                self.pop_reference(frame)?;
+                self.push(frame, Type::reference(class_name))?
+            }
+            INSTANCEOF(index) => {
+                self.class_name(*index)?;
+                self.pop_reference(frame)?;
+                self.push(frame, Integer)?
+            }
+            MONITORENTER | MONITOREXIT => {
+                self.pop_reference(frame)?;
+            }
+            WIDE(opcode) => return self.execute(frame, offset, opcode),
+            MULTIANEWARRAY(index, dimensions) => {
+                let class_name = self.class_name(*index)?;
+                let array_dimensions = class_name.chars().take_while(|c| *c == '[').count();
+                if *dimensions == 0 || array_dimensions < *dimensions as usize {
+                    return Err(format!(
+                        "multianewarray of {} with {} dimensions",
+                        class_name, dimensions
+                    ));
+                }
+                for _ in 0..*dimensions {
+                    self.pop(frame, &Integer)?;
+                }
+                self.push(frame, Type::reference(class_name))?
+            }
+        }
+        Ok(true)
+    }
+
+    fn invoke(&mut self, frame: &mut Frame, opcode: &Opcode) -> Result<(), String> {
+        let (index, kinds) = match opcode {
+            INVOKEVIRTUAL(index) => (index, "Methodref"),
+            INVOKESPECIAL(index) | INVOKESTATIC(index) => {
+                (index, "Methodref or InterfaceMethodref")
+            }
+            INVOKEINTERFACE(index, _) => (index, "InterfaceMethodref"),
+            _ => unreachable!(),
+        };
+        let (class_name, name_and_type) = match (self.cp(*index)?, opcode) {
+            (
+                CpEntry::MethodRef(class, name_and_type),
+                INVOKEVIRTUAL(_) | INVOKESPECIAL(_) | INVOKESTATIC(_),
+            )
+            | (
+                CpEntry::InterfaceMethodref(class, name_and_type),
+                INVOKESPECIAL(_) | INVOKESTATIC(_) | INVOKEINTERFACE(_, _),
+            ) => (self.class_name(*class)?, *name_and_type),
+            _ => return Err(format!("constant {} is not a {}", index, kinds)),
+        };
+        let (name, descriptor) = self.name_and_type(name_and_type)?;
+        let is_init = name == "<init>";
+        if name.starts_with('<') && !(is_init && matches!(opcode, INVOKESPECIAL(_))) {
+            return Err(format!("{:?} of {}", opcode, name));
+        }
+        let (parameters, return_type) = method_descriptor(descriptor)?;
+        if let INVOKEINTERFACE(_, count) = opcode {
+            let slots: usize = parameters.iter().map(Type::size).sum();
+            if *count as usize != slots + 1 {
+                return Err(format!(
+                    "invokeinterface count {} does not match the descriptor {}",
+                    count, descriptor
+                ));
+            }
+        }
+        for parameter in parameters.iter().rev() {
+            self.pop(frame, parameter)?;
+        }
+        match opcode {
+            INVOKESTATIC(_) => {}
+            INVOKESPECIAL(_) if is_init => {
+                if return_type.is_some() {
+                    return Err(format!("{} is not a constructor", descriptor));
+                }
+                let object = self.pop_any(frame)?;
+                let initialized = match &object {
+                    UninitializedThis => {
+                        if class_name != self.classdef.name()
+                            && Some(class_name) != self.classdef.super_class_name()
+                        {
+                            return Err(format!(
+                                "constructor of {} called on uninitializedThis",
+                                class_name
+                            ));
+                        }
+                        Type::reference(self.classdef.name())
+                    }
+                    Uninitialized(new_offset) => {
+                        let Some(NEW(new_index)) = self.instructions.get(new_offset) else {
+                            return Err(format!("{} is not created by new", object));
+                        };
+                        let new_class_name = self.class_name(*new_index)?;
+                        if new_class_name != class_name {
+                            return Err(format!(
+                                "constructor of {} called on a new {}",
+                                class_name, new_class_name
+                            ));
+                        }
+                        Type::reference(class_name)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "expecting an uninitialized object on the operand stack, found {}",
+                            object
+                        ))
+                    }
+                };
+                frame.initialize(&object, &initialized);
+            }
+            INVOKESPECIAL(_) => {
+                self.pop(frame, &Type::reference(self.classdef.name()))?;
+            }
+            _ => {
+                self.pop(frame, &Type::reference(class_name))?;
+            }
+        }
+        if let Some(return_type) = return_type {
+            self.push(frame, return_type)?;
+        }
+        Ok(())
+    }
+
+    /// checks that the target has a stack map frame that the frame is assignable to
+    fn branch(&mut self, frame: &Frame, target: i64) -> Result<(), String> {
+        let stack_map_frame = usize::try_from(target)
+            .ok()
+            .and_then(|target| self.stack_map.get(&target))
+            .cloned()
+            .ok_or_else(|| format!("no stack map frame at branch target {}", target))?;
+        self.check_frame(frame, &stack_map_frame, &format!(" at {}", target))
+    }
+
+    fn return_value(&mut self, frame: &mut Frame, value: Type) -> Result<(), String> {
+        if self.return_type.as_ref() != Some(&value) {
+            return Err(format!(
+                "returning {} from a method that returns {}",
+                value,
+                self.return_type
+                    .as_ref()
+                    .map_or("void".into(), |t| t.to_string())
+            ));
+        }
+        self.pop(frame, &value).map(|_| ())
+    }
+
+    /// pops the operands, the last one first, and pushes the result if there is one
+    fn operation(
+        &mut self,
+        frame: &mut Frame,
+        operands: &[Type],
+        result: Option<Type>,
+    ) -> Result<(), String> {
+        for operand in operands.iter().rev() {
+            self.pop(frame, operand)?;
+        }
+        match result {
+            Some(result) => self.push(frame, result),
+            None => Ok(()),
+        }
+    }
+
+    fn array_load(
+        &mut self,
+        frame: &mut Frame,
+        arrays: &[&str],
+        component: Type,
+    ) -> Result<(), String> {
+        self.pop(frame, &Integer)?;
+        self.pop_array(frame, arrays)?;
+        self.push(frame, component)
+    }
+
+    fn array_store(
+        &mut self,
+        frame: &mut Frame,
+        arrays: &[&str],
+        component: Type,
+    ) -> Result<(), String> {
+        self.pop(frame, &component)?;
+        self.pop(frame, &Integer)?;
+        self.pop_array(frame, arrays)
+    }
+
+    /// pops null or one of the array types
+    fn pop_array(&mut self, frame: &mut Frame, arrays: &[&str]) -> Result<(), String> {
+        let array = self.pop_any(frame)?;
+        match &array {
+            Null => Ok(()),
+            Reference(name) if arrays.contains(&name.as_str()) => Ok(()),
+            _ => Err(format!(
+                "expecting {} on the operand stack, found {}",
+                arrays.join(" or "),
+                array
+            )),
+        }
+    }
+
+    fn push(&self, frame: &mut Frame, value: Type) -> Result<(), String> {
+        frame.stack.push(value);
+        if frame.stack_size() > self.code.max_stack as usize {
+            return Err(format!(
+                "operand stack overflow, max_stack is {}",
+                self.code.max_stack
+            ));
+        }
+        Ok(())
+    }
+
+    fn push_all<const N: usize>(&self, frame: &mut Frame, values: [Type; N]) -> Result<(), String> {
+        for value in values {
+            self.push(frame, value)?;
+        }
+        Ok(())
+    }
+
+    fn pop_any(&self, frame: &mut Frame) -> Result<Type, String> {
+        frame
+            .stack
+            .pop()
+            .ok_or_else(|| "operand stack underflow".to_owned())
+    }
+
+    fn pop_category1(&self, frame: &mut Frame) -> Result<Type, String> {
+        let value = self.pop_any(frame)?;
+        if value.size() != 1 {
+            return Err(format!(
+                "expecting a category 1 type on the operand stack, found {}",
+                value
+            ));
+        }
+        Ok(value)
+    }
+
+    fn pop_reference(&self, frame: &mut Frame) -> Result<Type, String> {
+        let value = self.pop_any(frame)?;
+        if !value.is_reference() {
+            return Err(format!(
+                "expecting a reference on the operand stack, found {}",
+                value
+            ));
+        }
+        Ok(value)
+    }
+
+    /// pops a value that is assignable to the expected type
+    fn pop(&mut self, frame: &mut Frame, expected: &Type) -> Result<Type, String> {
+        let value = self.pop_any(frame)?;
+        self.check_assignable(&value, expected)?;
+        Ok(value)
+    }
+
+    fn check_assignable(&mut self, value: &Type, expected: &Type) -> Result<(), String> {
+        if !self.is_assignable(value, expected)? {
+            return Err(format!(
+                "expecting {} on the operand stack, found {}",
+                expected, value
+            ));
+        }
+        Ok(())
+    }
+
+    /// the type of the local variable, that must be assignable to the expected type
+    fn local(&mut self, frame: &Frame, index: usize, expected: &Type) -> Result<Type, String> {
+        if index + expected.size() > frame.locals.len() {
+            return Err(format!("local variable {} out of range", index));
+        }
+        let local = frame.locals[index].clone();
+        if !self.is_assignable(&local, expected)? {
+            return Err(format!(
+                "expecting {} in local variable {}, found {}",
+                expected, index, local
+            ));
+        }
+        Ok(local)
+    }
+
+    fn load(&mut self, frame: &mut Frame, index: usize, value: Type) -> Result<(), String> {
+        self.local(frame, index, &value)?;
+        self.push(frame, value)
+    }
+
+    fn load_reference(&mut self, frame: &mut Frame, index: usize) -> Result<(), String> {
+        let local = self.local(frame, index, &Top)?;
+        if !local.is_reference() {
+            return Err(format!(
+                "expecting a reference in local variable {}, found {}",
+                index, local
+            ));
+        }
+        self.push(frame, local)
+    }
+
+    fn store(&mut self, frame: &mut Frame, index: usize, value: Type) -> Result<(), String> {
+        self.pop(frame, &value)?;
+        self.set_local(frame, index, value)
+    }
+
+    fn store_reference(&mut self, frame: &mut Frame, index: usize) -> Result<(), String> {
+        let value = self.pop_reference(frame)?;
+        self.set_local(frame, index, value)
+    }
+
+    fn set_local(&self, frame: &mut Frame, index: usize, value: Type) -> Result<(), String> {
+        if index + value.size() > frame.locals.len() {
+            return Err(format!("local variable {} out of range", index));
+        }
+        // a long or double in the previous local is overwritten in part
+        if index > 0 && frame.locals[index - 1].size() == 2 {
+            frame.locals[index - 1] = Top;
+        }
+        if value.size() == 2 {
+            frame.locals[index + 1] = Top;
+        }
+        frame.locals[index] = value;
+        Ok(())
+    }
+
+    /// whether a value of the one type can be used where the other type is expected
+    fn is_assignable(&mut self, from: &Type, to: &Type) -> Result<bool, String> {
+        Ok(match (from, to) {
+            _ if from == to => true,
+            (_, Top) => true,
+            (Null, Reference(_)) => true,
+            (Reference(from), Reference(to)) => self.is_java_assignable(from, to)?,
+            _ => false,
+        })
+    }
+
+    /// whether the class or array type is assignable to the other one,
+    /// interfaces are treated like java/lang/Object, as in the JVMS
+    fn is_java_assignable(&mut self, from: &str, to: &str) -> Result<bool, String> {
+        if from == to || to == "java/lang/Object" {
+            return Ok(true);
+        }
+        match (from.strip_prefix('['), to.strip_prefix('[')) {
+            (Some(from), Some(to)) => {
+                let is_reference = |component: &str| component.starts_with(['L', '['].as_ref());
+                if is_reference(from) && is_reference(to) {
+                    self.is_java_assignable(component_name(from), component_name(to))
+                } else {
+                    Ok(from == to)
+                }
+            }
+            (Some(_), None) => Ok(to == "java/lang/Cloneable" || to == "java/io/Serializable"),
+            (None, Some(_)) => Ok(false),
+            (None, None) => match self.is_subclass(from, to) {
+                Ok(true) => Ok(true),
+                // an unknown class is fine if the target is an interface
+                is_subclass => {
+                    if self.class_info(to)?.1 {
+                        Ok(true)
+                    } else {
+                        is_subclass
+                    }
+                }
+            },
+        }
+    }
+
+    fn is_subclass(&mut self, class_name: &str, of: &str) -> Result<bool, String> {
+        let mut class_name = class_name.to_owned();
+        loop {
+            if class_name == of {
+                return Ok(true);
+            }
+            match self.class_info(&class_name)?.0 {
+                Some(super_class) => class_name = super_class,
+                None => return Ok(false),
+            }
+        }
+    }
+
+    fn class_info(&mut self, class_name: &str) -> Result<(Option<String>, bool), String> {
+        if class_name == self.classdef.name() {
+            return Ok((
+                self.classdef.super_class_name().map(str::to_owned),
+                self.classdef.is_interface(),
+            ));
+        }
+        self.hierarchy
+            .class_info(class_name)
+            .ok_or_else(|| format!("class {} cannot be loaded", class_name))
+    }
+
+    fn constant_type(&self, index: u16) -> Result<Type, String> {
+        Ok(match self.cp(index)? {
+            CpEntry::Integer(_) => Integer,
+            CpEntry::Float(_) => Float,
+            CpEntry::Long(_) => Long,
+            CpEntry::Double(_) => Double,
+            CpEntry::StringRef(_) => Type::reference("java/lang/String"),
+            CpEntry::ClassRef(_) => Type::reference("java/lang/Class"),
+            CpEntry::MethodType(_) => Type::reference("java/lang/invoke/MethodType"),
+            CpEntry::MethodHandle(_, _) => Type::reference("java/lang/invoke/MethodHandle"),
+            CpEntry::Dynamic(_, name_and_type) => Type::of(self.name_and_type(*name_and_type)?.1)?,
+            _ => return Err(format!("constant {} cannot be loaded", index)),
+        })
+    }
+
+    fn cp(&self, index: u16) -> Result<&'a CpEntry, String> {
+        self.classdef
+            .constant_pool
+            .get(&index)
+            .ok_or_else(|| format!("invalid constant pool index {}", index))
+    }
+
+    fn utf8(&self, index: u16) -> Result<&'a str, String> {
+        match self.cp(index)? {
+            CpEntry::Utf8(utf8, _) => Ok(utf8),
+            _ => Err(format!("constant {} is not a Utf8", index)),
+        }
+    }
+
+    fn class_name(&self, index: u16) -> Result<&'a str, String> {
+        match self.cp(index)? {
+            CpEntry::ClassRef(name_index) => self.utf8(*name_index),
+            _ => Err(format!("constant {} is not a Class", index)),
+        }
+    }
+
+    fn name_and_type(&self, index: u16) -> Result<(&'a str, &'a str), String> {
+        match self.cp(index)? {
+            CpEntry::NameAndType(name_index, descriptor_index) => {
+                Ok((self.utf8(*name_index)?, self.utf8(*descriptor_index)?))
+            }
+            _ => Err(format!("constant {} is not a NameAndType", index)),
+        }
+    }
+
+    /// the class name, field name and descriptor
+    fn field_ref(&self, index: u16) -> Result<(&'a str, &'a str, &'a str), String> {
+        match self.cp(index)? {
+            CpEntry::Fieldref(class_index, name_and_type_index) => {
+                let (name, descriptor) = self.name_and_type(*name_and_type_index)?;
+                Ok((self.class_name(*class_index)?, name, descriptor))
+            }
+            _ => Err(format!("constant {} is not a Fieldref", index)),
+        }
+    }
+}
+
+/// the name of the class for the descriptor of an array component
+fn component_name(descriptor: &str) -> &str {
+    descriptor
+        .strip_prefix('L')
+        .and_then(|name| name.strip_suffix(';'))
+        .unwrap_or(descriptor)
+}
+
+/// the types of the parameters, and the return type or None for void
+fn method_descriptor(descriptor: &str) -> Result<(Vec<Type>, Option<Type>), String> {
+    let invalid = || format!("invalid method descriptor {}", descriptor);
+    let (parameters, return_type) = descriptor
+        .strip_prefix('(')
+        .and_then(|descriptor| descriptor.split_once(')'))
+        .ok_or_else(invalid)?;
+    let mut types = vec![];
+    let mut rest = parameters;
+    while !rest.is_empty() {
+        let dimensions = rest.chars().take_while(|c| *c == '[').count();
+        let end = match rest[dimensions..].chars().next() {
+            Some('L') => rest.find(';').ok_or_else(invalid)? + 1,
+            Some(_) => dimensions + 1,
+            None => return Err(invalid()),
+        };
+        types.push(Type::of(&rest[..end])?);
+        rest = &rest[end..];
+    }
+    let return_type = match return_type {
+        "V" => None,
+        _ => Some(Type::of(return_type)?),
+    };
+    Ok((types, return_type))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::classloader::builder::{ClassBuilder, Code};
+    use crate::classloader::load_class;
+
+    struct Classes(HashMap<&'static str, (Option<String>, bool)>);
+
+    impl ClassHierarchy for Classes {
+        fn class_info(&mut self, class_name: &str) -> Option<(Option<String>, bool)> {
+            self.0.get(class_name).cloned()
+        }
+    }
+
+    fn classes() -> Classes {
+        Classes(HashMap::from([
+            ("java/lang/Object", (None, false)),
+            (
+                "java/lang/Throwable",
+                (Some("java/lang/Object".into()), false),
+            ),
+            (
+                "java/lang/Exception",
+                (Some("java/lang/Throwable".into()), false),
+            ),
+            ("java/lang/String", (Some("java/lang/Object".into()), false)),
+        ]))
+    }
+
+    fn verify_method(descriptor: &str, code: Code) -> Result<Verification, VerifyError> {
+        let mut class = ClassBuilder::new("t/Verified", Some("java/lang/Object"));
+        class.version(52, 0).method(0x0009, "m", descriptor, code);
+        verify(&class.build().unwrap(), &mut classes())
+    }
+
+    #[test]
+    fn verifies_test_classes() {
+        for bytecode in [
+            &include_bytes!("../../tests/testclasses/IfCmp.class")[..],
+            &include_bytes!("../../tests/testclasses/Int.class")[..],
+            &include_bytes!("../../tests/testclasses/Main.class")[..],
+        ] {
+            let classdef = load_class(bytecode.to_vec()).unwrap();
+            assert_eq!(
+                Verification::Verified,
+                verify(&classdef, &mut classes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn old_versions_are_not_verified() {
+        // return 1.0f + 1;
+        let mut code = Code::new();
+        code.op(FCONST(1)).op(ICONST(1)).op(IADD).op(IRETURN);
+        let mut class = ClassBuilder::new("t/Unverified", Some("java/lang/Object"));
+        class.method(0x0009, "m", "()I", code);
+
+        // version 49, the builder default
+        let Verification::NotVerified(reason) =
+            verify(&class.build().unwrap(), &mut classes()).unwrap()
+        else {
+            panic!("version 49 is verified");
+        };
+        assert_eq!(
+            reason,
+            "class file version 49 needs verification by type inference"
+        );
+
+        // version 50 fails type checking, and falls back to type inference
+        class.version(50, 0);
+        let Verification::NotVerified(reason) =
+            verify(&class.build().unwrap(), &mut classes()).unwrap()
+        else {
+            panic!("version 50 is verified");
+        };
+        assert!(
+            reason.starts_with("expecting int on the operand stack, found float in method m()I")
+        );
+
+        class.version(51, 0);
+        assert!(verify(&class.build().unwrap(), &mut classes()).is_err());
+    }
+
+    #[test]
+    fn wrong_operand_type() {
+        let mut code = Code::new();
+        code.op(FCONST(1)).op(ICONST(1)).op(IADD).op(IRETURN);
+        let error = verify_method("()I", code).unwrap_err();
+        assert_eq!(
+            error.message,
+            "expecting int on the operand stack, found float"
+        );
+        assert_eq!(error.offset, 2);
+        assert_eq!(error.method, "m()I");
+    }
+
+    #[test]
+    fn wrong_return_type() {
+        let mut code = Code::new();
+        code.op(LCONST(0)).op(LRETURN);
+        let error = verify_method("()I", code).unwrap_err();
+        assert_eq!(
+            error.message,
+            "returning long from a method that returns int"
+        );
+    }
+
+    #[test]
+    fn falls_off_the_end() {
+        let mut code = Code::new();
+        code.op(ICONST(0)).op(ISTORE(0));
+        let error = verify_method("()V", code).unwrap_err();
+        assert_eq!(error.message, "falling off the end of the code");
+    }
+
+    #[test]
+    fn branch_without_stack_map_frame() {
+        let mut code = Code::new();
+        code.op(ICONST(0))
+            .jump(IFEQ, "end")
+            .label("end")
+            .op(RETURN_VOID);
+        let error = verify_method("()V", code).unwrap_err();
+        assert_eq!(error.message, "no stack map frame at branch target 4");
+    }
+
+    #[test]
+    fn uninitialized_this() {
+        let mut class = ClassBuilder::new("t/Verified", Some("java/lang/Object"));
+        let mut code = Code::new();
+        code.op(RETURN_VOID);
+        class.version(52, 0).method(0x0001, "<init>", "()V", code);
+        let error = verify(&class.build().unwrap(), &mut classes()).unwrap_err();
+        assert_eq!(
+            error.message,
+            "return before the constructor of the superclass is called"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+
+use crate::classloader::annotations::{write_parameter_annotations, Annotation, TypeAnnotation};
+use crate::classloader::attributes::{
+    write_indices, write_table, InnerClass, LocalVariable, StackMapFrame,
+};
+use crate::classloader::classdef::{
+    AttributeType, Attributes, BootstrapMethod, ClassDef, CpEntry, Exception, LineNumber,
+    MethodCode,
+};
+use crate::classloader::io::{write_u16, write_u32, write_u8};
+use crate::classloader::mutf8;
+
+// Writes a ClassDef back to the class file format (JVMS 4), the reverse of load_class.
+// Constant pool indices are written as they are, so the constant pool is written entry for entry.
+// Fields, methods and attributes are written in their original order, so a class that was read
+// with load_class is written back byte for byte.
+
+/// the bytes of the class file for the classdef
+/// The code of methods is written from the bytes that were read, not from the parsed
+/// instructions, because those do not tell which of the equivalent encodings was used
+/// (eg. iload_0 or iload 0). Attribute names are written as the first Utf8 entry with the name.
+/// fails if the constant pool does not have the name of an attribute, or has a gap
+pub fn write_class(classdef: &ClassDef) -> Result<Vec<u8>, Error> {
+    let writer = ClassWriter::new(&classdef.constant_pool);
+    let mut out = vec![];
+    write_u32(&mut out, 0xCAFEBABE);
+    write_u16(&mut out, classdef.minor_version);
+    write_u16(&mut out, classdef.major_version);
+    write_constant_pool(&mut out, &classdef.constant_pool)?;
+    write_u16(&mut out, classdef.access_flags);
+    write_u16(&mut out, classdef.this_class);
+    write_u16(&mut out, classdef.super_class.unwrap_or(0));
+    write_indices(&mut out, &classdef.interfaces);
+
+    let mut fields: Vec<_> = classdef.fields.values().collect();
+    fields.sort_by_key(|field| field.index);
+    write_u16(&mut out, fields.len() as u16);
+    for field in fields {
+        write_u16(&mut out, field.access_flags());
+        write_u16(&mut out, field.name_index);
+        write_u16(&mut out, field.descriptor_index);
+        writer.write_attributes(&mut out, &field.attributes)?;
+    }
+
+    let mut methods: Vec<_> = classdef.methods.values().collect();
+    methods.sort_by_key(|method| method.index);
+    write_u16(&mut out, methods.len() as u16);
+    for method in methods {
+        write_u16(&mut out, method.access_flags);
+        write_u16(&mut out, method.name_index);
+        write_u16(&mut out, method.descriptor_index);
+        writer.write_attributes(&mut out, &method.attributes)?;
+    }
+
+    writer.write_attributes(&mut out, &classdef.attributes)?;
+    Ok(out)
+}
+
+pub(crate) fn write_constant_pool(
+    out: &mut Vec<u8>,
+    constant_pool: &HashMap<u16, CpEntry>,
+) -> Result<(), Error> {
+    let last_index = constant_pool.keys().max().copied().unwrap_or(0);
+    let constant_pool_count = match constant_pool.get(&last_index) {
+        // long and double take two entries
+        Some(CpEntry::Long(_)) | Some(CpEntry::Double(_)) => last_index + 2,
+        _ => last_index + 1,
+    };
+    write_u16(out, constant_pool_count);
+
+    let mut index = 1;
+    while index < constant_pool_count {
+        let entry = constant_pool
+            .get(&index)
+            .ok_or_else(|| anyhow!("no constant pool entry #{}", index))?;
+        write_constant_pool_entry(out, entry);
+        index += match entry {
+            CpEntry::Long(_) | CpEntry::Double(_) => 2,
+            _ => 1,
+        };
+    }
+    Ok(())
+}
+
+fn write_constant_pool_entry(out: &mut Vec<u8>, entry: &CpEntry) {
+    let (tag, indices): (u8, Vec<u16>) = match entry {
+        CpEntry::Utf8(_, units) => {
+            let bytes = mutf8::encode(units);
+            write_u8(out, 1);
+            write_u16(out, bytes.len() as u16);
+            out.extend_from_slice(&bytes);
+            return;
+        }
+        CpEntry::Integer(value) => {
+            write_u8(out, 3);
+            out.extend_from_slice(&value.to_be_bytes());
+            return;
+        }
+        CpEntry::Float(value) => {
+            write_u8(out, 4);
+            out.extend_from_slice(&value.to_be_bytes());
+            return;
+        }
+        CpEntry::Long(value) => {
+            write_u8(out, 5);
+            out.extend_from_slice(&value.to_be_bytes());
+            return;
+        }
+        CpEntry::Double(value) => {
+            write_u8(out, 6);
+            out.extend_from_slice(&value.to_be_bytes());
+            return;
+        }
+        CpEntry::MethodHandle(reference_kind, reference_index) => {
+            write_u8(out, 15);
+            write_u8(out, *reference_kind);
+            write_u16(out, *reference_index);
+            return;
+        }
+        CpEntry::ClassRef(name_index) => (7, vec![*name_index]),
+        CpEntry::StringRef(utf8_index) => (8, vec![*utf8_index]),
+        CpEntry::Fieldref(class_index, name_and_type_index) => {
+            (9, vec![*class_index, *name_and_type_index])
+        }
+        CpEntry::MethodRef(class_index, name_and_type_index) => {
+            (10, vec![*class_index, *name_and_type_index])
+        }
+        CpEntry::InterfaceMethodref(class_index, name_and_type_index) => {
+            (11, vec![*class_index, *name_and_type_index])
+        }
+        CpEntry::NameAndType(name_index, descriptor_index) => {
+            (12, vec![*name_index, *descriptor_index])
+        }
+        CpEntry::MethodType(descriptor_index) => (16, vec![*descriptor_index]),
+        CpEntry::Dynamic(bootstrap_index, name_and_type_index) => {
+            (17, vec![*bootstrap_index, *name_and_type_index])
+        }
+        CpEntry::InvokeDynamic(bootstrap_index, name_and_type_index) => {
+            (18, vec![*bootstrap_index, *name_and_type_index])
+        }
+        CpEntry::Module(name_index) => (19, vec![*name_index]),
+        CpEntry::Package(name_index) => (20, vec![*name_index]),
+    };
+    write_u8(out, tag);
+    for index in indices {
+        write_u16(out, index);
+    }
+}
+
+struct ClassWriter<'a> {
+    // the Utf8 entries, for the attribute names
+    utf8_indices: HashMap<&'a str, u16>,
+}
+
+impl<'a> ClassWriter<'a> {
+    fn new(constant_pool: &'a HashMap<u16, CpEntry>) -> Self {
+        let mut utf8_indices = HashMap::new();
+        for (index, entry) in constant_pool {
+            if let CpEntry::Utf8(utf8, _) = entry {
+                let lowest = utf8_indices.entry(utf8.as_str()).or_insert(*index);
+                *lowest = (*lowest).min(*index);
+            }
+        }
+        Self { utf8_indices }
+    }
+
+    /// writes an attributes count, followed by the attributes
+    fn write_attributes(&self, out: &mut Vec<u8>, attributes: &Attributes) -> Result<(), Error> {
+        write_u16(out, attributes.len() as u16);
+        for (name, attribute) in attributes.iter() {
+            let name_index = self
+                .utf8_indices
+                .get(name.as_str())
+                .ok_or_else(|| anyhow!("no Utf8 constant for attribute name {}", name))?;
+            let info = self.write_attribute_info(attribute)?;
+            write_u16(out, *name_index);
+            write_u32(out, info.len() as u32);
+            out.extend_from_slice(&info);
+        }
+        Ok(())
+    }
+
+    fn write_attribute_info(&self, attribute: &AttributeType) -> Result<Vec<u8>, Error> {
+        let mut info = vec![];
+        let out = &mut info;
+        match attribute {
+            AttributeType::ConstantValue(index)
+            | AttributeType::NestHost(index)
+            | AttributeType::Signature(index)
+            | AttributeType::SourceFile(index)
+            | AttributeType::ModuleMainClass(index) => write_u16(out, *index),
+            AttributeType::Code(code) => self.write_code(out, code)?,
+            AttributeType::StackMapTable(frames) => write_table(out, frames, StackMapFrame::write),
+            AttributeType::BootstrapMethods(methods) => {
+                write_table(out, methods, BootstrapMethod::write)
+            }
+            AttributeType::NestMembers(indices)
+            | AttributeType::PermittedSubclasses(indices)
+            | AttributeType::Exceptions(indices)
+            | AttributeType::ModulePackages(indices) => write_indices(out, indices),
+            AttributeType::InnerClasses(classes) => write_table(out, classes, InnerClass::write),
+            AttributeType::EnclosingMethod(method) => method.write(out),
+            AttributeType::Synthetic | AttributeType::Deprecated => {}
+            AttributeType::Record(components) => {
+                write_u16(out, components.len() as u16);
+                for component in components {
+                    write_u16(out, component.name_index);
+                    write_u16(out, component.descriptor_index);
+                    self.write_attributes(out, &component.attributes)?;
+                }
+            }
+            AttributeType::SourceDebugExtension(bytes) | AttributeType::Unknown(bytes) => {
+                out.extend_from_slice(bytes)
+            }
+            AttributeType::LineNumberTable(table) => write_table(out, table, LineNumber::write),
+            AttributeType::LocalVariableTable(table)
+            | AttributeType::LocalVariableTypeTable(table) => {
+                write_table(out, table, LocalVariable::write)
+            }
+            AttributeType::RuntimeVisibleAnnotations(annotations)
+            | AttributeType::RuntimeInvisibleAnnotations(annotations) => {
+                write_table(out, annotations, Annotation::write)
+            }
+            AttributeType::RuntimeVisibleParameterAnnotations(parameters)
+            | AttributeType::RuntimeInvisibleParameterAnnotations(parameters) => {
+                write_parameter_annotations(out, parameters)
+            }
+            AttributeType::RuntimeVisibleTypeAnnotations(annotations)
+            | AttributeType::RuntimeInvisibleTypeAnnotations(annotations) => {
+                write_table(out, annotations, TypeAnnotation::write)
+            }
+            AttributeType::AnnotationDefault(value) => value.write(out),
+            AttributeType::MethodParameters(parameters) => {
+                write_u8(out, parameters.len() as u8);
+                for parameter in parameters {
+                    parameter.write(out);
+                }
+            }
+            AttributeType::Module(module) => module.write(out),
+        }
+        Ok(info)
+    }
+
+    /// the Code attribute, with the code bytes and exception table as they were read
+    fn write_code(&self, out: &mut Vec<u8>, code: &MethodCode) -> Result<(), Error> {
+        write_u16(out, code.max_stack);
+        write_u16(out, code.max_locals);
+        write_u32(out, code.opcodes.len() as u32);
+        out.extend_from_slice(&code.opcodes);
+        write_table(out, &code.exception_table, Exception::write);
+        self.write_attributes(out, &code.code_attributes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::classloader::builder::{ClassBuilder, Code};
+    use crate::classloader::classdef::Modifier;
+    use crate::classloader::load_class;
+    use crate::vm::opcodes::Opcode::*;
+
+    #[test]
+    fn round_trip() {
+        for bytecode in [
+            &include_bytes!("../../tests/testclasses/Int.class")[..],
+            &include_bytes!("../../tests/testclasses/Main.class")[..],
+            &include_bytes!("../../tests/testclasses/Inheritance.class")[..],
+        ] {
+            let written = write_class(&load_class(bytecode.to_vec()).unwrap()).unwrap();
+            assert_eq!(bytecode, written);
+        }
+    }
+
+    #[test]
+    fn round_trip_with_line_number_tables() {
+        let mut class = ClassBuilder::new("Lines", Some("java/lang/Object"));
+        let mut code = Code::new();
+        code.label("first")
+            .op(ICONST(1))
+            .label("second")
+            .op(IRETURN)
+            .line_numbers(&[("first", 1)])
+            .line_numbers(&[("second", 2)]);
+        class.method(Modifier::Static as u16, "lines", "()I", code);
+        let bytecode = class.to_bytes().unwrap();
+
+        let classdef = load_class(bytecode.clone()).unwrap();
+        let method = classdef.get_method("lines()I").unwrap();
+        let Some(AttributeType::Code(code)) = method.attributes.get("Code") else {
+            panic!("no code");
+        };
+        assert_eq!(2, code.code_attributes.get_all("LineNumberTable").count());
+        assert_eq!(bytecode, write_class(&classdef).unwrap());
+    }
+}
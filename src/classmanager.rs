@@ -1,28 +1,53 @@
 use std::cell::RefCell;
 use std::collections::{HashMap, LinkedList};
+use std::fmt::{Display, Formatter};
 use std::rc::Rc;
 
+use anyhow::{anyhow, Error};
 use log::debug;
 use once_cell::sync::Lazy;
 
 use crate::class::{Class, ClassId, TypeIndex};
 use crate::classloader;
 use crate::classloader::classdef::{ClassDef, Method, Modifier};
+use crate::classloader::source::ClassSource;
+use crate::classloader::verifier::{verify, ClassHierarchy, Verification};
 use crate::value::Value;
 use crate::value::Value::*;
+use crate::vm::fault::{exception_in_initializer, Fault};
 use crate::vm::object::{Object, ObjectRef};
-use crate::vm::runtime::Vm;
+use crate::vm::runtime::{Abrupt, Vm};
 
 static PRIMITIVES: Lazy<Vec<&str>> =
     Lazy::new(|| vec!["B", "S", "I", "J", "F", "D", "Z", "J", "C"]);
 
+/// A class whose static initializer threw an exception, JVMS 5.5.
+/// The exception is thrown into the java code that caused the initialization,
+/// later attempts to use the class get a java.lang.NoClassDefFoundError.
+#[derive(Debug)]
+pub struct InitializationError {
+    pub class_name: String,
+}
+
+impl Display for InitializationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Could not initialize class {}",
+            self.class_name.replace('/', ".")
+        )
+    }
+}
+
+impl std::error::Error for InitializationError {}
+
 //TODO less pubs
 pub struct ClassManager {
     static_class_data: HashMap<ClassId, Vec<Value>>,
     // sequence for passing new classIds
     current_id: ClassId,
-    // the classpath
-    classpath: Vec<String>,
+    // where classes are loaded from, in order: the JDK, the classpath, anything else
+    sources: Vec<Box<dyn ClassSource>>,
 
     //references to classdefs, ie the static class info
     pub(crate) classdefs: HashMap<ClassId, ClassDef>,
@@ -32,10 +57,22 @@ pub struct ClassManager {
 
     pub names: HashMap<String, ClassId>,
     pub class_objects: HashMap<ClassId, Value>,
+
+    // the values of Dynamic constants, that are resolved once, per class and constant pool index
+    dynamic_constants: HashMap<(ClassId, u16), Value>,
+
+    // classes whose static initializer threw, with the exception until it has been thrown
+    erroneous_classes: HashMap<ClassId, Option<Value>>,
+
+    // whether the bytecode of classes is verified when they are linked
+    verify: bool,
+
+    // the system properties from the command line, in the order they were given
+    properties: Vec<(String, String)>,
 }
 
 impl ClassManager {
-    pub fn new(classpath: Vec<String>) -> Self {
+    pub fn new(sources: Vec<Box<dyn ClassSource>>) -> Self {
         Self {
             static_class_data: HashMap::new(),
             current_id: 0,
@@ -43,10 +80,29 @@ impl ClassManager {
             classes: HashMap::new(),
             class_objects: HashMap::new(),
             names: HashMap::new(),
-            classpath,
+            sources,
+            dynamic_constants: HashMap::new(),
+            erroneous_classes: HashMap::new(),
+            verify: true,
+            properties: vec![],
         }
     }
 
+    /// turns bytecode verification on (the default) or off, off is for trusted code only
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// adds a system property, as with -D<name>=<value> on the command line
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        self.properties.push((name.to_owned(), value.to_owned()));
+    }
+
+    /// the system properties that were set, for System.getProperties()
+    pub(crate) fn properties(&self) -> &[(String, String)] {
+        &self.properties
+    }
+
     pub fn get_static(&self, id: &ClassId, index: usize) -> Value {
         self.static_class_data.get(id).unwrap()[index].clone()
     }
@@ -55,6 +111,20 @@ impl ClassManager {
         self.static_class_data.get_mut(&id).unwrap()[index] = value;
     }
 
+    pub(crate) fn get_dynamic_constant(&self, id: ClassId, cp_index: u16) -> Option<&Value> {
+        self.dynamic_constants.get(&(id, cp_index))
+    }
+
+    pub(crate) fn set_dynamic_constant(&mut self, id: ClassId, cp_index: u16, value: Value) {
+        self.dynamic_constants.insert((id, cp_index), value);
+    }
+
+    /// the exception that the static initializer of the class threw, the first time it is asked for
+    pub(crate) fn take_initializer_exception(&mut self, class_name: &str) -> Option<Value> {
+        let id = self.names.get(class_name)?;
+        self.erroneous_classes.get_mut(id)?.take()
+    }
+
     pub fn get_classobject(&self, id: &ClassId) -> Option<&Value> {
         self.class_objects.get(id)
     }
@@ -63,7 +133,7 @@ impl ClassManager {
         if !self.classes.contains_key(id) {
             let name = self.classdef_name(id);
             if name.is_some() {
-                self.add_class(&name.unwrap());
+                self.add_class(&name.unwrap()).ok()?;
             }
         }
         self.classes.get(id)
@@ -82,7 +152,11 @@ impl ClassManager {
     }
 
     /// loads the class if not already there
-    pub fn load_class_by_name(&mut self, name: &str) {
+    /// fails if the class, or one of its superclasses or interfaces, cannot be found
+    /// or is not a valid class file (classloader::error::ClassFormatError),
+    /// or if its code does not pass verification (classloader::error::VerifyError),
+    /// or if its static initializer throws (InitializationError)
+    pub fn load_class_by_name(&mut self, name: &str) -> Result<(), Error> {
         debug!("load class {}", name);
         // determine no of dimensions and get type of array if any
         let mut chars = name.chars();
@@ -114,15 +188,80 @@ impl ClassManager {
             let id = self.names.get(&type_name);
             match id {
                 Some(id) => {
+                    if self.erroneous_classes.contains_key(id) {
+                        return Err(InitializationError {
+                            class_name: type_name,
+                        }
+                        .into());
+                    }
                     if self.classes.get(id).is_none() {
-                        self.add_class(&type_name);
+                        self.add_class(&type_name)?;
                     }
                 }
                 None => {
-                    self.add_class(&type_name);
+                    self.add_class(&type_name)?;
                 }
             }
         }
+        Ok(())
+    }
+
+    /// adds a class that is not read from a source, for instance one made with the ClassBuilder
+    /// it is linked and initialized when it is loaded, like classes from the sources
+    /// fails if there already is a class with the name
+    pub fn define_class(&mut self, classdef: ClassDef) -> Result<ClassId, Error> {
+        let name = classdef.name().to_owned();
+        if self.names.contains_key(&name) {
+            return Err(anyhow!("duplicate class definition for {}", name));
+        }
+        let id = self.get_or_new_id(name);
+        self.classdefs.insert(id, classdef);
+        Ok(id)
+    }
+
+    /// creates a new instance of the class, loading it if necessary.
+    /// The fields have their default values; the constructor is not run.
+    pub fn new_instance(&mut self, class_name: &str) -> Result<Value, Error> {
+        self.load_class_by_name(class_name)?;
+        let class = self.get_class_by_name(class_name).unwrap();
+        Ok(Ref(ObjectRef::Object(Rc::new(RefCell::new(Object::new(
+            class,
+        ))))))
+    }
+
+    /// finds the class that declares the method, starting at the given class and then up the class hierarchy
+    pub(crate) fn find_method_class(
+        &mut self,
+        class_id: &ClassId,
+        method_name: &str,
+    ) -> Option<ClassId> {
+        self.get_class_by_id(class_id)?;
+        let class = self.classes.get(class_id)?;
+        class
+            .parents
+            .iter()
+            .rev()
+            .find(|parent_id| self.get_classdef(parent_id).has_method(method_name))
+            .copied()
+    }
+
+    /// whether the class is the given class or interface, or a subclass or implementation of it
+    pub(crate) fn is_subtype(&mut self, class_id: &ClassId, of: &ClassId) -> bool {
+        if class_id == of {
+            return true;
+        }
+        let Some(class) = self.get_class_by_id(class_id) else {
+            return false;
+        };
+        let supertypes: Vec<ClassId> = class
+            .superclass
+            .iter()
+            .chain(class.interfaces.iter())
+            .copied()
+            .collect();
+        supertypes
+            .iter()
+            .any(|supertype| self.is_subtype(supertype, of))
     }
 
     pub(crate) fn get_method(&self, class_name: &str, method_name: &str) -> Option<&Method> {
@@ -153,9 +292,12 @@ impl ClassManager {
     /// index is an index into the list of values that object instances will use to store the values
     ///
     /// the function also instantiates a (java.lang.) Class object for each loaded class
-    fn add_class(&mut self, name: &str) -> ClassId {
+    fn add_class(&mut self, name: &str) -> Result<ClassId, Error> {
         debug!("add class {}", name);
-        let this_classid = self.load(name);
+        let this_classid = self.load(name)?;
+        if self.verify {
+            self.verify_class(this_classid)?;
+        }
         let this_classdef = self.classdefs.get(&this_classid).unwrap();
 
         //compute indices to fields
@@ -229,10 +371,34 @@ impl ClassManager {
 
         // run static init
         if this_classdef.methods.contains_key("<clinit>()V") {
-            Vm { stack: Vec::new() }.run2(self, this_classid, "<clinit>()V");
+            let result = Vm::default().run2(self, this_classid, "<clinit>()V");
+            if let Err(Abrupt::Exit(status)) = result {
+                return Err(Fault::Exit(status).into());
+            }
+            if let Err(Abrupt::Throw(exception)) = result {
+                let exception = exception_in_initializer(self, exception);
+                self.erroneous_classes.insert(this_classid, Some(exception));
+                return Err(InitializationError {
+                    class_name: name.into(),
+                }
+                .into());
+            }
         }
 
-        this_classid
+        Ok(this_classid)
+    }
+
+    /// runs the verifier on the code of the class
+    fn verify_class(&mut self, id: ClassId) -> Result<(), Error> {
+        // the verifier looks up other classes, so the classdef is taken out while it runs
+        let classdef = self.classdefs.remove(&id).unwrap();
+        let result = verify(&classdef, self);
+        let name = classdef.name().to_owned();
+        self.classdefs.insert(id, classdef);
+        if let Verification::NotVerified(reason) = result? {
+            debug!("{} is not verified: {}", name, reason);
+        }
+        Ok(())
     }
 
     /// like described above
@@ -245,7 +411,10 @@ impl ClassManager {
     ) {
         let mut instance_field_mappings: HashMap<String, TypeIndex> = HashMap::new();
         let mut static_field_mappings: HashMap<String, TypeIndex> = HashMap::new();
-        for (field_name, field) in &current_classdef.fields {
+        // in the order of declaration, so that the indices do not change between runs
+        let mut fields: Vec<_> = current_classdef.fields.iter().collect();
+        fields.sort_by_key(|(_, field)| field.index);
+        for (field_name, field) in fields {
             if !field.is(Modifier::Static) {
                 instance_field_mappings.insert(
                     field_name.to_owned(),
@@ -265,28 +434,29 @@ impl ClassManager {
     }
 
     /// loads the class and recursively its dependencies
-    fn load(&mut self, name: &str) -> ClassId {
-        let (id, mut classes_to_load) = self.load_class_and_deps(name);
+    fn load(&mut self, name: &str) -> Result<ClassId, Error> {
+        let (id, mut classes_to_load) = self.load_class_and_deps(name)?;
         while !classes_to_load.is_empty() {
             if let Some(classname) = classes_to_load.pop() {
-                classes_to_load.append(&mut self.load_class_and_deps(classname.as_str()).1);
+                classes_to_load.append(&mut self.load_class_and_deps(classname.as_str())?.1);
             }
         }
 
         debug!("new class {} -> {}", name, id);
-        id
+        Ok(id)
     }
 
     /// loads the class and returns it's dependencies
-    fn load_class_and_deps(&mut self, class_name: &str) -> (ClassId, Vec<String>) {
+    fn load_class_and_deps(&mut self, class_name: &str) -> Result<(ClassId, Vec<String>), Error> {
         debug!("load {}", class_name);
         let class_name = class_name.to_owned().replace(".", "/");
         let id = self.get_or_new_id(class_name.clone());
 
-        let classdef = self.classdefs.entry(id).or_insert_with(|| {
-            classloader::get_classdef(&self.classpath, class_name.as_str()).expect("ClassNotFound")
-        });
-        (id, inspect_dependencies(classdef))
+        if !self.classdefs.contains_key(&id) {
+            let classdef = classloader::get_classdef(&self.sources, class_name.as_str())?;
+            self.classdefs.insert(id, classdef);
+        }
+        Ok((id, inspect_dependencies(&self.classdefs[&id])))
     }
 
     fn get_or_new_id(&mut self, name: String) -> ClassId {
@@ -322,6 +492,26 @@ impl ClassManager {
     }
 }
 
+impl ClassHierarchy for ClassManager {
+    /// reads the class if it is not already there, without linking it
+    fn class_info(&mut self, class_name: &str) -> Option<(Option<String>, bool)> {
+        let id = match self.names.get(class_name) {
+            Some(id) if self.classdefs.contains_key(id) => *id,
+            _ => {
+                let classdef = classloader::get_classdef(&self.sources, class_name).ok()?;
+                let id = self.get_or_new_id(class_name.to_owned());
+                self.classdefs.insert(id, classdef);
+                id
+            }
+        };
+        let classdef = &self.classdefs[&id];
+        Some((
+            classdef.super_class_name().map(str::to_owned),
+            classdef.is_interface(),
+        ))
+    }
+}
+
 pub(crate) fn n_fields(field_mapping: &HashMap<String, HashMap<String, TypeIndex>>) -> usize {
     field_mapping
         .iter()
@@ -346,7 +536,7 @@ pub(crate) fn inspect_dependencies(classdef: &ClassDef) -> Vec<String> {
 mod test {
     use std::rc::Rc;
 
-    use crate::classloader::classdef::{CpEntry, Field};
+    use crate::classloader::classdef::{Attributes, CpEntry, Field};
 
     use super::*;
 
@@ -359,34 +549,34 @@ mod test {
 
         let mut constant_pool = HashMap::new();
         constant_pool.insert(0, CpEntry::ClassRef(1));
-        constant_pool.insert(1, CpEntry::Utf8("C".into()));
+        constant_pool.insert(1, CpEntry::utf8("C"));
         constant_pool.insert(2, CpEntry::NameAndType(3, 4));
-        constant_pool.insert(3, CpEntry::Utf8("name".into()));
-        constant_pool.insert(4, CpEntry::Utf8("java/lang/String".into()));
-        constant_pool.insert(5, CpEntry::Utf8("Ljava/lang/String;".into()));
+        constant_pool.insert(3, CpEntry::utf8("name"));
+        constant_pool.insert(4, CpEntry::utf8("java/lang/String"));
+        constant_pool.insert(5, CpEntry::utf8("Ljava/lang/String;"));
         constant_pool.insert(6, CpEntry::ClassRef(4));
-        constant_pool.insert(7, CpEntry::Utf8("java/lang/Class".into()));
+        constant_pool.insert(7, CpEntry::utf8("java/lang/Class"));
         constant_pool.insert(8, CpEntry::ClassRef(7));
-        constant_pool.insert(9, CpEntry::Utf8("value1".into()));
-        constant_pool.insert(10, CpEntry::Utf8("value2".into()));
+        constant_pool.insert(9, CpEntry::utf8("value1"));
+        constant_pool.insert(10, CpEntry::utf8("value2"));
         let constant_pool = Rc::new(constant_pool);
 
         // give class C a fields called value
         let mut c_fields = HashMap::new();
         c_fields.insert(
             "value1".to_owned(),
-            Field::new(constant_pool.clone(), 0, 9, 5, HashMap::new(), 0),
+            Field::new(constant_pool.clone(), 0, 9, 5, Attributes::new(), 0),
         );
         c_fields.insert(
             "value2".to_owned(),
-            Field::new(constant_pool.clone(), 0, 10, 5, HashMap::new(), 0),
+            Field::new(constant_pool.clone(), 0, 10, 5, Attributes::new(), 1),
         );
 
         // Class needs a public (non-static) field called name
         let mut class_fields = HashMap::new();
         class_fields.insert(
             "name".to_owned(),
-            Field::new(constant_pool.clone(), 1, 2, 5, HashMap::new(), 0),
+            Field::new(constant_pool.clone(), 1, 2, 5, Attributes::new(), 0),
         );
 
         let mut classdefs = HashMap::new();
@@ -402,7 +592,7 @@ mod test {
                 vec![],
                 c_fields,
                 HashMap::new(),
-                HashMap::new(),
+                Attributes::new(),
             ),
         );
 
@@ -419,7 +609,7 @@ mod test {
                 vec![],
                 HashMap::new(),
                 HashMap::new(),
-                HashMap::new(),
+                Attributes::new(),
             ),
         );
 
@@ -436,7 +626,7 @@ mod test {
                 vec![],
                 class_fields,
                 HashMap::new(),
-                HashMap::new(),
+                Attributes::new(),
             ),
         );
         let mut classes = HashMap::new();
@@ -474,10 +664,14 @@ mod test {
             classdefs,
             current_id: 1,
             names,
-            classpath: Vec::new(),
+            sources: Vec::new(),
+            dynamic_constants: HashMap::new(),
+            erroneous_classes: HashMap::new(),
+            verify: true,
+            properties: vec![],
         };
 
-        let c_id = cm.add_class("C");
+        let c_id = cm.add_class("C").unwrap();
         let loaded_class = cm.classes.get(&c_id).unwrap();
 
         assert_eq!(
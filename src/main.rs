@@ -1,13 +1,198 @@
 use java_rs::vm::runtime::Vm;
-use std::cmp::Ordering::Equal;
+
+const USAGE: &str = "Usage: java_rs [options] <mainclass> [args...]
+           (to execute a class)
+   or  java_rs [options] -jar <jarfile> [args...]
+           (to execute a jar file)
+
+ where options include:
+    -cp <class search path of directories and zip/jar files>
+    -classpath <class search path of directories and zip/jar files>
+    --class-path <class search path of directories and zip/jar files>
+                  A : separated list of directories, JAR archives,
+                  and ZIP archives to search for class files.
+    -D<name>=<value>
+                  set a system property
+    -Xverify:none
+                  do not verify the bytecode of classes when they are linked,
+                  for trusted code only
+    --system <jdk>
+                  load the system classes from this JDK
+                  instead of the one in JAVA_HOME or on the PATH";
+
+/// what to run
+#[derive(Debug, PartialEq)]
+enum Target {
+    /// main class in internal form (with slashes)
+    MainClass(String),
+    Jar(String),
+}
+
+/// the parsed command line
+#[derive(Debug, PartialEq)]
+struct Launch {
+    classpath: Option<String>,
+    system: Option<String>,
+    properties: Vec<(String, String)>,
+    verify: bool,
+    target: Target,
+    program_args: Vec<String>,
+}
 
 fn main() {
-    let a = 0.0;
-    println!("{}", 1.0 / a);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let launch = match parse_args(&args) {
+        Ok(launch) => launch,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
     let mut vm = Vm::new();
-    vm.run(
-        "/Users/Shautvast/dev/java.rs/tests",
-        "testclasses/Main",
-        "main([Ljava/lang/String;)V",
-    );
+    if let Some(system) = &launch.system {
+        vm.set_system(system);
+    }
+    for (name, value) in &launch.properties {
+        vm.set_property(name, value);
+    }
+    vm.set_verify(launch.verify);
+    let exit_code = match &launch.target {
+        Target::MainClass(main_class) => {
+            let classpath = launch
+                .classpath
+                .or_else(|| std::env::var("CLASSPATH").ok())
+                .unwrap_or_else(|| ".".into());
+            vm.run_main(&classpath, main_class, &launch.program_args)
+        }
+        Target::Jar(jar) => match vm.run_jar(jar, &launch.program_args) {
+            Ok(exit_code) => exit_code,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                1
+            }
+        },
+    };
+    std::process::exit(exit_code);
+}
+
+/// parses the options up to the main class or jar, everything after that is passed to main
+fn parse_args(args: &[String]) -> Result<Launch, String> {
+    let mut classpath = None;
+    let mut system = None;
+    let mut properties = vec![];
+    let mut verify = true;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-cp" | "-classpath" | "--class-path" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| format!("{} requires class path specification", arg))?;
+                classpath = Some(path.clone());
+            }
+            "--system" => {
+                let jdk = args.next().ok_or("--system requires a JDK directory")?;
+                system = Some(jdk.clone());
+            }
+            "-Xverify:none" | "-noverify" => verify = false,
+            "-Xverify:all" | "-Xverify:remote" => verify = true,
+            "-jar" => {
+                let jar = args.next().ok_or("-jar requires jar file specification")?;
+                return Ok(Launch {
+                    classpath,
+                    system,
+                    properties,
+                    verify,
+                    target: Target::Jar(jar.clone()),
+                    program_args: args.cloned().collect(),
+                });
+            }
+            _ => {
+                if let Some(path) = arg.strip_prefix("--class-path=") {
+                    classpath = Some(path.into());
+                } else if let Some(jdk) = arg.strip_prefix("--system=") {
+                    system = Some(jdk.into());
+                } else if let Some(property) = arg.strip_prefix("-D") {
+                    let (name, value) = property.split_once('=').unwrap_or((property, ""));
+                    if name.is_empty() {
+                        return Err(format!("{} is not a valid property definition", arg));
+                    }
+                    properties.push((name.into(), value.into()));
+                } else if arg.starts_with('-') {
+                    return Err(format!("Unrecognized option: {}", arg));
+                } else {
+                    return Ok(Launch {
+                        classpath,
+                        system,
+                        properties,
+                        verify,
+                        target: Target::MainClass(arg.replace('.', "/")),
+                        program_args: args.cloned().collect(),
+                    });
+                }
+            }
+        }
+    }
+    Err("no main class specified".into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn main_class_with_options_and_program_args() {
+        let launch = parse_args(&args(&[
+            "-cp",
+            "a:b.jar",
+            "--system",
+            "/opt/jdk",
+            "-Dfoo=bar",
+            "-Dflag",
+            "com.example.Main",
+            "-cp",
+            "x",
+        ]))
+        .unwrap();
+        assert_eq!(
+            Launch {
+                classpath: Some("a:b.jar".into()),
+                system: Some("/opt/jdk".into()),
+                properties: vec![("foo".into(), "bar".into()), ("flag".into(), "".into())],
+                verify: true,
+                target: Target::MainClass("com/example/Main".into()),
+                program_args: args(&["-cp", "x"]),
+            },
+            launch
+        );
+    }
+
+    #[test]
+    fn jar() {
+        let launch = parse_args(&args(&[
+            "--class-path=lib",
+            "-Xverify:none",
+            "-jar",
+            "app.jar",
+            "1",
+        ]))
+        .unwrap();
+        assert_eq!(Some("lib".into()), launch.classpath);
+        assert!(!launch.verify);
+        assert_eq!(Target::Jar("app.jar".into()), launch.target);
+        assert_eq!(args(&["1"]), launch.program_args);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse_args(&args(&["-cp"])).is_err());
+        assert!(parse_args(&args(&["-verbose", "Main"])).is_err());
+        assert!(parse_args(&args(&["-cp", "."])).is_err());
+    }
 }
@@ -16,6 +16,8 @@ pub enum Value {
     CHAR(i32),
     // objects and arrays
     Ref(ObjectRef),
+    // returnAddress, the index of the opcode after a jsr, for ret
+    ReturnAddress(usize),
     // special object
     Utf8(String),
 }
@@ -38,17 +40,19 @@ impl Value {
             | Value::BOOL(_)
             | Value::CHAR(_)
             | Value::Ref(_)
+            | Value::ReturnAddress(_)
             | Value::Utf8(_) => ComputationalType::C1,
             Value::I64(_) | Value::F64(_) => ComputationalType::C2,
         }
     }
 
     // panics if not correct type
+    // booleans and chars are ints on the operand stack
     pub fn into_i32(self) -> i32 {
-        if let Value::I32(v) = self {
-            v
-        } else {
-            panic!("{:?} is not I32", self);
+        match self {
+            Value::I32(v) | Value::CHAR(v) => v,
+            Value::BOOL(v) => v as i32,
+            _ => panic!("{:?} is not I32", self),
         }
     }
 
@@ -1,47 +1,46 @@
+use crate::vm::fault::Fault;
 use crate::vm::object::ObjectRef::*;
-use anyhow::{anyhow, Error};
 
 use crate::value::Value;
 use crate::value::Value::*;
 
-pub(crate) fn array_load(index: Value, arrayref: Value) -> Result<Value, Error> {
+pub(crate) fn array_load(index: Value, arrayref: Value) -> Result<Value, Fault> {
     if let I32(index) = index {
-        let index = index as usize;
-
         if let Null = arrayref {
-            return Err(anyhow!("NullpointerException"));
+            return Err(Fault::NullPointer);
         }
         if let Ref(objectref) = arrayref {
+            let index = check_index(index, objectref.get_array_length())?;
             match objectref {
                 ByteArray(array) => {
-                    return Ok(I32(array[index] as i32));
+                    return Ok(I32(array.borrow()[index] as i32));
                 }
                 ShortArray(array) => {
-                    return Ok(I32(array[index] as i32));
+                    return Ok(I32(array.borrow()[index] as i32));
                 }
                 IntArray(array) => {
-                    return Ok(I32(array[index]));
+                    return Ok(I32(array.borrow()[index]));
                 }
                 BooleanArray(array) => {
-                    return Ok(I32(array[index] as i32));
+                    return Ok(I32(array.borrow()[index] as i32));
                 }
                 CharArray(array) => {
-                    return Ok(CHAR(array[index]));
+                    return Ok(CHAR(array.borrow()[index]));
                 }
                 LongArray(array) => {
-                    return Ok(I64(array[index]));
+                    return Ok(I64(array.borrow()[index]));
                 }
                 FloatArray(array) => {
-                    return Ok(F32(array[index]));
+                    return Ok(F32(array.borrow()[index]));
                 }
                 DoubleArray(array) => {
-                    return Ok(F64(array[index]));
+                    return Ok(F64(array.borrow()[index]));
                 }
                 ObjectArray(_arraytype, data) => {
-                    return Ok(Ref(data[index].clone()));
+                    return Ok(data.borrow()[index].clone().map_or(Null, Ref));
                 }
                 StringArray(array) => {
-                    return Ok(Utf8(array[index].to_owned()));
+                    return Ok(Utf8(array.borrow()[index].to_owned()));
                 }
                 Class(_) => {
                     panic!("should be array")
@@ -55,82 +54,81 @@ pub(crate) fn array_load(index: Value, arrayref: Value) -> Result<Value, Error>
     panic!()
 }
 
-pub(crate) fn array_store(value: Value, index: Value, arrayref: Value) -> Result<(), Error> {
+/// stores the value in the array, that is shared by all references to it
+pub(crate) fn array_store(value: Value, index: Value, arrayref: Value) -> Result<(), Fault> {
     if let Null = arrayref {
-        return Err(anyhow!("NullpointerException"));
+        return Err(Fault::NullPointer);
     }
 
     if let I32(index) = index {
-        if let Ref(mut objectref) = arrayref {
+        if let Ref(objectref) = arrayref {
+            let index = check_index(index, objectref.get_array_length())?;
             match objectref {
-                ByteArray(ref mut array) => {
+                ByteArray(array) => {
                     if let I32(value) = value {
-                        // is i32 correct?
-                        array[index as usize] = value as i8;
+                        array.borrow_mut()[index] = value as i8;
                     } else {
                         unreachable!()
                     }
                 }
-                ShortArray(ref mut array) => {
+                ShortArray(array) => {
                     if let I32(value) = value {
-                        // is i32 correct?
-                        array[index as usize] = value as i16;
+                        array.borrow_mut()[index] = value as i16;
                     } else {
                         unreachable!()
                     }
                 }
-                IntArray(ref mut array) => {
+                IntArray(array) => {
                     if let I32(value) = value {
-                        array[index as usize] = value;
+                        array.borrow_mut()[index] = value;
                     } else {
                         unreachable!()
                     }
                 }
-                BooleanArray(ref mut array) => {
+                BooleanArray(array) => {
                     if let I32(value) = value {
-                        array[index as usize] = value > 0;
+                        // bastore keeps the lowest bit for boolean arrays
+                        array.borrow_mut()[index] = value & 1 != 0;
                     } else {
                         unreachable!()
                     }
                 }
-                CharArray(ref mut array) => {
-                    if let I32(value) = value {
-                        array[index as usize] = value
+                CharArray(array) => {
+                    if let I32(value) | CHAR(value) = value {
+                        array.borrow_mut()[index] = value as u16 as i32
                     } else {
                         unreachable!()
                     }
                 }
-                LongArray(ref mut array) => {
+                LongArray(array) => {
                     if let I64(value) = value {
-                        array[index as usize] = value;
+                        array.borrow_mut()[index] = value;
                     } else {
                         unreachable!()
                     }
                 }
-                FloatArray(ref mut array) => {
+                FloatArray(array) => {
                     if let F32(value) = value {
-                        array[index as usize] = value
+                        array.borrow_mut()[index] = value
                     } else {
                         unreachable!()
                     }
                 }
-                DoubleArray(ref mut array) => {
+                DoubleArray(array) => {
                     if let F64(value) = value {
-                        array[index as usize] = value
-                    } else {
-                        unreachable!()
-                    }
-                }
-                ObjectArray(_arraytype, ref mut array) => {
-                    if let Ref(ref value) = value {
-                        array[index as usize] = value.clone();
+                        array.borrow_mut()[index] = value
                     } else {
                         unreachable!()
                     }
                 }
-                StringArray(ref mut array) => {
-                    if let Utf8(ref value) = value {
-                        array[index as usize] = value.clone();
+                ObjectArray(_arraytype, array) => match value {
+                    Ref(value) => array.borrow_mut()[index] = Some(value),
+                    Null => array.borrow_mut()[index] = None,
+                    _ => unreachable!(),
+                },
+                StringArray(array) => {
+                    if let Utf8(value) = value {
+                        array.borrow_mut()[index] = value;
                     } else {
                         unreachable!()
                     }
@@ -143,3 +141,12 @@ pub(crate) fn array_store(value: Value, index: Value, arrayref: Value) -> Result
     }
     Ok(())
 }
+
+/// checks that the index lies within the array bounds
+fn check_index(index: i32, length: usize) -> Result<usize, Fault> {
+    if index < 0 || index as usize >= length {
+        Err(Fault::ArrayIndexOutOfBounds { index, length })
+    } else {
+        Ok(index as usize)
+    }
+}
@@ -0,0 +1,146 @@
+use crate::class::ClassId;
+use crate::classloader::classdef::CpEntry;
+use crate::classmanager::ClassManager;
+use crate::value::Value::{self, *};
+use crate::vm::fault::Fault;
+use crate::vm::object::ObjectRef;
+use crate::vm::runtime::{new_string, new_string_utf16, Abrupt, MethodResult, Stackframe};
+
+const REF_INVOKE_STATIC: u8 = 6;
+
+/// resolves the Dynamic constant at cp_index in the constant pool of the class (JVMS 5.4.3.6)
+/// The bootstrap method is invoked the first time only, after that the value comes from the class manager.
+///
+/// Only static bootstrap methods are supported. There are no method handles yet, so the lookup
+/// argument is null, as is the type argument for primitive types. Static arguments are not boxed,
+/// they are passed as the bootstrap method declares them.
+pub(crate) fn resolve_dynamic(
+    class_manager: &mut ClassManager,
+    class_id: ClassId,
+    cp_index: u16,
+) -> MethodResult {
+    if let Some(value) = class_manager.get_dynamic_constant(class_id, cp_index) {
+        return Ok(value.clone());
+    }
+
+    let classdef = class_manager.get_classdef(&class_id);
+    let (bootstrap_index, name_and_type_index) = classdef.cp_dynamic(&cp_index);
+    let (name_index, type_index) = classdef.cp_name_and_type(name_and_type_index);
+    let name = classdef.cp_utf8(name_index).to_owned();
+    let field_type = classdef.cp_utf8(type_index).to_owned();
+    let Some(bootstrap_method) = classdef.bootstrap_method(bootstrap_index) else {
+        let message = format!(
+            "no bootstrap method #{} in {}",
+            bootstrap_index,
+            classdef.name()
+        );
+        return Err(Fault::BootstrapMethod(message).into_abrupt(class_manager));
+    };
+    let (reference_kind, method_ref) = classdef.cp_method_handle(&bootstrap_method.method_ref);
+    if *reference_kind != REF_INVOKE_STATIC {
+        let message = format!("unsupported bootstrap method kind {}", reference_kind);
+        return Err(Fault::BootstrapMethod(message).into_abrupt(class_manager));
+    }
+    let (class_index, method_name_and_type_index) = classdef.cp_method_ref(method_ref);
+    let bootstrap_class = classdef.cp_class_name(class_index).to_owned();
+    let (method_name_index, descriptor_index) =
+        classdef.cp_name_and_type(method_name_and_type_index);
+    let method_name = format!(
+        "{}{}",
+        classdef.cp_utf8(method_name_index),
+        classdef.cp_utf8(descriptor_index)
+    );
+    let static_arguments = bootstrap_method.arguments.clone();
+
+    let mut args = vec![
+        Null,
+        new_string(class_manager, &name),
+        type_object(class_manager, &field_type)?,
+    ];
+    for argument in static_arguments {
+        args.push(static_argument(class_manager, class_id, argument)?);
+    }
+
+    let bootstrap_class_id = load_class_id(class_manager, &bootstrap_class)?;
+    let Some(declaring_class_id) =
+        class_manager.find_method_class(&bootstrap_class_id, &method_name)
+    else {
+        let message = format!("method {}.{} not found", bootstrap_class, method_name);
+        return Err(Fault::BootstrapMethod(message).into_abrupt(class_manager));
+    };
+    let mut value = Stackframe::new(args).run(class_manager, declaring_class_id, &method_name)?;
+    if !is_reference(&field_type) {
+        value = unbox(class_manager, value);
+    }
+
+    class_manager.set_dynamic_constant(class_id, cp_index, value.clone());
+    Ok(value)
+}
+
+/// the value of a loadable constant that is passed to the bootstrap method
+fn static_argument(
+    class_manager: &mut ClassManager,
+    class_id: ClassId,
+    cp_index: u16,
+) -> MethodResult {
+    let classdef = class_manager.get_classdef(&class_id);
+    match classdef.constant_pool.get(&cp_index) {
+        Some(CpEntry::Integer(i)) => Ok(I32(*i)),
+        Some(CpEntry::Float(f)) => Ok(F32(*f)),
+        Some(CpEntry::Long(l)) => Ok(I64(*l)),
+        Some(CpEntry::Double(d)) => Ok(F64(*d)),
+        Some(CpEntry::StringRef(utf8)) => {
+            let units = classdef.cp_utf16(utf8).to_vec();
+            Ok(new_string_utf16(class_manager, &units))
+        }
+        Some(CpEntry::ClassRef(name_index)) => {
+            let class_name = classdef.cp_utf8(name_index).to_owned();
+            class_object(class_manager, &class_name)
+        }
+        Some(CpEntry::Dynamic(..)) => resolve_dynamic(class_manager, class_id, cp_index),
+        other => {
+            let message = format!("unsupported bootstrap method argument {:?}", other);
+            Err(Fault::BootstrapMethod(message).into_abrupt(class_manager))
+        }
+    }
+}
+
+/// the Class object for a field descriptor, null for primitive types
+pub(crate) fn type_object(class_manager: &mut ClassManager, descriptor: &str) -> MethodResult {
+    if descriptor.starts_with('L') {
+        class_object(class_manager, &descriptor[1..descriptor.len() - 1])
+    } else if descriptor.starts_with('[') {
+        class_object(class_manager, descriptor)
+    } else {
+        Ok(Null)
+    }
+}
+
+fn class_object(class_manager: &mut ClassManager, class_name: &str) -> MethodResult {
+    let id = load_class_id(class_manager, class_name)?;
+    Ok(class_manager.get_classobject(&id).cloned().unwrap_or(Null))
+}
+
+/// loads the class, the java error if that fails
+fn load_class_id(class_manager: &mut ClassManager, class_name: &str) -> Result<ClassId, Abrupt> {
+    if let Err(error) = class_manager.load_class_by_name(class_name) {
+        return Err(Fault::class_not_loaded(class_name, &error).into_abrupt(class_manager));
+    }
+    Ok(*class_manager.get_classid(class_name))
+}
+
+fn is_reference(descriptor: &str) -> bool {
+    descriptor.starts_with('L') || descriptor.starts_with('[')
+}
+
+/// bootstrap methods return an Object, for a primitive type that is the box (Integer, Long etc.)
+fn unbox(class_manager: &mut ClassManager, value: Value) -> Value {
+    if let Ref(ObjectRef::Object(object)) = &value {
+        let object = object.borrow();
+        if let Some(class) = class_manager.get_class_by_id(&object.class_id) {
+            let box_type = class.name.clone();
+            return object.get(class, &box_type, &"value".to_owned()).clone();
+        }
+    }
+    value
+}
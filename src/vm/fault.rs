@@ -0,0 +1,222 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::Error;
+
+use crate::classloader::error::{ClassFormatError, FormatCheck, VerifyError};
+use crate::classmanager::{ClassManager, InitializationError};
+use crate::value::Value;
+use crate::vm::object::ObjectRef;
+use crate::vm::runtime::{new_string, Abrupt, Stackframe};
+
+/// Runtime faults detected by the interpreter.
+/// These are not rust errors, but are thrown into the running java code as the corresponding exception.
+#[derive(Debug)]
+pub(crate) enum Fault {
+    NullPointer,
+    DivisionByZero,
+    ArrayIndexOutOfBounds {
+        index: i32,
+        length: usize,
+    },
+    NegativeArraySize(i32),
+    ClassCast {
+        class_name: String,
+        type_name: String,
+    },
+    ClassFormat(String),
+    UnsupportedClassVersion(String),
+    Verify(String),
+    NoClassDefFound(String),
+    // the static initializer of the class threw
+    Initialization(String),
+    BootstrapMethod(String),
+    NoSuchMethod(String),
+    // a native method that the vm does not implement
+    UnsatisfiedLink(String),
+    // a native method that failed
+    Internal(String),
+    // a part of the JVMS that the vm does not implement, like method handles
+    Unsupported(String),
+    // System.exit or Runtime.halt, not an exception: no handler catches it
+    Exit(i32),
+}
+
+impl Fault {
+    /// the java exception class for this fault
+    pub(crate) fn class_name(&self) -> &'static str {
+        match self {
+            Fault::NullPointer => "java/lang/NullPointerException",
+            Fault::DivisionByZero => "java/lang/ArithmeticException",
+            Fault::ArrayIndexOutOfBounds { .. } => "java/lang/ArrayIndexOutOfBoundsException",
+            Fault::NegativeArraySize(_) => "java/lang/NegativeArraySizeException",
+            Fault::ClassCast { .. } => "java/lang/ClassCastException",
+            Fault::ClassFormat(_) => "java/lang/ClassFormatError",
+            Fault::UnsupportedClassVersion(_) => "java/lang/UnsupportedClassVersionError",
+            Fault::Verify(_) => "java/lang/VerifyError",
+            Fault::NoClassDefFound(_) | Fault::Initialization(_) => {
+                "java/lang/NoClassDefFoundError"
+            }
+            Fault::BootstrapMethod(_) => "java/lang/BootstrapMethodError",
+            Fault::NoSuchMethod(_) => "java/lang/NoSuchMethodError",
+            Fault::UnsatisfiedLink(_) => "java/lang/UnsatisfiedLinkError",
+            Fault::Internal(_) | Fault::Unsupported(_) => "java/lang/InternalError",
+            Fault::Exit(_) => unreachable!("exit is not an exception"),
+        }
+    }
+
+    /// the exception message, same as the ones hotspot uses
+    pub(crate) fn message(&self) -> Option<String> {
+        match self {
+            Fault::NullPointer | Fault::Exit(_) => None,
+            Fault::DivisionByZero => Some("/ by zero".into()),
+            Fault::ArrayIndexOutOfBounds { index, length } => Some(format!(
+                "Index {} out of bounds for length {}",
+                index, length
+            )),
+            Fault::NegativeArraySize(size) => Some(size.to_string()),
+            Fault::ClassCast {
+                class_name,
+                type_name,
+            } => Some(format!(
+                "class {} cannot be cast to class {}",
+                class_name.replace('/', "."),
+                type_name.replace('/', ".")
+            )),
+            Fault::ClassFormat(message)
+            | Fault::UnsupportedClassVersion(message)
+            | Fault::Verify(message) => Some(message.clone()),
+            Fault::NoClassDefFound(class_name) => Some(class_name.clone()),
+            Fault::Initialization(class_name) => Some(format!(
+                "Could not initialize class {}",
+                class_name.replace('/', ".")
+            )),
+            Fault::BootstrapMethod(message)
+            | Fault::NoSuchMethod(message)
+            | Fault::UnsatisfiedLink(message)
+            | Fault::Internal(message)
+            | Fault::Unsupported(message) => Some(message.clone()),
+        }
+    }
+
+    /// the fault for a class that could not be loaded:
+    /// a ClassFormatError if the class file is malformed, an UnsupportedClassVersionError if
+    /// its version is not supported, a VerifyError if its code does not pass verification,
+    /// the exception from the static initializer if that threw, otherwise a NoClassDefFoundError
+    pub(crate) fn class_not_loaded(class_name: &str, error: &Error) -> Self {
+        if let Some(format_error) = error.downcast_ref::<ClassFormatError>() {
+            if format_error.check == FormatCheck::Version {
+                Fault::UnsupportedClassVersion(format_error.to_string())
+            } else {
+                Fault::ClassFormat(format_error.to_string())
+            }
+        } else if let Some(verify_error) = error.downcast_ref::<VerifyError>() {
+            Fault::Verify(verify_error.to_string())
+        } else if let Some(initialization_error) = error.downcast_ref::<InitializationError>() {
+            Fault::Initialization(initialization_error.class_name.clone())
+        } else if let Some(Fault::Exit(status)) = error.downcast_ref::<Fault>() {
+            // the static initializer called System.exit
+            Fault::Exit(*status)
+        } else {
+            Fault::NoClassDefFound(class_name.to_owned())
+        }
+    }
+
+    /// the fault for a native method that returned an error: the fault itself if the native
+    /// method failed with one, otherwise an InternalError
+    pub(crate) fn native_failed(error: Error) -> Self {
+        error
+            .downcast::<Fault>()
+            .unwrap_or_else(|error| Fault::Internal(error.to_string()))
+    }
+
+    /// creates the exception instance that is to be thrown for this fault
+    /// the exception of a failed static initializer is thrown once, after that the class
+    /// is not usable and this is a NoClassDefFoundError
+    pub(crate) fn into_exception(self, class_manager: &mut ClassManager) -> Value {
+        if let Fault::Initialization(class_name) = &self {
+            if let Some(exception) = class_manager.take_initializer_exception(class_name) {
+                return exception;
+            }
+        }
+        new_exception(class_manager, self.class_name(), self.message())
+    }
+
+    /// how the method that ran into this fault completes: with the exit of the vm,
+    /// or with the exception for the fault
+    pub(crate) fn into_abrupt(self, class_manager: &mut ClassManager) -> Abrupt {
+        match self {
+            Fault::Exit(status) => Abrupt::Exit(status),
+            fault => Abrupt::Throw(fault.into_exception(class_manager)),
+        }
+    }
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.class_name().replace('/', "."))?;
+        if let Some(message) = self.message() {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Fault {}
+
+/// the exception for a static initializer that threw, JVMS 5.5:
+/// an Error is thrown as it is, other exceptions are wrapped in an ExceptionInInitializerError
+pub(crate) fn exception_in_initializer(
+    class_manager: &mut ClassManager,
+    exception: Value,
+) -> Value {
+    let Value::Ref(ObjectRef::Object(object)) = &exception else {
+        unreachable!("can only throw objects, not {:?}", exception)
+    };
+    let class_id = object.borrow().class_id;
+    if let Some(error_id) = class_manager.names.get("java/lang/Error").copied() {
+        if class_manager.is_subtype(&class_id, &error_id) {
+            return exception;
+        }
+    }
+    let class_name = "java/lang/ExceptionInInitializerError";
+    let error = match class_manager.new_instance(class_name) {
+        Ok(error) => error,
+        Err(error) => {
+            return Fault::class_not_loaded(class_name, &error).into_exception(class_manager)
+        }
+    };
+    let class_id = *class_manager.get_classid(class_name);
+    match Stackframe::new(vec![error.clone(), exception]).run(
+        class_manager,
+        class_id,
+        "<init>(Ljava/lang/Throwable;)V",
+    ) {
+        Ok(_) => error,
+        Err(Abrupt::Throw(constructor_exception)) => constructor_exception,
+        Err(Abrupt::Exit(_)) => panic!("System.exit in the constructor of {}", class_name),
+    }
+}
+
+/// instantiates an exception and runs the constructor, with the message if there is one
+pub(crate) fn new_exception(
+    class_manager: &mut ClassManager,
+    class_name: &str,
+    message: Option<String>,
+) -> Value {
+    let exception = class_manager
+        .new_instance(class_name)
+        .unwrap_or_else(|error| panic!("cannot load {}: {}", class_name, error));
+    let class_id = *class_manager.get_classid(class_name);
+    let (constructor, args) = match message {
+        Some(message) => (
+            "<init>(Ljava/lang/String;)V",
+            vec![exception.clone(), new_string(class_manager, &message)],
+        ),
+        None => ("<init>()V", vec![exception.clone()]),
+    };
+    match Stackframe::new(args).run(class_manager, class_id, constructor) {
+        Ok(_) => exception,
+        Err(Abrupt::Throw(constructor_exception)) => constructor_exception,
+        Err(Abrupt::Exit(_)) => panic!("System.exit in the constructor of {}", class_name),
+    }
+}
@@ -1,5 +1,8 @@
 mod array;
+mod dynamic;
+pub(crate) mod fault;
 mod native;
 pub(crate) mod object;
-pub(crate) mod opcodes;
-pub mod runtime;
\ No newline at end of file
+pub mod opcodes;
+mod reflection;
+pub mod runtime;
@@ -5,16 +5,23 @@ use std::collections::HashMap;
 use std::future::Future;
 use std::rc::Rc;
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use log::debug;
 use once_cell::sync::Lazy;
 
+use crate::classloader::classdef::Modifier;
 use crate::classmanager::ClassManager;
 use crate::value::Value;
 use crate::value::Value::{Utf8, Void, I32};
+use crate::vm::fault::Fault;
 use crate::vm::object::ObjectRef::Object;
 use crate::vm::object::{self, ObjectRef};
-use crate::vm::runtime::Stackframe;
+use crate::vm::reflection::{
+    self, GET_ANNOTATION, GET_ANNOTATIONS, GET_DECLARED_ANNOTATION, GET_DECLARED_ANNOTATIONS,
+    GET_DECLARED_FIELDS, GET_DECLARED_METHODS, GET_DEFAULT_VALUE, GET_PARAMETER_ANNOTATIONS,
+    IS_ANNOTATION_PRESENT,
+};
+use crate::vm::runtime::{new_string, CallFrame, Stackframe, Vm};
 
 const primitive_name_classes: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
     let mut mapping = HashMap::new();
@@ -40,12 +47,25 @@ pub fn invoke_native(
 
     match class_name {
         "java/lang/Class" => java_lang_Class(class_manager, method_name, args),
-        "java/lang/System" => java_lang_System(method_name),
+        "java/lang/Shutdown" => java_lang_Shutdown(method_name, args),
+        "java/lang/Runtime" => java_lang_Runtime(method_name, args),
+        "java/lang/System" => java_lang_System(method_name, args),
+        "java/lang/Throwable" => java_lang_Throwable(class_manager, method_name, args),
+        "java/lang/reflect/AccessibleObject"
+        | "java/lang/reflect/Method"
+        | "java/lang/reflect/Field" => {
+            java_lang_reflect_AccessibleObject(class_manager, method_name, args)
+        }
         "jdk/internal/misc/Unsafe" => jdk_internal_misc_Unsafe(method_name),
         "jdk/internal/util/SystemProps$Raw" => {
             jdk_internal_util_SystemProps_Raw(class_manager, method_name)
         }
-        _ => unimplemented!(""),
+        _ => {
+            Err(
+                Fault::UnsatisfiedLink(format!("{}.{}", class_name.replace('/', "."), method_name))
+                    .into(),
+            )
+        }
     }
 }
 
@@ -59,16 +79,200 @@ fn java_lang_Class(
         "getPrimitiveClass(Ljava/lang/String;)Ljava/lang/Class;" => {
             get_primitive_class(class_manager, args)
         }
+        GET_ANNOTATION => reflection::class_annotation(class_manager, &args[0], &args[1], true)?,
+        GET_DECLARED_ANNOTATION => {
+            reflection::class_annotation(class_manager, &args[0], &args[1], false)?
+        }
+        IS_ANNOTATION_PRESENT => {
+            let annotation = reflection::class_annotation(class_manager, &args[0], &args[1], true)?;
+            I32(!matches!(annotation, Value::Null) as i32)
+        }
+        GET_ANNOTATIONS => reflection::class_annotations(class_manager, &args[0], true)?,
+        GET_DECLARED_ANNOTATIONS => reflection::class_annotations(class_manager, &args[0], false)?,
+        GET_DECLARED_METHODS => reflection::declared_methods(class_manager, &args[0])?,
+        GET_DECLARED_FIELDS => reflection::declared_fields(class_manager, &args[0])?,
         _ => Void,
     })
 }
 
-fn java_lang_System(method_name: &str) -> Result<Value, Error> {
+/// the annotations of a java.lang.reflect.Method or Field
+/// methods and fields have no inherited annotations
+fn java_lang_reflect_AccessibleObject(
+    class_manager: &mut ClassManager,
+    method_name: &str,
+    args: Vec<Value>,
+) -> Result<Value, Error> {
     Ok(match method_name {
+        GET_ANNOTATION | GET_DECLARED_ANNOTATION => {
+            reflection::member_annotation(class_manager, &args[0], &args[1])?
+        }
+        IS_ANNOTATION_PRESENT => {
+            let annotation = reflection::member_annotation(class_manager, &args[0], &args[1])?;
+            I32(!matches!(annotation, Value::Null) as i32)
+        }
+        GET_ANNOTATIONS | GET_DECLARED_ANNOTATIONS => {
+            reflection::member_annotations(class_manager, &args[0])?
+        }
+        GET_PARAMETER_ANNOTATIONS => reflection::parameter_annotations(class_manager, &args[0])?,
+        GET_DEFAULT_VALUE => reflection::default_value(class_manager, &args[0])?,
         _ => Void,
     })
 }
 
+fn java_lang_Runtime(method_name: &str, args: Vec<Value>) -> Result<Value, Error> {
+    Ok(match method_name {
+        "exit(I)V" | "halt(I)V" => halt(&args[1])?,
+        _ => Void,
+    })
+}
+
+fn java_lang_Shutdown(method_name: &str, args: Vec<Value>) -> Result<Value, Error> {
+    Ok(match method_name {
+        "halt0(I)V" => halt(&args[0])?,
+        _ => Void,
+    })
+}
+
+fn java_lang_System(method_name: &str, args: Vec<Value>) -> Result<Value, Error> {
+    Ok(match method_name {
+        "exit(I)V" => halt(&args[0])?,
+        _ => Void,
+    })
+}
+
+/// exits the vm with the status, the fault unwinds all frames to Vm::start
+/// there are no other threads to wait for, so shutdown hooks are not run
+fn halt(status: &Value) -> Result<Value, Error> {
+    if let I32(status) = status {
+        return Err(Fault::Exit(*status).into());
+    }
+    Err(anyhow!("exit status is not an int: {:?}", status))
+}
+
+/// java methods that the vm replaces with a native implementation
+pub(crate) fn is_intrinsic(class_name: &str, method_name: &str) -> bool {
+    matches!(
+        (class_name, method_name),
+        (
+            "java/lang/Throwable",
+            "getStackTrace()[Ljava/lang/StackTraceElement;"
+        ) | ("java/lang/System", "exit(I)V")
+            | ("java/lang/Runtime", "exit(I)V")
+            | ("java/lang/Runtime", "halt(I)V")
+    ) || reflection::INTRINSICS.contains(&(class_name, method_name))
+}
+
+fn java_lang_Throwable(
+    class_manager: &mut ClassManager,
+    method_name: &str,
+    args: Vec<Value>,
+) -> Result<Value, Error> {
+    match method_name {
+        "fillInStackTrace(I)Ljava/lang/Throwable;" => fill_in_stack_trace(class_manager, args),
+        "getStackTrace()[Ljava/lang/StackTraceElement;" => get_stack_trace(class_manager, args),
+        _ => Ok(Void),
+    }
+}
+
+/// records the current call stack in the throwable, as an array of StackTraceElement
+/// the frames of fillInStackTrace and of the constructors of the throwable itself are left out
+fn fill_in_stack_trace(class_manager: &mut ClassManager, args: Vec<Value>) -> Result<Value, Error> {
+    let this = args[0].clone();
+    if let Value::Ref(Object(throwable)) = &this {
+        let class_id = throwable.borrow().class_id;
+        let parents = class_manager
+            .get_class_by_id(&class_id)
+            .unwrap()
+            .parents
+            .clone();
+        let frames: Vec<CallFrame> = Vm::call_stack()
+            .into_iter()
+            .rev()
+            .skip_while(|frame| frame.method_name.starts_with("fillInStackTrace"))
+            .skip_while(|frame| {
+                frame.method_name.starts_with("<init>") && parents.contains(&frame.class_id)
+            })
+            .collect();
+
+        let depth = frames.len() as i32;
+        let elements = frames
+            .iter()
+            .map(|frame| new_stack_trace_element(class_manager, frame))
+            .collect::<Result<_, _>>()?;
+        class_manager.load_class_by_name("java/lang/StackTraceElement")?;
+        let element_class_id = *class_manager.get_classid("java/lang/StackTraceElement");
+        let backtrace = Value::Ref(ObjectRef::object_array(element_class_id, elements));
+
+        let class = class_manager.get_class_by_id(&class_id).unwrap();
+        let mut throwable = throwable.borrow_mut();
+        throwable.set(class, "java/lang/Throwable", "backtrace", backtrace);
+        throwable.set(class, "java/lang/Throwable", "depth", I32(depth));
+    }
+    Ok(this)
+}
+
+fn new_stack_trace_element(
+    class_manager: &mut ClassManager,
+    frame: &CallFrame,
+) -> Result<ObjectRef, Error> {
+    let classdef = class_manager.get_classdef(&frame.class_id);
+    let declaring_class = classdef.name().replace('/', ".");
+    let file_name = classdef.source_file().cloned();
+    let method = classdef.get_method(&frame.method_name).unwrap();
+    let line_number = if method.is(Modifier::Native) {
+        -2
+    } else {
+        method
+            .line_number(frame.pc)
+            .map(|line| line as i32)
+            .unwrap_or(-1)
+    };
+    let method_name = frame.method_name[..frame.method_name.find('(').unwrap()].to_owned();
+
+    let declaring_class = new_string(class_manager, &declaring_class);
+    let method_name = new_string(class_manager, &method_name);
+    let file_name = file_name
+        .map(|file_name| new_string(class_manager, &file_name))
+        .unwrap_or(Value::Null);
+
+    let element = class_manager.new_instance("java/lang/StackTraceElement")?;
+    let class = class_manager
+        .get_class_by_name("java/lang/StackTraceElement")
+        .unwrap();
+    if let Value::Ref(Object(object)) = &element {
+        let mut object = object.borrow_mut();
+        let declared_type = "java/lang/StackTraceElement";
+        object.set(class, declared_type, "declaringClass", declaring_class);
+        object.set(class, declared_type, "methodName", method_name);
+        object.set(class, declared_type, "fileName", file_name);
+        object.set(class, declared_type, "lineNumber", I32(line_number));
+    }
+    Ok(element.into_object())
+}
+
+/// returns the stack trace that was recorded by fillInStackTrace
+fn get_stack_trace(class_manager: &mut ClassManager, args: Vec<Value>) -> Result<Value, Error> {
+    if let Value::Ref(Object(throwable)) = &args[0] {
+        let throwable = throwable.borrow();
+        let class = class_manager.get_class_by_id(&throwable.class_id).unwrap();
+        let backtrace = throwable.get(
+            class,
+            &"java/lang/Throwable".to_owned(),
+            &"backtrace".to_owned(),
+        );
+        if let Value::Ref(ObjectRef::ObjectArray(..)) = backtrace {
+            return Ok(backtrace.clone());
+        }
+    }
+    // not writable, or not filled in
+    class_manager.load_class_by_name("java/lang/StackTraceElement")?;
+    let element_class_id = *class_manager.get_classid("java/lang/StackTraceElement");
+    Ok(Value::Ref(ObjectRef::object_array(
+        element_class_id,
+        vec![],
+    )))
+}
+
 fn get_primitive_class(class_manager: &mut ClassManager, args: Vec<Value>) -> Value {
     if let Utf8(primitive) = args.get(0).unwrap().to_owned() {
         unsafe {
@@ -112,14 +316,30 @@ fn jdk_internal_util_SystemProps_Raw(
 }
 
 fn cmdProps(class_manager: &mut ClassManager) -> Result<Value, Error> {
-    class_manager.load_class_by_name("java/util/HashMap");
+    class_manager.load_class_by_name("java/util/HashMap")?;
     let hashmap_class = class_manager
         .get_class_by_name("java/util/HashMap")
         .unwrap();
     let hashmap = Value::Ref(Object(Rc::new(RefCell::new(object::Object::new(
         hashmap_class,
     ))))); // this is convoluted
-    Stackframe::new(vec![hashmap.clone()]).run(class_manager, hashmap_class.id, "<init>()V");
+    let hashmap_id = hashmap_class.id;
+    Stackframe::new(vec![hashmap.clone()])
+        .run(class_manager, hashmap_id, "<init>()V")
+        .map_err(|exception| anyhow!("{:?} in HashMap.<init>", exception))?;
+
+    let properties = class_manager.properties().to_vec();
+    for (name, value) in properties {
+        let name = new_string(class_manager, &name);
+        let value = new_string(class_manager, &value);
+        Stackframe::new(vec![hashmap.clone(), name, value])
+            .run(
+                class_manager,
+                hashmap_id,
+                "put(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+            )
+            .map_err(|exception| anyhow!("{:?} in HashMap.put", exception))?;
+    }
     Ok(hashmap)
 }
 
@@ -129,7 +349,9 @@ fn vmProperties() -> Result<Value, Error> {
         //TODO insert some values
         vec
     });
-    Ok(Value::Ref(ObjectRef::StringArray(props.to_vec())))
+    Ok(Value::Ref(ObjectRef::StringArray(object::array(
+        props.to_vec(),
+    ))))
 }
 
 fn platformProperties() -> Result<Value, Error> {
@@ -216,5 +438,7 @@ fn platformProperties() -> Result<Value, Error> {
 
         vec
     });
-    Ok(Value::Ref(ObjectRef::StringArray(props.to_vec())))
+    Ok(Value::Ref(ObjectRef::StringArray(object::array(
+        props.to_vec(),
+    ))))
 }
@@ -7,20 +7,30 @@ use std::cell::RefCell;
 use std::fmt::{Debug, Formatter, Pointer};
 use std::rc::Rc;
 
+/// the elements of an array, shared by all references to it
+/// so that stores are seen through each of them, and the array has an identity
+pub type Array<T> = Rc<RefCell<Vec<T>>>;
+
+pub(crate) fn array<T>(elements: Vec<T>) -> Array<T> {
+    Rc::new(RefCell::new(elements))
+}
+
 #[derive(Clone)]
 pub enum ObjectRef {
-    ByteArray(Vec<i8>), //maybe use arrays?
-    ShortArray(Vec<i16>),
-    IntArray(Vec<i32>),
-    LongArray(Vec<i64>),
-    FloatArray(Vec<f32>),
-    DoubleArray(Vec<f64>),
-    BooleanArray(Vec<bool>),
-    CharArray(Vec<i32>),
-    StringArray(Vec<String>),
-    ObjectArray(ClassId, Vec<ObjectRef>),
+    ByteArray(Array<i8>),
+    ShortArray(Array<i16>),
+    IntArray(Array<i32>),
+    LongArray(Array<i64>),
+    FloatArray(Array<f32>),
+    DoubleArray(Array<f64>),
+    BooleanArray(Array<bool>),
+    CharArray(Array<i32>),
+    StringArray(Array<String>),
+    // the elements are null (None) or references
+    ObjectArray(ClassId, Array<Option<ObjectRef>>),
     Object(Rc<RefCell<Object>>),
-    Class(Class),
+    // boxed, a Class is much larger than the other variants
+    Class(Box<Class>),
 }
 
 impl Debug for ObjectRef {
@@ -46,16 +56,16 @@ impl Debug for ObjectRef {
 impl ObjectRef {
     pub fn get_array_length(&self) -> usize {
         match self {
-            ByteArray(d) => d.len(),
-            ShortArray(d) => d.len(),
-            IntArray(d) => d.len(),
-            LongArray(d) => d.len(),
-            FloatArray(d) => d.len(),
-            DoubleArray(d) => d.len(),
-            BooleanArray(d) => d.len(),
-            CharArray(d) => d.len(),
-            StringArray(d) => d.len(),
-            ObjectArray(_, d) => d.len(),
+            ByteArray(d) => d.borrow().len(),
+            ShortArray(d) => d.borrow().len(),
+            IntArray(d) => d.borrow().len(),
+            LongArray(d) => d.borrow().len(),
+            FloatArray(d) => d.borrow().len(),
+            DoubleArray(d) => d.borrow().len(),
+            BooleanArray(d) => d.borrow().len(),
+            CharArray(d) => d.borrow().len(),
+            StringArray(d) => d.borrow().len(),
+            ObjectArray(_, d) => d.borrow().len(),
             _ => unreachable!("not an array {:?}", self),
         }
     }
@@ -72,31 +82,54 @@ pub enum ArrayType {
     LONG = 11,
 }
 
+impl ArrayType {
+    /// the array type for the descriptor of a primitive type, eg. I for int
+    pub fn from_descriptor(descriptor: u8) -> Option<Self> {
+        match descriptor {
+            b'Z' => Some(ArrayType::BOOLEAN),
+            b'C' => Some(ArrayType::CHAR),
+            b'F' => Some(ArrayType::FLOAT),
+            b'D' => Some(ArrayType::DOUBLE),
+            b'B' => Some(ArrayType::BYTE),
+            b'S' => Some(ArrayType::SHORT),
+            b'I' => Some(ArrayType::INT),
+            b'J' => Some(ArrayType::LONG),
+            _ => None,
+        }
+    }
+}
+
 impl ObjectRef {
-    pub fn new_object_array(class: &Class, size: usize) -> Self {
-        ObjectArray(class.id, Vec::with_capacity(size))
+    /// an array of nulls, of the class with the id
+    pub fn new_object_array(class_id: ClassId, size: usize) -> Self {
+        ObjectArray(class_id, array(vec![None; size]))
+    }
+
+    /// an array with the references, none of which is null
+    pub fn object_array(class_id: ClassId, elements: Vec<ObjectRef>) -> Self {
+        ObjectArray(class_id, array(elements.into_iter().map(Some).collect()))
     }
 
     pub fn new_array(arraytype: u8, size: usize) -> Self {
         match arraytype {
-            8 => ByteArray(vec![0; size]),
-            9 => ShortArray(vec![0; size]),
-            10 => IntArray(vec![0; size]),
-            11 => LongArray(vec![0; size]),
-            6 => FloatArray(vec![0.0; size]),
-            7 => DoubleArray(vec![0.0; size]),
-            4 => BooleanArray(vec![false; size]),
-            5 => CharArray(vec![0; size]),
+            8 => ByteArray(array(vec![0; size])),
+            9 => ShortArray(array(vec![0; size])),
+            10 => IntArray(array(vec![0; size])),
+            11 => LongArray(array(vec![0; size])),
+            6 => FloatArray(array(vec![0.0; size])),
+            7 => DoubleArray(array(vec![0.0; size])),
+            4 => BooleanArray(array(vec![false; size])),
+            5 => CharArray(array(vec![0; size])),
             _ => unreachable!("impossible array type"),
         }
     }
 
     pub fn new_int_array(size: usize) -> Self {
-        IntArray(Vec::with_capacity(size))
+        IntArray(array(vec![0; size]))
     }
 
     pub fn new_byte_array(d: Vec<u8>) -> Self {
-        ByteArray(into_vec_i8(d))
+        ByteArray(array(into_vec_i8(d)))
     }
 }
 
@@ -0,0 +1,720 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Error};
+
+use crate::class::ClassId;
+use crate::classloader::annotations::{Annotation, ElementValue};
+use crate::classloader::classdef::{ClassDef, CpEntry, Method, Modifier};
+use crate::classmanager::ClassManager;
+use crate::value::Value::{self, *};
+use crate::vm::dynamic::type_object;
+use crate::vm::object::{array, Object, ObjectRef};
+use crate::vm::runtime::{new_string, new_string_utf16};
+
+// Annotations at runtime (java.lang.reflect.AnnotatedElement).
+// There are no dynamic proxies, instead an annotation is an instance of the annotation interface
+// itself, created by the vm, that holds the values of the elements in its data, in the order of
+// the element names. Invocations on it are answered by the vm, see annotation_method.
+
+/// the java methods that are implemented here, as intrinsics because the JDK parses
+/// the annotation bytes itself
+pub(crate) const INTRINSICS: [(&str, &str); 18] = [
+    ("java/lang/Class", GET_ANNOTATION),
+    ("java/lang/Class", GET_DECLARED_ANNOTATION),
+    ("java/lang/Class", IS_ANNOTATION_PRESENT),
+    ("java/lang/Class", GET_ANNOTATIONS),
+    ("java/lang/Class", GET_DECLARED_ANNOTATIONS),
+    ("java/lang/Class", GET_DECLARED_METHODS),
+    ("java/lang/Class", GET_DECLARED_FIELDS),
+    ("java/lang/reflect/AccessibleObject", GET_ANNOTATION),
+    (
+        "java/lang/reflect/AccessibleObject",
+        GET_DECLARED_ANNOTATION,
+    ),
+    ("java/lang/reflect/AccessibleObject", IS_ANNOTATION_PRESENT),
+    ("java/lang/reflect/AccessibleObject", GET_ANNOTATIONS),
+    (
+        "java/lang/reflect/AccessibleObject",
+        GET_DECLARED_ANNOTATIONS,
+    ),
+    ("java/lang/reflect/Method", GET_ANNOTATION),
+    ("java/lang/reflect/Method", GET_DECLARED_ANNOTATIONS),
+    ("java/lang/reflect/Method", GET_PARAMETER_ANNOTATIONS),
+    ("java/lang/reflect/Method", GET_DEFAULT_VALUE),
+    ("java/lang/reflect/Field", GET_ANNOTATION),
+    ("java/lang/reflect/Field", GET_DECLARED_ANNOTATIONS),
+];
+
+pub(crate) const GET_ANNOTATION: &str =
+    "getAnnotation(Ljava/lang/Class;)Ljava/lang/annotation/Annotation;";
+pub(crate) const GET_DECLARED_ANNOTATION: &str =
+    "getDeclaredAnnotation(Ljava/lang/Class;)Ljava/lang/annotation/Annotation;";
+pub(crate) const IS_ANNOTATION_PRESENT: &str = "isAnnotationPresent(Ljava/lang/Class;)Z";
+pub(crate) const GET_ANNOTATIONS: &str = "getAnnotations()[Ljava/lang/annotation/Annotation;";
+pub(crate) const GET_DECLARED_ANNOTATIONS: &str =
+    "getDeclaredAnnotations()[Ljava/lang/annotation/Annotation;";
+pub(crate) const GET_DECLARED_METHODS: &str = "getDeclaredMethods()[Ljava/lang/reflect/Method;";
+pub(crate) const GET_DECLARED_FIELDS: &str = "getDeclaredFields()[Ljava/lang/reflect/Field;";
+pub(crate) const GET_PARAMETER_ANNOTATIONS: &str =
+    "getParameterAnnotations()[[Ljava/lang/annotation/Annotation;";
+pub(crate) const GET_DEFAULT_VALUE: &str = "getDefaultValue()Ljava/lang/Object;";
+
+type ConstantPool = Rc<HashMap<u16, CpEntry>>;
+
+/// the annotation of the given type on the class, or null
+/// with inherited, the @Inherited annotations of the superclasses are included
+pub(crate) fn class_annotation(
+    class_manager: &mut ClassManager,
+    class_object: &Value,
+    annotation_type: &Value,
+    inherited: bool,
+) -> Result<Value, Error> {
+    let class_id = class_id_of(class_manager, class_object)?;
+    let type_name = class_name_of(class_manager, annotation_type)?;
+    let annotations = annotations_on_class(class_manager, class_id, inherited)?;
+    find_annotation(class_manager, annotations, &type_name)
+}
+
+/// all annotations on the class, as an Annotation[]
+pub(crate) fn class_annotations(
+    class_manager: &mut ClassManager,
+    class_object: &Value,
+    inherited: bool,
+) -> Result<Value, Error> {
+    let class_id = class_id_of(class_manager, class_object)?;
+    let annotations = annotations_on_class(class_manager, class_id, inherited)?;
+    annotation_array(class_manager, annotations)
+}
+
+/// the java.lang.reflect.Method objects for the methods that the class declares,
+/// constructors and static initializers left out, in class file order
+/// The vm has no Class objects for primitive types, so parameterTypes and exceptionTypes
+/// are not filled in, and returnType is null for a primitive return type.
+pub(crate) fn declared_methods(
+    class_manager: &mut ClassManager,
+    class_object: &Value,
+) -> Result<Value, Error> {
+    let class_id = class_id_of(class_manager, class_object)?;
+    let mut methods: Vec<(u16, String, String, u16)> = class_manager
+        .get_classdef(&class_id)
+        .methods
+        .values()
+        .map(|method| {
+            let full_name = method.name();
+            let split = full_name.find('(').unwrap();
+            let (name, descriptor) = full_name.split_at(split);
+            (
+                method.index,
+                name.to_owned(),
+                descriptor.to_owned(),
+                method.access_flags,
+            )
+        })
+        .filter(|(_, name, _, _)| !name.starts_with('<'))
+        .collect();
+    methods.sort_by_key(|(index, ..)| *index);
+
+    let mut elements = vec![];
+    for (index, name, descriptor, access_flags) in methods {
+        let return_type = &descriptor[descriptor.find(')').unwrap() + 1..];
+        let return_type = type_object_or_error(class_manager, return_type)?;
+        let name = new_string(class_manager, &name);
+        let method = new_member(
+            class_manager,
+            "java/lang/reflect/Method",
+            class_object,
+            index,
+            name,
+            access_flags,
+        )?;
+        set_member_field(class_manager, &method, "returnType", return_type);
+        elements.push(method.into_object());
+    }
+    class_manager.load_class_by_name("java/lang/reflect/Method")?;
+    let method_class_id = *class_manager.get_classid("java/lang/reflect/Method");
+    Ok(Ref(ObjectRef::object_array(method_class_id, elements)))
+}
+
+/// the java.lang.reflect.Field objects for the fields that the class declares, in class file order
+/// type is null for a field of a primitive type
+pub(crate) fn declared_fields(
+    class_manager: &mut ClassManager,
+    class_object: &Value,
+) -> Result<Value, Error> {
+    let class_id = class_id_of(class_manager, class_object)?;
+    let mut fields: Vec<(u16, String, String, u16)> = class_manager
+        .get_classdef(&class_id)
+        .fields
+        .values()
+        .map(|field| {
+            (
+                field.index,
+                field.name().to_owned(),
+                field.type_of().to_owned(),
+                field.access_flags(),
+            )
+        })
+        .collect();
+    fields.sort_by_key(|(index, ..)| *index);
+
+    let mut elements = vec![];
+    for (index, name, descriptor, access_flags) in fields {
+        let field_type = type_object_or_error(class_manager, &descriptor)?;
+        let name = new_string(class_manager, &name);
+        let field = new_member(
+            class_manager,
+            "java/lang/reflect/Field",
+            class_object,
+            index,
+            name,
+            access_flags,
+        )?;
+        set_member_field(class_manager, &field, "type", field_type);
+        elements.push(field.into_object());
+    }
+    class_manager.load_class_by_name("java/lang/reflect/Field")?;
+    let field_class_id = *class_manager.get_classid("java/lang/reflect/Field");
+    Ok(Ref(ObjectRef::object_array(field_class_id, elements)))
+}
+
+/// the annotation of the given type on the method or field, or null
+pub(crate) fn member_annotation(
+    class_manager: &mut ClassManager,
+    member: &Value,
+    annotation_type: &Value,
+) -> Result<Value, Error> {
+    let type_name = class_name_of(class_manager, annotation_type)?;
+    let annotations = annotations_on_member(class_manager, member)?;
+    find_annotation(class_manager, annotations, &type_name)
+}
+
+/// all annotations on the method or field, as an Annotation[]
+pub(crate) fn member_annotations(
+    class_manager: &mut ClassManager,
+    member: &Value,
+) -> Result<Value, Error> {
+    let annotations = annotations_on_member(class_manager, member)?;
+    annotation_array(class_manager, annotations)
+}
+
+/// the annotations of each parameter of the method, as an Annotation[][]
+pub(crate) fn parameter_annotations(
+    class_manager: &mut ClassManager,
+    method: &Value,
+) -> Result<Value, Error> {
+    let (class_id, slot) = member_slot(class_manager, method)?;
+    let classdef = class_manager.get_classdef(&class_id);
+    let constant_pool = classdef.constant_pool.clone();
+    let method = find_method(classdef, slot)?;
+    let num_parameters = parameter_count(&method.name());
+    let mut parameters = method.parameter_annotations().to_vec();
+    // implicit parameters come first, and have no entry
+    while parameters.len() < num_parameters {
+        parameters.insert(0, vec![]);
+    }
+
+    let mut elements = vec![];
+    for parameter in parameters {
+        let annotations = parameter
+            .into_iter()
+            .map(|annotation| (constant_pool.clone(), annotation))
+            .collect();
+        elements.push(annotation_array(class_manager, annotations)?.into_object());
+    }
+    let array_name = "[Ljava/lang/annotation/Annotation;";
+    class_manager.load_class_by_name(array_name)?;
+    let array_class_id = *class_manager.get_classid(array_name);
+    Ok(Ref(ObjectRef::object_array(array_class_id, elements)))
+}
+
+/// the default value of an element of an annotation interface, or null
+/// a value of a primitive type is boxed
+pub(crate) fn default_value(
+    class_manager: &mut ClassManager,
+    method: &Value,
+) -> Result<Value, Error> {
+    let (class_id, slot) = member_slot(class_manager, method)?;
+    let classdef = class_manager.get_classdef(&class_id);
+    let constant_pool = classdef.constant_pool.clone();
+    let method = find_method(classdef, slot)?;
+    let Some(default) = method.annotation_default().cloned() else {
+        return Ok(Null);
+    };
+    let full_name = method.name();
+    let return_type = full_name[full_name.find(')').unwrap() + 1..].to_owned();
+    let value = element_value(class_manager, &constant_pool, &default, &return_type)?;
+    box_value(class_manager, value, &return_type)
+}
+
+/// answers an invocation on an annotation instance, for the element methods and annotationType()
+/// None for other methods, that are invoked as usual
+pub(crate) fn annotation_method(
+    class_manager: &mut ClassManager,
+    annotation: &Rc<RefCell<Object>>,
+    method_name: &str,
+) -> Option<Value> {
+    let class_id = annotation.borrow().class_id;
+    if method_name == "annotationType()Ljava/lang/Class;" {
+        return class_manager.get_classobject(&class_id).cloned();
+    }
+    let position = element_names(class_manager.get_classdef(&class_id))
+        .iter()
+        .position(|name| name == method_name)?;
+    let value = annotation.borrow().data[position].clone();
+    Some(value)
+}
+
+/// the element methods of an annotation interface, sorted, which is the order of the values
+fn element_names(classdef: &ClassDef) -> Vec<String> {
+    let mut names: Vec<String> = classdef
+        .methods
+        .values()
+        .filter(|method| method.is(Modifier::Abstract))
+        .map(|method| method.name())
+        .collect();
+    names.sort();
+    names
+}
+
+/// the annotations on the class, with the constant pool they refer to
+fn annotations_on_class(
+    class_manager: &mut ClassManager,
+    class_id: ClassId,
+    inherited: bool,
+) -> Result<Vec<(ConstantPool, Annotation)>, Error> {
+    let mut annotations = declared_annotations(class_manager.get_classdef(&class_id));
+    if !inherited {
+        return Ok(annotations);
+    }
+    let superclasses: Vec<ClassId> = class_manager
+        .get_class_by_id(&class_id)
+        .ok_or_else(|| anyhow!("class {} not loaded", class_id))?
+        .parents
+        .iter()
+        .rev()
+        .skip(1)
+        .copied()
+        .collect();
+    for superclass_id in superclasses {
+        for (constant_pool, annotation) in
+            declared_annotations(class_manager.get_classdef(&superclass_id))
+        {
+            let type_name = annotation_type_name(&constant_pool, &annotation)?;
+            let present = annotations.iter().any(|(constant_pool, annotation)| {
+                annotation_type_name(constant_pool, annotation).ok() == Some(type_name.clone())
+            });
+            if !present && is_inherited(class_manager, &type_name)? {
+                annotations.push((constant_pool, annotation));
+            }
+        }
+    }
+    Ok(annotations)
+}
+
+fn declared_annotations(classdef: &ClassDef) -> Vec<(ConstantPool, Annotation)> {
+    classdef
+        .annotations()
+        .iter()
+        .map(|annotation| (classdef.constant_pool.clone(), annotation.clone()))
+        .collect()
+}
+
+/// whether the annotation interface is annotated with @Inherited
+fn is_inherited(class_manager: &mut ClassManager, type_name: &str) -> Result<bool, Error> {
+    class_manager.load_class_by_name(type_name)?;
+    let classdef = class_manager.get_classdef(class_manager.get_classid(type_name));
+    Ok(classdef.annotations().iter().any(|annotation| {
+        annotation_type_name(&classdef.constant_pool, annotation).ok()
+            == Some("java/lang/annotation/Inherited".to_owned())
+    }))
+}
+
+/// the annotations on the reflected method or field
+fn annotations_on_member(
+    class_manager: &mut ClassManager,
+    member: &Value,
+) -> Result<Vec<(ConstantPool, Annotation)>, Error> {
+    let (class_id, slot) = member_slot(class_manager, member)?;
+    let member_class = member_class_name(class_manager, member)?;
+    let classdef = class_manager.get_classdef(&class_id);
+    let annotations = if member_class == "java/lang/reflect/Field" {
+        classdef
+            .fields
+            .values()
+            .find(|field| field.index == slot)
+            .ok_or_else(|| anyhow!("no field #{} in {}", slot, classdef.name()))?
+            .annotations()
+    } else {
+        find_method(classdef, slot)?.annotations()
+    };
+    Ok(annotations
+        .iter()
+        .map(|annotation| (classdef.constant_pool.clone(), annotation.clone()))
+        .collect())
+}
+
+fn find_method(classdef: &ClassDef, slot: u16) -> Result<&Method, Error> {
+    classdef
+        .methods
+        .values()
+        .find(|method| method.index == slot)
+        .ok_or_else(|| anyhow!("no method #{} in {}", slot, classdef.name()))
+}
+
+/// creates the annotation of the given type, if it is in the list
+fn find_annotation(
+    class_manager: &mut ClassManager,
+    annotations: Vec<(ConstantPool, Annotation)>,
+    type_name: &str,
+) -> Result<Value, Error> {
+    for (constant_pool, annotation) in annotations {
+        if annotation_type_name(&constant_pool, &annotation)? == type_name {
+            return new_annotation(class_manager, &constant_pool, &annotation);
+        }
+    }
+    Ok(Null)
+}
+
+fn annotation_array(
+    class_manager: &mut ClassManager,
+    annotations: Vec<(ConstantPool, Annotation)>,
+) -> Result<Value, Error> {
+    let mut elements = vec![];
+    for (constant_pool, annotation) in annotations {
+        elements.push(new_annotation(class_manager, &constant_pool, &annotation)?.into_object());
+    }
+    class_manager.load_class_by_name("java/lang/annotation/Annotation")?;
+    let annotation_class_id = *class_manager.get_classid("java/lang/annotation/Annotation");
+    Ok(Ref(ObjectRef::object_array(annotation_class_id, elements)))
+}
+
+/// creates the instance of the annotation interface, with the values of the elements,
+/// or their defaults
+fn new_annotation(
+    class_manager: &mut ClassManager,
+    constant_pool: &ConstantPool,
+    annotation: &Annotation,
+) -> Result<Value, Error> {
+    let type_name = annotation_type_name(constant_pool, annotation)?;
+    class_manager.load_class_by_name(&type_name)?;
+    let class_id = *class_manager.get_classid(&type_name);
+    let classdef = class_manager.get_classdef(&class_id);
+    let annotation_constant_pool = classdef.constant_pool.clone();
+    let elements: Vec<(String, Option<ElementValue>)> = element_names(classdef)
+        .into_iter()
+        .map(|name| {
+            let default = classdef
+                .get_method(&name)
+                .and_then(Method::annotation_default)
+                .cloned();
+            (name, default)
+        })
+        .collect();
+
+    let mut data = Vec::with_capacity(elements.len());
+    for (full_name, default) in elements {
+        let split = full_name
+            .find("()")
+            .ok_or_else(|| anyhow!("annotation element {} has parameters", full_name))?;
+        let (name, return_type) = (&full_name[..split], &full_name[split + 2..]);
+        let mut value = None;
+        for pair in &annotation.element_value_pairs {
+            if utf8(constant_pool, pair.element_name_index)? == name {
+                value = Some(element_value(
+                    class_manager,
+                    constant_pool,
+                    &pair.value,
+                    return_type,
+                )?);
+            }
+        }
+        let value = match (value, default) {
+            (Some(value), _) => value,
+            (None, Some(default)) => element_value(
+                class_manager,
+                &annotation_constant_pool,
+                &default,
+                return_type,
+            )?,
+            (None, None) => {
+                return Err(anyhow!(
+                    "annotation {} is missing element {}",
+                    type_name,
+                    name
+                ))
+            }
+        };
+        data.push(value);
+    }
+
+    let class = class_manager.get_class_by_id(&class_id).unwrap();
+    let mut instance = Object::new(class);
+    instance.data = data;
+    Ok(Ref(ObjectRef::Object(Rc::new(RefCell::new(instance)))))
+}
+
+/// the value of an annotation element, as returned by the element method, whose return type is
+/// the descriptor
+fn element_value(
+    class_manager: &mut ClassManager,
+    constant_pool: &ConstantPool,
+    value: &ElementValue,
+    descriptor: &str,
+) -> Result<Value, Error> {
+    Ok(match value {
+        ElementValue::Const(tag, index) => match (tag, constant_pool.get(index)) {
+            (b'J', Some(CpEntry::Long(l))) => I64(*l),
+            (b'F', Some(CpEntry::Float(f))) => F32(*f),
+            (b'D', Some(CpEntry::Double(d))) => F64(*d),
+            (b's', Some(CpEntry::Utf8(_, units))) => new_string_utf16(class_manager, units),
+            // boolean, byte, char, short and int are all ints on the operand stack
+            (_, Some(CpEntry::Integer(i))) => I32(*i),
+            (_, entry) => {
+                return Err(anyhow!(
+                    "invalid constant for element value {}: {:?}",
+                    *tag as char,
+                    entry
+                ))
+            }
+        },
+        ElementValue::Enum {
+            type_name_index,
+            const_name_index,
+        } => {
+            let enum_type = utf8(constant_pool, *type_name_index)?;
+            let enum_class = enum_type[1..enum_type.len() - 1].to_owned();
+            let const_name = utf8(constant_pool, *const_name_index)?;
+            enum_constant(class_manager, &enum_class, const_name)?
+        }
+        ElementValue::Class(index) => {
+            let class_descriptor = utf8(constant_pool, *index)?.to_owned();
+            type_object_or_error(class_manager, &class_descriptor)?
+        }
+        ElementValue::Annotation(annotation) => {
+            new_annotation(class_manager, constant_pool, annotation)?
+        }
+        ElementValue::Array(values) => {
+            let component_type = descriptor
+                .strip_prefix('[')
+                .ok_or_else(|| anyhow!("array value for element of type {}", descriptor))?;
+            let mut elements = vec![];
+            for value in values {
+                elements.push(element_value(
+                    class_manager,
+                    constant_pool,
+                    value,
+                    component_type,
+                )?);
+            }
+            new_array(class_manager, component_type, elements)?
+        }
+    })
+}
+
+fn new_array(
+    class_manager: &mut ClassManager,
+    component_type: &str,
+    elements: Vec<Value>,
+) -> Result<Value, Error> {
+    let ints = || elements.iter().map(|e| e.clone().into_i32());
+    Ok(Ref(match component_type {
+        "Z" => ObjectRef::BooleanArray(array(ints().map(|i| i != 0).collect())),
+        "B" => ObjectRef::ByteArray(array(ints().map(|i| i as i8).collect())),
+        "C" => ObjectRef::CharArray(array(ints().collect())),
+        "S" => ObjectRef::ShortArray(array(ints().map(|i| i as i16).collect())),
+        "I" => ObjectRef::IntArray(array(ints().collect())),
+        "J" => ObjectRef::LongArray(array(elements.into_iter().map(Value::into_i64).collect())),
+        "F" => ObjectRef::FloatArray(array(elements.into_iter().map(Value::into_f32).collect())),
+        "D" => ObjectRef::DoubleArray(array(elements.into_iter().map(Value::into_f64).collect())),
+        _ => {
+            let class_name = &component_type[1..component_type.len() - 1];
+            class_manager.load_class_by_name(class_name)?;
+            let class_id = *class_manager.get_classid(class_name);
+            ObjectRef::object_array(
+                class_id,
+                elements.into_iter().map(Value::into_object).collect(),
+            )
+        }
+    }))
+}
+
+/// the value of the static field of the enum class, which is initialized when loaded
+fn enum_constant(
+    class_manager: &mut ClassManager,
+    enum_class: &str,
+    const_name: &str,
+) -> Result<Value, Error> {
+    class_manager.load_class_by_name(enum_class)?;
+    let class = class_manager
+        .get_class_by_name(enum_class)
+        .ok_or_else(|| anyhow!("enum class {} not loaded", enum_class))?;
+    let index = class
+        .static_field_mapping
+        .get(enum_class)
+        .and_then(|fields| fields.get(const_name))
+        .ok_or_else(|| anyhow!("no enum constant {}.{}", enum_class, const_name))?
+        .index;
+    Ok(class_manager.get_static(&class.id, index))
+}
+
+/// the box (Integer, Long etc.) for a value of a primitive type
+fn box_value(
+    class_manager: &mut ClassManager,
+    value: Value,
+    descriptor: &str,
+) -> Result<Value, Error> {
+    let box_type = match descriptor {
+        "Z" => "java/lang/Boolean",
+        "B" => "java/lang/Byte",
+        "C" => "java/lang/Character",
+        "S" => "java/lang/Short",
+        "I" => "java/lang/Integer",
+        "J" => "java/lang/Long",
+        "F" => "java/lang/Float",
+        "D" => "java/lang/Double",
+        _ => return Ok(value),
+    };
+    let value = match (descriptor, value) {
+        ("Z", I32(i)) => BOOL(i != 0),
+        (_, value) => value,
+    };
+    let instance = class_manager.new_instance(box_type)?;
+    let class = class_manager
+        .get_class_by_name(box_type)
+        .ok_or_else(|| anyhow!("box class {} not loaded", box_type))?;
+    if let Ref(ObjectRef::Object(object)) = &instance {
+        object.borrow_mut().set(class, box_type, "value", value);
+    }
+    Ok(instance)
+}
+
+/// creates a java.lang.reflect.Method or Field, for the member of the class at the slot
+fn new_member(
+    class_manager: &mut ClassManager,
+    member_class: &str,
+    class_object: &Value,
+    slot: u16,
+    name: Value,
+    access_flags: u16,
+) -> Result<Value, Error> {
+    let member = class_manager.new_instance(member_class)?;
+    set_member_field(class_manager, &member, "clazz", class_object.clone());
+    set_member_field(class_manager, &member, "slot", I32(slot as i32));
+    set_member_field(class_manager, &member, "name", name);
+    set_member_field(
+        class_manager,
+        &member,
+        "modifiers",
+        I32(access_flags as i32),
+    );
+    Ok(member)
+}
+
+fn set_member_field(class_manager: &mut ClassManager, member: &Value, name: &str, value: Value) {
+    if let Ref(ObjectRef::Object(object)) = member {
+        let mut object = object.borrow_mut();
+        let class = class_manager.get_class_by_id(&object.class_id).unwrap();
+        let declared_type = class.name.clone();
+        object.set(class, &declared_type, name, value);
+    }
+}
+
+/// the declaring class and slot of a java.lang.reflect.Method or Field
+fn member_slot(class_manager: &mut ClassManager, member: &Value) -> Result<(ClassId, u16), Error> {
+    let member_class = member_class_name(class_manager, member)?;
+    let Ref(ObjectRef::Object(object)) = member else {
+        unreachable!()
+    };
+    let object = object.borrow();
+    let class = class_manager.get_class_by_id(&object.class_id).unwrap();
+    let class_object = object
+        .get(class, &member_class, &"clazz".to_owned())
+        .clone();
+    let slot = object.get(class, &member_class, &"slot".to_owned()).clone();
+    Ok((
+        class_id_of(class_manager, &class_object)?,
+        slot.into_i32() as u16,
+    ))
+}
+
+fn member_class_name(class_manager: &mut ClassManager, member: &Value) -> Result<String, Error> {
+    if let Ref(ObjectRef::Object(object)) = member {
+        let class_id = object.borrow().class_id;
+        if let Some(class) = class_manager.get_class_by_id(&class_id) {
+            return Ok(class.name.clone());
+        }
+    }
+    Err(anyhow!("not a method or field: {:?}", member))
+}
+
+/// the ClassId for a java.lang.Class object
+fn class_id_of(class_manager: &ClassManager, class_object: &Value) -> Result<ClassId, Error> {
+    let class_name = class_name_of(class_manager, class_object)?;
+    class_manager
+        .names
+        .get(&class_name)
+        .copied()
+        .ok_or_else(|| anyhow!("class {} not loaded", class_name))
+}
+
+/// the name (with slashes) of the class that the java.lang.Class object stands for
+fn class_name_of(class_manager: &ClassManager, class_object: &Value) -> Result<String, Error> {
+    if let Ref(ObjectRef::Object(object)) = class_object {
+        let class = class_manager.get_class_by_name("java/lang/Class").unwrap();
+        let object = object.borrow();
+        if let Utf8(name) = object.get(class, &"java/lang/Class".to_owned(), &"name".to_owned()) {
+            return Ok(name.clone());
+        }
+    }
+    Err(anyhow!("not a class object: {:?}", class_object))
+}
+
+fn type_object_or_error(
+    class_manager: &mut ClassManager,
+    descriptor: &str,
+) -> Result<Value, Error> {
+    type_object(class_manager, descriptor)
+        .map_err(|_| anyhow!("type {} could not be loaded", descriptor))
+}
+
+/// the name (with slashes) of the annotation interface
+fn annotation_type_name(
+    constant_pool: &ConstantPool,
+    annotation: &Annotation,
+) -> Result<String, Error> {
+    let descriptor = utf8(constant_pool, annotation.type_index)?;
+    Ok(descriptor[1..descriptor.len() - 1].to_owned())
+}
+
+fn utf8(constant_pool: &ConstantPool, index: u16) -> Result<&str, Error> {
+    if let Some(CpEntry::Utf8(utf8, _)) = constant_pool.get(&index) {
+        Ok(utf8)
+    } else {
+        Err(anyhow!("no utf8 constant at #{}", index))
+    }
+}
+
+/// the number of parameters in a method descriptor (name included)
+fn parameter_count(method_name: &str) -> usize {
+    let parameters =
+        &method_name[method_name.find('(').unwrap() + 1..method_name.find(')').unwrap()];
+    let mut count = 0;
+    let mut chars = parameters.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => continue,
+            'L' => {
+                for c in chars.by_ref() {
+                    if c == ';' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+        count += 1;
+    }
+    count
+}
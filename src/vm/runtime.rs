@@ -2,28 +2,59 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use log::debug;
 
 use crate::class::ClassId;
-use crate::classloader::classdef::{CpEntry, CpEntry::*, Modifier};
+use crate::classloader::boot::BootClassPath;
+use crate::classloader::classdef::{CpEntry, CpEntry::*, Exception, Modifier};
+use crate::classloader::error::{ClassFormatError, FormatCheck, VerifyError};
 use crate::classloader::io::PATH_SEPARATOR;
-use crate::classmanager::ClassManager;
+use crate::classloader::manifest::Manifest;
+use crate::classloader::source::{classpath_sources, ClassSource};
+use crate::classmanager::{ClassManager, InitializationError};
 use crate::value::ComputationalType;
 use crate::value::Value::{self, *};
 use crate::vm::array::{array_load, array_store};
-use crate::vm::native::invoke_native;
+use crate::vm::dynamic::resolve_dynamic;
+use crate::vm::fault::Fault::{self, *};
+use crate::vm::native::{invoke_native, is_intrinsic};
 use crate::vm::object;
-use crate::vm::object::ObjectRef;
 use crate::vm::object::ObjectRef::Object;
+use crate::vm::object::{ArrayType, ObjectRef};
 use crate::vm::opcodes::Opcode;
 use crate::vm::opcodes::Opcode::*;
+use crate::vm::reflection::annotation_method;
 use std::io::Write;
 
 const MASK_LOWER_5BITS: i32 = 0b00011111;
+const MASK_LOWER_6BITS: i32 = 0b00111111;
 
+thread_local! {
+    // the call stack of the current thread, innermost invocation last
+    static CALL_STACK: RefCell<Vec<CallFrame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// a method invocation on the call stack, as needed for stack traces
+#[derive(Debug, Clone)]
+pub(crate) struct CallFrame {
+    pub class_id: ClassId,
+    pub method_name: String,
+    // index of the opcode that is being executed
+    pub pc: usize,
+}
+
+#[derive(Default)]
 pub struct Vm {
     pub stack: Vec<Stackframe>,
+    // the JDK to load the system classes from, as with --system
+    system: Option<String>,
+    // class sources that come after the classpath
+    sources: Vec<Box<dyn ClassSource>>,
+    // as with -Xverify:none
+    skip_verification: bool,
+    // as with -D<name>=<value>
+    properties: Vec<(String, String)>,
 }
 
 impl Vm {
@@ -32,23 +63,153 @@ impl Vm {
             .format(|buf, record| writeln!(buf, "{}: {}", record.level(), record.args()))
             .try_init()
             .unwrap();
-        Self { stack: vec![] }
+        Self::default()
+    }
+
+    /// use the JDK in this directory, instead of finding it through JAVA_HOME or the PATH
+    pub fn set_system(&mut self, java_home: &str) {
+        self.system = Some(java_home.to_owned());
+    }
+
+    /// adds a system property, as with -D<name>=<value> on the command line
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        self.properties.push((name.to_owned(), value.to_owned()));
+    }
+
+    /// turns bytecode verification on (the default) or off, as with -Xverify:none
+    pub fn set_verify(&mut self, verify: bool) {
+        self.skip_verification = !verify;
+    }
+
+    /// adds a source of classes, that is searched after the JDK and the classpath
+    pub fn add_class_source(&mut self, source: impl ClassSource + 'static) {
+        self.sources.push(Box::new(source));
+    }
+
+    pub fn run(self, classpath: &str, class_name: &str, method_name: &str) -> i32 {
+        self.start(classpath, class_name, method_name, None)
     }
 
-    pub fn run(mut self, classpath: &str, class_name: &str, method_name: &str) {
-        let classpath = classpath.split(PATH_SEPARATOR).map(|s| s.into()).collect();
-        let mut class_manager = ClassManager::new(classpath);
+    /// runs main(String[]) of the class with the program arguments
+    /// returns the exit code: 0, or 1 if main ended with an uncaught exception,
+    /// or the status that was passed to System.exit
+    pub fn run_main(self, classpath: &str, class_name: &str, args: &[String]) -> i32 {
+        self.start(
+            classpath,
+            class_name,
+            "main([Ljava/lang/String;)V",
+            Some(args),
+        )
+    }
+
+    /// runs the main method of an executable jar
+    /// the main class is taken from the Main-Class in the manifest,
+    /// and the classpath is the jar followed by the manifest Class-Path entries
+    pub fn run_jar(self, jar_path: &str, args: &[String]) -> Result<i32, Error> {
+        let manifest = Manifest::read(jar_path)?;
+        let main_class = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.main_class())
+            .ok_or_else(|| anyhow!("no main manifest attribute, in {}", jar_path))?;
+
+        let mut classpath = vec![jar_path.to_owned()];
+        if let Some(manifest) = manifest {
+            classpath.append(&mut manifest.class_path(jar_path));
+        }
+        let classpath = classpath.join(&PATH_SEPARATOR.to_string());
+
+        Ok(self.run_main(&classpath, &main_class, args))
+    }
+
+    fn start(
+        mut self,
+        classpath: &str,
+        class_name: &str,
+        method_name: &str,
+        program_args: Option<&[String]>,
+    ) -> i32 {
+        let boot_classpath = match BootClassPath::locate(self.system.as_deref()) {
+            Ok(boot_classpath) => boot_classpath,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                return 1;
+            }
+        };
+        let mut sources: Vec<Box<dyn ClassSource>> = vec![Box::new(boot_classpath)];
+        sources.append(&mut classpath_sources(classpath));
+        sources.append(&mut self.sources);
+        let mut class_manager = ClassManager::new(sources);
+        class_manager.set_verify(!self.skip_verification);
+        for (name, value) in &self.properties {
+            class_manager.set_property(name, value);
+        }
 
-        class_manager.load_class_by_name("java/lang/Class");
-        class_manager.load_class_by_name("java/lang/System");
-        class_manager.load_class_by_name("java/lang/String");
-        class_manager.load_class_by_name("java/util/Collections");
+        for boot_class in [
+            "java/lang/Class",
+            "java/lang/System",
+            "java/lang/String",
+            "java/util/Collections",
+        ] {
+            if let Err(error) = class_manager.load_class_by_name(boot_class) {
+                eprintln!("Error: {}", error);
+                return 1;
+            }
+        }
 
-        class_manager.load_class_by_name(class_name);
+        if let Err(error) = class_manager.load_class_by_name(class_name) {
+            if let Some(Exit(status)) = error.downcast_ref::<Fault>() {
+                return *status;
+            }
+            if error.downcast_ref::<InitializationError>().is_some() {
+                let exception =
+                    Fault::class_not_loaded(class_name, &error).into_exception(&mut class_manager);
+                eprint!(
+                    "Exception in thread \"main\" {}",
+                    format_stack_trace(&mut class_manager, &exception)
+                );
+                return 1;
+            }
+            eprintln!(
+                "Error: Could not find or load main class {}",
+                class_name.replace('/', ".")
+            );
+            if let Some(format_error) = error.downcast_ref::<ClassFormatError>() {
+                let exception = if format_error.check == FormatCheck::Version {
+                    "UnsupportedClassVersionError"
+                } else {
+                    "ClassFormatError"
+                };
+                eprintln!("Caused by: java.lang.{}: {}", exception, format_error);
+            }
+            if let Some(verify_error) = error.downcast_ref::<VerifyError>() {
+                eprintln!("Caused by: java.lang.VerifyError: {}", verify_error);
+            }
+            return 1;
+        }
         let system_id = *class_manager.get_classid("java/lang/System");
-        self.run2(&mut class_manager, system_id, "initPhase1()V");
         let class_id = *class_manager.get_classid(class_name);
-        self.run2(&mut class_manager, class_id, method_name);
+        let args = program_args
+            .map(|program_args| vec![new_string_array(&mut class_manager, program_args)])
+            .unwrap_or_default();
+        let result = self
+            .run2(&mut class_manager, system_id, "initPhase1()V")
+            .and_then(|_| Stackframe::new(args).run(&mut class_manager, class_id, method_name));
+        match result {
+            Ok(_) => 0,
+            Err(Abrupt::Exit(status)) => status,
+            Err(Abrupt::Throw(exception)) => {
+                eprint!(
+                    "Exception in thread \"main\" {}",
+                    format_stack_trace(&mut class_manager, &exception)
+                );
+                1
+            }
+        }
+    }
+
+    /// a snapshot of the call stack of the current thread, innermost invocation last
+    pub(crate) fn call_stack() -> Vec<CallFrame> {
+        CALL_STACK.with(|stack| stack.borrow().clone())
     }
 
     pub(crate) fn run2(
@@ -56,11 +217,24 @@ impl Vm {
         class_manager: &mut ClassManager,
         class_id: ClassId,
         method_name: &str,
-    ) {
-        Stackframe::default().run(class_manager, class_id, method_name);
+    ) -> MethodResult {
+        Stackframe::default().run(class_manager, class_id, method_name)
     }
 }
 
+/// The outcome of running a method: the return value (Void for void methods),
+/// or how the method completed abruptly.
+pub type MethodResult = Result<Value, Abrupt>;
+
+/// A method completes abruptly with an exception that it did not catch, which is rethrown
+/// in the invoking frame, or because the vm exits. No handler catches an exit, it unwinds
+/// all frames to Vm::start, which returns the status.
+#[derive(Debug)]
+pub enum Abrupt {
+    Throw(Value),
+    Exit(i32),
+}
+
 pub struct Stackframe {
     pc: usize,
     locals: Vec<Value>,
@@ -97,7 +271,25 @@ impl Stackframe {
         class_manager: &mut ClassManager,
         class_id: ClassId,
         method_name: &str,
-    ) -> Value {
+    ) -> MethodResult {
+        CALL_STACK.with(|stack| {
+            stack.borrow_mut().push(CallFrame {
+                class_id,
+                method_name: method_name.into(),
+                pc: self.pc,
+            })
+        });
+        let result = self.execute(class_manager, class_id, method_name);
+        CALL_STACK.with(|stack| stack.borrow_mut().pop());
+        result
+    }
+
+    fn execute(
+        &mut self,
+        class_manager: &mut ClassManager,
+        class_id: ClassId,
+        method_name: &str,
+    ) -> MethodResult {
         let classname = class_manager
             .get_class_by_id(&class_id)
             .unwrap()
@@ -116,6 +308,18 @@ impl Stackframe {
             .unwrap()
             .constant_pool
             .clone();
+        let exception_table = class_manager
+            .get_classdef(&class_id)
+            .get_method(method_name)
+            .unwrap()
+            .exception_table
+            .clone();
+        let max_locals = class_manager
+            .get_classdef(&class_id)
+            .get_method(method_name)
+            .unwrap()
+            .max_locals();
+        self.allocate_locals(max_locals);
 
         let len = code.len();
         while self.pc < len {
@@ -124,7 +328,13 @@ impl Stackframe {
                 "\tat {}.{}: {} #{:?} - {:?}",
                 classname, method_name, self.pc, opcode, self.stack
             );
+            CALL_STACK.with(|stack| stack.borrow_mut().last_mut().unwrap().pc = self.pc);
             self.pc += 1;
+            // the wide instructions are executed like the ones they widen
+            let opcode = match opcode {
+                WIDE(wide) => wide.as_ref(),
+                opcode => opcode,
+            };
             match opcode {
                 NOP => {}
                 ACONST_NULL => {
@@ -148,8 +358,8 @@ impl Stackframe {
                 BIPUSH(bi) => {
                     self.push(I32(*bi as i32));
                 }
-                LDC(c) | LDC_W(c) | LDC2_W(c) => {
-                    let c = constant_pool.get(&c).unwrap();
+                LDC(cp_index) | LDC_W(cp_index) | LDC2_W(cp_index) => {
+                    let c = constant_pool.get(cp_index).unwrap();
                     match c {
                         Integer(i) => {
                             self.push(I32(*i));
@@ -161,21 +371,11 @@ impl Stackframe {
                             self.push(F64(*d));
                         }
                         StringRef(utf8) => {
-                            //TODO
-                            let string = class_manager.get_classdef(&class_id).cp_utf8(&utf8);
-                            let string: Vec<u8> = string.as_bytes().into();
-                            class_manager.load_class_by_name("java/lang/String");
-                            let stringclass =
-                                class_manager.get_class_by_name("java/lang/String").unwrap();
-                            let mut stringinstance = object::Object::new(stringclass);
-                            stringinstance.set(
-                                stringclass,
-                                "java/lang/String",
-                                "value",
-                                Ref(ObjectRef::new_byte_array(string)),
-                            );
-
-                            self.push(Ref(Object(Rc::new(RefCell::new(stringinstance)))));
+                            let units = class_manager
+                                .get_classdef(&class_id)
+                                .cp_utf16(utf8)
+                                .to_vec();
+                            self.push(new_string_utf16(class_manager, &units));
                         }
                         Long(l) => {
                             self.push(I64(*l));
@@ -185,7 +385,11 @@ impl Stackframe {
                                 .get_classdef(&class_id)
                                 .cp_utf8(&utf8_index)
                                 .to_owned();
-                            class_manager.load_class_by_name(&class_name);
+                            if let Err(error) = class_manager.load_class_by_name(&class_name) {
+                                let fault = Fault::class_not_loaded(&class_name, &error);
+                                self.fault(class_manager, class_id, &exception_table, fault)?;
+                                continue;
+                            }
                             let klass_id = class_manager.get_classid(&class_name);
                             if let Some(class) = class_manager.get_classobject(klass_id) {
                                 self.push(class.clone());
@@ -193,32 +397,60 @@ impl Stackframe {
                                 unreachable!("should not be here");
                             }
                         }
-                        _ => {
-                            panic!("add variant {:?}", c)
+                        Dynamic(..) => match resolve_dynamic(class_manager, class_id, *cp_index) {
+                            Ok(value) => self.push(value),
+                            Err(abrupt) => {
+                                self.throw(class_manager, class_id, &exception_table, abrupt)?;
+                                continue;
+                            }
+                        },
+                        MethodHandle(..) | MethodType(_) => {
+                            let fault = Unsupported(format!("ldc of {:?}", c));
+                            self.fault(class_manager, class_id, &exception_table, fault)?;
                         }
+                        _ => unreachable!("ldc of {:?}", c),
                     }
                 }
                 ILOAD(n) | LLOAD(n) | FLOAD(n) | DLOAD(n) | ALOAD(n) => {
                     // omitting the type checks so far
                     self.push(self.locals[*n as usize].clone());
                 }
+                WIDE_ILOAD(n) | WIDE_LLOAD(n) | WIDE_FLOAD(n) | WIDE_DLOAD(n) | WIDE_ALOAD(n) => {
+                    self.push(self.locals[*n as usize].clone());
+                }
                 IALOAD | LALOAD | FALOAD | DALOAD | AALOAD | BALOAD | CALOAD | SALOAD => {
                     let index = self.pop();
                     let arrayref = self.pop();
-                    self.push(array_load(index, arrayref).unwrap()); //TODO errorhandling
+                    match array_load(index, arrayref) {
+                        Ok(value) => self.push(value),
+                        Err(fault) => {
+                            self.fault(class_manager, class_id, &exception_table, fault)?
+                        }
+                    }
                 }
                 ISTORE(c) | LSTORE(c) | FSTORE(c) | DSTORE(c) | ASTORE(c) => {
-                    self.store(*c).unwrap();
+                    self.store(*c as usize).unwrap();
+                }
+                WIDE_ISTORE(c) | WIDE_LSTORE(c) | WIDE_FSTORE(c) | WIDE_DSTORE(c)
+                | WIDE_ASTORE(c) => {
+                    self.store(*c as usize).unwrap();
                 }
                 BASTORE | IASTORE | LASTORE | CASTORE | SASTORE | FASTORE | DASTORE | AASTORE => {
                     let value = self.pop();
                     let index = self.pop();
                     let arrayref = self.pop();
-                    array_store(value, index, arrayref).unwrap() //TODO
+                    if let Err(fault) = array_store(value, index, arrayref) {
+                        self.fault(class_manager, class_id, &exception_table, fault)?;
+                    }
                 }
                 POP => {
                     self.pop();
                 }
+                POP2 => {
+                    if let ComputationalType::C1 = self.pop().category() {
+                        self.pop();
+                    }
+                }
                 DUP => {
                     let value = self.pop();
                     self.push(value.clone());
@@ -234,11 +466,17 @@ impl Stackframe {
                 DUP_X2 => {
                     let value1 = self.pop();
                     let value2 = self.pop();
-                    let value3 = self.pop();
-                    self.push(value1.clone());
-                    self.push(value3);
-                    self.push(value2);
-                    self.push(value1);
+                    if let ComputationalType::C2 = value2.category() {
+                        self.push(value1.clone());
+                        self.push(value2);
+                        self.push(value1);
+                    } else {
+                        let value3 = self.pop();
+                        self.push(value1.clone());
+                        self.push(value3);
+                        self.push(value2);
+                        self.push(value1);
+                    }
                 }
                 DUP2 => {
                     let value1 = self.pop();
@@ -317,17 +555,23 @@ impl Stackframe {
                         // unless the compiler prevents this combination from occurring
                     }
                 }
+                SWAP => {
+                    let value1 = self.pop();
+                    let value2 = self.pop();
+                    self.push(value1);
+                    self.push(value2);
+                }
                 IADD => {
                     let value2 = self.pop().into_i32();
                     let value1 = self.pop().into_i32();
                     debug!("{:?}+{:?}", value1, value2);
-                    self.push(I32(value1 + value2));
+                    self.push(I32(value1.wrapping_add(value2)));
                 }
                 LADD => {
                     let value2 = self.pop().into_i64();
                     let value1 = self.pop().into_i64();
                     debug!("{:?}-{:?}", value1, value2);
-                    self.push(I64(value1 + value2));
+                    self.push(I64(value1.wrapping_add(value2)));
                 }
                 FADD => {
                     let value2 = self.pop().into_f32();
@@ -345,13 +589,13 @@ impl Stackframe {
                     let value2 = self.pop().into_i32();
                     let value1 = self.pop().into_i32();
                     debug!("{:?}-{:?}", value1, value2);
-                    self.push(I32(value1 - value2));
+                    self.push(I32(value1.wrapping_sub(value2)));
                 }
                 LSUB => {
                     let value2 = self.pop().into_i64();
                     let value1 = self.pop().into_i64();
                     debug!("{:?}-{:?}", value1, value2);
-                    self.push(I64(value1 - value2));
+                    self.push(I64(value1.wrapping_sub(value2)));
                 }
                 FSUB => {
                     let value2 = self.pop().into_f32();
@@ -368,12 +612,12 @@ impl Stackframe {
                 IMUL => {
                     let value2 = self.pop().into_i32();
                     let value1 = self.pop().into_i32();
-                    self.push(I32(value1 * value2))
+                    self.push(I32(value1.wrapping_mul(value2)))
                 }
                 LMUL => {
                     let value2 = self.pop().into_i64();
                     let value1 = self.pop().into_i64();
-                    self.push(I64(value1 * value2))
+                    self.push(I64(value1.wrapping_mul(value2)))
                 }
                 FMUL => {
                     let value2 = self.pop().into_f32();
@@ -386,14 +630,22 @@ impl Stackframe {
                     self.push(F64(value1 * value2))
                 }
                 IDIV => {
-                    let value2 = self.pop();
-                    let value1 = self.pop();
-                    self.push(I32(value1.into_i32() / value2.into_i32()));
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i32();
+                    if value2 == 0 {
+                        self.fault(class_manager, class_id, &exception_table, DivisionByZero)?;
+                    } else {
+                        self.push(I32(value1.wrapping_div(value2)));
+                    }
                 }
                 LDIV => {
-                    let value2 = self.pop();
-                    let value1 = self.pop();
-                    self.push(I64(value1.into_i64() / value2.into_i64()));
+                    let value2 = self.pop().into_i64();
+                    let value1 = self.pop().into_i64();
+                    if value2 == 0 {
+                        self.fault(class_manager, class_id, &exception_table, DivisionByZero)?;
+                    } else {
+                        self.push(I64(value1.wrapping_div(value2)));
+                    }
                 }
                 FDIV => {
                     let value2 = self.pop().into_f32();
@@ -405,6 +657,51 @@ impl Stackframe {
                     let value1 = self.pop().into_f64();
                     self.push(F64(value1 / value2))
                 }
+                IREM => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i32();
+                    if value2 == 0 {
+                        self.fault(class_manager, class_id, &exception_table, DivisionByZero)?;
+                    } else {
+                        self.push(I32(value1.wrapping_rem(value2)));
+                    }
+                }
+                LREM => {
+                    let value2 = self.pop().into_i64();
+                    let value1 = self.pop().into_i64();
+                    if value2 == 0 {
+                        self.fault(class_manager, class_id, &exception_table, DivisionByZero)?;
+                    } else {
+                        self.push(I64(value1.wrapping_rem(value2)));
+                    }
+                }
+                FREM => {
+                    let value2 = self.pop().into_f32();
+                    let value1 = self.pop().into_f32();
+                    // like fmod in C, which is what java specifies
+                    self.push(F32(value1 % value2));
+                }
+                DREM => {
+                    let value2 = self.pop().into_f64();
+                    let value1 = self.pop().into_f64();
+                    self.push(F64(value1 % value2));
+                }
+                INEG => {
+                    let value = self.pop().into_i32();
+                    self.push(I32(value.wrapping_neg()));
+                }
+                LNEG => {
+                    let value = self.pop().into_i64();
+                    self.push(I64(value.wrapping_neg()));
+                }
+                FNEG => {
+                    let value = self.pop().into_f32();
+                    self.push(F32(-value));
+                }
+                DNEG => {
+                    let value = self.pop().into_f64();
+                    self.push(F64(-value));
+                }
                 ISHL => {
                     let value2 = self.pop();
                     let value1 = self.pop();
@@ -413,6 +710,11 @@ impl Stackframe {
                         value1.into_i32() << (value2.into_i32() & MASK_LOWER_5BITS)
                     ));
                 }
+                LSHL => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i64();
+                    self.push(I64(value1 << (value2 & MASK_LOWER_6BITS)));
+                }
                 ISHR => {
                     let value2 = self.pop();
                     let value1 = self.pop();
@@ -421,13 +723,58 @@ impl Stackframe {
                         value1.into_i32() >> (value2.into_i32() & MASK_LOWER_5BITS)
                     ));
                 }
+                LSHR => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i64();
+                    self.push(I64(value1 >> (value2 & MASK_LOWER_6BITS)));
+                }
+                IUSHR => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i32();
+                    self.push(I32(((value1 as u32) >> (value2 & MASK_LOWER_5BITS)) as i32));
+                }
+                LUSHR => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i64();
+                    self.push(I64(((value1 as u64) >> (value2 & MASK_LOWER_6BITS)) as i64));
+                }
+                IAND => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i32();
+                    self.push(I32(value1 & value2));
+                }
+                LAND => {
+                    let value2 = self.pop().into_i64();
+                    let value1 = self.pop().into_i64();
+                    self.push(I64(value1 & value2));
+                }
+                IOR => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i32();
+                    self.push(I32(value1 | value2));
+                }
+                LOR => {
+                    let value2 = self.pop().into_i64();
+                    let value1 = self.pop().into_i64();
+                    self.push(I64(value1 | value2));
+                }
+                IXOR => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i32();
+                    self.push(I32(value1 ^ value2));
+                }
+                LXOR => {
+                    let value2 = self.pop().into_i64();
+                    let value1 = self.pop().into_i64();
+                    self.push(I64(value1 ^ value2));
+                }
                 LCMP => {
                     let value2 = self.pop().into_i64();
                     let value1 = self.pop().into_i64();
                     self.push(I32(compare(value1, value2)));
                 }
                 IINC(index8, const8) => {
-                    self.increment(*index8 as usize, *const8 as u16);
+                    self.increment(*index8 as usize, *const8 as i32);
                 }
                 I2L => {
                     let value = self.pop().into_i32() as i64;
@@ -454,9 +801,10 @@ impl Stackframe {
                     self.push(F64(value));
                 }
                 WIDE_IINC(index16, const16) => {
-                    self.increment(*index16 as usize, *const16);
+                    self.increment(*index16 as usize, *const16 as i32);
                 }
                 F2I => {
+                    // rust casts saturate and turn NaN into 0, as java does
                     let value = self.pop().into_f32() as i32;
                     self.push(I32(value));
                 }
@@ -481,12 +829,12 @@ impl Stackframe {
                     self.push(F32(value));
                 }
                 I2B => {
-                    let value = self.pop().into_i32() as u8;
+                    let value = self.pop().into_i32() as i8;
                     self.push(I32(value as i32));
                 }
                 I2C => {
-                    let value = self.pop().into_i32();
-                    self.push(CHAR(value));
+                    let value = self.pop().into_i32() as u16;
+                    self.push(I32(value as i32));
                 }
                 I2S => {
                     let value = self.pop().into_i32() as i16; //semantics for narrowing seems same as java
@@ -495,29 +843,65 @@ impl Stackframe {
                 FCMPG | FCMPL => {
                     let value2 = self.pop().into_f32();
                     let value1 = self.pop().into_f32();
-                    self.push(I32(compare(value1, value2)));
+                    let nan = if let FCMPG = opcode { 1 } else { -1 };
+                    self.push(I32(compare_floats(value1, value2, nan)));
                 }
                 DCMPG | DCMPL => {
                     let value2 = self.pop().into_f64();
                     let value1 = self.pop().into_f64();
-                    self.push(I32(compare(value1, value2)));
+                    let nan = if let DCMPG = opcode { 1 } else { -1 };
+                    self.push(I32(compare_floats(value1, value2, nan)));
                 }
                 IFEQ(jmp_to) | IFNE(jmp_to) | IFLT(jmp_to) | IFGE(jmp_to) | IFGT(jmp_to)
                 | IFLE(jmp_to) => {
-                    let value = self.pop();
-                    if_cmp(&mut self.pc, opcode, jmp_to, &value, &I32(0));
+                    let value = self.pop().into_i32();
+                    if_cmp(&mut self.pc, opcode, jmp_to, value, 0);
                 }
 
                 IF_ICMPEQ(jmp_to) | IF_ICMPNE(jmp_to) | IF_ICMPGT(jmp_to) | IF_ICMPGE(jmp_to)
                 | IF_ICMPLT(jmp_to) | IF_ICMPLE(jmp_to) => {
+                    let value2 = self.pop().into_i32();
+                    let value1 = self.pop().into_i32();
+                    if_cmp(&mut self.pc, opcode, jmp_to, value1, value2);
+                }
+                IF_ACMPEQ(jmp_to) | IF_ACMPNE(jmp_to) => {
                     let value2 = self.pop();
                     let value1 = self.pop();
-                    if_cmp(&mut self.pc, opcode, jmp_to, &value1, &value2);
+                    let same = is_same_reference(&value1, &value2);
+                    if same == matches!(opcode, IF_ACMPEQ(_)) {
+                        self.pc = *jmp_to as usize;
+                    }
                 }
                 GOTO(jmp_to) => {
-                    self.pc += *jmp_to as usize;
+                    self.pc = *jmp_to as usize;
+                }
+                GOTOW(jmp_to) => {
+                    self.pc = *jmp_to as usize;
                 }
-                INVOKEVIRTUAL(c) => {
+                JSR(jmp_to) => {
+                    // the subroutine returns to the opcode after the jsr
+                    self.push(ReturnAddress(self.pc));
+                    self.pc = *jmp_to as usize;
+                }
+                JSR_W(jmp_to) => {
+                    self.push(ReturnAddress(self.pc));
+                    self.pc = *jmp_to as usize;
+                }
+                RET(index) => {
+                    self.pc = self.return_address(*index as usize);
+                }
+                WIDE_RET(index) => {
+                    self.pc = self.return_address(*index as usize);
+                }
+                TABLESWITCH(tableswitch) => {
+                    let key = self.pop().into_i32();
+                    self.pc = tableswitch.target(key) as usize;
+                }
+                LOOKUPSWITCH(lookupswitch) => {
+                    let key = self.pop().into_i32();
+                    self.pc = lookupswitch.target(key) as usize;
+                }
+                INVOKEVIRTUAL(c) | INVOKEINTERFACE(c, _) => {
                     if let Some(invocation) = get_signature_for_invoke(&constant_pool, *c) {
                         let mut args = Vec::with_capacity(invocation.method.num_args);
                         for _ in 0..invocation.method.num_args {
@@ -529,68 +913,72 @@ impl Stackframe {
                         debug!("invoke {:?}", invocation);
                         let mut invoke_class: Option<ClassId> = None;
                         if let Null = this_ref {
-                            panic!("NullPointer Exception");
+                            self.fault(class_manager, class_id, &exception_table, NullPointer)?;
+                            continue;
                         }
                         if let Ref(this) = this_ref {
                             if let Object(this) = this {
-                                let invoke_classdef =
-                                    class_manager.get_classdef(&this.borrow().class_id);
-                                let invoke_method =
-                                    invoke_classdef.get_method(&invocation.method.name);
-                                if invoke_method.is_some() {
-                                    class_manager.load_class_by_name(&invocation.class_name);
-                                    invoke_class =
-                                        Some(*class_manager.get_classid(&invocation.class_name));
-                                } else {
-                                    let name = class_manager.classdef_name(&this.borrow().class_id);
-                                    if let Some(name) = name {
-                                        class_manager.load_class_by_name(&name);
-                                        let class = class_manager.get_class_by_name(&name).unwrap();
-
-                                        for parent_id in &class.parents {
-                                            let classdef = class_manager.get_classdef(parent_id);
-                                            if classdef.has_method(method_name) {
-                                                invoke_class = Some(*parent_id);
-                                                break;
-                                            }
-                                        }
-                                    } else {
-                                        panic!("ClassNotFound");
+                                let runtime_class_id = this.borrow().class_id;
+                                if class_manager
+                                    .get_classdef(&runtime_class_id)
+                                    .is_annotation()
+                                {
+                                    // the vm creates the annotation instances, and answers for them
+                                    if let Some(value) = annotation_method(
+                                        class_manager,
+                                        &this,
+                                        &invocation.method.name,
+                                    ) {
+                                        self.push(value);
+                                        continue;
                                     }
                                 }
+                                invoke_class = class_manager
+                                    .find_method_class(&runtime_class_id, &invocation.method.name);
                             } else if let ObjectRef::Class(_class) = this {
                                 // special case for Class ?
                                 invoke_class = Some(*class_manager.get_classid("java/lang/Class"));
                             }
                         }
-                        if invoke_class.is_none() {
-                            panic!(
-                                "method {:?}.{} not found",
-                                invocation.class_name, invocation.method.name
-                            );
-                        }
-
-                        let return_value = if class_manager
-                            .get_classdef(&invoke_class.unwrap())
+                        let Some(invoke_class) = invoke_class else {
+                            let fault = no_such_method(&invocation);
+                            self.fault(class_manager, class_id, &exception_table, fault)?;
+                            continue;
+                        };
+                        let invoke_classdef = class_manager.get_classdef(&invoke_class);
+                        let return_value = if invoke_classdef
                             .get_method(&invocation.method.name)
                             .unwrap()
                             .is(Modifier::Native)
+                            || is_intrinsic(invoke_classdef.name(), &invocation.method.name)
                         {
-                            invoke_native(
+                            let invoke_class_name = invoke_classdef.name().to_owned();
+                            match invoke_native(
                                 class_manager,
-                                invocation.class_name.as_str(),
+                                invoke_class_name.as_str(),
                                 invocation.method.name.as_str(),
                                 args,
-                            )
-                            .unwrap()
-                            // TODO remove unwrap in line above, error handling
+                            ) {
+                                Ok(value) => value,
+                                Err(error) => {
+                                    let fault = Fault::native_failed(error);
+                                    self.fault(class_manager, class_id, &exception_table, fault)?;
+                                    continue;
+                                }
+                            }
                         } else {
                             let mut new_stackframe = Stackframe::new(args);
-                            new_stackframe.run(
+                            match new_stackframe.run(
                                 class_manager,
-                                invoke_class.unwrap(),
+                                invoke_class,
                                 &invocation.method.name,
-                            )
+                            ) {
+                                Ok(value) => value,
+                                Err(abrupt) => {
+                                    self.throw(class_manager, class_id, &exception_table, abrupt)?;
+                                    continue;
+                                }
+                            }
                         };
                         match return_value {
                             Void => {}
@@ -608,34 +996,65 @@ impl Stackframe {
                             args.insert(0, self.pop().clone());
                         }
                         if let INVOKESPECIAL(_) = opcode {
-                            args.insert(0, self.pop());
+                            let this_ref = self.pop();
+                            if let Null = this_ref {
+                                self.fault(class_manager, class_id, &exception_table, NullPointer)?;
+                                continue;
+                            }
+                            args.insert(0, this_ref);
                         }
 
-                        class_manager.load_class_by_name(invocation.class_name.as_str());
-                        let invoke_class =
-                            class_manager.get_classid(invocation.class_name.as_str());
+                        if let Err(error) =
+                            class_manager.load_class_by_name(invocation.class_name.as_str())
+                        {
+                            let fault = Fault::class_not_loaded(&invocation.class_name, &error);
+                            self.fault(class_manager, class_id, &exception_table, fault)?;
+                            continue;
+                        }
+                        let referenced_class =
+                            *class_manager.get_classid(invocation.class_name.as_str());
+                        let Some(invoke_class) = &class_manager
+                            .find_method_class(&referenced_class, &invocation.method.name)
+                        else {
+                            let fault = no_such_method(&invocation);
+                            self.fault(class_manager, class_id, &exception_table, fault)?;
+                            continue;
+                        };
 
-                        let return_value = if class_manager
-                            .get_classdef(&invoke_class)
+                        let invoke_classdef = class_manager.get_classdef(invoke_class);
+                        let return_value = if invoke_classdef
                             .get_method(&invocation.method.name)
                             .unwrap()
                             .is(Modifier::Native)
+                            || is_intrinsic(invoke_classdef.name(), &invocation.method.name)
                         {
-                            invoke_native(
+                            let invoke_class_name = invoke_classdef.name().to_owned();
+                            match invoke_native(
                                 class_manager,
-                                invocation.class_name.as_str(),
+                                invoke_class_name.as_str(),
                                 invocation.method.name.as_str(),
                                 args,
-                            )
-                            .unwrap()
-                            // TODO remove unwrap in line above, error handling
+                            ) {
+                                Ok(value) => value,
+                                Err(error) => {
+                                    let fault = Fault::native_failed(error);
+                                    self.fault(class_manager, class_id, &exception_table, fault)?;
+                                    continue;
+                                }
+                            }
                         } else {
                             let mut new_stackframe = Stackframe::new(args);
-                            new_stackframe.run(
+                            match new_stackframe.run(
                                 class_manager,
                                 *invoke_class,
                                 &invocation.method.name,
-                            )
+                            ) {
+                                Ok(value) => value,
+                                Err(abrupt) => {
+                                    self.throw(class_manager, class_id, &exception_table, abrupt)?;
+                                    continue;
+                                }
+                            }
                         };
                         debug!("returning {:?}", return_value);
                         match return_value {
@@ -655,7 +1074,11 @@ impl Stackframe {
                     let that_class_name = classdef
                         .cp_utf8(classdef.cp_class_ref(class_index))
                         .to_owned();
-                    class_manager.load_class_by_name(&that_class_name);
+                    if let Err(error) = class_manager.load_class_by_name(&that_class_name) {
+                        let fault = Fault::class_not_loaded(&that_class_name, &error);
+                        self.fault(class_manager, class_id, &exception_table, fault)?;
+                        continue;
+                    }
                     let that_class = class_manager.get_class_by_name(&that_class_name).unwrap();
 
                     let type_index = that_class
@@ -700,7 +1123,9 @@ impl Stackframe {
                     let field_name = classdef.cp_utf8(field_name_index).to_owned();
                     debug!("get field {}.{}", declared_type, field_name);
                     let objectref = self.pop();
-                    if let Ref(instance) = objectref {
+                    if let Null = objectref {
+                        self.fault(class_manager, class_id, &exception_table, NullPointer)?;
+                    } else if let Ref(instance) = objectref {
                         if let Object(object) = instance {
                             let runtime_type = class_manager
                                 .get_class_by_id(&object.borrow().class_id)
@@ -727,7 +1152,9 @@ impl Stackframe {
 
                     let value = self.pop();
                     let objectref = self.pop();
-                    if let Ref(instance) = objectref {
+                    if let Null = objectref {
+                        self.fault(class_manager, class_id, &exception_table, NullPointer)?;
+                    } else if let Ref(instance) = objectref {
                         if let Object(object) = instance {
                             let runtime_type = class_manager
                                 .get_class_by_id(&object.borrow().class_id)
@@ -751,20 +1178,28 @@ impl Stackframe {
                         .get_classdef(&class_id)
                         .cp_utf8(&class_name_index)
                         .to_owned();
-                    class_manager.load_class_by_name(&class_name);
-                    let class_to_instantiate =
-                        class_manager.get_class_by_name(&class_name).unwrap();
-
-                    let object = Object(Rc::new(RefCell::new(object::Object::new(
-                        class_to_instantiate,
-                    ))));
-                    self.push(Ref(object));
+                    match class_manager.new_instance(&class_name) {
+                        Ok(instance) => self.push(instance),
+                        Err(error) => {
+                            let fault = Fault::class_not_loaded(&class_name, &error);
+                            self.fault(class_manager, class_id, &exception_table, fault)?;
+                        }
+                    }
                 }
                 NEWARRAY(arraytype) => {
-                    let count = self.pop();
+                    let count = self.pop().into_i32();
                     debug!("create array with size {:?}", count);
-                    let array = ObjectRef::new_array(*arraytype, count.into_i32() as usize);
-                    self.push(Ref(array));
+                    if count < 0 {
+                        self.fault(
+                            class_manager,
+                            class_id,
+                            &exception_table,
+                            NegativeArraySize(count),
+                        )?;
+                    } else {
+                        let array = ObjectRef::new_array(*arraytype, count as usize);
+                        self.push(Ref(array));
+                    }
                 }
                 ANEWARRAY(class_index) => {
                     let class_name_index = *class_manager
@@ -774,26 +1209,120 @@ impl Stackframe {
                         .get_classdef(&class_id)
                         .cp_utf8(&class_name_index)
                         .to_owned();
-                    class_manager.load_class_by_name(&class_name);
+                    if let Err(error) = class_manager.load_class_by_name(&class_name) {
+                        let fault = Fault::class_not_loaded(&class_name, &error);
+                        self.fault(class_manager, class_id, &exception_table, fault)?;
+                        continue;
+                    }
                     let arraytype = class_manager.get_class_by_name(&class_name).unwrap();
                     let count = self.pop().into_i32();
-                    let array = ObjectRef::new_object_array(arraytype, count as usize);
-                    self.push(Ref(array));
+                    if count < 0 {
+                        self.fault(
+                            class_manager,
+                            class_id,
+                            &exception_table,
+                            NegativeArraySize(count),
+                        )?;
+                    } else {
+                        let array = ObjectRef::new_object_array(arraytype.id, count as usize);
+                        self.push(Ref(array));
+                    }
+                }
+                MULTIANEWARRAY(class_index, dimensions) => {
+                    let class_name = class_manager
+                        .get_classdef(&class_id)
+                        .cp_class_name(class_index)
+                        .to_owned();
+                    // array classes have no class file, but the element class is loaded
+                    if let Some(element) = class_name
+                        .trim_start_matches('[')
+                        .strip_prefix('L')
+                        .and_then(|element| element.strip_suffix(';'))
+                    {
+                        if let Err(error) = class_manager.load_class_by_name(element) {
+                            let fault = Fault::class_not_loaded(element, &error);
+                            self.fault(class_manager, class_id, &exception_table, fault)?;
+                            continue;
+                        }
+                    }
+                    let mut counts = vec![0; *dimensions as usize];
+                    for count in counts.iter_mut().rev() {
+                        *count = self.pop().into_i32();
+                    }
+                    if let Some(count) = counts.iter().find(|count| **count < 0) {
+                        self.fault(
+                            class_manager,
+                            class_id,
+                            &exception_table,
+                            NegativeArraySize(*count),
+                        )?;
+                    } else {
+                        let array = new_multi_array(class_manager, &class_name, &counts);
+                        self.push(Ref(array));
+                    }
                 }
                 ARRAYLENGTH => {
                     let val = self.pop();
-                    if let Ref(val) = val {
+                    if let Null = val {
+                        self.fault(class_manager, class_id, &exception_table, NullPointer)?;
+                    } else if let Ref(val) = val {
                         self.push(I32(val.get_array_length() as i32));
                     } else {
                         unreachable!("array length {:?}", val);
                     }
                 }
-                ATHROW => {
+                CHECKCAST(class_index) | INSTANCEOF(class_index) => {
                     let value = self.pop();
-                    panic!("{:?}", value);
+                    let type_name = class_manager
+                        .get_classdef(&class_id)
+                        .cp_class_name(class_index)
+                        .to_owned();
+                    let is_instance = match &value {
+                        Null => None,
+                        Ref(Object(object)) => {
+                            if let Err(error) = class_manager.load_class_by_name(&type_name) {
+                                let fault = Fault::class_not_loaded(&type_name, &error);
+                                self.fault(class_manager, class_id, &exception_table, fault)?;
+                                continue;
+                            }
+                            let runtime_class_id = object.borrow().class_id;
+                            let type_id = *class_manager.get_classid(&type_name);
+                            Some(class_manager.is_subtype(&runtime_class_id, &type_id))
+                        }
+                        // TODO check array types
+                        _ => Some(true),
+                    };
+                    if let INSTANCEOF(_) = opcode {
+                        self.push(I32(is_instance.unwrap_or(false) as i32));
+                    } else if is_instance == Some(false) {
+                        let Ref(Object(object)) = &value else {
+                            unreachable!()
+                        };
+                        let class_name = class_manager
+                            .classdef_name(&object.borrow().class_id)
+                            .unwrap();
+                        let fault = ClassCast {
+                            class_name,
+                            type_name,
+                        };
+                        self.fault(class_manager, class_id, &exception_table, fault)?;
+                    } else {
+                        self.push(value);
+                    }
+                }
+                ATHROW => {
+                    let exception = self.pop();
+                    if let Null = exception {
+                        self.fault(class_manager, class_id, &exception_table, NullPointer)?;
+                    } else {
+                        let exception = Abrupt::Throw(exception);
+                        self.throw(class_manager, class_id, &exception_table, exception)?;
+                    }
                 }
                 MONITORENTER | MONITOREXIT => {
-                    self.pop();
+                    if let Null = self.pop() {
+                        self.fault(class_manager, class_id, &exception_table, NullPointer)?;
+                    }
                 } //TODO implement
                 IFNULL(_) | IFNONNULL(_) => {
                     let value = self.pop();
@@ -816,89 +1345,142 @@ impl Stackframe {
                     self.push(value.clone());
                 }
                 IRETURN | LRETURN | FRETURN | DRETURN | ARETURN => {
-                    return self.pop();
+                    return Ok(self.pop());
                 }
                 RETURN_VOID => {
-                    return Void;
-                }
-                DREM => {
-                    let value2 = self.pop().into_f64();
-                    let value1 = self.pop().into_f64();
-                    self.push(F64(value1 % value2)); // what about Nan?
-                }
-                INEG => {
-                    let value = self.pop().into_i32();
-                    self.push(I32(-value));
-                }
-                LNEG => {
-                    let value = self.pop().into_i64();
-                    self.push(I64(-value));
-                }
-                FNEG => {
-                    let value = self.pop().into_f32();
-                    self.push(F32(-value));
-                }
-                DNEG => {
-                    let value = self.pop().into_f64();
-                    self.push(F64(-value));
-                }
-                LSHL => {
-                    let value2 = self.pop().into_i64();
-                    let value1 = self.pop().into_i64();
-                    self.push(I64(value1 << value2));
-                }
-                LSHR => {
-                    let value2 = self.pop().into_i64();
-                    let value1 = self.pop().into_i64();
-                    self.push(I64(value1 >> value2));
-                }
-                IUSHR => {
-                    let value2 = self.pop().into_i32();
-                    let value1 = self.pop().into_i32();
-                    if value1 > 0 {
-                        self.push(I32(value1 >> value2));
-                    } else {
-                        self.push(I32(((value1 as u32) >> value2) as i32));
-                    }
+                    return Ok(Void);
                 }
-                LUSHR => {
-                    let value2 = self.pop().into_i64();
-                    let value1 = self.pop().into_i64();
-                    if value1 > 0 {
-                        self.push(I64(value1 >> value2));
-                    } else {
-                        self.push(Value::I64(((value1 as u64) >> value2) as i64));
-                    }
+                INVOKEDYNAMIC(_) => {
+                    let fault = Unsupported("invokedynamic".into());
+                    self.fault(class_manager, class_id, &exception_table, fault)?;
                 }
                 _ => {
-                    panic!("opcode not implemented")
+                    let fault = Unsupported(format!("opcode {:?}", opcode));
+                    self.fault(class_manager, class_id, &exception_table, fault)?;
                 }
             }
         }
-        Void
+        Ok(Void)
+    }
+
+    /// Looks for a handler for the exception, in the exception table of the current method.
+    /// If found, the operand stack is cleared, the exception pushed, and execution continues at the handler.
+    /// Otherwise the exception is returned to be rethrown in the invoking frame.
+    /// An exit is never caught.
+    fn throw(
+        &mut self,
+        class_manager: &mut ClassManager,
+        class_id: ClassId,
+        exception_table: &[Exception],
+        abrupt: Abrupt,
+    ) -> Result<(), Abrupt> {
+        let Abrupt::Throw(exception) = abrupt else {
+            return Err(abrupt);
+        };
+        let pc = self.pc - 1; // index of the instruction that threw
+        let exception_class_id = if let Ref(Object(object)) = &exception {
+            object.borrow().class_id
+        } else {
+            unreachable!("can only throw objects, not {:?}", exception)
+        };
+
+        for handler in exception_table.iter().filter(|h| h.covers(pc)) {
+            let catches = if handler.catch_type == 0 {
+                true // finally
+            } else {
+                let catch_type = class_manager
+                    .get_classdef(&class_id)
+                    .cp_class_name(&handler.catch_type)
+                    .to_owned();
+                if let Err(error) = class_manager.load_class_by_name(&catch_type) {
+                    // the handler cannot be resolved, that error replaces the exception
+                    return Err(
+                        Fault::class_not_loaded(&catch_type, &error).into_abrupt(class_manager)
+                    );
+                }
+                let catch_type_id = *class_manager.get_classid(&catch_type);
+                class_manager
+                    .get_class_by_id(&exception_class_id)
+                    .unwrap()
+                    .parents
+                    .contains(&catch_type_id)
+            };
+            if catches {
+                debug!("caught {:?} at {}", exception, handler.handler_pc);
+                self.stack.clear();
+                self.push(exception);
+                self.pc = handler.handler_pc as usize;
+                return Ok(());
+            }
+        }
+        Err(Abrupt::Throw(exception))
+    }
+
+    /// throws the java exception for a runtime fault, see throw
+    fn fault(
+        &mut self,
+        class_manager: &mut ClassManager,
+        class_id: ClassId,
+        exception_table: &[Exception],
+        fault: Fault,
+    ) -> Result<(), Abrupt> {
+        debug!("fault {:?}", fault);
+        let abrupt = fault.into_abrupt(class_manager);
+        self.throw(class_manager, class_id, exception_table, abrupt)
+    }
+
+    /// iinc, the constant is sign extended and the addition wraps around
+    fn increment(&mut self, index: usize, inc: i32) {
+        if let I32(l) = &mut self.locals[index] {
+            *l = l.wrapping_add(inc);
+        }
     }
 
-    fn increment(&mut self, index: usize, inc: u16) {
-        match &mut self.locals[index] {
-            I32(l) => *l += (inc as i32),
-            I64(l) => *l += (inc as i64),
-            F32(l) => *l += (inc as f32),
-            F64(l) => *l += (inc as f64),
-            _ => {}
+    /// the return address in the local variable, for ret
+    fn return_address(&self, index: usize) -> usize {
+        match &self.locals[index] {
+            ReturnAddress(address) => *address,
+            value => panic!("{:?} is not a return address", value),
+        }
+    }
+
+    /// lays out the arguments in the local variables, JVMS 2.6.1
+    /// a long or double takes two slots, the second one and the slots that are not arguments are Void
+    fn allocate_locals(&mut self, max_locals: usize) {
+        let args = std::mem::take(&mut self.locals);
+        self.locals = Vec::with_capacity(max_locals);
+        for arg in args {
+            let category = arg.category();
+            self.locals.push(arg);
+            if let ComputationalType::C2 = category {
+                self.locals.push(Void);
+            }
+        }
+        if self.locals.len() < max_locals {
+            self.locals.resize(max_locals, Void);
         }
     }
 
-    fn store(&mut self, index: u8) -> Result<(), Error> {
-        let index = index as usize;
+    fn store(&mut self, index: usize) -> Result<(), Error> {
         let value = self.pop();
-        while self.locals.len() < index + 1 {
-            self.locals.push(Null); //ensure capacity
+        if let ComputationalType::C2 = value.category() {
+            // a long or double also takes the next slot
+            self.locals[index + 1] = Void;
         }
         self.locals[index] = value;
         Ok(())
     }
 }
 
+/// the fault for an invocation of a method that the class does not have
+fn no_such_method(invocation: &Invocation) -> Fault {
+    Fault::NoSuchMethod(format!(
+        "{}.{}",
+        invocation.class_name.replace('/', "."),
+        invocation.method.name
+    ))
+}
+
 pub(crate) fn get_signature_for_invoke(
     cp: &HashMap<u16, CpEntry>,
     index: u16,
@@ -908,7 +1490,7 @@ pub(crate) fn get_signature_for_invoke(
     {
         if let Some(method_signature) = get_name_and_type(cp, *name_and_type_index) {
             if let ClassRef(class_name_index) = cp.get(class_index).unwrap() {
-                if let CpEntry::Utf8(class_name) = cp.get(class_name_index).unwrap() {
+                if let CpEntry::Utf8(class_name, _) = cp.get(class_name_index).unwrap() {
                     return Some(Invocation::new(class_name.into(), method_signature));
                 }
             }
@@ -919,8 +1501,8 @@ pub(crate) fn get_signature_for_invoke(
 
 pub(crate) fn get_name_and_type(cp: &HashMap<u16, CpEntry>, index: u16) -> Option<MethodSignature> {
     if let NameAndType(method_name_index, signature_index) = cp.get(&index).unwrap() {
-        if let CpEntry::Utf8(method_name) = cp.get(method_name_index).unwrap() {
-            if let CpEntry::Utf8(signature) = cp.get(signature_index).unwrap() {
+        if let CpEntry::Utf8(method_name, _) = cp.get(method_name_index).unwrap() {
+            if let CpEntry::Utf8(signature, _) = cp.get(signature_index).unwrap() {
                 let mut method_signature: String = method_name.into();
                 let num_args = get_num_args(signature);
                 method_signature.push_str(signature);
@@ -931,25 +1513,50 @@ pub(crate) fn get_name_and_type(cp: &HashMap<u16, CpEntry>, index: u16) -> Optio
     None
 }
 
-fn if_cmp(pc: &mut usize, opcode: &Opcode, jmp_to: &u16, value1: &Value, value2: &Value) {
-    if let I32(value1) = value1 {
-        if let I32(value2) = value2 {
-            let jump = match opcode {
-                IF_ICMPEQ(_) | IFEQ(_) => value1 == value2,
-                IF_ICMPNE(_) | IFNE(_) => value1 != value2,
-                IF_ICMPGT(_) | IFGT(_) => value1 > value2,
-                IF_ICMPGE(_) | IFGE(_) => value1 >= value2,
-                IF_ICMPLT(_) | IFLT(_) => value1 < value2,
-                IF_ICMPLE(_) | IFLE(_) => value1 <= value2,
-                _ => false,
-            };
-            if jump {
-                debug!("\t\tIF({}) JMP {}", jump, *jmp_to as usize);
-                *pc = *jmp_to as usize;
-            } else {
-                debug!("\t\tIF({}) NO JMP", jump);
-            }
-        }
+fn if_cmp(pc: &mut usize, opcode: &Opcode, jmp_to: &u16, value1: i32, value2: i32) {
+    let jump = match opcode {
+        IF_ICMPEQ(_) | IFEQ(_) => value1 == value2,
+        IF_ICMPNE(_) | IFNE(_) => value1 != value2,
+        IF_ICMPGT(_) | IFGT(_) => value1 > value2,
+        IF_ICMPGE(_) | IFGE(_) => value1 >= value2,
+        IF_ICMPLT(_) | IFLT(_) => value1 < value2,
+        IF_ICMPLE(_) | IFLE(_) => value1 <= value2,
+        _ => false,
+    };
+    if jump {
+        debug!("\t\tIF({}) JMP {}", jump, *jmp_to as usize);
+        *pc = *jmp_to as usize;
+    } else {
+        debug!("\t\tIF({}) NO JMP", jump);
+    }
+}
+
+/// whether the values refer to the same object, for if_acmpeq and if_acmpne
+fn is_same_reference(value1: &Value, value2: &Value) -> bool {
+    match (value1, value2) {
+        (Null, Null) => true,
+        (Ref(object1), Ref(object2)) => is_same_object(object1, object2),
+        (Value::Utf8(string1), Value::Utf8(string2)) => string1 == string2,
+        _ => false,
+    }
+}
+
+fn is_same_object(object1: &ObjectRef, object2: &ObjectRef) -> bool {
+    use ObjectRef::*;
+    match (object1, object2) {
+        (Object(object1), Object(object2)) => Rc::ptr_eq(object1, object2),
+        (Class(class1), Class(class2)) => class1.id == class2.id,
+        (ByteArray(array1), ByteArray(array2)) => Rc::ptr_eq(array1, array2),
+        (ShortArray(array1), ShortArray(array2)) => Rc::ptr_eq(array1, array2),
+        (IntArray(array1), IntArray(array2)) => Rc::ptr_eq(array1, array2),
+        (LongArray(array1), LongArray(array2)) => Rc::ptr_eq(array1, array2),
+        (FloatArray(array1), FloatArray(array2)) => Rc::ptr_eq(array1, array2),
+        (DoubleArray(array1), DoubleArray(array2)) => Rc::ptr_eq(array1, array2),
+        (BooleanArray(array1), BooleanArray(array2)) => Rc::ptr_eq(array1, array2),
+        (CharArray(array1), CharArray(array2)) => Rc::ptr_eq(array1, array2),
+        (StringArray(array1), StringArray(array2)) => Rc::ptr_eq(array1, array2),
+        (ObjectArray(_, array1), ObjectArray(_, array2)) => Rc::ptr_eq(array1, array2),
+        _ => false,
     }
 }
 
@@ -1002,9 +1609,204 @@ impl MethodSignature {
     }
 }
 
+// the values of String.coder
+const LATIN1: i32 = 0;
+const UTF16: i32 = 1;
+
+/// creates a java.lang.String instance
+/// Like the JDK does with compact strings, the characters are stored as one byte each (LATIN1)
+/// if they all fit, or else as UTF-16 code units in native byte order.
+pub(crate) fn new_string(class_manager: &mut ClassManager, string: &str) -> Value {
+    let units: Vec<u16> = string.encode_utf16().collect();
+    new_string_utf16(class_manager, &units)
+}
+
+/// creates a java.lang.String instance with the UTF-16 code units, that can include lone
+/// surrogates, as the string constants in class files can
+pub(crate) fn new_string_utf16(class_manager: &mut ClassManager, units: &[u16]) -> Value {
+    let (value, coder): (Vec<u8>, i32) = if units.iter().all(|unit| *unit <= 0xFF) {
+        (units.iter().map(|unit| *unit as u8).collect(), LATIN1)
+    } else {
+        let value = units.iter().flat_map(|unit| unit.to_ne_bytes()).collect();
+        (value, UTF16)
+    };
+    class_manager
+        .load_class_by_name("java/lang/String")
+        .expect("java/lang/String is loaded at startup");
+    let stringclass = class_manager.get_class_by_name("java/lang/String").unwrap();
+    let mut stringinstance = object::Object::new(stringclass);
+    stringinstance.set(
+        stringclass,
+        "java/lang/String",
+        "value",
+        Ref(ObjectRef::new_byte_array(value)),
+    );
+    stringinstance.set(stringclass, "java/lang/String", "coder", I32(coder));
+    Ref(Object(Rc::new(RefCell::new(stringinstance))))
+}
+
+/// creates the array for multianewarray, with the count for each of the dimensions
+/// the elements of the last dimension that is created are 0 or null
+fn new_multi_array(
+    class_manager: &mut ClassManager,
+    descriptor: &str,
+    counts: &[i32],
+) -> ObjectRef {
+    let component = &descriptor[1..];
+    let count = counts[0] as usize;
+    if counts.len() > 1 {
+        let elements = (0..count)
+            .map(|_| new_multi_array(class_manager, component, &counts[1..]))
+            .collect();
+        return ObjectRef::object_array(component_id(class_manager, component), elements);
+    }
+    match ArrayType::from_descriptor(component.as_bytes()[0]) {
+        Some(array_type) => ObjectRef::new_array(array_type as u8, count),
+        None => ObjectRef::new_object_array(component_id(class_manager, component), count),
+    }
+}
+
+/// the class id for the component type of an array, that is an array or a loaded class
+fn component_id(class_manager: &mut ClassManager, component: &str) -> ClassId {
+    let name = component
+        .strip_prefix('L')
+        .and_then(|name| name.strip_suffix(';'))
+        .unwrap_or(component);
+    class_manager
+        .load_class_by_name(name)
+        .expect("array classes and loaded classes do not fail to load");
+    *class_manager.get_classid(name)
+}
+
+/// creates a java.lang.String[] with the given strings
+pub(crate) fn new_string_array(class_manager: &mut ClassManager, strings: &[String]) -> Value {
+    let elements = strings
+        .iter()
+        .map(|string| match new_string(class_manager, string) {
+            Ref(string) => string,
+            _ => unreachable!(),
+        })
+        .collect();
+    let string_id = *class_manager.get_classid("java/lang/String");
+    Ref(ObjectRef::object_array(string_id, elements))
+}
+
+/// formats an exception as "class name: message", for reporting uncaught exceptions
+pub(crate) fn describe_exception(class_manager: &mut ClassManager, exception: &Value) -> String {
+    if let Ref(Object(object)) = exception {
+        let object = object.borrow();
+        let class_name = class_manager.classdef_name(&object.class_id).unwrap();
+        let class = class_manager.get_class_by_id(&object.class_id).unwrap();
+        let message = object.get(
+            class,
+            &"java/lang/Throwable".to_owned(),
+            &"detailMessage".to_owned(),
+        );
+        let class_name = class_name.replace('/', ".");
+        match java_string_to_rust(class_manager, message) {
+            Some(message) => format!("{}: {}", class_name, message),
+            None => class_name,
+        }
+    } else {
+        format!("{:?}", exception)
+    }
+}
+
+/// formats an exception like Throwable.printStackTrace does:
+/// the description, followed by the stack trace elements, and then the same for the cause, if any
+pub(crate) fn format_stack_trace(class_manager: &mut ClassManager, exception: &Value) -> String {
+    let mut trace = format!("{}\n", describe_exception(class_manager, exception));
+    if let Ref(Object(exception)) = exception {
+        let throwable = "java/lang/Throwable".to_owned();
+        let object = exception.borrow();
+        let class = class_manager.get_class_by_id(&object.class_id).unwrap();
+        let backtrace = object
+            .get(class, &throwable, &"backtrace".to_owned())
+            .clone();
+        let cause = object.get(class, &throwable, &"cause".to_owned()).clone();
+        if let Ref(ObjectRef::ObjectArray(_, elements)) = backtrace {
+            for element in elements.borrow().iter().flatten() {
+                let element = format_stack_trace_element(class_manager, element);
+                trace.push_str(&format!("\tat {}\n", element));
+            }
+        }
+        // an unset cause is the exception itself
+        if let Ref(Object(cause_object)) = &cause {
+            if !Rc::ptr_eq(cause_object, exception) {
+                trace.push_str("Caused by: ");
+                trace.push_str(&format_stack_trace(class_manager, &cause));
+            }
+        }
+    }
+    trace
+}
+
+/// formats a java.lang.StackTraceElement, eg: pkg.Class.method(File.java:42)
+fn format_stack_trace_element(class_manager: &mut ClassManager, element: &ObjectRef) -> String {
+    if let Object(element) = element {
+        let element = element.borrow();
+        let class = class_manager.get_class_by_id(&element.class_id).unwrap();
+        let ste = "java/lang/StackTraceElement".to_owned();
+        let declaring_class = element
+            .get(class, &ste, &"declaringClass".to_owned())
+            .clone();
+        let method_name = element.get(class, &ste, &"methodName".to_owned()).clone();
+        let file_name = element.get(class, &ste, &"fileName".to_owned()).clone();
+        let line_number = element.get(class, &ste, &"lineNumber".to_owned()).clone();
+
+        let declaring_class =
+            java_string_to_rust(class_manager, &declaring_class).unwrap_or_default();
+        let method_name = java_string_to_rust(class_manager, &method_name).unwrap_or_default();
+        let location = match (java_string_to_rust(class_manager, &file_name), line_number) {
+            (_, I32(-2)) => "Native Method".to_owned(),
+            (Some(file_name), I32(line)) if line >= 0 => format!("{}:{}", file_name, line),
+            (Some(file_name), _) => file_name,
+            (None, _) => "Unknown Source".to_owned(),
+        };
+        format!("{}.{}({})", declaring_class, method_name, location)
+    } else {
+        format!("{:?}", element)
+    }
+}
+
+/// gets the contents of a java.lang.String instance, None for null
+pub(crate) fn java_string_to_rust(
+    class_manager: &mut ClassManager,
+    string: &Value,
+) -> Option<String> {
+    if let Ref(Object(string)) = string {
+        let string = string.borrow();
+        let string_class = class_manager.get_class_by_id(&string.class_id).unwrap();
+        let string_type = "java/lang/String".to_owned();
+        let value = string.get(string_class, &string_type, &"value".to_owned());
+        let coder = string.get(string_class, &string_type, &"coder".to_owned());
+        if let Ref(ObjectRef::ByteArray(bytes)) = value {
+            let bytes: Vec<u8> = bytes.borrow().iter().map(|b| *b as u8).collect();
+            return Some(if let I32(UTF16) = coder {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_ne_bytes([unit[0], unit[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            } else {
+                bytes.iter().map(|b| *b as char).collect()
+            });
+        }
+    }
+    None
+}
+
 fn compare<T>(a: T, b: T) -> i32
 where
     T: PartialOrd,
 {
     a.partial_cmp(&b).unwrap() as i32
 }
+
+/// like compare, but with the result for NaN: -1 for fcmpl and dcmpl, 1 for fcmpg and dcmpg
+fn compare_floats<T>(a: T, b: T, nan: i32) -> i32
+where
+    T: PartialOrd,
+{
+    a.partial_cmp(&b).map_or(nan, |ordering| ordering as i32)
+}
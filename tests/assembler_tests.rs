@@ -0,0 +1,318 @@
+mod common;
+
+mod test {
+    use java_rs::classloader::assembler::assemble;
+    use java_rs::classloader::error::VerifyError;
+    use java_rs::classloader::load_class;
+    use java_rs::vm::runtime::Stackframe;
+
+    use crate::common::class_manager;
+
+    #[test]
+    fn loop_with_backward_jump() {
+        let source = r#"
+            .class public t/Sum
+            .super java/lang/Object
+
+            ; 1 + 2 + ... + n
+            .method public static sum(I)I
+                iconst_0
+                istore_1
+            Loop:
+                iload_1
+                iload_0
+                iadd
+                istore_1
+                iload_0
+                iconst_1
+                isub
+                istore_0
+                iload_0
+                ifgt Loop
+                iload_1
+                ireturn
+            .end method
+
+            .method public static sum10()I
+                bipush 10
+                invokestatic t/Sum/sum(I)I
+                ireturn
+            .end method
+        "#;
+        let classdef = load_class(assemble(source).unwrap()).unwrap();
+
+        let mut class_manager = class_manager();
+        let id = class_manager.define_class(classdef).unwrap();
+        class_manager.load_class_by_name("t/Sum").unwrap();
+        let result = Stackframe::default().run(&mut class_manager, id, "sum10()I");
+        assert_eq!(55, result.unwrap().into_i32());
+    }
+
+    #[test]
+    fn negative_zero_constants() {
+        let source = r#"
+            .bytecode 51.0
+            .class public t/Zero
+            .super java/lang/Object
+
+            .method public static float()F
+                ldc 0.0
+                pop
+                ldc -0.0
+                freturn
+            .end method
+
+            .method public static double()D
+                ldc2_w 0.0
+                pop2
+                ldc2_w -0.0
+                dreturn
+            .end method
+        "#;
+        let classdef = load_class(assemble(source).unwrap()).unwrap();
+
+        let mut class_manager = class_manager();
+        let id = class_manager.define_class(classdef).unwrap();
+        class_manager.load_class_by_name("t/Zero").unwrap();
+        let float = Stackframe::default().run(&mut class_manager, id, "float()F");
+        assert!(float.unwrap().into_f32().is_sign_negative());
+        let double = Stackframe::default().run(&mut class_manager, id, "double()D");
+        assert!(double.unwrap().into_f64().is_sign_negative());
+    }
+
+    /// assembles the class, and runs the static method that returns an int
+    /// the class is verified when it is version 50 or later
+    fn run(source: &str, class_name: &str, method: &str) -> i32 {
+        let classdef = load_class(assemble(source).unwrap()).unwrap();
+        let mut class_manager = class_manager();
+        let id = class_manager.define_class(classdef).unwrap();
+        class_manager.load_class_by_name(class_name).unwrap();
+        let result = Stackframe::default().run(&mut class_manager, id, method);
+        result.unwrap().into_i32()
+    }
+
+    #[test]
+    fn subroutines() {
+        let source = r#"
+            .class public t/Subroutines
+            .super java/lang/Object
+
+            ; the subroutine adds 10, and is called twice
+            .method public static jsr()I
+                iconst_0
+                istore_0
+                jsr Add
+                jsr_w Add
+                iload_0
+                ireturn
+            Add:
+                astore_1
+                iinc 0 10
+                ret 1
+            .end method
+
+            ; the return address in a local that needs a wide ret
+            .method public static wideRet()I
+                iconst_1
+                istore_0
+                jsr Double
+                iload_0
+                ireturn
+            Double:
+                astore 300
+                iload_0
+                iconst_2
+                imul
+                istore_0
+                ret 300
+            .end method
+        "#;
+        assert_eq!(20, run(source, "t/Subroutines", "jsr()I"));
+        assert_eq!(2, run(source, "t/Subroutines", "wideRet()I"));
+    }
+
+    #[test]
+    fn dup2_x2_forms() {
+        // each method returns the values on the stack after dup2_x2 as digits,
+        // from the bottom of the stack to the top
+        // version 51, so that the verifier checks the types that dup2_x2 leaves on the stack
+        let source = r#"
+            .bytecode 51.0
+            .class public t/Dup
+            .super java/lang/Object
+
+            ; four ints: 1 2 3 4 -> 3 4 1 2 3 4
+            .method public static form1()I
+                iconst_1
+                iconst_2
+                iconst_3
+                iconst_4
+                dup2_x2
+                istore 5
+                istore 4
+                istore_3
+                istore_2
+                istore_1
+                istore_0
+                iload_0
+                bipush 10
+                imul
+                iload_1
+                iadd
+                bipush 10
+                imul
+                iload_2
+                iadd
+                bipush 10
+                imul
+                iload_3
+                iadd
+                bipush 10
+                imul
+                iload 4
+                iadd
+                bipush 10
+                imul
+                iload 5
+                iadd
+                ireturn
+            .end method
+
+            ; a long on two ints: 1 2 7L -> 7L 1 2 7L
+            .method public static form2()I
+                iconst_1
+                iconst_2
+                ldc2_w 7
+                dup2_x2
+                lstore_0
+                istore_2
+                istore_3
+                lstore 4
+                lload 4
+                l2i
+                bipush 10
+                imul
+                iload_3
+                iadd
+                bipush 10
+                imul
+                iload_2
+                iadd
+                bipush 10
+                imul
+                lload_0
+                l2i
+                iadd
+                ireturn
+            .end method
+
+            ; two ints on a long: 5L 1 2 -> 1 2 5L 1 2
+            .method public static form3()I
+                ldc2_w 5
+                iconst_1
+                iconst_2
+                dup2_x2
+                istore_0
+                istore_1
+                lstore_2
+                istore 4
+                istore 5
+                iload 5
+                bipush 10
+                imul
+                iload 4
+                iadd
+                bipush 10
+                imul
+                lload_2
+                l2i
+                iadd
+                bipush 10
+                imul
+                iload_1
+                iadd
+                bipush 10
+                imul
+                iload_0
+                iadd
+                ireturn
+            .end method
+
+            ; a long on a long: 4L 6L -> 6L 4L 6L
+            .method public static form4()I
+                ldc2_w 4
+                ldc2_w 6
+                dup2_x2
+                lstore_0
+                lstore_2
+                lstore 4
+                lload 4
+                l2i
+                bipush 10
+                imul
+                lload_2
+                l2i
+                iadd
+                bipush 10
+                imul
+                lload_0
+                l2i
+                iadd
+                ireturn
+            .end method
+        "#;
+        assert_eq!(341234, run(source, "t/Dup", "form1()I"));
+        assert_eq!(7127, run(source, "t/Dup", "form2()I"));
+        assert_eq!(12512, run(source, "t/Dup", "form3()I"));
+        assert_eq!(646, run(source, "t/Dup", "form4()I"));
+    }
+
+    #[test]
+    fn dup2_x2_on_half_a_long() {
+        // the int and the upper half of the long are not a form of dup2_x2
+        let source = r#"
+            .bytecode 51.0
+            .class public t/Split
+            .super java/lang/Object
+
+            .method public static split()I
+                lconst_0
+                iconst_1
+                dup2_x2
+                ireturn
+            .end method
+        "#;
+        let classdef = load_class(assemble(source).unwrap()).unwrap();
+        let mut class_manager = class_manager();
+        class_manager.define_class(classdef).unwrap();
+        let error = class_manager.load_class_by_name("t/Split").unwrap_err();
+        let error = error.downcast_ref::<VerifyError>().unwrap();
+        assert_eq!("split()I", error.method);
+        assert_eq!(2, error.offset);
+    }
+
+    #[test]
+    fn wide_locals() {
+        let source = r#"
+            .bytecode 51.0
+            .class public t/Wide
+            .super java/lang/Object
+
+            .method public static locals()I
+                sipush 1000
+                istore 300
+                iinc 300 2000
+                ldc2_w 5
+                lstore 400
+                iload 300
+                lload 400
+                l2i
+                iadd
+                wide istore 1
+                wide iload 1
+                ireturn
+            .end method
+        "#;
+        assert_eq!(3005, run(source, "t/Wide", "locals()I"));
+    }
+}
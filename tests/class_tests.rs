@@ -1,23 +1,288 @@
+mod common;
+
 mod test {
-    use java_rs::vm::runtime::Vm;
+    use java_rs::classloader::builder::{ClassBuilder, Code};
+    use java_rs::classloader::classdef::Modifier;
+    use java_rs::classloader::error::VerifyError;
+    use java_rs::vm::opcodes::Opcode::*;
+    use java_rs::vm::runtime::Stackframe;
+
+    use crate::common::class_manager;
+
+    const PUBLIC_STATIC: u16 = Modifier::Public as u16 | Modifier::Static as u16;
 
     #[test]
     fn if_cmp() {
-        let vm = Vm::new();
-        vm.run(
-            "/Users/Shautvast/dev/java/tests",
-            "testclasses.IfCmp",
-            "i_is_1()Z",
-        );
+        let mut class = ClassBuilder::new("testclasses/IfCmp", Some("java/lang/Object"));
+        class.field(Modifier::Private as u16 | Modifier::Static as u16, "i", "I");
+        let i = class.field_ref("testclasses/IfCmp", "i", "I");
+
+        // return i == 1;
+        let mut i_is_1 = Code::new();
+        i_is_1
+            .op(GETSTATIC(i))
+            .op(ICONST(1))
+            .jump(IF_ICMPNE, "false")
+            .op(ICONST(1))
+            .op(IRETURN)
+            .label("false")
+            .op(ICONST(0))
+            .op(IRETURN);
+        class.method(PUBLIC_STATIC, "i_is_1", "()Z", i_is_1);
+
+        // i = 1;
+        let mut set_1 = Code::new();
+        set_1.op(ICONST(1)).op(PUTSTATIC(i)).op(RETURN_VOID);
+        class.method(PUBLIC_STATIC, "set_1", "()V", set_1);
+
+        let mut class_manager = class_manager();
+        let id = class_manager.define_class(class.build().unwrap()).unwrap();
+        class_manager
+            .load_class_by_name("testclasses/IfCmp")
+            .unwrap();
+
+        let i_is_1 = |class_manager: &mut _| {
+            Stackframe::default()
+                .run(class_manager, id, "i_is_1()Z")
+                .unwrap()
+                .into_i32()
+        };
+        assert_eq!(0, i_is_1(&mut class_manager));
+        Stackframe::default()
+            .run(&mut class_manager, id, "set_1()V")
+            .unwrap();
+        assert_eq!(1, i_is_1(&mut class_manager));
     }
 
     #[test]
     fn consts() {
-        let vm = Vm::new();
-        vm.run(
-            "/Users/Shautvast/dev/java/tests",
-            "testclasses.Const",
-            "hello()Ljava/lang/String;",
-        )
+        let mut class = ClassBuilder::new("testclasses/Const", Some("java/lang/Object"));
+        let hello = class.string("hello world");
+        let length = class.method_ref("java/lang/String", "length", "()I");
+
+        // return "hello world".length();
+        let mut code = Code::new();
+        code.op(LDC(hello)).op(INVOKEVIRTUAL(length)).op(IRETURN);
+        class.method(PUBLIC_STATIC, "hello_length", "()I", code);
+
+        let mut class_manager = class_manager();
+        let id = class_manager.define_class(class.build().unwrap()).unwrap();
+        class_manager
+            .load_class_by_name("testclasses/Const")
+            .unwrap();
+        let result = Stackframe::default().run(&mut class_manager, id, "hello_length()I");
+        assert_eq!(11, result.unwrap().into_i32());
+    }
+
+    #[test]
+    fn verify_error() {
+        // return 1.0f + 1;
+        let mut code = Code::new();
+        code.op(FCONST(1)).op(ICONST(1)).op(IADD).op(IRETURN);
+        let mut class = ClassBuilder::new("testclasses/Unverifiable", Some("java/lang/Object"));
+        class
+            .version(52, 0)
+            .method(PUBLIC_STATIC, "add", "()I", code);
+
+        let mut verifying = class_manager();
+        verifying.define_class(class.build().unwrap()).unwrap();
+        let error = verifying
+            .load_class_by_name("testclasses/Unverifiable")
+            .unwrap_err();
+        let error = error.downcast_ref::<VerifyError>().unwrap();
+        assert_eq!("add()I", error.method);
+        assert_eq!(2, error.offset);
+
+        let mut trusting = class_manager();
+        trusting.set_verify(false);
+        trusting.define_class(class.build().unwrap()).unwrap();
+        trusting
+            .load_class_by_name("testclasses/Unverifiable")
+            .unwrap();
+    }
+
+    /// a java/util/HashMap that is a linked list of entries, and a java/lang/System whose
+    /// getProperty looks the name up in the map from SystemProps.Raw.cmdProperties(),
+    /// as the JDK does when it initializes the system properties
+    fn system_properties() -> Vec<ClassBuilder> {
+        let public = Modifier::Public as u16;
+        let object = "Ljava/lang/Object;";
+
+        let mut hashmap = ClassBuilder::new("java/util/HashMap", Some("java/lang/Object"));
+        hashmap
+            .field(public, "key", object)
+            .field(public, "value", object)
+            .field(public, "next", "Ljava/util/HashMap;");
+        let key = hashmap.field_ref("java/util/HashMap", "key", object);
+        let value = hashmap.field_ref("java/util/HashMap", "value", object);
+        let next = hashmap.field_ref("java/util/HashMap", "next", "Ljava/util/HashMap;");
+        let hashmap_class = hashmap.class("java/util/HashMap");
+        let object_init = hashmap.method_ref("java/lang/Object", "<init>", "()V");
+        let hashmap_init = hashmap.method_ref("java/util/HashMap", "<init>", "()V");
+        let string_class = hashmap.class("java/lang/String");
+        let equals = hashmap.method_ref("java/lang/String", "equals", "(Ljava/lang/Object;)Z");
+
+        let mut init = Code::new();
+        init.op(ALOAD(0))
+            .op(INVOKESPECIAL(object_init))
+            .op(RETURN_VOID);
+        hashmap.method(public, "<init>", "()V", init);
+
+        // inserts a new entry after the head, the map itself
+        let mut put = Code::new();
+        put.op(NEW(hashmap_class))
+            .op(DUP)
+            .op(INVOKESPECIAL(hashmap_init))
+            .op(ASTORE(3))
+            .op(ALOAD(3))
+            .op(ALOAD(1))
+            .op(PUTFIELD(key))
+            .op(ALOAD(3))
+            .op(ALOAD(2))
+            .op(PUTFIELD(value))
+            .op(ALOAD(3))
+            .op(ALOAD(0))
+            .op(GETFIELD(next))
+            .op(PUTFIELD(next))
+            .op(ALOAD(0))
+            .op(ALOAD(3))
+            .op(PUTFIELD(next))
+            .op(ACONST_NULL)
+            .op(ARETURN);
+        hashmap.method(
+            public,
+            "put",
+            "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+            put,
+        );
+
+        let mut get = Code::new();
+        get.op(ALOAD(0))
+            .op(GETFIELD(next))
+            .op(ASTORE(2))
+            .label("loop")
+            .op(ALOAD(2))
+            .jump(IFNULL, "missing")
+            .op(ALOAD(1))
+            .op(CHECKCAST(string_class))
+            .op(ALOAD(2))
+            .op(GETFIELD(key))
+            .op(INVOKEVIRTUAL(equals))
+            .jump(IFNE, "found")
+            .op(ALOAD(2))
+            .op(GETFIELD(next))
+            .op(ASTORE(2))
+            .jump(GOTO, "loop")
+            .label("found")
+            .op(ALOAD(2))
+            .op(GETFIELD(value))
+            .op(ARETURN)
+            .label("missing")
+            .op(ACONST_NULL)
+            .op(ARETURN);
+        hashmap.method(public, "get", "(Ljava/lang/Object;)Ljava/lang/Object;", get);
+
+        let mut raw = ClassBuilder::new(
+            "jdk/internal/util/SystemProps$Raw",
+            Some("java/lang/Object"),
+        );
+        raw.abstract_method(
+            PUBLIC_STATIC | Modifier::Native as u16,
+            "cmdProperties",
+            "()Ljava/util/HashMap;",
+        );
+
+        let mut system = ClassBuilder::new("java/lang/System", Some("java/lang/Object"));
+        let cmd_properties = system.method_ref(
+            "jdk/internal/util/SystemProps$Raw",
+            "cmdProperties",
+            "()Ljava/util/HashMap;",
+        );
+        let hashmap_get = system.method_ref(
+            "java/util/HashMap",
+            "get",
+            "(Ljava/lang/Object;)Ljava/lang/Object;",
+        );
+        let string_class = system.class("java/lang/String");
+        let mut get_property = Code::new();
+        get_property
+            .op(INVOKESTATIC(cmd_properties))
+            .op(ALOAD(0))
+            .op(INVOKEVIRTUAL(hashmap_get))
+            .op(CHECKCAST(string_class))
+            .op(ARETURN);
+        system.method(
+            PUBLIC_STATIC,
+            "getProperty",
+            "(Ljava/lang/String;)Ljava/lang/String;",
+            get_property,
+        );
+
+        vec![hashmap, raw, system]
+    }
+
+    #[test]
+    fn command_line_properties() {
+        // the length of System.getProperty(name), -1 if the property is not set
+        let mut class = ClassBuilder::new("testclasses/Properties", Some("java/lang/Object"));
+        let get_property = class.method_ref(
+            "java/lang/System",
+            "getProperty",
+            "(Ljava/lang/String;)Ljava/lang/String;",
+        );
+        let length = class.method_ref("java/lang/String", "length", "()I");
+        let mut property_length = Code::new();
+        property_length
+            .op(ALOAD(0))
+            .op(INVOKESTATIC(get_property))
+            .op(DUP)
+            .jump(IFNULL, "missing")
+            .op(INVOKEVIRTUAL(length))
+            .op(IRETURN)
+            .label("missing")
+            .op(POP)
+            .op(ICONST(-1))
+            .op(IRETURN);
+        class.method(
+            PUBLIC_STATIC,
+            "propertyLength",
+            "(Ljava/lang/String;)I",
+            property_length,
+        );
+        let property_length = class.method_ref(
+            "testclasses/Properties",
+            "propertyLength",
+            "(Ljava/lang/String;)I",
+        );
+        let expected = [("greeting", 5), ("user.name", 4), ("user.home", -1)];
+        for (i, (name, _)) in expected.iter().enumerate() {
+            let name = class.string(name);
+            let mut code = Code::new();
+            code.op(LDC_W(name))
+                .op(INVOKESTATIC(property_length))
+                .op(IRETURN);
+            class.method(PUBLIC_STATIC, &format!("property{}", i), "()I", code);
+        }
+
+        let mut class_manager = class_manager();
+        // as with -Dgreeting=hello -Duser.name=duke
+        class_manager.set_property("greeting", "hello");
+        class_manager.set_property("user.name", "duke");
+        for builder in system_properties() {
+            class_manager
+                .define_class(builder.build().unwrap())
+                .unwrap();
+        }
+        let id = class_manager.define_class(class.build().unwrap()).unwrap();
+        class_manager
+            .load_class_by_name("testclasses/Properties")
+            .unwrap();
+
+        for (i, (name, length)) in expected.iter().enumerate() {
+            let result = Stackframe::default()
+                .run(&mut class_manager, id, &format!("property{}()I", i))
+                .unwrap();
+            assert_eq!(*length, result.into_i32(), "{}", name);
+        }
     }
 }
@@ -0,0 +1,218 @@
+use java_rs::classloader::builder::{ClassBuilder, Code};
+use java_rs::classloader::classdef::Modifier;
+use java_rs::classmanager::ClassManager;
+use java_rs::vm::opcodes::Opcode::*;
+
+/// a ClassManager without a JDK, with the classes that the vm needs to run code:
+/// java/lang/Object, java/lang/Class with the reflection methods that the vm implements,
+/// a java/lang/String that only has length() and equals() and the throwables that the vm throws
+pub fn class_manager() -> ClassManager {
+    let mut class_manager = ClassManager::new(vec![]);
+    let public = Modifier::Public as u16;
+
+    let mut object = ClassBuilder::new("java/lang/Object", None);
+    let mut init = Code::new();
+    init.op(RETURN_VOID);
+    object.method(public, "<init>", "()V", init);
+
+    let mut class = ClassBuilder::new("java/lang/Class", Some("java/lang/Object"));
+    class.field(Modifier::Private as u16, "name", "Ljava/lang/String;");
+    // the vm replaces them with a native implementation
+    let native = public | Modifier::Native as u16;
+    class
+        .abstract_method(
+            native,
+            "getAnnotation",
+            "(Ljava/lang/Class;)Ljava/lang/annotation/Annotation;",
+        )
+        .abstract_method(
+            native,
+            "getAnnotations",
+            "()[Ljava/lang/annotation/Annotation;",
+        )
+        .abstract_method(
+            native,
+            "getDeclaredMethods",
+            "()[Ljava/lang/reflect/Method;",
+        );
+
+    let mut string = ClassBuilder::new("java/lang/String", Some("java/lang/Object"));
+    string.field(Modifier::Private as u16, "value", "[B").field(
+        Modifier::Private as u16,
+        "coder",
+        "B",
+    );
+    let value = string.field_ref("java/lang/String", "value", "[B");
+    let mut length = Code::new();
+    // only for latin1 strings
+    length
+        .op(ALOAD(0))
+        .op(GETFIELD(value))
+        .op(ARRAYLENGTH)
+        .op(IRETURN);
+    string.method(public, "length", "()I", length);
+    let string_class = string.class("java/lang/String");
+    let mut equals = Code::new();
+    // only for latin1 strings, compares the bytes
+    equals
+        .op(ALOAD(0))
+        .op(GETFIELD(value))
+        .op(ASTORE(2))
+        .op(ALOAD(1))
+        .op(CHECKCAST(string_class))
+        .op(GETFIELD(value))
+        .op(ASTORE(3))
+        .op(ALOAD(2))
+        .op(ARRAYLENGTH)
+        .op(ALOAD(3))
+        .op(ARRAYLENGTH)
+        .jump(IF_ICMPNE, "false")
+        .op(ICONST(0))
+        .op(ISTORE(4))
+        .label("loop")
+        .op(ILOAD(4))
+        .op(ALOAD(2))
+        .op(ARRAYLENGTH)
+        .jump(IF_ICMPGE, "true")
+        .op(ALOAD(2))
+        .op(ILOAD(4))
+        .op(BALOAD)
+        .op(ALOAD(3))
+        .op(ILOAD(4))
+        .op(BALOAD)
+        .jump(IF_ICMPNE, "false")
+        .op(IINC(4, 1))
+        .jump(GOTO, "loop")
+        .label("true")
+        .op(ICONST(1))
+        .op(IRETURN)
+        .label("false")
+        .op(ICONST(0))
+        .op(IRETURN);
+    string.method(public, "equals", "(Ljava/lang/Object;)Z", equals);
+
+    for builder in [object, class, string] {
+        class_manager
+            .define_class(builder.build().unwrap())
+            .unwrap();
+    }
+    for builder in throwables() {
+        class_manager
+            .define_class(builder.build().unwrap())
+            .unwrap();
+    }
+    class_manager.load_class_by_name("java/lang/Class").unwrap();
+    class_manager
+}
+
+/// java/lang/Throwable, that keeps the message and the cause and records the stack trace,
+/// the subclasses that the vm throws, with the constructors that it calls,
+/// and java/lang/StackTraceElement
+fn throwables() -> Vec<ClassBuilder> {
+    let public = Modifier::Public as u16;
+    let native = public | Modifier::Native as u16;
+    let constructors = [
+        ("()V", None),
+        ("(Ljava/lang/String;)V", Some("detailMessage")),
+        ("(Ljava/lang/Throwable;)V", Some("cause")),
+    ];
+
+    let mut throwable = ClassBuilder::new("java/lang/Throwable", Some("java/lang/Object"));
+    throwable
+        .field(public, "detailMessage", "Ljava/lang/String;")
+        .field(public, "cause", "Ljava/lang/Throwable;")
+        .field(public, "backtrace", "Ljava/lang/Object;")
+        .field(public, "depth", "I")
+        .abstract_method(native, "fillInStackTrace", "(I)Ljava/lang/Throwable;");
+    // the vm replaces it with a native implementation
+    let mut get_stack_trace = Code::new();
+    get_stack_trace.op(ACONST_NULL).op(ARETURN);
+    throwable.method(
+        public,
+        "getStackTrace",
+        "()[Ljava/lang/StackTraceElement;",
+        get_stack_trace,
+    );
+    let fill_in_stack_trace = throwable.method_ref(
+        "java/lang/Throwable",
+        "fillInStackTrace",
+        "(I)Ljava/lang/Throwable;",
+    );
+    for (descriptor, field) in constructors {
+        let mut init = Code::new();
+        init.op(ALOAD(0))
+            .op(ICONST(0))
+            .op(INVOKEVIRTUAL(fill_in_stack_trace))
+            .op(POP);
+        if let Some(field) = field {
+            let field_type = &descriptor[1..descriptor.len() - 2];
+            let field = throwable.field_ref("java/lang/Throwable", field, field_type);
+            init.op(ALOAD(0)).op(ALOAD(1)).op(PUTFIELD(field));
+        }
+        init.op(RETURN_VOID);
+        throwable.method(public, "<init>", descriptor, init);
+    }
+
+    let mut throwables = vec![throwable];
+    for (name, super_class) in [
+        ("java/lang/Exception", "java/lang/Throwable"),
+        ("java/lang/RuntimeException", "java/lang/Exception"),
+        (
+            "java/lang/ArithmeticException",
+            "java/lang/RuntimeException",
+        ),
+        (
+            "java/lang/NullPointerException",
+            "java/lang/RuntimeException",
+        ),
+        (
+            "java/lang/ArrayIndexOutOfBoundsException",
+            "java/lang/RuntimeException",
+        ),
+        (
+            "java/lang/NegativeArraySizeException",
+            "java/lang/RuntimeException",
+        ),
+        ("java/lang/Error", "java/lang/Throwable"),
+        ("java/lang/InternalError", "java/lang/Error"),
+        ("java/lang/LinkageError", "java/lang/Error"),
+        ("java/lang/NoClassDefFoundError", "java/lang/LinkageError"),
+        (
+            "java/lang/ExceptionInInitializerError",
+            "java/lang/LinkageError",
+        ),
+        (
+            "java/lang/IncompatibleClassChangeError",
+            "java/lang/LinkageError",
+        ),
+        (
+            "java/lang/NoSuchMethodError",
+            "java/lang/IncompatibleClassChangeError",
+        ),
+    ] {
+        let mut builder = ClassBuilder::new(name, Some(super_class));
+        for (descriptor, field) in constructors {
+            let super_init = builder.method_ref(super_class, "<init>", descriptor);
+            let mut init = Code::new();
+            init.op(ALOAD(0));
+            if field.is_some() {
+                init.op(ALOAD(1));
+            }
+            init.op(INVOKESPECIAL(super_init)).op(RETURN_VOID);
+            builder.method(public, "<init>", descriptor, init);
+        }
+        throwables.push(builder);
+    }
+
+    let mut element = ClassBuilder::new("java/lang/StackTraceElement", Some("java/lang/Object"));
+    for (name, descriptor) in [
+        ("declaringClass", "Ljava/lang/String;"),
+        ("methodName", "Ljava/lang/String;"),
+        ("fileName", "Ljava/lang/String;"),
+        ("lineNumber", "I"),
+    ] {
+        element.field(public, name, descriptor);
+    }
+    throwables.push(element);
+    throwables
+}
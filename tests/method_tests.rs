@@ -1,17 +1,22 @@
 mod test {
-    use java_rs::classloader::classdef::{Method, Modifier};
-    use std::collections::HashMap;
-    use std::rc::Rc;
+    use java_rs::classloader::builder::{ClassBuilder, Code};
+    use java_rs::classloader::classdef::Modifier;
+    use java_rs::vm::opcodes::Opcode::*;
 
     #[test]
     fn access_flags() {
-        let m = Method::new(
-            Rc::new(HashMap::new()),
+        let mut class = ClassBuilder::new("C", Some("java/lang/Object"));
+        let mut code = Code::new();
+        code.op(RETURN_VOID);
+        class.method(
             Modifier::Public as u16 | Modifier::Static as u16,
-            0,
-            0,
-            HashMap::new(),
+            "m",
+            "()V",
+            code,
         );
+        let classdef = class.build().unwrap();
+
+        let m = classdef.get_method("m()V").unwrap();
         assert!(m.is(Modifier::Public));
         assert!(m.is(Modifier::Static));
         assert!(!m.is(Modifier::Private));
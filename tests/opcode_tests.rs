@@ -0,0 +1,1206 @@
+mod common;
+
+/// The arithmetic, bitwise, conversion, comparison and stack opcodes, with the edge cases of
+/// the JVMS: wrapping overflow, the sign of remainders, shift distances, saturating float to int
+/// conversions and the ordering of NaN. And the exceptions, thrown by athrow or by the vm,
+/// and how they unwind through the exception tables.
+#[cfg(test)]
+mod test {
+    use java_rs::classloader::attributes::VerificationType::{Float as FloatType, Integer};
+    use java_rs::classloader::builder::{ClassBuilder, Code};
+    use java_rs::classloader::classdef::{CpEntry, Modifier};
+    use java_rs::vm::opcodes::Opcode::{self, *};
+    use java_rs::vm::runtime::{Abrupt, MethodResult, Stackframe};
+
+    use crate::common::class_manager;
+
+    const PUBLIC_STATIC: u16 = Modifier::Public as u16 | Modifier::Static as u16;
+
+    /// a value that is pushed on the operand stack with ldc
+    #[derive(Clone, Copy)]
+    enum Operand {
+        Int(i32),
+        Long(i64),
+        Float(f32),
+        Double(f64),
+    }
+    use Operand::*;
+
+    /// pushes the operands and then runs the code, in a static method with the descriptor
+    /// the class file version is 51, so that the verifier checks the code first
+    fn run(operands: &[Operand], code: impl FnOnce(&mut Code), descriptor: &str) -> MethodResult {
+        let mut class = ClassBuilder::new("Opcodes", Some("java/lang/Object"));
+        class.version(51, 0);
+        let mut method = Code::new();
+        for operand in operands {
+            method.op(ldc(&mut class, *operand));
+        }
+        code(&mut method);
+        class.method(PUBLIC_STATIC, "run", descriptor, method);
+        run_classes(&[class], &format!("run{}", descriptor))
+    }
+
+    /// the ldc_w or ldc2_w for the operand
+    fn ldc(class: &mut ClassBuilder, operand: Operand) -> Opcode {
+        match operand {
+            Int(value) => LDC_W(class.integer(value)),
+            Long(value) => LDC2_W(class.long(value)),
+            Float(value) => LDC_W(class.float(value)),
+            Double(value) => LDC2_W(class.double(value)),
+        }
+    }
+
+    /// defines the classes and runs the static method in the first one
+    fn run_classes(classes: &[ClassBuilder], method: &str) -> MethodResult {
+        let mut class_manager = class_manager();
+        let mut ids = vec![];
+        for class in classes {
+            ids.push(class_manager.define_class(class.build().unwrap()).unwrap());
+        }
+        let name = class_manager.classdef_name(&ids[0]).unwrap();
+        class_manager.load_class_by_name(&name).unwrap();
+        Stackframe::default().run(&mut class_manager, ids[0], method)
+    }
+
+    /// throws a new instance of the exception class
+    fn throw_new(class: &mut ClassBuilder, code: &mut Code, exception: &str) {
+        let exception_class = class.class(exception);
+        let init = class.method_ref(exception, "<init>", "()V");
+        code.op(NEW(exception_class))
+            .op(DUP)
+            .op(INVOKESPECIAL(init))
+            .op(ATHROW);
+    }
+
+    /// the int that the opcode leaves on the operand stack
+    fn int(operands: &[Operand], opcode: Opcode) -> i32 {
+        run(
+            operands,
+            |code| {
+                code.op(opcode).op(IRETURN);
+            },
+            "()I",
+        )
+        .unwrap()
+        .into_i32()
+    }
+
+    fn long(operands: &[Operand], opcode: Opcode) -> i64 {
+        run(
+            operands,
+            |code| {
+                code.op(opcode).op(LRETURN);
+            },
+            "()J",
+        )
+        .unwrap()
+        .into_i64()
+    }
+
+    fn float(operands: &[Operand], opcode: Opcode) -> f32 {
+        run(
+            operands,
+            |code| {
+                code.op(opcode).op(FRETURN);
+            },
+            "()F",
+        )
+        .unwrap()
+        .into_f32()
+    }
+
+    fn double(operands: &[Operand], opcode: Opcode) -> f64 {
+        run(
+            operands,
+            |code| {
+                code.op(opcode).op(DRETURN);
+            },
+            "()D",
+        )
+        .unwrap()
+        .into_f64()
+    }
+
+    /// 1 if the jump is taken, 0 if not
+    fn jumps(operands: &[Operand], opcode: fn(u16) -> Opcode) -> i32 {
+        run(
+            operands,
+            |code| {
+                code.jump(opcode, "jump")
+                    .op(ICONST(0))
+                    .op(IRETURN)
+                    .label("jump")
+                    .op(ICONST(1))
+                    .op(IRETURN)
+                    .frame("jump", &[], &[]);
+            },
+            "()I",
+        )
+        .unwrap()
+        .into_i32()
+    }
+
+    #[test]
+    fn add_sub_mul_wrap_around() {
+        assert_eq!(i32::MIN, int(&[Int(i32::MAX), Int(1)], IADD));
+        assert_eq!(i64::MIN, long(&[Long(i64::MAX), Long(1)], LADD));
+        assert_eq!(i32::MAX, int(&[Int(i32::MIN), Int(1)], ISUB));
+        assert_eq!(i64::MAX, long(&[Long(i64::MIN), Long(1)], LSUB));
+        assert_eq!(0, int(&[Int(0x10000), Int(0x10000)], IMUL));
+        assert_eq!(-2, long(&[Long(i64::MAX), Long(2)], LMUL));
+        assert_eq!(0.3f32 + 0.6f32, float(&[Float(0.3), Float(0.6)], FADD));
+        assert_eq!(0.1 + 0.2, double(&[Double(0.1), Double(0.2)], DADD));
+        assert_eq!(f32::INFINITY, float(&[Float(f32::MAX), Float(2.0)], FMUL));
+        assert_eq!(-0.5, double(&[Double(0.25), Double(0.75)], DSUB));
+    }
+
+    #[test]
+    fn division_rounds_towards_zero() {
+        assert_eq!(-3, int(&[Int(-7), Int(2)], IDIV));
+        assert_eq!(i32::MIN, int(&[Int(i32::MIN), Int(-1)], IDIV));
+        assert_eq!(i64::MIN, long(&[Long(i64::MIN), Long(-1)], LDIV));
+        assert_eq!(f32::NEG_INFINITY, float(&[Float(-1.0), Float(0.0)], FDIV));
+        assert!(double(&[Double(0.0), Double(0.0)], DDIV).is_nan());
+    }
+
+    #[test]
+    fn remainder_has_the_sign_of_the_dividend() {
+        assert_eq!(-1, int(&[Int(-7), Int(2)], IREM));
+        assert_eq!(1, int(&[Int(7), Int(-2)], IREM));
+        assert_eq!(0, int(&[Int(i32::MIN), Int(-1)], IREM));
+        assert_eq!(-1, long(&[Long(-7), Long(2)], LREM));
+        assert_eq!(0, long(&[Long(i64::MIN), Long(-1)], LREM));
+        assert_eq!(-1.5, float(&[Float(-7.5), Float(2.0)], FREM));
+        assert_eq!(5.0, double(&[Double(5.0), Double(f64::INFINITY)], DREM));
+        assert!(float(&[Float(1.0), Float(0.0)], FREM).is_nan());
+        assert!(double(&[Double(f64::INFINITY), Double(1.0)], DREM).is_nan());
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(i32::MIN, int(&[Int(i32::MIN)], INEG));
+        assert_eq!(i64::MIN, long(&[Long(i64::MIN)], LNEG));
+        assert!(float(&[Float(0.0)], FNEG).is_sign_negative());
+        assert_eq!(-1.5, double(&[Double(1.5)], DNEG));
+    }
+
+    #[test]
+    fn shift_distances_are_masked() {
+        assert_eq!(2, int(&[Int(1), Int(33)], ISHL));
+        assert_eq!(-4, int(&[Int(-8), Int(1)], ISHR));
+        assert_eq!(-8, int(&[Int(-8), Int(32)], ISHR));
+        assert_eq!(15, int(&[Int(-1), Int(28)], IUSHR));
+        assert_eq!(-1, int(&[Int(-1), Int(32)], IUSHR));
+        assert_eq!(2, long(&[Long(1), Int(65)], LSHL));
+        assert_eq!(-4, long(&[Long(-8), Int(1)], LSHR));
+        assert_eq!(15, long(&[Long(-1), Int(60)], LUSHR));
+        assert_eq!(-1, long(&[Long(-1), Int(-64)], LUSHR));
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!(0b1000, int(&[Int(0b1100), Int(0b1010)], IAND));
+        assert_eq!(0b1110, int(&[Int(0b1100), Int(0b1010)], IOR));
+        assert_eq!(0b0110, int(&[Int(0b1100), Int(0b1010)], IXOR));
+        assert_eq!(1 << 40, long(&[Long(-1), Long(1 << 40)], LAND));
+        assert_eq!(-1, long(&[Long(i64::MIN), Long(i64::MAX)], LOR));
+        assert_eq!(!0xFF, long(&[Long(-1), Long(0xFF)], LXOR));
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(-1, long(&[Int(-1)], I2L));
+        assert_eq!(16777216.0, float(&[Int(16777217)], I2F));
+        assert_eq!(-2.0, double(&[Int(-2)], I2D));
+        assert_eq!(1, int(&[Long(0x1_0000_0001)], L2I));
+        assert_eq!(9.223372e18, float(&[Long(i64::MAX)], L2F));
+        assert_eq!(1e15, double(&[Long(1_000_000_000_000_000)], L2D));
+        assert_eq!(1.5, double(&[Float(1.5)], F2D));
+        assert_eq!(f32::INFINITY, float(&[Double(1e300)], D2F));
+        assert_eq!(-56, int(&[Int(200)], I2B));
+        assert_eq!(65535, int(&[Int(-1)], I2C));
+        assert_eq!(-25536, int(&[Int(40000)], I2S));
+    }
+
+    #[test]
+    fn float_to_integer_saturates() {
+        assert_eq!(-1, int(&[Float(-1.9)], F2I));
+        assert_eq!(0, int(&[Float(f32::NAN)], F2I));
+        assert_eq!(i32::MAX, int(&[Float(1e20)], F2I));
+        assert_eq!(i32::MIN, int(&[Float(f32::NEG_INFINITY)], F2I));
+        assert_eq!(0, long(&[Float(f32::NAN)], F2L));
+        assert_eq!(i64::MAX, long(&[Float(f32::INFINITY)], F2L));
+        assert_eq!(0, int(&[Double(f64::NAN)], D2I));
+        assert_eq!(i32::MIN, int(&[Double(-1e10)], D2I));
+        assert_eq!(i64::MIN, long(&[Double(-1e30)], D2L));
+        assert_eq!(3, long(&[Double(3.99)], D2L));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(-1, int(&[Long(i64::MIN), Long(0)], LCMP));
+        assert_eq!(0, int(&[Long(5), Long(5)], LCMP));
+        assert_eq!(1, int(&[Long(1), Long(-1)], LCMP));
+        assert_eq!(0, int(&[Float(0.0), Float(-0.0)], FCMPL));
+        assert_eq!(1, int(&[Float(2.0), Float(1.0)], FCMPG));
+        assert_eq!(-1, int(&[Float(f32::NAN), Float(1.0)], FCMPL));
+        assert_eq!(1, int(&[Float(1.0), Float(f32::NAN)], FCMPG));
+        assert_eq!(-1, int(&[Double(f64::NAN), Double(f64::NAN)], DCMPL));
+        assert_eq!(1, int(&[Double(f64::NAN), Double(0.0)], DCMPG));
+        assert_eq!(-1, int(&[Double(-1.0), Double(0.0)], DCMPG));
+    }
+
+    #[test]
+    fn conditional_jumps() {
+        assert_eq!(1, jumps(&[Int(0)], IFEQ));
+        assert_eq!(0, jumps(&[Int(0)], IFNE));
+        assert_eq!(1, jumps(&[Int(-1)], IFLT));
+        assert_eq!(1, jumps(&[Int(0)], IFGE));
+        assert_eq!(0, jumps(&[Int(0)], IFGT));
+        assert_eq!(1, jumps(&[Int(i32::MIN)], IFLE));
+        assert_eq!(1, jumps(&[Int(3), Int(3)], IF_ICMPEQ));
+        assert_eq!(1, jumps(&[Int(3), Int(4)], IF_ICMPNE));
+        assert_eq!(1, jumps(&[Int(-4), Int(3)], IF_ICMPLT));
+        assert_eq!(0, jumps(&[Int(-4), Int(3)], IF_ICMPGE));
+        assert_eq!(1, jumps(&[Int(4), Int(3)], IF_ICMPGT));
+        assert_eq!(1, jumps(&[Int(3), Int(3)], IF_ICMPLE));
+    }
+
+    #[test]
+    fn reference_comparisons() {
+        // null and null, or a new array and null
+        let compare = |same: bool, opcode: fn(u16) -> Opcode| {
+            run(
+                &[],
+                |code| {
+                    if same {
+                        code.op(ACONST_NULL);
+                    } else {
+                        code.op(ICONST(0)).op(NEWARRAY(10));
+                    }
+                    code.op(ACONST_NULL)
+                        .jump(opcode, "jump")
+                        .op(ICONST(0))
+                        .op(IRETURN)
+                        .label("jump")
+                        .op(ICONST(1))
+                        .op(IRETURN)
+                        .frame("jump", &[], &[]);
+                },
+                "()I",
+            )
+            .unwrap()
+            .into_i32()
+        };
+        assert_eq!(1, compare(true, IF_ACMPEQ));
+        assert_eq!(0, compare(true, IF_ACMPNE));
+        assert_eq!(0, compare(false, IF_ACMPEQ));
+        assert_eq!(1, compare(false, IF_ACMPNE));
+    }
+
+    #[test]
+    fn stack_manipulation() {
+        let result = |operands: &[Operand], code: fn(&mut Code)| {
+            run(operands, code, "()I").unwrap().into_i32()
+        };
+        assert_eq!(
+            1,
+            result(&[Int(1), Long(2)], |code| {
+                code.op(POP2).op(IRETURN);
+            })
+        );
+        assert_eq!(
+            1,
+            result(&[Int(1), Int(2), Int(3)], |code| {
+                code.op(POP2).op(IRETURN);
+            })
+        );
+        assert_eq!(
+            1,
+            result(&[Int(1), Int(2)], |code| {
+                code.op(SWAP).op(ISUB).op(IRETURN);
+            })
+        );
+        // dup_x2 with a long below the int
+        assert_eq!(
+            5,
+            result(&[Long(1), Int(5)], |code| {
+                code.op(DUP_X2).op(POP).op(POP2).op(IRETURN);
+            })
+        );
+    }
+
+    #[test]
+    fn signed_immediates() {
+        let push = |opcode: Opcode| {
+            run(
+                &[],
+                |code| {
+                    code.op(opcode).op(IRETURN);
+                },
+                "()I",
+            )
+            .unwrap()
+            .into_i32()
+        };
+        assert_eq!(-1, push(BIPUSH(-1)));
+        assert_eq!(-128, push(BIPUSH(i8::MIN)));
+        assert_eq!(127, push(BIPUSH(i8::MAX)));
+        assert_eq!(-32768, push(SIPUSH(i16::MIN)));
+        assert_eq!(32767, push(SIPUSH(i16::MAX)));
+
+        let increment = |start: i32, iinc: Opcode| {
+            run(
+                &[Int(start)],
+                |code| {
+                    code.op(ISTORE(0)).op(iinc).op(ILOAD(0)).op(IRETURN);
+                },
+                "()I",
+            )
+            .unwrap()
+            .into_i32()
+        };
+        assert_eq!(9, increment(10, IINC(0, -1)));
+        assert_eq!(-118, increment(10, IINC(0, i8::MIN)));
+        assert_eq!(-990, increment(10, WIDE(Box::new(WIDE_IINC(0, -1000)))));
+        assert_eq!(i32::MIN, increment(i32::MAX, IINC(0, 1)));
+        assert_eq!(i32::MAX, increment(i32::MIN, IINC(0, -1)));
+    }
+
+    #[test]
+    fn wide_locals() {
+        let result = run(
+            &[Int(7)],
+            |code| {
+                code.op(WIDE_ISTORE(300)).op(WIDE_ILOAD(300)).op(IRETURN);
+            },
+            "()I",
+        );
+        assert_eq!(7, result.unwrap().into_i32());
+    }
+
+    #[test]
+    fn goto_loop() {
+        // int sum = 0; for (; n != 0; n--) { sum += n; } return sum;
+        let sum = |goto: bool| {
+            run(
+                &[Int(10)],
+                |code| {
+                    code.op(ISTORE(0))
+                        .op(ICONST(0))
+                        .op(ISTORE(1))
+                        .label("loop")
+                        .op(ILOAD(0))
+                        .jump(IFEQ, "end")
+                        .op(ILOAD(1))
+                        .op(ILOAD(0))
+                        .op(IADD)
+                        .op(ISTORE(1))
+                        .op(ILOAD(0))
+                        .op(ICONST(-1))
+                        .op(IADD)
+                        .op(ISTORE(0));
+                    if goto {
+                        code.jump(GOTO, "loop");
+                    } else {
+                        code.jump_w(GOTOW, "loop");
+                    }
+                    code.label("end")
+                        .op(ILOAD(1))
+                        .op(IRETURN)
+                        .frame("loop", &[Integer, Integer], &[])
+                        .frame("end", &[Integer, Integer], &[]);
+                },
+                "()I",
+            )
+            .unwrap()
+            .into_i32()
+        };
+        assert_eq!(55, sum(true));
+        assert_eq!(55, sum(false));
+    }
+
+    #[test]
+    fn switches() {
+        let cases = |code: &mut Code| {
+            for (label, value) in [("one", 1), ("two", 2), ("three", 3), ("default", 0)] {
+                code.label(label)
+                    .op(ICONST(value))
+                    .op(IRETURN)
+                    .frame(label, &[], &[]);
+            }
+        };
+        let tableswitch = |key: i32| {
+            run(
+                &[Int(key)],
+                |code| {
+                    code.tableswitch(1, &["one", "two", "three"], "default");
+                    cases(code);
+                },
+                "()I",
+            )
+            .unwrap()
+            .into_i32()
+        };
+        assert_eq!(1, tableswitch(1));
+        assert_eq!(3, tableswitch(3));
+        assert_eq!(0, tableswitch(0));
+        assert_eq!(0, tableswitch(4));
+        assert_eq!(0, tableswitch(i32::MIN));
+
+        let lookupswitch = |key: i32| {
+            run(
+                &[Int(key)],
+                |code| {
+                    code.lookupswitch(&[(7, "two"), (-5, "one"), (i32::MAX, "three")], "default");
+                    cases(code);
+                },
+                "()I",
+            )
+            .unwrap()
+            .into_i32()
+        };
+        assert_eq!(1, lookupswitch(-5));
+        assert_eq!(2, lookupswitch(7));
+        assert_eq!(3, lookupswitch(i32::MAX));
+        assert_eq!(0, lookupswitch(0));
+    }
+
+    #[test]
+    fn subroutines() {
+        // a pre-java 6 finally block: the subroutine doubles n, it is called with jsr and jsr_w
+        // and returns with ret and wide ret
+        // jsr and ret are not allowed from class file version 51, so the class has the version
+        // of the builder, 49, and is not verified
+        let subroutine = |wide: bool| {
+            let mut class = ClassBuilder::new("Opcodes", Some("java/lang/Object"));
+            let mut code = Code::new();
+            code.op(ICONST(5))
+                .op(ISTORE(0))
+                .jump(JSR, "finally")
+                .jump_w(JSR_W, "finally")
+                .op(ILOAD(0))
+                .op(IRETURN)
+                .label("finally");
+            if wide {
+                code.op(WIDE_ASTORE(300));
+            } else {
+                code.op(ASTORE(1));
+            }
+            code.op(ILOAD(0)).op(ILOAD(0)).op(IADD).op(ISTORE(0));
+            if wide {
+                code.op(WIDE_RET(300));
+            } else {
+                code.op(RET(1));
+            }
+            class.method(PUBLIC_STATIC, "run", "()I", code);
+            run_classes(&[class], "run()I").unwrap().into_i32()
+        };
+        assert_eq!(20, subroutine(false));
+        assert_eq!(20, subroutine(true));
+    }
+
+    #[test]
+    fn two_slot_locals() {
+        // static double sum(long a, int b, double c, int d) { double sum = a + b + c + d; return sum; }
+        let mut class = ClassBuilder::new("Locals", Some("java/lang/Object"));
+        let mut sum = Code::new();
+        sum.op(LLOAD(0))
+            .op(L2D)
+            .op(ILOAD(2))
+            .op(I2D)
+            .op(DADD)
+            .op(DLOAD(3))
+            .op(DADD)
+            .op(ILOAD(5))
+            .op(I2D)
+            .op(DADD)
+            .op(WIDE_DSTORE(6))
+            .op(WIDE_DLOAD(6))
+            .op(DRETURN);
+        class.method(PUBLIC_STATIC, "sum", "(JIDI)D", sum);
+
+        let mut run = Code::new();
+        let (a, c) = (class.long(1), class.double(3.5));
+        let sum = class.method_ref("Locals", "sum", "(JIDI)D");
+        run.op(LDC2_W(a))
+            .op(ICONST(2))
+            .op(LDC2_W(c))
+            .op(ICONST(4))
+            .op(INVOKESTATIC(sum))
+            .op(DRETURN);
+        class.method(PUBLIC_STATIC, "run", "()D", run);
+        let result = run_classes(&[class], "run()D");
+        assert_eq!(10.5, result.unwrap().into_f64());
+    }
+
+    #[test]
+    fn catch_by_superclass() {
+        let mut class = ClassBuilder::new("Catch", Some("java/lang/Object"));
+        let mut code = Code::new();
+        code.label("start");
+        throw_new(&mut class, &mut code, "java/lang/ArithmeticException");
+        code.label("end")
+            .label("null")
+            .op(POP)
+            .op(ICONST(1))
+            .op(IRETURN)
+            .label("runtime")
+            .op(POP)
+            .op(ICONST(2))
+            .op(IRETURN)
+            .catch(
+                "start",
+                "end",
+                "null",
+                Some("java/lang/NullPointerException"),
+            )
+            .catch(
+                "start",
+                "end",
+                "runtime",
+                Some("java/lang/RuntimeException"),
+            );
+        class.method(PUBLIC_STATIC, "run", "()I", code);
+        assert_eq!(2, run_classes(&[class], "run()I").unwrap().into_i32());
+    }
+
+    #[test]
+    fn finally() {
+        let mut class = ClassBuilder::new("Finally", Some("java/lang/Object"));
+        let mut code = Code::new();
+        code.label("start");
+        throw_new(&mut class, &mut code, "java/lang/ArithmeticException");
+        code.label("end")
+            .op(ASTORE(0))
+            .op(ICONST(3))
+            .op(IRETURN)
+            .catch("start", "end", "end", None);
+        class.method(PUBLIC_STATIC, "run", "()I", code);
+        assert_eq!(3, run_classes(&[class], "run()I").unwrap().into_i32());
+    }
+
+    #[test]
+    fn exit_is_not_caught() {
+        let public_static_native = PUBLIC_STATIC | Modifier::Native as u16;
+        let mut shutdown = ClassBuilder::new("java/lang/Shutdown", Some("java/lang/Object"));
+        shutdown.abstract_method(public_static_native, "halt0", "(I)V");
+
+        let mut class = ClassBuilder::new("Exit", Some("java/lang/Object"));
+        let halt = class.method_ref("java/lang/Shutdown", "halt0", "(I)V");
+        let mut exits = Code::new();
+        exits
+            .label("start")
+            .op(ICONST(3))
+            .op(INVOKESTATIC(halt))
+            .op(RETURN_VOID)
+            .label("end")
+            .op(POP)
+            .op(RETURN_VOID)
+            .catch("start", "end", "end", None);
+        class.method(PUBLIC_STATIC, "exits", "()V", exits);
+        let exits = class.method_ref("Exit", "exits", "()V");
+        let mut code = Code::new();
+        code.label("start")
+            .op(INVOKESTATIC(exits))
+            .op(ICONST(0))
+            .op(IRETURN)
+            .label("end")
+            .op(POP)
+            .op(ICONST(1))
+            .op(IRETURN)
+            .catch("start", "end", "end", None);
+        class.method(PUBLIC_STATIC, "run", "()I", code);
+
+        // the status unwinds through the finally handlers of all frames
+        let result = run_classes(&[class, shutdown], "run()I");
+        assert!(matches!(result, Err(Abrupt::Exit(3))), "{:?}", result);
+    }
+
+    #[test]
+    fn rethrow_across_frames() {
+        let mut class = ClassBuilder::new("Rethrow", Some("java/lang/Object"));
+        let mut thrower = Code::new();
+        throw_new(&mut class, &mut thrower, "java/lang/ArithmeticException");
+        class.method(PUBLIC_STATIC, "thrower", "()V", thrower);
+
+        // the finally block stores 1 in the static field and rethrows
+        class.field(PUBLIC_STATIC, "finished", "I");
+        let finished = class.field_ref("Rethrow", "finished", "I");
+        let thrower = class.method_ref("Rethrow", "thrower", "()V");
+        let mut middle = Code::new();
+        middle
+            .label("start")
+            .op(INVOKESTATIC(thrower))
+            .label("end")
+            .op(RETURN_VOID)
+            .label("finally")
+            .op(ASTORE(0))
+            .op(ICONST(1))
+            .op(PUTSTATIC(finished))
+            .op(ALOAD(0))
+            .op(ATHROW)
+            .catch("start", "end", "finally", None);
+        class.method(PUBLIC_STATIC, "middle", "()V", middle);
+
+        let middle = class.method_ref("Rethrow", "middle", "()V");
+        let mut run = Code::new();
+        run.label("start")
+            .op(INVOKESTATIC(middle))
+            .label("end")
+            .op(ICONST(0))
+            .op(IRETURN)
+            .label("handler")
+            .op(POP)
+            .op(GETSTATIC(finished))
+            .op(ICONST(1))
+            .op(IADD)
+            .op(IRETURN)
+            .catch(
+                "start",
+                "end",
+                "handler",
+                Some("java/lang/ArithmeticException"),
+            );
+        class.method(PUBLIC_STATIC, "run", "()I", run);
+        assert_eq!(2, run_classes(&[class], "run()I").unwrap().into_i32());
+    }
+
+    #[test]
+    fn uncaught_exception() {
+        let mut class = ClassBuilder::new("Uncaught", Some("java/lang/Object"));
+        // the handler does not cover the athrow
+        let mut code = Code::new();
+        throw_new(&mut class, &mut code, "java/lang/ArithmeticException");
+        code.label("start")
+            .op(ACONST_NULL)
+            .label("end")
+            .op(ARETURN)
+            .catch("start", "end", "start", None);
+        class.method(PUBLIC_STATIC, "run", "()Ljava/lang/Object;", code);
+
+        let arithmetic = class.class("java/lang/ArithmeticException");
+        let mut is_arithmetic = Code::new();
+        is_arithmetic
+            .op(ALOAD(0))
+            .op(INSTANCEOF(arithmetic))
+            .op(IRETURN);
+        class.method(
+            PUBLIC_STATIC,
+            "isArithmetic",
+            "(Ljava/lang/Throwable;)I",
+            is_arithmetic,
+        );
+
+        let mut class_manager = class_manager();
+        let id = class_manager.define_class(class.build().unwrap()).unwrap();
+        class_manager.load_class_by_name("Uncaught").unwrap();
+        let result = Stackframe::default().run(&mut class_manager, id, "run()Ljava/lang/Object;");
+        let Err(Abrupt::Throw(exception)) = result else {
+            panic!("expected an exception, not {:?}", result)
+        };
+        let is_arithmetic = Stackframe::new(vec![exception]).run(
+            &mut class_manager,
+            id,
+            "isArithmetic(Ljava/lang/Throwable;)I",
+        );
+        assert_eq!(1, is_arithmetic.unwrap().into_i32());
+    }
+
+    #[test]
+    fn exception_in_initializer() {
+        let mut failing = ClassBuilder::new("Failing", Some("java/lang/Object"));
+        let mut clinit = Code::new();
+        throw_new(&mut failing, &mut clinit, "java/lang/ArithmeticException");
+        failing.method(PUBLIC_STATIC, "<clinit>", "()V", clinit);
+        let mut value = Code::new();
+        value.op(ICONST(1)).op(IRETURN);
+        failing.method(PUBLIC_STATIC, "value", "()I", value);
+
+        // the first use throws the ExceptionInInitializerError with the exception as its cause,
+        // the second a NoClassDefFoundError
+        let mut class = ClassBuilder::new("Init", Some("java/lang/Object"));
+        let value = class.method_ref("Failing", "value", "()I");
+        let cause = class.field_ref("java/lang/Throwable", "cause", "Ljava/lang/Throwable;");
+        let arithmetic = class.class("java/lang/ArithmeticException");
+        let mut code = Code::new();
+        code.label("first")
+            .op(INVOKESTATIC(value))
+            .label("first_end")
+            .op(IRETURN)
+            .label("initializer")
+            .op(GETFIELD(cause))
+            .op(INSTANCEOF(arithmetic))
+            .op(ISTORE(0))
+            .label("second")
+            .op(INVOKESTATIC(value))
+            .label("second_end")
+            .op(IRETURN)
+            .label("no_class")
+            .op(POP)
+            .op(ILOAD(0))
+            .op(ICONST(1))
+            .op(IADD)
+            .op(IRETURN)
+            .catch(
+                "first",
+                "first_end",
+                "initializer",
+                Some("java/lang/ExceptionInInitializerError"),
+            )
+            .catch(
+                "second",
+                "second_end",
+                "no_class",
+                Some("java/lang/NoClassDefFoundError"),
+            );
+        class.method(PUBLIC_STATIC, "run", "()I", code);
+        assert_eq!(
+            2,
+            run_classes(&[class, failing], "run()I").unwrap().into_i32()
+        );
+    }
+
+    /// 1 if the code throws the exception, 0 if it does not
+    fn catches(exception: &str, code: impl FnOnce(&mut ClassBuilder, &mut Code)) -> i32 {
+        let mut class = ClassBuilder::new("Faults", Some("java/lang/Object"));
+        let mut method = Code::new();
+        method.label("start");
+        code(&mut class, &mut method);
+        method
+            .label("end")
+            .op(ICONST(0))
+            .op(IRETURN)
+            .label("handler")
+            .op(POP)
+            .op(ICONST(1))
+            .op(IRETURN)
+            .catch("start", "end", "handler", Some(exception));
+        class.method(PUBLIC_STATIC, "run", "()I", method);
+        run_classes(&[class], "run()I").unwrap().into_i32()
+    }
+
+    #[test]
+    fn null_pointer_on_invoke() {
+        let caught = catches("java/lang/NullPointerException", |class, code| {
+            let length = class.method_ref("java/lang/String", "length", "()I");
+            code.op(ACONST_NULL).op(INVOKEVIRTUAL(length));
+        });
+        assert_eq!(1, caught);
+    }
+
+    #[test]
+    fn null_pointer_on_field() {
+        let caught = catches("java/lang/NullPointerException", |class, code| {
+            let message =
+                class.field_ref("java/lang/Throwable", "detailMessage", "Ljava/lang/String;");
+            code.op(ACONST_NULL).op(GETFIELD(message));
+        });
+        assert_eq!(1, caught);
+    }
+
+    #[test]
+    fn null_pointer_on_array() {
+        let caught = catches("java/lang/NullPointerException", |_, code| {
+            code.op(ACONST_NULL).op(ICONST(0)).op(IALOAD);
+        });
+        assert_eq!(1, caught);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        for opcode in [IDIV, IREM] {
+            let caught = catches("java/lang/ArithmeticException", |_, code| {
+                code.op(ICONST(1)).op(ICONST(0)).op(opcode);
+            });
+            assert_eq!(1, caught);
+        }
+        for opcode in [LDIV, LREM] {
+            let caught = catches("java/lang/ArithmeticException", |_, code| {
+                code.op(LCONST(1)).op(LCONST(0)).op(opcode);
+            });
+            assert_eq!(1, caught);
+        }
+    }
+
+    #[test]
+    fn array_index_out_of_bounds() {
+        let caught = catches("java/lang/ArrayIndexOutOfBoundsException", |_, code| {
+            // (new int[2])[2]
+            code.op(ICONST(2)).op(NEWARRAY(10)).op(ICONST(2)).op(IALOAD);
+        });
+        assert_eq!(1, caught);
+    }
+
+    #[test]
+    fn negative_array_size() {
+        let caught = catches("java/lang/NegativeArraySizeException", |_, code| {
+            code.op(ICONST(-1)).op(NEWARRAY(10));
+        });
+        assert_eq!(1, caught);
+    }
+
+    #[test]
+    fn no_such_method() {
+        let caught = catches("java/lang/NoSuchMethodError", |class, code| {
+            let missing = class.method_ref("Faults", "missing", "()V");
+            code.op(INVOKESTATIC(missing));
+        });
+        assert_eq!(1, caught);
+    }
+
+    #[test]
+    fn multianewarray_negative_size() {
+        let caught = catches("java/lang/NegativeArraySizeException", |class, code| {
+            let array = class.class("[[I");
+            code.op(ICONST(1))
+                .op(ICONST(-1))
+                .op(MULTIANEWARRAY(array, 2))
+                .op(POP);
+        });
+        assert_eq!(1, caught);
+    }
+
+    #[test]
+    fn stack_trace_line_numbers() {
+        let mut class = ClassBuilder::new("Trace", Some("java/lang/Object"));
+        // the lines are split over two tables, the throw is in the first one
+        let mut thrower = Code::new();
+        thrower.label("start").op(NOP).label("throw");
+        throw_new(&mut class, &mut thrower, "java/lang/ArithmeticException");
+        thrower
+            .line_numbers(&[("throw", 10)])
+            .line_numbers(&[("start", 9)]);
+        class.method(PUBLIC_STATIC, "thrower", "()V", thrower);
+
+        // the line numbers of the two frames in the stack trace, as 1000 * first + second
+        let thrower = class.method_ref("Trace", "thrower", "()V");
+        let get_stack_trace = class.method_ref(
+            "java/lang/Throwable",
+            "getStackTrace",
+            "()[Ljava/lang/StackTraceElement;",
+        );
+        let line_number = class.field_ref("java/lang/StackTraceElement", "lineNumber", "I");
+        let mut run = Code::new();
+        run.label("start")
+            .op(INVOKESTATIC(thrower))
+            .label("end")
+            .op(ICONST(0))
+            .op(IRETURN)
+            .label("handler")
+            .op(INVOKEVIRTUAL(get_stack_trace))
+            .op(ASTORE(0))
+            .op(ALOAD(0))
+            .op(ARRAYLENGTH)
+            .op(ICONST(2))
+            .jump(IF_ICMPNE, "end")
+            .op(ALOAD(0))
+            .op(ICONST(0))
+            .op(AALOAD)
+            .op(GETFIELD(line_number))
+            .op(SIPUSH(1000))
+            .op(IMUL)
+            .op(ALOAD(0))
+            .op(ICONST(1))
+            .op(AALOAD)
+            .op(GETFIELD(line_number))
+            .op(IADD)
+            .op(IRETURN)
+            .catch(
+                "start",
+                "end",
+                "handler",
+                Some("java/lang/ArithmeticException"),
+            )
+            .line_numbers(&[("start", 20), ("end", 21)]);
+        class.method(PUBLIC_STATIC, "run", "()I", run);
+        assert_eq!(10020, run_classes(&[class], "run()I").unwrap().into_i32());
+    }
+
+    #[test]
+    fn multianewarray() {
+        // new int[2][3], the lengths of the array and its second element, and the last int
+        let mut class = ClassBuilder::new("Arrays", Some("java/lang/Object"));
+        let array = class.class("[[I");
+        let mut run = Code::new();
+        run.op(ICONST(2))
+            .op(ICONST(3))
+            .op(MULTIANEWARRAY(array, 2))
+            .op(ASTORE(0))
+            .op(ALOAD(0))
+            .op(ARRAYLENGTH)
+            .op(BIPUSH(100))
+            .op(IMUL)
+            .op(ALOAD(0))
+            .op(ICONST(1))
+            .op(AALOAD)
+            .op(ASTORE(1))
+            .op(ALOAD(1))
+            .op(ARRAYLENGTH)
+            .op(BIPUSH(10))
+            .op(IMUL)
+            .op(IADD)
+            .op(ALOAD(1))
+            .op(ICONST(2))
+            .op(IALOAD)
+            .op(IADD)
+            .op(IRETURN);
+        class.method(PUBLIC_STATIC, "run", "()I", run);
+        assert_eq!(230, run_classes(&[class], "run()I").unwrap().into_i32());
+    }
+
+    /// stores the value at index 1 of a new array of length 2, then loads it from the array
+    fn store_and_load(
+        new_array: Opcode,
+        value: Operand,
+        store: Opcode,
+        load: Opcode,
+        descriptor: &str,
+    ) -> MethodResult {
+        let mut class = ClassBuilder::new("Arrays", Some("java/lang/Object"));
+        let mut run = Code::new();
+        run.op(ICONST(2))
+            .op(new_array)
+            .op(ASTORE(0))
+            .op(ALOAD(0))
+            .op(ICONST(1))
+            .op(ldc(&mut class, value))
+            .op(store)
+            .op(ALOAD(0))
+            .op(ICONST(1))
+            .op(load)
+            .op(match descriptor {
+                "()J" => LRETURN,
+                "()F" => FRETURN,
+                "()D" => DRETURN,
+                _ => IRETURN,
+            });
+        class.method(PUBLIC_STATIC, "run", descriptor, run);
+        run_classes(&[class], &format!("run{}", descriptor))
+    }
+
+    #[test]
+    fn array_stores_are_kept() {
+        let int = |new_array, value, store, load| {
+            store_and_load(new_array, Int(value), store, load, "()I")
+                .unwrap()
+                .into_i32()
+        };
+        assert_eq!(5, int(NEWARRAY(10), 5, IASTORE, IALOAD));
+        // narrowed to the type of the array
+        assert_eq!(-1, int(NEWARRAY(8), 0x1ff, BASTORE, BALOAD));
+        assert_eq!(1, int(NEWARRAY(4), 3, BASTORE, BALOAD));
+        assert_eq!(0, int(NEWARRAY(4), 2, BASTORE, BALOAD));
+        assert_eq!(-32768, int(NEWARRAY(9), 0x18000, SASTORE, SALOAD));
+        assert_eq!(0x41, int(NEWARRAY(5), 0x10041, CASTORE, CALOAD));
+        let long = store_and_load(NEWARRAY(11), Long(1 << 40), LASTORE, LALOAD, "()J");
+        assert_eq!(1 << 40, long.unwrap().into_i64());
+        let float = store_and_load(NEWARRAY(6), Float(1.5), FASTORE, FALOAD, "()F");
+        assert_eq!(1.5, float.unwrap().into_f32());
+        let double = store_and_load(NEWARRAY(7), Double(-2.5), DASTORE, DALOAD, "()D");
+        assert_eq!(-2.5, double.unwrap().into_f64());
+    }
+
+    #[test]
+    fn object_arrays_start_out_null() {
+        // a = new Object[3]; a[2] = a; the length, whether a[0] is null and a[2] is a
+        let mut class = ClassBuilder::new("Arrays", Some("java/lang/Object"));
+        let object = class.class("java/lang/Object");
+        let mut run = Code::new();
+        run.op(ICONST(3))
+            .op(ANEWARRAY(object))
+            .op(ASTORE(0))
+            .op(ALOAD(0))
+            .op(ICONST(2))
+            .op(ALOAD(0))
+            .op(AASTORE)
+            .op(ALOAD(0))
+            .op(ARRAYLENGTH)
+            .op(BIPUSH(100))
+            .op(IMUL)
+            .op(ALOAD(0))
+            .op(ICONST(0))
+            .op(AALOAD)
+            .jump(IFNONNULL, "not null")
+            .op(BIPUSH(10))
+            .op(IADD)
+            .label("not null")
+            .op(ALOAD(0))
+            .op(ICONST(2))
+            .op(AALOAD)
+            .op(ALOAD(0))
+            .jump(IF_ACMPNE, "other")
+            .op(ICONST(1))
+            .op(IADD)
+            .label("other")
+            .op(IRETURN);
+        class.method(PUBLIC_STATIC, "run", "()I", run);
+        assert_eq!(311, run_classes(&[class], "run()I").unwrap().into_i32());
+    }
+
+    #[test]
+    fn array_lengths() {
+        let mut class = ClassBuilder::new("Arrays", Some("java/lang/Object"));
+        let object = class.class("java/lang/Object");
+        let objects = class.class("[[Ljava/lang/Object;");
+        let mut new_arrays: Vec<Vec<Opcode>> = (4..=11)
+            .map(|array_type| vec![ICONST(3), NEWARRAY(array_type)])
+            .collect();
+        new_arrays.push(vec![ICONST(3), ANEWARRAY(object)]);
+        // the last dimension of new Object[2][3]
+        new_arrays.push(vec![
+            ICONST(2),
+            ICONST(3),
+            MULTIANEWARRAY(objects, 2),
+            ICONST(1),
+            AALOAD,
+        ]);
+        for (i, new_array) in new_arrays.into_iter().enumerate() {
+            let mut code = Code::new();
+            for opcode in new_array {
+                code.op(opcode);
+            }
+            code.op(ARRAYLENGTH).op(IRETURN);
+            class.method(PUBLIC_STATIC, &format!("length{}", i), "()I", code);
+        }
+        let mut class_manager = class_manager();
+        let id = class_manager.define_class(class.build().unwrap()).unwrap();
+        class_manager.load_class_by_name("Arrays").unwrap();
+        for i in 0..10 {
+            let length = Stackframe::default()
+                .run(&mut class_manager, id, &format!("length{}()I", i))
+                .unwrap();
+            assert_eq!(3, length.into_i32(), "length{}", i);
+        }
+    }
+
+    #[test]
+    fn arrays_are_compared_by_identity() {
+        // two arrays with the same elements, or an array with itself
+        let compare = |same: bool| {
+            run(
+                &[Float(f32::NAN)],
+                |code| {
+                    // a float[1] that holds NaN
+                    code.op(FSTORE(0))
+                        .op(ICONST(1))
+                        .op(NEWARRAY(6))
+                        .op(DUP)
+                        .op(ICONST(0))
+                        .op(FLOAD(0))
+                        .op(FASTORE);
+                    if same {
+                        code.op(DUP);
+                    } else {
+                        code.op(ICONST(1)).op(NEWARRAY(6));
+                    }
+                    code.jump(IF_ACMPEQ, "same")
+                        .op(ICONST(0))
+                        .op(IRETURN)
+                        .label("same")
+                        .op(ICONST(1))
+                        .op(IRETURN)
+                        .frame("same", &[FloatType], &[]);
+                },
+                "()I",
+            )
+            .unwrap()
+            .into_i32()
+        };
+        assert_eq!(1, compare(true));
+        assert_eq!(0, compare(false));
+
+        let equal_int_arrays = run(
+            &[],
+            |code| {
+                code.op(ICONST(1))
+                    .op(NEWARRAY(10))
+                    .op(ICONST(1))
+                    .op(NEWARRAY(10))
+                    .jump(IF_ACMPNE, "different")
+                    .op(ICONST(0))
+                    .op(IRETURN)
+                    .label("different")
+                    .op(ICONST(1))
+                    .op(IRETURN)
+                    .frame("different", &[], &[]);
+            },
+            "()I",
+        );
+        assert_eq!(1, equal_int_arrays.unwrap().into_i32());
+    }
+
+    #[test]
+    fn string_constant_with_lone_surrogate() {
+        // the string keeps the code unit, that a rust string would replace with U+FFFD
+        let mut class = ClassBuilder::new("Surrogate", Some("java/lang/Object"));
+        let utf8 = class.constant(CpEntry::Utf8("\u{FFFD}".into(), vec![0xD800]));
+        let string = class.constant(CpEntry::StringRef(utf8));
+        let value = class.field_ref("java/lang/String", "value", "[B");
+        let mut run = Code::new();
+        run.op(LDC_W(string))
+            .op(GETFIELD(value))
+            .op(ASTORE(0))
+            .op(ALOAD(0))
+            .op(ICONST(0))
+            .op(BALOAD)
+            .op(SIPUSH(0xFF))
+            .op(IAND)
+            .op(ALOAD(0))
+            .op(ICONST(1))
+            .op(BALOAD)
+            .op(SIPUSH(0xFF))
+            .op(IAND)
+            .op(BIPUSH(8))
+            .op(ISHL)
+            .op(IOR)
+            .op(IRETURN);
+        class.method(PUBLIC_STATIC, "run", "()I", run);
+        // the utf-16 bytes are in native byte order
+        let expected = u16::from_le_bytes(0xD800u16.to_ne_bytes()) as i32;
+        assert_eq!(
+            expected,
+            run_classes(&[class], "run()I").unwrap().into_i32()
+        );
+    }
+
+    #[test]
+    fn dynamic_constant() {
+        // the bootstrap method counts its calls and returns a new object
+        let mut class = ClassBuilder::new("Dynamic", Some("java/lang/Object"));
+        // Dynamic constants are in class files from version 55 (Java 11)
+        class.version(55, 0);
+        class.field(PUBLIC_STATIC, "calls", "I");
+        let calls = class.field_ref("Dynamic", "calls", "I");
+        let object = class.class("java/lang/Object");
+        let init = class.method_ref("java/lang/Object", "<init>", "()V");
+        let bootstrap_descriptor = "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;\
+                                    Ljava/lang/Class;)Ljava/lang/Object;";
+        let mut bootstrap = Code::new();
+        bootstrap
+            .op(GETSTATIC(calls))
+            .op(ICONST(1))
+            .op(IADD)
+            .op(PUTSTATIC(calls))
+            .op(NEW(object))
+            .op(DUP)
+            .op(INVOKESPECIAL(init))
+            .op(ARETURN);
+        class.method(PUBLIC_STATIC, "bootstrap", bootstrap_descriptor, bootstrap);
+
+        // ldc resolves the constant once, the second load takes it from the cache
+        let bootstrap_ref = class.method_ref("Dynamic", "bootstrap", bootstrap_descriptor);
+        let bootstrap_handle = class.method_handle(6, bootstrap_ref);
+        let bootstrap_method = class.bootstrap_method(bootstrap_handle, &[]);
+        let constant = class.dynamic(bootstrap_method, "constant", "Ljava/lang/Object;");
+        let mut run = Code::new();
+        run.op(LDC_W(constant))
+            .op(POP)
+            .op(LDC_W(constant))
+            .op(POP)
+            .op(GETSTATIC(calls))
+            .op(IRETURN);
+        class.method(PUBLIC_STATIC, "run", "()I", run);
+        assert_eq!(1, run_classes(&[class], "run()I").unwrap().into_i32());
+    }
+}
@@ -0,0 +1,139 @@
+mod common;
+
+/// Annotations read back through reflection, on the classes in testclasses/Annotated.java:
+/// the natives behind getAnnotation, getAnnotations, getDefaultValue and getParameterAnnotations,
+/// and the element methods that the vm answers for annotation instances.
+mod test {
+    use java_rs::classloader::builder::{ClassBuilder, Code};
+    use java_rs::classloader::classdef::Modifier;
+    use java_rs::classloader::load_class;
+    use java_rs::vm::opcodes::Opcode::*;
+    use java_rs::vm::runtime::Stackframe;
+
+    use crate::common::class_manager;
+
+    const PUBLIC: u16 = Modifier::Public as u16;
+    const NATIVE: u16 = PUBLIC | Modifier::Native as u16;
+    const INTERFACE: u16 = PUBLIC | 0x0200 | Modifier::Abstract as u16;
+
+    /// java/lang/annotation/Annotation, java/lang/Enum, java/lang/Integer and
+    /// java/lang/reflect/Method with the fields that the vm fills in
+    fn reflection_classes() -> Vec<ClassBuilder> {
+        let mut annotation =
+            ClassBuilder::new("java/lang/annotation/Annotation", Some("java/lang/Object"));
+        annotation.access_flags(INTERFACE).abstract_method(
+            PUBLIC | Modifier::Abstract as u16,
+            "annotationType",
+            "()Ljava/lang/Class;",
+        );
+
+        let mut enum_class = ClassBuilder::new("java/lang/Enum", Some("java/lang/Object"));
+        enum_class
+            .access_flags(PUBLIC | Modifier::Abstract as u16)
+            .field(PUBLIC, "name", "Ljava/lang/String;")
+            .field(PUBLIC, "ordinal", "I");
+        let object_init = enum_class.method_ref("java/lang/Object", "<init>", "()V");
+        let name = enum_class.field_ref("java/lang/Enum", "name", "Ljava/lang/String;");
+        let ordinal = enum_class.field_ref("java/lang/Enum", "ordinal", "I");
+        let mut init = Code::new();
+        init.op(ALOAD(0))
+            .op(INVOKESPECIAL(object_init))
+            .op(ALOAD(0))
+            .op(ALOAD(1))
+            .op(PUTFIELD(name))
+            .op(ALOAD(0))
+            .op(ILOAD(2))
+            .op(PUTFIELD(ordinal))
+            .op(RETURN_VOID);
+        enum_class.method(PUBLIC, "<init>", "(Ljava/lang/String;I)V", init);
+
+        let mut integer = ClassBuilder::new("java/lang/Integer", Some("java/lang/Object"));
+        integer.field(PUBLIC, "value", "I");
+        let value = integer.field_ref("java/lang/Integer", "value", "I");
+        let mut int_value = Code::new();
+        int_value.op(ALOAD(0)).op(GETFIELD(value)).op(IRETURN);
+        integer.method(PUBLIC, "intValue", "()I", int_value);
+
+        let mut method = ClassBuilder::new("java/lang/reflect/Method", Some("java/lang/Object"));
+        method
+            .field(PUBLIC, "clazz", "Ljava/lang/Class;")
+            .field(PUBLIC, "slot", "I")
+            .field(PUBLIC, "name", "Ljava/lang/String;")
+            .field(PUBLIC, "modifiers", "I")
+            .field(PUBLIC, "returnType", "Ljava/lang/Class;")
+            .abstract_method(
+                NATIVE,
+                "getParameterAnnotations",
+                "()[[Ljava/lang/annotation/Annotation;",
+            )
+            .abstract_method(NATIVE, "getDefaultValue", "()Ljava/lang/Object;");
+        let name = method.field_ref("java/lang/reflect/Method", "name", "Ljava/lang/String;");
+        let mut get_name = Code::new();
+        get_name.op(ALOAD(0)).op(GETFIELD(name)).op(ARETURN);
+        method.method(PUBLIC, "getName", "()Ljava/lang/String;", get_name);
+
+        vec![annotation, enum_class, integer, method]
+    }
+
+    /// runs the static method of testclasses/Annotated
+    fn run(method: &str) -> i32 {
+        let mut class_manager = class_manager();
+        for class in reflection_classes() {
+            class_manager.define_class(class.build().unwrap()).unwrap();
+        }
+        for bytecode in [
+            &include_bytes!("testclasses/Annotated.class")[..],
+            &include_bytes!("testclasses/AnnotatedBase.class")[..],
+            &include_bytes!("testclasses/Marker.class")[..],
+            &include_bytes!("testclasses/Info.class")[..],
+            &include_bytes!("testclasses/Param.class")[..],
+            &include_bytes!("testclasses/Color.class")[..],
+        ] {
+            let classdef = load_class(bytecode.to_vec()).unwrap();
+            class_manager.define_class(classdef).unwrap();
+        }
+        class_manager
+            .load_class_by_name("testclasses/Annotated")
+            .unwrap();
+        let id = *class_manager.get_classid("testclasses/Annotated");
+        Stackframe::default()
+            .run(&mut class_manager, id, method)
+            .unwrap()
+            .into_i32()
+    }
+
+    #[test]
+    fn inherited_annotations() {
+        assert_eq!(2, run("declaredAnnotations()I"));
+        assert_eq!(1, run("inheritedAnnotations()I"));
+        assert_eq!(1, run("inheritsMarker()Z"));
+        assert_eq!(0, run("inheritsInfo()Z"));
+    }
+
+    #[test]
+    fn element_values() {
+        assert_eq!(4, run("nameLength()I"));
+        assert_eq!(1, run("isRed()Z"));
+        assert_eq!(6, run("sumOfSizes()I"));
+        assert_eq!(1, run("isStringType()Z"));
+        assert_eq!(1, run("isInfoType()Z"));
+    }
+
+    #[test]
+    fn missing_elements_have_the_default_value() {
+        assert_eq!(7, run("count()I"));
+    }
+
+    #[test]
+    fn default_values() {
+        assert_eq!(7, run("defaultCount()I"));
+        assert_eq!(0, run("hasDefaultName()Z"));
+    }
+
+    #[test]
+    fn parameter_annotations() {
+        assert_eq!(2, run("parameters()I"));
+        assert_eq!(0, run("firstParameterAnnotations()I"));
+        assert_eq!(4, run("secondParameterValueLength()I"));
+    }
+}
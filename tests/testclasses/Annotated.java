@@ -0,0 +1,123 @@
+package testclasses;
+
+import java.lang.annotation.Inherited;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.reflect.Method;
+
+public class Annotated extends AnnotatedBase {
+
+    public void describe(int size, @Param("name") String name) {
+    }
+
+    // @Marker is inherited, @Info is not
+    public static int inheritedAnnotations() {
+        return Annotated.class.getAnnotations().length;
+    }
+
+    public static boolean inheritsMarker() {
+        return Annotated.class.getAnnotation(Marker.class) != null;
+    }
+
+    public static boolean inheritsInfo() {
+        return Annotated.class.getAnnotation(Info.class) != null;
+    }
+
+    public static int declaredAnnotations() {
+        return AnnotatedBase.class.getAnnotations().length;
+    }
+
+    public static int nameLength() {
+        return info().name().length();
+    }
+
+    public static int count() {
+        return info().count();
+    }
+
+    public static boolean isRed() {
+        return info().color() == Color.RED;
+    }
+
+    public static int sumOfSizes() {
+        int sum = 0;
+        for (int size : info().sizes()) {
+            sum += size;
+        }
+        return sum;
+    }
+
+    public static boolean isStringType() {
+        return info().type() == String.class;
+    }
+
+    public static boolean isInfoType() {
+        return info().annotationType() == Info.class;
+    }
+
+    public static int defaultCount() {
+        return ((Integer) method(Info.class, "count").getDefaultValue()).intValue();
+    }
+
+    public static boolean hasDefaultName() {
+        return method(Info.class, "name").getDefaultValue() != null;
+    }
+
+    public static int parameters() {
+        return method(Annotated.class, "describe").getParameterAnnotations().length;
+    }
+
+    public static int firstParameterAnnotations() {
+        return method(Annotated.class, "describe").getParameterAnnotations()[0].length;
+    }
+
+    public static int secondParameterValueLength() {
+        Param param = (Param) method(Annotated.class, "describe").getParameterAnnotations()[1][0];
+        return param.value().length();
+    }
+
+    private static Info info() {
+        return AnnotatedBase.class.getAnnotation(Info.class);
+    }
+
+    private static Method method(Class<?> type, String name) {
+        for (Method method : type.getDeclaredMethods()) {
+            if (method.getName().equals(name)) {
+                return method;
+            }
+        }
+        return null;
+    }
+}
+
+@Marker
+@Info(name = "base", color = Color.RED, sizes = {1, 2, 3}, type = String.class)
+class AnnotatedBase {
+}
+
+@Retention(RetentionPolicy.RUNTIME)
+@Inherited
+@interface Marker {
+}
+
+@Retention(RetentionPolicy.RUNTIME)
+@interface Info {
+    String name();
+
+    int count() default 7;
+
+    Color color() default Color.GREEN;
+
+    int[] sizes() default {};
+
+    Class<?> type() default Object.class;
+}
+
+@Retention(RetentionPolicy.RUNTIME)
+@interface Param {
+    String value();
+}
+
+enum Color {
+    RED, GREEN
+}